@@ -0,0 +1,218 @@
+//! Times discovery, name parsing & planning over a generated library, so
+//! changes made for speed can be measured, and kept from regressing.
+//!
+//! ```text
+//! cargo bench --bench pipeline -- --size 2000 --save-baseline target/bench-baseline.txt
+//! cargo bench --bench pipeline -- --size 2000 --baseline target/bench-baseline.txt
+//! ```
+//!
+//! With `--baseline`, the run fails if anything is more than `--tolerance`
+//! percent (default 25) slower than it was
+
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context};
+use camino::{Utf8Path, Utf8PathBuf};
+use subfix::{
+    cli::Options,
+    discovery::{discover_subtitles, discover_videos},
+    fsops::set_read_only,
+    process,
+    report::Report,
+};
+
+const LANGUAGES: &[&str] = &["English", "French", "German", "Spanish"];
+
+struct Args {
+    size: usize,
+    iterations: usize,
+    baseline: Option<Utf8PathBuf>,
+    save_baseline: Option<Utf8PathBuf>,
+    tolerance: f64,
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = parse_args()?;
+    // Planning never changes anything, and read-only doesn't take locks
+    set_read_only();
+    let root = generate(args.size)?;
+    let result = run(&args, &root);
+    let _ = fs::remove_dir_all(&root);
+    let timings = result?;
+    if let Some(path) = &args.save_baseline {
+        let saved = timings
+            .iter()
+            .map(|(name, time)| format!("{name} {}\n", time.as_nanos()))
+            .collect::<String>();
+        fs::write(path, saved)
+            .with_context(|| format!("failed to save baseline {path}"))?;
+        println!("saved baseline to {path}");
+    }
+    if let Some(path) = &args.baseline {
+        compare(&timings, path, args.tolerance)?;
+    }
+    Ok(())
+}
+
+fn parse_args() -> anyhow::Result<Args> {
+    let mut parsed = Args {
+        size: 500,
+        iterations: 10,
+        baseline: None,
+        save_baseline: None,
+        tolerance: 25.0,
+    };
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .with_context(|| format!("{arg} requires a value"))
+        };
+        match arg.as_str() {
+            "--size" => parsed.size = value()?.parse()?,
+            "--iterations" => parsed.iterations = value()?.parse()?,
+            "--baseline" => parsed.baseline = Some(value()?.into()),
+            "--save-baseline" => parsed.save_baseline = Some(value()?.into()),
+            "--tolerance" => parsed.tolerance = value()?.parse()?,
+            // Passed by `cargo bench`
+            "--bench" => {},
+            _ => bail!("unknown argument {arg:?}"),
+        }
+    }
+    if parsed.size == 0 || parsed.iterations == 0 {
+        bail!("--size and --iterations need to be at least 1");
+    }
+    Ok(parsed)
+}
+
+/// A library of `size` movies, each with a `Subs` folder, and a show with
+/// `size` episodes in one folder, with a folder of subtitles for each
+fn generate(size: usize) -> anyhow::Result<Utf8PathBuf> {
+    let root = Utf8PathBuf::try_from(env::temp_dir())?
+        .join(format!("subfix-bench-{}", std::process::id()));
+    for movie in 0..size {
+        let dir = root.join(format!("movies/Movie {movie} (2010)"));
+        touch(&dir.join(format!("Movie {movie} (2010) 1080p.mkv")))?;
+        for lang in LANGUAGES {
+            touch(&dir.join(format!("Subs/{lang}.srt")))?;
+        }
+    }
+    let show = root.join("Show");
+    for episode in 0..size {
+        let (season, episode) = (episode / 50 + 1, episode % 50 + 1);
+        let name = format!("S{season:02}E{episode:02}");
+        touch(&show.join(format!("Show {name} 1080p.mkv")))?;
+        for lang in LANGUAGES {
+            touch(&show.join(format!("Subs/{name}/{lang}.srt")))?;
+        }
+    }
+    Ok(root)
+}
+
+fn touch(path: &Utf8Path) -> anyhow::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, "1\n00:00:01,000 --> 00:00:02,000\nHello\n")
+        .with_context(|| format!("failed to create {path}"))
+}
+
+fn run(
+    args: &Args,
+    root: &Utf8Path,
+) -> anyhow::Result<BTreeMap<&'static str, Duration>> {
+    let options = Options::default();
+    let movies = fs::read_dir(root.join("movies"))?
+        .map(|entry| Ok(Utf8PathBuf::try_from(entry?.path())?))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let show = root.join("Show");
+    let mut timings = BTreeMap::new();
+
+    timings.insert(
+        "discover-videos",
+        time(args, || {
+            discover_videos(&show, &options, &mut Report::default());
+        }),
+    );
+    let videos = discover_videos(&show, &options, &mut Report::default());
+    timings.insert(
+        "discover-subtitles",
+        time(args, || {
+            discover_subtitles(
+                &show,
+                &videos,
+                &options,
+                &mut Report::default(),
+            );
+        }),
+    );
+    timings.insert(
+        "plan-show",
+        time(args, || {
+            process(&show, &options).expect("show should be processed");
+        }),
+    );
+    timings.insert(
+        "plan-movies",
+        time(args, || {
+            for movie in &movies {
+                process(movie, &options).expect("movie should be processed");
+            }
+        }),
+    );
+    for (name, time) in &timings {
+        println!("{name:<20} {time:>12.2?}");
+    }
+    Ok(timings)
+}
+
+/// The median of the runs, which is steadier than the mean when the odd one
+/// is slowed down by something else on the machine
+fn time(args: &Args, mut bench: impl FnMut()) -> Duration {
+    let mut runs = (0..args.iterations)
+        .map(|_| {
+            let start = Instant::now();
+            bench();
+            start.elapsed()
+        })
+        .collect::<Vec<_>>();
+    runs.sort();
+    runs[runs.len() / 2]
+}
+
+fn compare(
+    timings: &BTreeMap<&'static str, Duration>,
+    baseline: &Utf8Path,
+    tolerance: f64,
+) -> anyhow::Result<()> {
+    let saved = fs::read_to_string(baseline)
+        .with_context(|| format!("failed to read baseline {baseline}"))?;
+    let mut regressed = Vec::new();
+    for line in saved.lines() {
+        let Some((name, nanos)) = line.split_once(' ') else {
+            continue;
+        };
+        let Some(time) = timings.get(name) else {
+            continue;
+        };
+        let before = nanos
+            .parse::<f64>()
+            .with_context(|| format!("bad baseline line {line:?}"))?;
+        let change = (time.as_nanos() as f64 - before) / before * 100.0;
+        println!("{name:<20} {change:>+7.1}%");
+        if change > tolerance {
+            regressed.push(name);
+        }
+    }
+    if !regressed.is_empty() {
+        bail!(
+            "slower than the baseline by more than {tolerance}%: {}",
+            regressed.join(", ")
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,126 @@
+//! Taking on links made before subfix was used (by hand, or by scripts), so
+//! what works on the links subfix manages works on those too
+
+use std::fs;
+
+use anyhow::bail;
+use camino::{Utf8Path, Utf8PathBuf};
+use log::{debug, error, info, warn};
+
+use crate::{
+    cli::Options,
+    discovery::{discover_videos, folders, Listing},
+    jellyfin,
+    manifest::{Entry, Manifest, Origin},
+    predicates,
+    report::{dry_run_prefix, Report},
+    time::Stamp,
+    xattr,
+};
+
+/// Records the subtitle links next to videos anywhere under `root` in their
+/// folder's manifest, returning how many weren't there already.
+///
+/// Only symlinks named the way Jellyfin expects (`Movie.en.srt`) that point
+/// at a subtitle which exists are taken on. Broken ones are left for the user
+/// to look at, rather than being recorded as subfix's
+pub fn adopt_links(
+    root: &Utf8Path,
+    options: &Options,
+) -> anyhow::Result<usize> {
+    if !options.force {
+        if let Some(why) = jellyfin::server_data_reason(root) {
+            bail!(
+                "not adopting links in {root}, it looks like Jellyfin's own \
+                 data: {why} (see --force)"
+            );
+        }
+    }
+    let mut adopted = 0;
+    for dir in folders(root, options) {
+        match adopt_in(&dir, options) {
+            Ok(count) => adopted += count,
+            Err(why) => error!("{why:#}"),
+        }
+    }
+    Ok(adopted)
+}
+
+fn adopt_in(dir: &Utf8Path, options: &Options) -> anyhow::Result<usize> {
+    // Skips are only of interest when linking
+    let videos = discover_videos(dir, options, &mut Report::default());
+    if videos.is_empty() {
+        return Ok(0);
+    }
+    let listing = Listing::read(dir);
+    let links = videos.iter().flat_map(|video| {
+        listing
+            .sidecars(video, |entry| {
+                entry
+                    .file_type()
+                    .map(|file_type| file_type.is_symlink())
+                    .unwrap_or_default()
+            })
+            .into_iter()
+            .map(move |link| (video, link))
+    });
+    let mut manifest = Manifest::load(dir)?;
+    let mut adopted = 0;
+    for (video, link) in links {
+        let Some(name) = link.file_name() else {
+            continue;
+        };
+        if manifest.get(name).is_some() {
+            debug!("{link} is already managed");
+            continue;
+        }
+        let Some(target) = subtitle_target(&link) else {
+            continue;
+        };
+        info!(
+            "{}adopting {link}, which links to {target}",
+            dry_run_prefix(),
+        );
+        // Made by subfix, but left out of the manifest (or it was lost)
+        let origin = match xattr::is_marked_created(&link) {
+            true => Origin::Created,
+            false => Origin::Adopted,
+        };
+        manifest.record(Entry {
+            name: name.to_owned(),
+            target,
+            video: video.path.file_name().map(str::to_owned),
+            // Only symlinks are adopted
+            written: None,
+            origin,
+            recorded: Stamp::now(),
+        });
+        adopted += 1;
+    }
+    manifest.save()?;
+    Ok(adopted)
+}
+
+/// Where a link points, if that's a subtitle that exists
+pub(crate) fn subtitle_target(link: &Utf8Path) -> Option<Utf8PathBuf> {
+    let target = match fs::read_link(link).map(Utf8PathBuf::try_from) {
+        Ok(Ok(target)) => target,
+        Ok(Err(_)) => {
+            warn!("not adopting {link}, it points at a non-UTF-8 path");
+            return None;
+        },
+        Err(why) => {
+            warn!("not adopting {link}, couldn't read it: {why}");
+            return None;
+        },
+    };
+    if !link.exists() {
+        warn!("not adopting {link}, it's broken (points at {target})");
+        return None;
+    }
+    if !predicates::has_subtitle_extension(&target) {
+        warn!("not adopting {link}, {target} isn't a subtitle");
+        return None;
+    }
+    Some(target)
+}
@@ -0,0 +1,209 @@
+//! The subtitles a whole library has next to its videos, for `stats`: how
+//! many of each language & format, how many are flagged, and which languages
+//! the most videos go without, to see what's worth fetching next
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use isolang::Language;
+
+use crate::{
+    cli::Options,
+    discovery::{discover_videos, folders, Listing},
+    json::Value,
+    lint::Sidecar,
+    naming::LanguageCodes,
+    report::Report,
+};
+
+/// How many gaps are listed, largest first
+const GAPS: usize = 5;
+
+#[derive(Debug, Default)]
+pub struct Census {
+    pub videos: usize,
+    /// Videos with no subtitles next to them at all
+    pub without_any: usize,
+    pub subtitles: usize,
+    pub languages: BTreeMap<Language, usize>,
+    /// Subtitles whose names don't give a language
+    pub unknown_language: usize,
+    /// By extension, lowercase
+    pub formats: BTreeMap<String, usize>,
+    pub forced: usize,
+    pub hearing_impaired: usize,
+    /// Languages some videos have, and how many videos don't, most first
+    pub gaps: Vec<(Language, usize)>,
+}
+
+impl Census {
+    pub fn per_video(&self) -> f64 {
+        match self.videos {
+            0 => 0.0,
+            videos => self.subtitles as f64 / videos as f64,
+        }
+    }
+
+    fn to_json(&self, root: &Utf8Path) -> Value {
+        let counts = |counts: Vec<(&str, usize)>| {
+            Value::object(
+                counts
+                    .into_iter()
+                    .map(|(key, count)| (key.to_owned(), count.into())),
+            )
+        };
+        Value::object([
+            ("path", root.as_str().into()),
+            ("videos", self.videos.into()),
+            ("without_any", self.without_any.into()),
+            ("subtitles", self.subtitles.into()),
+            ("per_video", Value::Number(self.per_video())),
+            (
+                "languages",
+                counts(
+                    self.languages
+                        .iter()
+                        .map(|(lang, count)| (code(*lang), *count))
+                        .collect(),
+                ),
+            ),
+            ("unknown_language", self.unknown_language.into()),
+            (
+                "formats",
+                counts(
+                    self.formats
+                        .iter()
+                        .map(|(format, count)| (format.as_str(), *count))
+                        .collect(),
+                ),
+            ),
+            ("forced", self.forced.into()),
+            ("hearing_impaired", self.hearing_impaired.into()),
+            (
+                "gaps",
+                Value::Array(
+                    self.gaps
+                        .iter()
+                        .map(|(lang, missing)| {
+                            Value::object([
+                                ("language", code(*lang).into()),
+                                ("videos_without", (*missing).into()),
+                            ])
+                        })
+                        .collect(),
+                ),
+            ),
+        ])
+    }
+}
+
+/// `{"libraries": [...]}`, for `--json`
+pub fn to_json(libraries: &[(Utf8PathBuf, Census)]) -> Value {
+    Value::object([(
+        "libraries",
+        Value::Array(
+            libraries
+                .iter()
+                .map(|(root, census)| census.to_json(root))
+                .collect(),
+        ),
+    )])
+}
+
+/// Counts the subtitles named after videos anywhere under `root`
+pub fn census(root: &Utf8Path, options: &Options) -> Census {
+    let mut census = Census::default();
+    // The languages of each video, for the gaps
+    let mut videos = Vec::<BTreeSet<Language>>::new();
+    for dir in folders(root, options) {
+        let listing = Listing::read(&dir);
+        for video in discover_videos(&dir, options, &mut Report::default()) {
+            let sidecars = listing.sidecars(&video, |_| true);
+            census.videos += 1;
+            if sidecars.is_empty() {
+                census.without_any += 1;
+            }
+            census.subtitles += sidecars.len();
+            let mut languages = BTreeSet::new();
+            for path in sidecars {
+                let format = path.extension().unwrap_or_default();
+                *census.formats.entry(format.to_lowercase()).or_default() += 1;
+                let Some(sidecar) = Sidecar::parse(&video.path, path) else {
+                    census.unknown_language += 1;
+                    continue;
+                };
+                *census.languages.entry(sidecar.lang).or_default() += 1;
+                census.forced += sidecar.is_forced() as usize;
+                census.hearing_impaired +=
+                    sidecar.is_hearing_impaired() as usize;
+                languages.insert(sidecar.lang);
+            }
+            videos.push(languages);
+        }
+    }
+    let mut gaps = census
+        .languages
+        .keys()
+        .map(|lang| {
+            let missing =
+                videos.iter().filter(|langs| !langs.contains(lang)).count();
+            (*lang, missing)
+        })
+        .filter(|(_, missing)| *missing > 0)
+        .collect::<Vec<_>>();
+    // Stable, so ties stay in language order
+    gaps.sort_by_key(|(_, missing)| std::cmp::Reverse(*missing));
+    gaps.truncate(GAPS);
+    census.gaps = gaps;
+    census
+}
+
+/// What's printed for a library without `--json`
+pub fn summary(root: &Utf8Path, census: &Census) -> String {
+    let list = |counts: Vec<(String, usize)>| match counts.is_empty() {
+        true => "none".to_owned(),
+        false => counts
+            .into_iter()
+            .map(|(key, count)| format!("{key} {count}"))
+            .collect::<Vec<_>>()
+            .join(", "),
+    };
+    let mut languages = census
+        .languages
+        .iter()
+        .map(|(lang, count)| (code(*lang).to_owned(), *count))
+        .collect::<Vec<_>>();
+    languages.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+    if census.unknown_language > 0 {
+        languages.push(("unknown".to_owned(), census.unknown_language));
+    }
+    let mut formats = census
+        .formats
+        .iter()
+        .map(|(format, count)| (format.clone(), *count))
+        .collect::<Vec<_>>();
+    formats.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+    let gaps = census
+        .gaps
+        .iter()
+        .map(|(lang, missing)| (code(*lang).to_owned(), *missing))
+        .collect();
+    format!(
+        "{root}: {} videos, {} subtitles ({:.1} per video), {} videos with \
+         none\n  languages: {}\n  formats: {}\n  forced: {}, hearing \
+         impaired: {}\n  videos without: {}\n",
+        census.videos,
+        census.subtitles,
+        census.per_video(),
+        census.without_any,
+        list(languages),
+        list(formats),
+        census.forced,
+        census.hearing_impaired,
+        list(gaps),
+    )
+}
+
+fn code(lang: Language) -> &'static str {
+    LanguageCodes::TwoLetter.code(lang)
+}
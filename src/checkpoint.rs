@@ -0,0 +1,85 @@
+use std::{collections::BTreeSet, fs, io};
+
+use anyhow::Context;
+use camino::{Utf8Path, Utf8PathBuf};
+use log::debug;
+
+use crate::{
+    canonicalize,
+    fsops::{is_dry_run, remove_file, write_atomically},
+    lock::Lock,
+};
+
+/// Remembers which directories have already been processed, so a run that
+/// ran out of time can pick up where it left off
+#[derive(Debug)]
+pub struct Checkpoint {
+    path: Utf8PathBuf,
+    done: BTreeSet<Utf8PathBuf>,
+}
+
+impl Checkpoint {
+    pub fn load(path: impl Into<Utf8PathBuf>) -> anyhow::Result<Self> {
+        let path = path.into();
+        let done = match fs::read_to_string(&path) {
+            Ok(contents) => contents.lines().map(Utf8PathBuf::from).collect(),
+            Err(why) if why.kind() == io::ErrorKind::NotFound => {
+                BTreeSet::new()
+            },
+            Err(why) => {
+                return Err(why)
+                    .with_context(|| format!("failed to read {path}"))
+            },
+        };
+        debug!("checkpoint {path} has {} done directories", done.len());
+        Ok(Checkpoint { path, done })
+    }
+
+    pub fn is_done(&self, dir: impl AsRef<Utf8Path>) -> bool {
+        Self::key(dir.as_ref())
+            .map(|key| self.done.contains(&key))
+            .unwrap_or_default()
+    }
+
+    pub fn mark_done(
+        &mut self,
+        dir: impl AsRef<Utf8Path>,
+    ) -> anyhow::Result<()> {
+        let key = Self::key(dir.as_ref())?;
+        self.done.insert(key);
+        // Nothing was done, so the next (real) run should still do all of it
+        if is_dry_run() {
+            return Ok(());
+        }
+        let _lock = Lock::for_file(&self.path)?;
+        // Another run may have finished directories since this one started
+        if let Ok(contents) = fs::read_to_string(&self.path) {
+            self.done.extend(contents.lines().map(Utf8PathBuf::from));
+        }
+        let mut contents = String::new();
+        self.done.iter().for_each(|dir| {
+            contents.push_str(dir.as_str());
+            contents.push('\n');
+        });
+        write_atomically(&self.path, contents)
+            .with_context(|| format!("failed to write {}", self.path))
+    }
+
+    /// Everything got processed, so the next run should start afresh
+    pub fn finish(self) -> anyhow::Result<()> {
+        if is_dry_run() {
+            return Ok(());
+        }
+        let _lock = Lock::for_file(&self.path)?;
+        match remove_file(&self.path) {
+            Ok(()) => Ok(()),
+            Err(why) if why.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(why) => Err(why)
+                .with_context(|| format!("failed to remove {}", self.path)),
+        }
+    }
+
+    fn key(dir: &Utf8Path) -> anyhow::Result<Utf8PathBuf> {
+        canonicalize(dir).with_context(|| format!("failed to resolve {dir}"))
+    }
+}
@@ -0,0 +1,865 @@
+use std::{
+    env,
+    net::SocketAddr,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{anyhow, bail, Context};
+use camino::Utf8PathBuf;
+use isolang::Language;
+
+use crate::{
+    link::{Fallback, Mode},
+    matcher::MatcherConfig,
+    naming::{LanguageCodes, Naming},
+    normalize::LineEnding,
+    parallel,
+    split::SplitPoint,
+    subtitle::{language_from_tag, Source},
+    sync::{SyncProvider, PROVIDER_VAR},
+    time::parse_date,
+    video::ContentType,
+};
+
+/// How `serve` takes requests
+#[derive(Debug, Copy, Clone)]
+pub enum Transport {
+    /// One JSON request per line on stdin, one JSON response per line on
+    /// stdout
+    Stdio,
+    /// A small HTTP API
+    Http(SocketAddr),
+}
+
+#[derive(Debug, Default)]
+pub enum Command {
+    /// Create links for the given directories
+    #[default]
+    Process,
+    /// Narrate how one subtitle file would be handled
+    Explain(Utf8PathBuf),
+    /// Take requests from another program. `base` is the rest of the
+    /// command line, which every request's own options go on top of
+    Serve {
+        transport: Transport,
+        base: Vec<String>,
+    },
+    /// Remove links made by earlier runs in languages other than these
+    PruneLangs(Vec<Language>),
+    /// Record links made some other way as subfix's own
+    Adopt,
+    /// Record the links subfix made again, going by what's on disk
+    RebuildJournal,
+    /// Remove every link earlier runs made
+    Undo,
+    /// List what could be better about the subtitles already there
+    Lint,
+    /// Plan the links for what a `.torrent` would download
+    Torrent(Utf8PathBuf),
+    /// Count the subtitles there are in each language, format & so on
+    Stats,
+    /// Put the subtitles linked to MKVs inside them
+    Embed,
+    /// Print the settings given, or with `effective`, every setting and
+    /// what else applies to each folder
+    ShowConfig { effective: bool },
+}
+
+#[derive(Debug)]
+pub struct Options {
+    pub command: Command,
+    /// Folders (other than per-video ones) that are searched for subtitles
+    /// beyond the top level
+    pub subtitle_folders: Vec<String>,
+    /// Search every folder for subtitles, not just the known ones
+    pub all_folders: bool,
+    /// Stop starting new directories once this much time has passed
+    pub max_duration: Option<Duration>,
+    /// Where to record finished directories so an interrupted run can resume
+    pub checkpoint: Option<Utf8PathBuf>,
+    /// How many threads read the subtitles in a directory
+    pub jobs: usize,
+    /// Only link languages that videos don't already have a subtitle for
+    pub only_missing: bool,
+    /// Link subtitles with lines in two languages under this label, rather
+    /// than guessing one language from their name
+    pub bilingual_label: Option<String>,
+    /// Treat every subtitle as this language, whatever it's called
+    pub assume_lang: Option<Language>,
+    /// Read subtitles whose names don't give their language, to tell it
+    pub detect_language: bool,
+    /// Subtitles with fewer words of dialogue than this aren't read for
+    /// their language
+    pub detect_min_words: usize,
+    /// How far ahead of the next most likely language (in percent) the most
+    /// likely one has to be to go by it
+    pub detect_confidence: u32,
+    /// The only languages text is read as, or any if empty
+    pub detect_languages: Vec<Language>,
+    /// Languages of particular subtitles, relative to the folder being
+    /// processed, which take precedence over everything else
+    pub set_lang: Vec<(Utf8PathBuf, Language)>,
+    /// Subtitles to link to every video in their folder, given like
+    /// `set_lang`
+    pub all_episodes: Vec<Utf8PathBuf>,
+    /// Don't link subtitles a video already has, even under another name
+    pub skip_identical: bool,
+    /// Leave languages alone if Bazarr has downloaded a subtitle for them
+    pub defer_to_bazarr: bool,
+    /// Where to write a JSON list of the links made, for Bazarr
+    pub bazarr_map: Option<Utf8PathBuf>,
+    /// Link subtitles to every version, even ones of a different edition
+    pub ignore_editions: bool,
+    /// Merge subtitles split into `.cd1`/`.cd2` parts for a single video
+    pub merge_split: Option<SplitPoint>,
+    /// Split subtitles for the whole of a stacked movie into one per part,
+    /// with the `probe` feature
+    pub split_stacked: bool,
+    /// Link fixed copies of SRT subtitles with overlapping or out of order
+    /// cues
+    pub fix_timing: bool,
+    /// What lines subtitles up with their video, when they're to be synced
+    pub sync_provider: Option<SyncProvider>,
+    /// Give files subfix writes the permissions of their source, rather
+    /// than the defaults from the umask and directory ACLs
+    pub preserve_permissions: bool,
+    /// Line endings used for subtitle files subfix writes
+    pub line_ending: LineEnding,
+    /// The longest file name the filesystem allows, in bytes
+    pub max_name_length: usize,
+    /// How links are named
+    pub naming: Naming,
+    /// How languages are written in the names of links
+    pub language_codes: LanguageCodes,
+    /// Write Cyrillic & Greek titles in link names in Latin letters
+    pub transliterate: bool,
+    /// Copy the fonts styled subtitles come with next to the videos
+    pub fonts: bool,
+    /// How subtitles are put next to videos
+    pub mode: Mode,
+    /// What's made instead of a hard link to a subtitle on another
+    /// filesystem
+    pub fallback_mode: Fallback,
+    /// Mark what's made with an extended attribute saying it's subfix's
+    pub xattr: bool,
+    /// Also link the default language without the `.default` flag
+    pub also_unflagged: bool,
+    /// What's used to decide which videos a subtitle is for, in order
+    pub matchers: Vec<MatcherConfig>,
+    /// When there are several subtitles of a language, prefer the one from
+    /// the first of these
+    pub source_order: Vec<Source>,
+    /// Skip files whose folders name a different episode to the file name
+    pub strict: bool,
+    /// The kind of library, for which heuristics apply
+    pub content_type: ContentType,
+    /// Process folders even if they look like Jellyfin's own data
+    pub force: bool,
+    /// Look inside videos with ffprobe when there are no external subtitles,
+    /// with the `probe` feature
+    pub probe: bool,
+    /// With `probe`, leave out videos shorter than this, like samples
+    pub min_video_duration: Duration,
+    /// Keep short videos even when probing
+    pub include_short: bool,
+    /// With `probe`, versions whose lengths differ by more than this are
+    /// different cuts, only linked subtitles named after them
+    pub cut_threshold: Duration,
+    /// Languages to extract from videos with no external subtitles
+    pub extract_if_missing: Vec<Language>,
+    /// Extract every text subtitle track, to be linked like the others
+    pub extract_embedded: bool,
+    /// Skip directories with nothing modified after this
+    pub since: Option<SystemTime>,
+    /// Convert image-based subtitles to text before linking them, with the
+    /// `ocr` feature
+    pub ocr: bool,
+    /// Only episodes of this season, from its folder if given a show's
+    pub season: Option<u8>,
+    /// Treat the folders given as libraries, and process every title in them
+    pub library: bool,
+    /// Leave out what sync tools' ignore files exclude
+    pub respect_ignore_files: bool,
+    /// Process the most recently modified directories first
+    pub newest_first: bool,
+    /// Process the folders of this Jellyfin collection or playlist, with the
+    /// `jellyfin` feature
+    pub jellyfin_collection: Option<String>,
+    /// The Jellyfin server to ask about collections
+    pub jellyfin_url: String,
+    /// On Windows, make links that need administrator rights by asking for
+    /// them, rather than printing the commands
+    pub elevate: bool,
+    /// Append a timestamped record of every change made to this file
+    pub audit_log: Option<Utf8PathBuf>,
+    /// What HTTP requests to `serve` have to send as a bearer token
+    pub token: Option<String>,
+    /// Ask what to do about folders that can't be handled automatically
+    pub interactive: bool,
+    /// Report what would be done without changing anything
+    pub dry_run: bool,
+    /// Like a dry run, but anything that would change the filesystem fails
+    /// instead, even if it forgets to check for a dry run
+    pub read_only: bool,
+    /// Print a JSON report of what was done to stdout
+    pub json: bool,
+    /// Where to write a report of what was done as a web page
+    pub html_report: Option<Utf8PathBuf>,
+    /// Print how many videos have subtitles in these languages
+    pub coverage: Vec<Language>,
+    pub verbose: bool,
+    pub show_version: bool,
+}
+
+impl Options {
+    pub fn from_args(
+        args: impl IntoIterator<Item = String>,
+    ) -> anyhow::Result<(Self, Vec<Utf8PathBuf>)> {
+        let mut options = Options::default();
+        let mut paths = Vec::new();
+        let mut sync = false;
+        let mut sync_provider = None;
+        let mut args =
+            args.into_iter().collect::<Vec<_>>().into_iter().peekable();
+        if args.peek().map(String::as_str) == Some("explain") {
+            args.next();
+            let path = args
+                .next_if(|arg| !arg.starts_with("--"))
+                .context("explain requires a subtitle file")?;
+            options.command = Command::Explain(Utf8PathBuf::from(path));
+        } else if args.peek().map(String::as_str) == Some("serve") {
+            args.next();
+            if !cfg!(feature = "serve") {
+                bail!("serve needs subfix to be built with the serve feature");
+            }
+            let transport = match args.next().as_deref() {
+                Some("--stdio") => Transport::Stdio,
+                Some("--http") => {
+                    let addr = args
+                        .next()
+                        .context("--http requires an address to listen on")?;
+                    Transport::Http(addr.parse().with_context(|| {
+                        format!(
+                            "invalid address {addr:?}, expected e.g. \
+                             127.0.0.1:7878"
+                        )
+                    })?)
+                },
+                _ => bail!("serve requires --stdio or --http <ADDRESS>"),
+            };
+            // The token is only for checking requests against
+            let mut base = Vec::new();
+            let mut rest = args.clone();
+            while let Some(arg) = rest.next() {
+                match arg.as_str() {
+                    "--token" => {
+                        rest.next();
+                    },
+                    _ => base.push(arg),
+                }
+            }
+            options.command = Command::Serve { transport, base };
+        } else if args.peek().map(String::as_str) == Some("prune-langs") {
+            args.next();
+            if args.next().as_deref() != Some("--keep") {
+                bail!("prune-langs requires --keep <LANGUAGES>");
+            }
+            let list = args.next().context(
+                "--keep requires a comma-separated list of languages",
+            )?;
+            options.command = Command::PruneLangs(parse_languages(&list)?);
+        } else if args.peek().map(String::as_str) == Some("adopt") {
+            args.next();
+            options.command = Command::Adopt;
+        } else if args.peek().map(String::as_str) == Some("rebuild-journal") {
+            args.next();
+            options.command = Command::RebuildJournal;
+        } else if args.peek().map(String::as_str) == Some("torrent") {
+            args.next();
+            let path = args
+                .next_if(|arg| !arg.starts_with("--"))
+                .context("torrent requires a .torrent file")?;
+            options.command = Command::Torrent(Utf8PathBuf::from(path));
+        } else if args.peek().map(String::as_str) == Some("lint") {
+            args.next();
+            options.command = Command::Lint;
+        } else if args.peek().map(String::as_str) == Some("stats") {
+            args.next();
+            options.command = Command::Stats;
+        } else if args.peek().map(String::as_str) == Some("embed") {
+            args.next();
+            if !cfg!(feature = "embed") {
+                bail!("embed needs subfix to be built with the embed feature");
+            }
+            options.command = Command::Embed;
+        } else if args.peek().map(String::as_str) == Some("config") {
+            args.next();
+            if args.next().as_deref() != Some("show") {
+                bail!("config requires show");
+            }
+            let effective = args.next_if(|arg| arg == "--effective").is_some();
+            options.command = Command::ShowConfig { effective };
+        }
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--subs-folder" => {
+                    let name = args
+                        .next()
+                        .context("--subs-folder requires a folder name")?;
+                    options.subtitle_folders.push(name);
+                },
+                "--all-folders" => options.all_folders = true,
+                "--max-duration" => {
+                    let duration = args
+                        .next()
+                        .context("--max-duration requires a duration")?;
+                    options.max_duration = Some(parse_duration(&duration)?);
+                },
+                "--checkpoint" => {
+                    let path = args
+                        .next()
+                        .context("--checkpoint requires a file path")?;
+                    options.checkpoint = Some(Utf8PathBuf::from(path));
+                },
+                "--jobs" => {
+                    let jobs = args
+                        .next()
+                        .context("--jobs requires a number of threads")?;
+                    options.jobs = jobs
+                        .parse()
+                        .ok()
+                        .filter(|jobs| *jobs > 0)
+                        .with_context(|| {
+                        format!("invalid number of threads {jobs:?}")
+                    })?;
+                },
+                "--only-missing" => options.only_missing = true,
+                "--bilingual-label" => {
+                    let label = args
+                        .next()
+                        .context("--bilingual-label requires a label")?;
+                    options.bilingual_label = Some(label);
+                },
+                "--detect-language" => options.detect_language = true,
+                "--detect-min-words" => {
+                    let words = args
+                        .next()
+                        .context("--detect-min-words requires a number")?;
+                    options.detect_min_words = words
+                        .parse()
+                        .ok()
+                        .filter(|words| *words > 0)
+                        .with_context(|| {
+                            format!("invalid number of words {words:?}")
+                        })?;
+                    options.detect_language = true;
+                },
+                "--detect-confidence" => {
+                    let percent = args
+                        .next()
+                        .context("--detect-confidence requires a percentage")?;
+                    options.detect_confidence = percent
+                        .trim_end_matches('%')
+                        .parse()
+                        .ok()
+                        .filter(|percent| *percent <= 100)
+                        .with_context(|| {
+                            format!("invalid percentage {percent:?}")
+                        })?;
+                    options.detect_language = true;
+                },
+                "--detect-languages" => {
+                    let languages = args.next().context(
+                        "--detect-languages requires a comma-separated list \
+                         of languages",
+                    )?;
+                    options.detect_languages = parse_languages(&languages)?;
+                    options.detect_language = true;
+                },
+                "--assume-lang" => {
+                    let tag = args
+                        .next()
+                        .context("--assume-lang requires a language")?;
+                    options.assume_lang =
+                        Some(language_from_tag(&tag).ok_or_else(|| {
+                            anyhow!("unknown language {tag:?}")
+                        })?);
+                },
+                "--set-lang" => {
+                    let pair = args
+                        .next()
+                        .context("--set-lang requires <FILE>=<LANGUAGE>")?;
+                    let (path, tag) =
+                        pair.rsplit_once('=').with_context(|| {
+                            format!(
+                                "expected <FILE>=<LANGUAGE>, found {pair:?}"
+                            )
+                        })?;
+                    let lang = language_from_tag(tag)
+                        .ok_or_else(|| anyhow!("unknown language {tag:?}"))?;
+                    options.set_lang.push((Utf8PathBuf::from(path), lang));
+                },
+                "--all-episodes" => {
+                    let arg = args.next().context(
+                        "--all-episodes requires <FILE> or <FILE>=<LANGUAGE>",
+                    )?;
+                    let path = match arg.rsplit_once('=') {
+                        Some((path, tag)) => {
+                            let lang =
+                                language_from_tag(tag).ok_or_else(|| {
+                                    anyhow!("unknown language {tag:?}")
+                                })?;
+                            options.set_lang.push((path.into(), lang));
+                            path.to_owned()
+                        },
+                        None => arg,
+                    };
+                    options.all_episodes.push(Utf8PathBuf::from(path));
+                },
+                "--skip-identical" => options.skip_identical = true,
+                "--defer-to-bazarr" => options.defer_to_bazarr = true,
+                "--bazarr-map" => {
+                    let path = args
+                        .next()
+                        .context("--bazarr-map requires a file path")?;
+                    options.bazarr_map = Some(Utf8PathBuf::from(path));
+                },
+                "--ignore-editions" => options.ignore_editions = true,
+                "--merge-split-srt" => {
+                    let split_point = args.next().context(
+                        "--merge-split-srt requires a split point \
+                         (HH:MM:SS,mmm or auto)",
+                    )?;
+                    options.merge_split =
+                        Some(SplitPoint::parse(&split_point)?);
+                },
+                "--split-stacked" => match cfg!(feature = "probe") {
+                    true => options.split_stacked = true,
+                    false => bail!(
+                        "--split-stacked needs subfix to be built with the \
+                         probe feature"
+                    ),
+                },
+                "--fix-timing" => options.fix_timing = true,
+                "--sync" | "--sync-provider" if !cfg!(feature = "sync") => {
+                    bail!(
+                        "{arg} needs subfix to be built with the sync feature"
+                    )
+                },
+                "--sync" => sync = true,
+                "--sync-provider" => {
+                    let provider = args
+                        .next()
+                        .context("--sync-provider requires a command line")?;
+                    sync_provider = Some(provider.parse()?);
+                },
+                "--preserve-source-permissions" => {
+                    options.preserve_permissions = true
+                },
+                "--line-endings" => {
+                    let line_ending = args
+                        .next()
+                        .context("--line-endings requires lf or crlf")?;
+                    options.line_ending = line_ending.parse()?;
+                },
+                "--max-name-length" => {
+                    let length = args.next().context(
+                        "--max-name-length requires a number of bytes",
+                    )?;
+                    options.max_name_length =
+                        length.parse().with_context(|| {
+                            format!("invalid name length {length:?}")
+                        })?;
+                },
+                "--mode" => {
+                    let mode = args.next().context(
+                        "--mode requires symlink, hardlink, copy or rename",
+                    )?;
+                    options.mode = mode.parse()?;
+                },
+                "--fallback-mode" => {
+                    let fallback = args.next().context(
+                        "--fallback-mode requires copy, symlink or skip",
+                    )?;
+                    options.fallback_mode = fallback.parse()?;
+                },
+                "--xattr" => options.xattr = true,
+                "--naming" => {
+                    let naming = args.next().context(
+                        "--naming requires jellyfin or multi-player",
+                    )?;
+                    options.naming = naming.parse()?;
+                },
+                "--language-codes" => {
+                    let codes = args.next().context(
+                        "--language-codes requires two-letter, terminological \
+                         or bibliographic",
+                    )?;
+                    options.language_codes = codes.parse()?;
+                },
+                "--transliterate" => options.transliterate = true,
+                "--fonts" => options.fonts = true,
+                "--also-unflagged" => options.also_unflagged = true,
+                "--matchers" => {
+                    let matchers = args.next().context(
+                        "--matchers requires a comma-separated list of \
+                         matchers",
+                    )?;
+                    options.matchers = matchers
+                        .split(',')
+                        .map(str::parse)
+                        .collect::<anyhow::Result<_>>()?;
+                },
+                "--source-order" => {
+                    let order = args.next().context(
+                        "--source-order requires a comma-separated list of \
+                         sources",
+                    )?;
+                    options.source_order = order
+                        .split(',')
+                        .map(str::parse)
+                        .collect::<anyhow::Result<_>>()?;
+                },
+                "--strict" => options.strict = true,
+                "--content-type" => {
+                    let content_type = args.next().context(
+                        "--content-type requires movies, shows, music-videos \
+                         or home-videos",
+                    )?;
+                    options.content_type = content_type.parse()?;
+                },
+                "--force" => options.force = true,
+                "--undo" => match options.command {
+                    Command::Process => options.command = Command::Undo,
+                    _ => bail!("--undo can't be combined with another command"),
+                },
+                "--probe" | "--extract-if-missing" | "--extract-embedded"
+                    if !cfg!(feature = "probe") =>
+                {
+                    bail!(
+                        "{arg} needs subfix to be built with the probe feature"
+                    )
+                },
+                "--probe" => options.probe = true,
+                "--min-video-duration" => {
+                    let duration = args
+                        .next()
+                        .context("--min-video-duration requires a duration")?;
+                    options.min_video_duration = parse_duration(&duration)?;
+                },
+                "--include-short" => options.include_short = true,
+                "--cut-threshold" => {
+                    let duration = args
+                        .next()
+                        .context("--cut-threshold requires a duration")?;
+                    options.cut_threshold = parse_duration(&duration)?;
+                },
+                "--extract-embedded" => options.extract_embedded = true,
+                "--extract-if-missing" => {
+                    let languages = args.next().context(
+                        "--extract-if-missing requires a comma-separated list \
+                         of languages",
+                    )?;
+                    options.extract_if_missing = parse_languages(&languages)?;
+                    options.probe = true;
+                },
+                "--since" => {
+                    let since = args
+                        .next()
+                        .context("--since requires a duration or date")?;
+                    options.since = Some(parse_since(&since)?);
+                },
+                "--ocr" => match cfg!(feature = "ocr") {
+                    true => options.ocr = true,
+                    false => bail!(
+                        "--ocr needs subfix to be built with the ocr feature"
+                    ),
+                },
+                "--season" => {
+                    let season =
+                        args.next().context("--season requires a number")?;
+                    options.season = match season.parse() {
+                        Ok(0) | Err(_) => bail!("invalid season {season:?}"),
+                        Ok(season) => Some(season),
+                    };
+                },
+                "--library" => options.library = true,
+                "--respect-ignore-files" => options.respect_ignore_files = true,
+                "--newest-first" => options.newest_first = true,
+                "--jellyfin-collection" | "--jellyfin-url"
+                    if !cfg!(feature = "jellyfin") =>
+                {
+                    bail!(
+                        "{arg} needs subfix to be built with the jellyfin \
+                         feature"
+                    )
+                },
+                "--jellyfin-collection" => {
+                    let name = args
+                        .next()
+                        .context("--jellyfin-collection requires a name")?;
+                    options.jellyfin_collection = Some(name);
+                },
+                "--jellyfin-url" => {
+                    options.jellyfin_url =
+                        args.next().context("--jellyfin-url requires a URL")?;
+                },
+                "--elevate" => options.elevate = true,
+                "--audit-log" => {
+                    let path = args
+                        .next()
+                        .context("--audit-log requires a file path")?;
+                    options.audit_log = Some(Utf8PathBuf::from(path));
+                },
+                "--token" => {
+                    let token =
+                        args.next().context("--token requires a token")?;
+                    options.token = Some(token);
+                },
+                "--interactive" | "-i" => options.interactive = true,
+                "--dry-run" | "-n" => options.dry_run = true,
+                "--read-only" => options.read_only = true,
+                "--json" => options.json = true,
+                "--report" => {
+                    let path =
+                        args.next().context("--report requires a file path")?;
+                    options.html_report = Some(Utf8PathBuf::from(path));
+                },
+                "--coverage" => {
+                    let languages = args.next().context(
+                        "--coverage requires a comma-separated list of \
+                         languages",
+                    )?;
+                    options.coverage = parse_languages(&languages)?;
+                },
+                "--verbose" | "-v" => options.verbose = true,
+                "--version" | "-V" => options.show_version = true,
+                flag if flag.starts_with("--") => {
+                    bail!("unrecognised option {flag}")
+                },
+                _ => paths.push(Utf8PathBuf::from(arg)),
+            }
+        }
+        if sync {
+            options.sync_provider = match sync_provider {
+                Some(provider) => Some(provider),
+                None => match env::var(PROVIDER_VAR) {
+                    Ok(provider) => Some(
+                        provider
+                            .parse()
+                            .with_context(|| format!("in ${PROVIDER_VAR}"))?,
+                    ),
+                    Err(_) => bail!(
+                        "--sync requires --sync-provider or ${PROVIDER_VAR}"
+                    ),
+                },
+            };
+        }
+        match &options.command {
+            Command::PruneLangs(_) if options.library => bail!(
+                "prune-langs already goes through every folder under the ones \
+                 given, --library isn't needed"
+            ),
+            Command::Adopt if options.library => bail!(
+                "adopt already goes through every folder under the ones \
+                 given, --library isn't needed"
+            ),
+            Command::RebuildJournal if options.library => bail!(
+                "rebuild-journal already goes through every folder under the \
+                 ones given, --library isn't needed"
+            ),
+            Command::Lint if options.library => bail!(
+                "lint already goes through every folder under the ones given, \
+                 --library isn't needed"
+            ),
+            Command::Stats if options.library => bail!(
+                "stats already goes through every folder under the ones \
+                 given, --library isn't needed"
+            ),
+            Command::Embed if options.library => bail!(
+                "embed already goes through every folder under the ones \
+                 given, --library isn't needed"
+            ),
+            Command::Undo if options.library => bail!(
+                "--undo already goes through every folder under the ones \
+                 given, --library isn't needed"
+            ),
+            _ => {},
+        }
+        if options.token.is_some()
+            && !matches!(options.command, Command::Serve {
+                transport: Transport::Http(_),
+                ..
+            })
+        {
+            bail!("--token is only for serve --http");
+        }
+        if options.read_only {
+            let writers = [
+                ("--checkpoint", options.checkpoint.is_some()),
+                ("--audit-log", options.audit_log.is_some()),
+                ("--report", options.html_report.is_some()),
+                ("--elevate", options.elevate),
+            ];
+            if let Some((flag, _)) = writers.iter().find(|(_, given)| *given) {
+                bail!("{flag} can't be used with --read-only");
+            }
+        }
+        Ok((options, paths))
+    }
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            command: Command::Process,
+            subtitle_folders: DEFAULT_SUBTITLE_FOLDERS
+                .iter()
+                .map(|&name| name.to_owned())
+                .collect(),
+            all_folders: false,
+            max_duration: None,
+            checkpoint: None,
+            jobs: parallel::default_jobs(),
+            only_missing: false,
+            bilingual_label: None,
+            assume_lang: None,
+            detect_language: false,
+            detect_min_words: 50,
+            detect_confidence: 33,
+            detect_languages: Vec::new(),
+            set_lang: Vec::new(),
+            all_episodes: Vec::new(),
+            skip_identical: false,
+            defer_to_bazarr: false,
+            bazarr_map: None,
+            ignore_editions: false,
+            merge_split: None,
+            split_stacked: false,
+            fix_timing: false,
+            sync_provider: None,
+            preserve_permissions: false,
+            line_ending: LineEnding::default(),
+            max_name_length: 255,
+            naming: Naming::default(),
+            language_codes: LanguageCodes::default(),
+            transliterate: false,
+            fonts: false,
+            mode: Mode::default(),
+            fallback_mode: Fallback::default(),
+            xattr: false,
+            also_unflagged: false,
+            matchers: MatcherConfig::defaults(),
+            source_order: Source::DEFAULT_ORDER.to_vec(),
+            strict: false,
+            content_type: ContentType::default(),
+            force: false,
+            probe: false,
+            min_video_duration: Duration::from_secs(10 * 60),
+            include_short: false,
+            cut_threshold: Duration::from_secs(2 * 60),
+            extract_if_missing: Vec::new(),
+            extract_embedded: false,
+            since: None,
+            ocr: false,
+            season: None,
+            library: false,
+            respect_ignore_files: false,
+            newest_first: false,
+            jellyfin_collection: None,
+            jellyfin_url: "http://localhost:8096".to_owned(),
+            elevate: false,
+            audit_log: None,
+            token: None,
+            interactive: false,
+            dry_run: false,
+            read_only: false,
+            json: false,
+            html_report: None,
+            coverage: Vec::new(),
+            verbose: false,
+            show_version: false,
+        }
+    }
+}
+
+// Compared case-insensitively
+const DEFAULT_SUBTITLE_FOLDERS: &[&str] = &[
+    "Subs",
+    "Subtitles",
+    // French
+    "Sous-titres",
+    // German
+    "Untertitel",
+    // Portuguese
+    "Legendas",
+    // Spanish
+    "Subtítulos",
+    // Italian
+    "Sottotitoli",
+    // Dutch
+    "Ondertitels",
+    // Polish
+    "Napisy",
+    // Russian
+    "Субтитры",
+    // Chinese/Japanese
+    "字幕",
+];
+
+/// Parses a comma-separated list of language codes or names
+fn parse_languages(list: &str) -> anyhow::Result<Vec<Language>> {
+    list.split(',')
+        .map(|tag| {
+            language_from_tag(tag.trim())
+                .ok_or_else(|| anyhow!("unknown language {tag:?}"))
+        })
+        .collect()
+}
+
+/// Parses either how long ago (`24h`) or a date (`2024-05-01`)
+fn parse_since(s: &str) -> anyhow::Result<SystemTime> {
+    if s.contains('-') {
+        return parse_date(s);
+    }
+    SystemTime::now()
+        .checked_sub(parse_duration(s)?)
+        .with_context(|| format!("{s:?} is too long ago"))
+}
+
+/// Parses durations like `90s`, `10m`, `2h`, or `1d`
+pub fn parse_duration(s: &str) -> anyhow::Result<Duration> {
+    let split = s
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(|| anyhow!("duration {s:?} is missing a unit"))?;
+    let (number, unit) = s.split_at(split);
+    let number = number
+        .parse::<u64>()
+        .with_context(|| format!("couldn't parse duration {s:?}"))?;
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => bail!("unknown duration unit {unit:?}, expected s, m, h, or d"),
+    };
+    number
+        .checked_mul(seconds)
+        .map(Duration::from_secs)
+        .with_context(|| format!("duration {s:?} is too long"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("90s").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("2h").unwrap(), Duration::from_secs(7200));
+        for bad in ["10", "1w", "999999999999999999d", "99999999999999999999s"]
+        {
+            assert!(parse_duration(bad).is_err(), "{bad:?} was read");
+        }
+    }
+}
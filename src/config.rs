@@ -0,0 +1,265 @@
+//! What a run would be configured with, for `config show`. There are no
+//! config files, so that's the defaults with the flags given on top, and
+//! for a folder, the files in and above it that change how it's handled
+
+use std::time::Duration;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use isolang::Language;
+
+use crate::{
+    cli::Options,
+    ignore::IgnoreFiles,
+    jellyfin,
+    manifest::{Manifest, MANIFEST},
+    manual::{self, MATCHES},
+    naming::LanguageCodes,
+    time::rfc3339,
+};
+
+/// A flag and what it's set to
+#[derive(Debug)]
+pub struct Setting {
+    pub flag: &'static str,
+    pub value: String,
+    /// Whether it's been changed from the default
+    pub given: bool,
+}
+
+/// A file that changes how a folder's handled, and how
+#[derive(Debug)]
+pub struct Marker {
+    pub path: Utf8PathBuf,
+    pub effect: String,
+}
+
+/// Every setting, in the order of `--help`
+pub fn settings(options: &Options) -> Vec<Setting> {
+    values(&Options::default())
+        .into_iter()
+        .zip(values(options))
+        .map(|((flag, default), (_, value))| Setting {
+            flag,
+            given: value != default,
+            value,
+        })
+        .collect()
+}
+
+/// The files in and above `dir` that decide whether and how it's processed
+pub fn markers(dir: &Utf8Path, options: &Options) -> Vec<Marker> {
+    let mut markers = Vec::new();
+    let ignore_files = IgnoreFiles::above(dir);
+    let excluded_by = ignore_files.excluded_by(dir);
+    for file in ignore_files.files() {
+        let effect = match (options.respect_ignore_files, excluded_by) {
+            (false, _) => "not followed (see --respect-ignore-files)",
+            (true, Some(excluded_by)) if excluded_by == file => {
+                "excludes this folder, so it isn't processed"
+            },
+            (true, _) => "followed for what's in this folder",
+        };
+        markers.push(Marker {
+            path: file.to_owned(),
+            effect: effect.to_owned(),
+        });
+    }
+    let manifest = dir.join(MANIFEST);
+    if manifest.is_file() {
+        let effect = match Manifest::load(dir) {
+            Ok(manifest) => format!(
+                "records {} links made by earlier runs, which are kept up to \
+                 date",
+                manifest.entries().len()
+            ),
+            Err(why) => format!("can't be read, so processing fails: {why:#}"),
+        };
+        markers.push(Marker {
+            path: manifest,
+            effect,
+        });
+    }
+    let matches = dir.join(MATCHES);
+    if matches.is_file() {
+        let effect = match manual::load(dir) {
+            Ok(matches) => format!(
+                "matches {} subtitles by hand, which are all that's linked",
+                matches.map(|matches| matches.len()).unwrap_or_default()
+            ),
+            Err(why) => format!("can't be read, so processing fails: {why:#}"),
+        };
+        markers.push(Marker {
+            path: matches,
+            effect,
+        });
+    }
+    markers
+}
+
+/// Why `dir` wouldn't be processed with these settings, if it wouldn't,
+/// going by where it is rather than what's in it
+pub fn refusal(dir: &Utf8Path, options: &Options) -> Option<String> {
+    if !options.force {
+        if let Some(why) = jellyfin::server_data_reason(dir) {
+            return Some(format!(
+                "it looks like Jellyfin's own data: {why} (see --force)"
+            ));
+        }
+    }
+    if options.respect_ignore_files {
+        if let Some(file) = IgnoreFiles::above(dir).excluded_by(dir) {
+            return Some(format!("it's excluded by {file}"));
+        }
+    }
+    None
+}
+
+fn values(options: &Options) -> Vec<(&'static str, String)> {
+    vec![
+        ("--subs-folder", list(&options.subtitle_folders)),
+        ("--all-folders", switch(options.all_folders)),
+        (
+            "--max-duration",
+            optional(options.max_duration.map(duration)),
+        ),
+        ("--checkpoint", optional(options.checkpoint.as_ref())),
+        ("--jobs", options.jobs.to_string()),
+        ("--only-missing", switch(options.only_missing)),
+        (
+            "--bilingual-label",
+            optional(options.bilingual_label.as_ref()),
+        ),
+        ("--detect-language", switch(options.detect_language)),
+        ("--detect-min-words", options.detect_min_words.to_string()),
+        ("--detect-confidence", options.detect_confidence.to_string()),
+        (
+            "--detect-languages",
+            match options.detect_languages.is_empty() {
+                true => "any".to_owned(),
+                false => {
+                    list(options.detect_languages.iter().copied().map(language))
+                },
+            },
+        ),
+        ("--assume-lang", optional(options.assume_lang.map(language))),
+        (
+            "--set-lang",
+            list(
+                options
+                    .set_lang
+                    .iter()
+                    .map(|(path, lang)| format!("{path}={}", language(*lang))),
+            ),
+        ),
+        ("--all-episodes", list(&options.all_episodes)),
+        ("--skip-identical", switch(options.skip_identical)),
+        ("--defer-to-bazarr", switch(options.defer_to_bazarr)),
+        ("--bazarr-map", optional(options.bazarr_map.as_ref())),
+        ("--ignore-editions", switch(options.ignore_editions)),
+        ("--merge-split-srt", optional(options.merge_split)),
+        ("--split-stacked", switch(options.split_stacked)),
+        ("--fix-timing", switch(options.fix_timing)),
+        ("--sync", switch(options.sync_provider.is_some())),
+        ("--sync-provider", optional(options.sync_provider.as_ref())),
+        (
+            "--preserve-source-permissions",
+            switch(options.preserve_permissions),
+        ),
+        ("--line-endings", options.line_ending.to_string()),
+        ("--max-name-length", options.max_name_length.to_string()),
+        ("--mode", options.mode.to_string()),
+        ("--fallback-mode", options.fallback_mode.to_string()),
+        ("--xattr", switch(options.xattr)),
+        ("--naming", options.naming.to_string()),
+        ("--language-codes", options.language_codes.to_string()),
+        ("--transliterate", switch(options.transliterate)),
+        ("--fonts", switch(options.fonts)),
+        ("--also-unflagged", switch(options.also_unflagged)),
+        ("--matchers", list(&options.matchers)),
+        (
+            "--source-order",
+            list(options.source_order.iter().map(|source| source.as_str())),
+        ),
+        ("--strict", switch(options.strict)),
+        ("--content-type", options.content_type.to_string()),
+        ("--force", switch(options.force)),
+        ("--probe", switch(options.probe)),
+        ("--min-video-duration", duration(options.min_video_duration)),
+        ("--include-short", switch(options.include_short)),
+        ("--cut-threshold", duration(options.cut_threshold)),
+        ("--extract-embedded", switch(options.extract_embedded)),
+        (
+            "--extract-if-missing",
+            list(options.extract_if_missing.iter().copied().map(language)),
+        ),
+        ("--since", optional(options.since.map(rfc3339))),
+        ("--ocr", switch(options.ocr)),
+        ("--season", optional(options.season)),
+        ("--library", switch(options.library)),
+        (
+            "--respect-ignore-files",
+            switch(options.respect_ignore_files),
+        ),
+        ("--newest-first", switch(options.newest_first)),
+        (
+            "--jellyfin-collection",
+            optional(options.jellyfin_collection.as_ref()),
+        ),
+        ("--jellyfin-url", options.jellyfin_url.clone()),
+        ("--elevate", switch(options.elevate)),
+        ("--audit-log", optional(options.audit_log.as_ref())),
+        // Never printed, as `config show` output gets pasted into issues
+        (
+            "--token",
+            optional(options.token.as_ref().map(|_| "(hidden)")),
+        ),
+        ("--interactive", switch(options.interactive)),
+        ("--dry-run", switch(options.dry_run)),
+        ("--read-only", switch(options.read_only)),
+        ("--json", switch(options.json)),
+        ("--report", optional(options.html_report.as_ref())),
+        (
+            "--coverage",
+            list(options.coverage.iter().copied().map(language)),
+        ),
+        ("--verbose", switch(options.verbose)),
+    ]
+}
+
+fn switch(on: bool) -> String {
+    match on {
+        true => "on".to_owned(),
+        false => "off".to_owned(),
+    }
+}
+
+fn optional(value: Option<impl ToString>) -> String {
+    value
+        .map(|value| value.to_string())
+        .unwrap_or_else(|| "none".to_owned())
+}
+
+fn list<T: ToString>(values: impl IntoIterator<Item = T>) -> String {
+    let values = values
+        .into_iter()
+        .map(|value| value.to_string())
+        .collect::<Vec<_>>();
+    match values.is_empty() {
+        true => "none".to_owned(),
+        false => values.join(","),
+    }
+}
+
+fn language(lang: Language) -> String {
+    LanguageCodes::TwoLetter.code(lang).to_owned()
+}
+
+// Like they're given, in the largest unit that's exact
+fn duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    [(24 * 60 * 60, "d"), (60 * 60, "h"), (60, "m")]
+        .into_iter()
+        .find(|(size, _)| seconds > 0 && seconds % size == 0)
+        .map(|(size, unit)| format!("{}{unit}", seconds / size))
+        .unwrap_or_else(|| format!("{seconds}s"))
+}
@@ -0,0 +1,557 @@
+use std::{
+    cmp::Reverse,
+    collections::{hash_map::DefaultHasher, BTreeMap},
+    fs::File,
+    hash::Hasher,
+    io::{self, Read},
+    sync::Mutex,
+};
+
+use anyhow::{bail, Context};
+use camino::Utf8Path;
+use isolang::Language;
+use log::debug;
+use once_cell::sync::Lazy;
+
+use crate::{
+    cli::Options,
+    srt::{self, MAX_SUBTITLE_SIZE},
+    stats,
+};
+
+/// Hashes a file's bytes, to spot the same subtitle under different names.
+/// Only comparable within a run, the hasher isn't stable across builds
+pub fn hash_file(path: &Utf8Path) -> io::Result<u64> {
+    let mut file = File::open(path)?.take(MAX_SUBTITLE_SIZE);
+    let mut hasher = DefaultHasher::new();
+    let mut buf = [0; 64 * 1024];
+    loop {
+        match file.read(&mut buf)? {
+            0 => return Ok(hasher.finish()),
+            read => {
+                stats::add_bytes_read(read);
+                hasher.write(&buf[..read])
+            },
+        }
+    }
+}
+
+/// Whether most cues of a SubRip file have lines in two different scripts,
+/// like Chinese & English "dual row" subtitles
+pub fn looks_bilingual(path: &Utf8Path) -> bool {
+    if !path
+        .extension()
+        .map(|ext| ext.eq_ignore_ascii_case("srt"))
+        .unwrap_or_default()
+    {
+        return false;
+    }
+    let cues = match srt::open(path) {
+        Ok(cues) => cues,
+        Err(why) => {
+            debug!("not checking if {path} is bilingual: {why:#}");
+            return false;
+        },
+    };
+    let (mut total, mut mixed) = (0usize, 0usize);
+    for cue in cues {
+        let Ok(cue) = cue else {
+            break;
+        };
+        total += 1;
+        let mut scripts = cue.text.lines().filter_map(script_of);
+        if let Some(first) = scripts.next() {
+            if scripts.any(|script| script != first) {
+                mixed += 1;
+            }
+        }
+    }
+    debug!("{mixed} of {total} cues in {path} mix scripts");
+    // A handful of cues isn't enough to go on
+    total >= BILINGUAL_MIN_CUES && mixed * 2 > total
+}
+
+const BILINGUAL_MIN_CUES: usize = 5;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+enum Script {
+    Latin,
+    Cyrillic,
+    Greek,
+    Arabic,
+    Hebrew,
+    Devanagari,
+    Thai,
+    Hangul,
+    Kana,
+    Han,
+}
+
+// The script most of a line's letters are in
+fn script_of(line: &str) -> Option<Script> {
+    let mut counts = BTreeMap::new();
+    for c in line.chars().filter(|c| c.is_alphabetic()) {
+        use Script::*;
+        let script = match c as u32 {
+            0x0000..=0x024F | 0x1E00..=0x1EFF => Latin,
+            0x0370..=0x03FF => Greek,
+            0x0400..=0x052F => Cyrillic,
+            0x0590..=0x05FF => Hebrew,
+            0x0600..=0x06FF | 0x0750..=0x077F => Arabic,
+            0x0900..=0x097F => Devanagari,
+            0x0E00..=0x0E7F => Thai,
+            0x1100..=0x11FF | 0xAC00..=0xD7AF => Hangul,
+            0x3040..=0x30FF => Kana,
+            0x3400..=0x4DBF | 0x4E00..=0x9FFF | 0xF900..=0xFAFF => Han,
+            _ => continue,
+        };
+        *counts.entry(script).or_insert(0usize) += 1;
+    }
+    counts
+        .into_iter()
+        .max_by_key(|&(_, count)| count)
+        .map(|(script, _)| script)
+}
+
+/// Words common in dialogue, and rare in other languages written the same
+/// way, for telling apart languages that share a script
+const COMMON_WORDS: &[(Language, &[&str])] = &[
+    (Language::Eng, &[
+        "the", "and", "you", "that", "is", "it", "to", "of", "what", "this",
+        "have", "are", "was", "for", "not", "with", "don't", "i'm", "your",
+        "we", "be", "he", "she", "they",
+    ]),
+    (Language::Fra, &[
+        "le", "la", "les", "et", "est", "vous", "je", "pas", "une", "qui",
+        "du", "ce", "c'est", "pour", "dans", "mais", "il", "elle", "nous",
+        "avec", "suis", "oui", "tu", "au",
+    ]),
+    (Language::Deu, &[
+        "der", "die", "das", "und", "ist", "nicht", "ich", "sie", "du", "ein",
+        "eine", "es", "zu", "mit", "wir", "auf", "ja", "den", "dem", "mein",
+        "sind", "hat", "kann", "was",
+    ]),
+    (Language::Spa, &[
+        "el", "los", "las", "que", "y", "es", "no", "un", "una", "por", "para",
+        "qué", "está", "lo", "se", "del", "con", "pero", "sí", "eso", "yo",
+        "esto", "muy", "estoy",
+    ]),
+    (Language::Ita, &[
+        "il", "che", "di", "e", "non", "è", "un", "una", "per", "sono", "mi",
+        "ti", "ho", "della", "questo", "cosa", "come", "ma", "gli", "hai",
+        "ci", "sei", "bene", "perché",
+    ]),
+    (Language::Por, &[
+        "o", "os", "que", "não", "é", "um", "uma", "para", "você", "eu", "com",
+        "isso", "está", "do", "da", "se", "mas", "ele", "ela", "muito", "sim",
+        "aqui", "meu", "então",
+    ]),
+    (Language::Nld, &[
+        "de", "het", "een", "en", "is", "niet", "ik", "je", "dat", "wat",
+        "van", "zijn", "op", "we", "hij", "maar", "met", "voor", "hebben",
+        "er", "dit", "ze", "nee", "ook",
+    ]),
+    (Language::Swe, &[
+        "och", "att", "det", "är", "jag", "inte", "du", "en", "som", "på",
+        "för", "med", "har", "vi", "han", "hon", "vad", "den", "kan", "så",
+        "ska", "mig", "här", "nej",
+    ]),
+    (Language::Dan, &[
+        "og", "at", "det", "er", "jeg", "ikke", "du", "en", "på", "til", "med",
+        "har", "vi", "han", "hun", "hvad", "den", "kan", "så", "skal", "mig",
+        "dig", "nej", "af",
+    ]),
+    (Language::Nor, &[
+        "og", "det", "er", "jeg", "ikke", "du", "en", "på", "til", "med",
+        "har", "vi", "han", "hun", "hva", "den", "kan", "så", "skal", "meg",
+        "deg", "nei", "av", "hvordan",
+    ]),
+    (Language::Fin, &[
+        "ja", "on", "ei", "se", "että", "mitä", "minä", "sinä", "hän", "en",
+        "oli", "me", "tämä", "niin", "kun", "mutta", "olen", "olet", "voi",
+        "nyt", "jos", "vain", "kanssa", "mikä",
+    ]),
+    (Language::Pol, &[
+        "nie", "to", "się", "jest", "w", "i", "na", "że", "co", "jak", "tak",
+        "ale", "z", "do", "mnie", "ty", "ja", "już", "czy", "jestem", "tym",
+        "dla", "jego", "tutaj",
+    ]),
+    (Language::Tur, &[
+        "bir", "ve", "bu", "ne", "için", "ben", "sen", "değil", "da", "de",
+        "mi", "çok", "o", "var", "ama", "evet", "hayır", "şey", "gibi", "daha",
+        "beni", "seni", "ile", "neden",
+    ]),
+    (Language::Rus, &[
+        "и",
+        "не",
+        "в",
+        "что",
+        "я",
+        "ты",
+        "на",
+        "это",
+        "с",
+        "он",
+        "как",
+        "мы",
+        "так",
+        "да",
+        "нет",
+        "вы",
+        "но",
+        "она",
+        "все",
+        "меня",
+        "его",
+        "был",
+        "здесь",
+        "тебя",
+    ]),
+    (Language::Ukr, &[
+        "і", "не", "в", "що", "я", "ти", "на", "це", "з", "він", "як", "ми",
+        "так", "ні", "ви", "але", "вона", "все", "мене", "його", "був", "тут",
+        "тебе", "й",
+    ]),
+];
+
+// Letters needed for each of the words needed, for scripts written without
+// spaces between words
+const DETECT_LETTERS_PER_WORD: usize = 4;
+// Text is only read up to here, which is plenty to tell
+const DETECT_MAX_WORDS: usize = 5000;
+
+/// What reading a subtitle's text for its language came to
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Reading {
+    /// Clearly the language, this far ahead of the next most likely (in
+    /// percent)
+    Clear(Language, u32),
+    /// Most like the language, but not far enough ahead
+    Unclear(Language, u32),
+    /// Not one of `--detect-languages`
+    NotCandidate(Language),
+    /// No language stands out at all
+    Unknown,
+    TooShort,
+}
+
+/// How the subtitles read for their language this run went
+#[derive(Default)]
+struct Readings {
+    clear: BTreeMap<Language, Vec<u32>>,
+    unclear: Vec<u32>,
+    not_candidates: usize,
+    unknown: usize,
+    too_short: usize,
+}
+
+static READINGS: Lazy<Mutex<Readings>> = Lazy::new(Default::default);
+
+/// The language of a subtitle's dialogue, for files whose names say nothing
+/// about it (`subs.srt`, `Movie.Name.2019.srt`). SubRip, ASS/SSA & WebVTT
+/// are read. `None` unless it's clearly one language, as a wrong guess is
+/// worse than none
+pub fn detect_language(path: &Utf8Path, options: &Options) -> Option<Language> {
+    let text = match dialogue(path) {
+        Ok(text) => text,
+        Err(why) => {
+            debug!("not detecting the language of {path}: {why:#}");
+            return None;
+        },
+    };
+    let reading = language_of_text(&text, options);
+    let mut readings = READINGS.lock().unwrap_or_else(|e| e.into_inner());
+    match reading {
+        Reading::Clear(lang, confidence) => {
+            debug!("{path} reads as {} ({confidence}% ahead)", lang.to_name());
+            readings.clear.entry(lang).or_default().push(confidence);
+            return Some(lang);
+        },
+        Reading::Unclear(lang, confidence) => {
+            debug!(
+                "{path} reads most like {}, but only {confidence}% ahead of \
+                 the next best (see --detect-confidence)",
+                lang.to_name()
+            );
+            readings.unclear.push(confidence);
+        },
+        Reading::NotCandidate(lang) => {
+            debug!(
+                "{path} reads as {}, which isn't one of --detect-languages",
+                lang.to_name()
+            );
+            readings.not_candidates += 1;
+        },
+        Reading::Unknown => {
+            debug!("couldn't tell the language of {path} from its text");
+            readings.unknown += 1;
+        },
+        Reading::TooShort => {
+            debug!(
+                "{path} has too little text to tell its language (see \
+                 --detect-min-words)"
+            );
+            readings.too_short += 1;
+        },
+    }
+    None
+}
+
+/// Logs how sure the languages read from text this run were, for tuning
+/// `--detect-confidence` & `--detect-min-words`
+pub fn log_detection_stats() {
+    let readings = READINGS.lock().unwrap_or_else(|e| e.into_inner());
+    let clear = readings.clear.values().map(Vec::len).sum::<usize>();
+    let read = clear
+        + readings.unclear.len()
+        + readings.not_candidates
+        + readings.unknown
+        + readings.too_short;
+    if read == 0 {
+        return;
+    }
+    debug!(
+        "read the text of {read} subtitles for their language: {clear} clear, \
+         {} not clear enough{}, {} in other languages, {} unknown, {} too \
+         short",
+        readings.unclear.len(),
+        spread(&readings.unclear)
+            .map(|spread| format!(" ({spread})"))
+            .unwrap_or_default(),
+        readings.not_candidates,
+        readings.unknown,
+        readings.too_short
+    );
+    for (lang, confidences) in &readings.clear {
+        debug!(
+            "  {}: {}, {}",
+            lang.to_name(),
+            confidences.len(),
+            spread(confidences).unwrap_or_default()
+        );
+    }
+}
+
+// The least, median & most of how far ahead languages were
+fn spread(confidences: &[u32]) -> Option<String> {
+    let mut sorted = confidences.to_vec();
+    sorted.sort_unstable();
+    let (least, most) = (sorted.first()?, sorted.last()?);
+    let median = sorted[sorted.len() / 2];
+    Some(format!("{least}-{most}% ahead, {median}% median"))
+}
+
+fn language_of_text(text: &str, options: &Options) -> Reading {
+    let mut scripts = BTreeMap::new();
+    for line in text.lines() {
+        if let Some(script) = script_of(line) {
+            *scripts.entry(script).or_insert(0usize) +=
+                line.chars().filter(|c| c.is_alphabetic()).count();
+        }
+    }
+    let letters = scripts.values().sum::<usize>();
+    if letters < options.detect_min_words * DETECT_LETTERS_PER_WORD {
+        return Reading::TooShort;
+    }
+    let share = |script| scripts.get(&script).copied().unwrap_or_default();
+    let (&script, &count) = scripts
+        .iter()
+        .max_by_key(|&(_, count)| count)
+        .expect("there are letters");
+    let lang = match script {
+        // Japanese is written with kanji as well as kana
+        _ if share(Script::Kana) * 10 > letters => Language::Jpn,
+        _ if count * 10 < letters * 8 => return Reading::Unknown,
+        Script::Latin | Script::Cyrillic => {
+            return by_common_words(text, options)
+        },
+        Script::Greek => Language::Ell,
+        Script::Arabic => Language::Ara,
+        Script::Hebrew => Language::Heb,
+        Script::Devanagari => Language::Hin,
+        Script::Thai => Language::Tha,
+        Script::Hangul => Language::Kor,
+        Script::Kana => Language::Jpn,
+        Script::Han => Language::Zho,
+    };
+    // The only language written that way here
+    match is_candidate(lang, options) {
+        true => Reading::Clear(lang, 100),
+        false => Reading::NotCandidate(lang),
+    }
+}
+
+fn is_candidate(lang: Language, options: &Options) -> bool {
+    options.detect_languages.is_empty()
+        || options.detect_languages.contains(&lang)
+}
+
+fn by_common_words(text: &str, options: &Options) -> Reading {
+    let words = text
+        .split(|c: char| !(c.is_alphabetic() || c == '\'' || c == '’'))
+        .filter(|word| !word.is_empty())
+        .take(DETECT_MAX_WORDS)
+        .map(|word| word.replace('’', "'").to_lowercase())
+        .collect::<Vec<_>>();
+    if words.len() < options.detect_min_words {
+        return Reading::TooShort;
+    }
+    let mut scores = COMMON_WORDS
+        .iter()
+        .filter(|(lang, _)| is_candidate(*lang, options))
+        .map(|(lang, common)| {
+            let hits = words
+                .iter()
+                .filter(|word| common.contains(&word.as_str()))
+                .count();
+            (hits, *lang)
+        })
+        .collect::<Vec<_>>();
+    scores.sort_by_key(|&(hits, _)| Reverse(hits));
+    let Some(&(best, lang)) = scores.first() else {
+        return Reading::Unknown;
+    };
+    // Something like the language, not just sharing a few words with it
+    if best * 10 < words.len() * 2 {
+        return Reading::Unknown;
+    }
+    let runner_up = scores.get(1).map(|&(hits, _)| hits).unwrap_or_default();
+    debug!(
+        "{best} of {} words are common in {}, {runner_up} in the next best",
+        words.len(),
+        lang.to_name()
+    );
+    let confidence = ((best - runner_up) * 100 / best) as u32;
+    match confidence >= options.detect_confidence {
+        true => Reading::Clear(lang, confidence),
+        false => Reading::Unclear(lang, confidence),
+    }
+}
+
+/// The text of a subtitle's cues, a line for each line of dialogue
+fn dialogue(path: &Utf8Path) -> anyhow::Result<String> {
+    let ext = path.extension().unwrap_or_default().to_lowercase();
+    if ext == "srt" {
+        let mut text = String::new();
+        for cue in srt::open(path)?.take(DETECT_MAX_WORDS) {
+            text.push_str(&strip_markup(&cue?.text, '<', '>'));
+            text.push('\n');
+        }
+        return Ok(text);
+    }
+    let mut file = File::open(path)
+        .with_context(|| format!("failed to open {path}"))?
+        .take(MAX_SUBTITLE_SIZE);
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)
+        .with_context(|| format!("failed to read {path}"))?;
+    stats::add_bytes_read(bytes.len());
+    let contents = String::from_utf8_lossy(&bytes);
+    let lines = contents.lines().map(str::trim);
+    let text = match ext.as_str() {
+        "ass" | "ssa" => lines
+            .filter_map(|line| line.strip_prefix("Dialogue:"))
+            // The text comes after the 9 other fields
+            .filter_map(|line| line.splitn(10, ',').nth(9))
+            .map(|text| {
+                strip_markup(text, '{', '}')
+                    .replace("\\N", "\n")
+                    .replace("\\n", "\n")
+            })
+            .collect::<Vec<_>>(),
+        "vtt" => {
+            let mut in_cue = false;
+            lines
+                .filter(|line| {
+                    if line.is_empty() {
+                        in_cue = false;
+                    } else if line.contains("-->") {
+                        in_cue = true;
+                        return false;
+                    }
+                    in_cue && !line.is_empty()
+                })
+                .map(|line| strip_markup(line, '<', '>'))
+                .collect()
+        },
+        _ => bail!("can't read the text of .{ext} subtitles"),
+    };
+    Ok(text.join("\n"))
+}
+
+/// Removes formatting like `<i>` or `{\an8}` from cue text
+fn strip_markup(text: &str, open: char, close: char) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut depth = 0usize;
+    for c in text.chars() {
+        match c {
+            c if c == open => depth += 1,
+            c if c == close && depth > 0 => depth -= 1,
+            c if depth == 0 => stripped.push(c),
+            _ => {},
+        }
+    }
+    stripped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DANISH: &str = "Jeg ved ikke, hvad du vil have. Det er ikke min \
+                          skyld, og jeg kan ikke gøre det. Hun har sagt, at \
+                          vi skal med, men han er ikke her. Hvad så med dig? \
+                          Det er på tide, at du går hjem til mig. Nej, jeg \
+                          har ikke tid til det. Vi kan tale om det i morgen.";
+
+    #[test]
+    fn thresholds() {
+        // Danish shares most of its common words with Norwegian
+        let options = Options::default();
+        assert_eq!(
+            language_of_text(&DANISH.repeat(2), &options),
+            Reading::Unclear(Language::Dan, 17)
+        );
+        let options = Options {
+            detect_confidence: 15,
+            ..Options::default()
+        };
+        assert_eq!(
+            language_of_text(&DANISH.repeat(2), &options),
+            Reading::Clear(Language::Dan, 17)
+        );
+        let options = Options {
+            detect_min_words: 200,
+            ..Options::default()
+        };
+        assert_eq!(
+            language_of_text(&DANISH.repeat(2), &options),
+            Reading::TooShort
+        );
+    }
+
+    #[test]
+    fn candidates() {
+        let options = Options {
+            detect_languages: vec![Language::Dan, Language::Eng],
+            ..Options::default()
+        };
+        assert_eq!(
+            language_of_text(&DANISH.repeat(2), &options),
+            Reading::Clear(Language::Dan, 97)
+        );
+        let options = Options {
+            detect_languages: vec![Language::Eng],
+            ..Options::default()
+        };
+        assert_eq!(
+            language_of_text(&DANISH.repeat(2), &options),
+            Reading::Unknown
+        );
+        let greek = "Δεν ξέρω τι θέλεις από μένα. ".repeat(20);
+        assert_eq!(
+            language_of_text(&greek, &options),
+            Reading::NotCandidate(Language::Ell)
+        );
+    }
+}
@@ -0,0 +1,171 @@
+//! How many videos have subtitles in the languages someone cares about, so
+//! filling the gaps can be tracked from one run to the next
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use isolang::Language;
+
+use crate::{
+    discovery::Listing,
+    json::Value,
+    predicates,
+    report::{Report, VideoLanguages},
+    video::Video,
+};
+
+/// Movies aren't grouped by anything, so they're all counted together
+const MOVIES: &str = "Movies";
+
+/// Notes down the languages each video has, those already next to it and
+/// those linked (or that would be) this run
+pub fn record(videos: &[Video], report: &mut Report) {
+    let listing = videos.first().map(Listing::around).unwrap_or_default();
+    report.videos = videos
+        .iter()
+        .map(|video| {
+            let mut languages = listing.existing_languages(video);
+            languages.extend(
+                report
+                    .links
+                    .iter()
+                    .filter(|link| link.video == video.path)
+                    .map(|link| link.lang),
+            );
+            VideoLanguages {
+                path: video.path.clone(),
+                show: video.series_info.map(|_| {
+                    predicates::title_of(
+                        video.path.file_stem().unwrap_or_default(),
+                    )
+                    .trim()
+                    .to_owned()
+                }),
+                languages,
+            }
+        })
+        .collect();
+}
+
+/// How many videos there are and how many have each language, per show
+#[derive(Debug, Default)]
+struct Tally {
+    videos: usize,
+    with: BTreeMap<Language, usize>,
+}
+
+impl Tally {
+    fn add(&mut self, video: &VideoLanguages) {
+        self.videos += 1;
+        for lang in &video.languages {
+            *self.with.entry(*lang).or_default() += 1;
+        }
+    }
+
+    fn percent(&self, lang: Language) -> f64 {
+        match self.videos {
+            0 => 0.0,
+            videos => {
+                self.with.get(&lang).copied().unwrap_or_default() as f64 * 100.0
+                    / videos as f64
+            },
+        }
+    }
+}
+
+fn tally(
+    results: &[(Utf8PathBuf, anyhow::Result<Report>)],
+) -> (BTreeMap<String, Tally>, Tally) {
+    let mut shows = BTreeMap::<String, Tally>::new();
+    let mut total = Tally::default();
+    // Folders given twice (or found twice) only count once
+    let mut seen = BTreeSet::new();
+    for (dir, report) in results {
+        let Ok(report) = report else {
+            continue;
+        };
+        for video in &report.videos {
+            if !seen.insert(absolute(dir, &video.path)) {
+                continue;
+            }
+            let show = match &video.show {
+                Some(show) if !show.is_empty() => show.clone(),
+                Some(_) => dir.file_name().unwrap_or_default().to_owned(),
+                None => MOVIES.to_owned(),
+            };
+            shows.entry(show).or_default().add(video);
+            total.add(video);
+        }
+    }
+    (shows, total)
+}
+
+fn absolute(dir: &Utf8Path, path: &Utf8Path) -> Utf8PathBuf {
+    dir.join(path.strip_prefix(".").unwrap_or(path))
+}
+
+fn code(lang: Language) -> &'static str {
+    lang.to_639_1().unwrap_or(lang.to_639_3())
+}
+
+/// A table of the percentage of videos with each language, per show and in
+/// total
+pub fn table(
+    results: &[(Utf8PathBuf, anyhow::Result<Report>)],
+    languages: &[Language],
+) -> String {
+    let (shows, total) = tally(results);
+    let width = shows
+        .keys()
+        .map(|show| show.chars().count())
+        .chain(["Total".len()])
+        .max()
+        .unwrap_or_default();
+    let mut table = format!("{:width$}  {:>6}", "", "videos");
+    for lang in languages {
+        table.push_str(&format!("  {:>5}", code(*lang)));
+    }
+    table.push('\n');
+    let rows = shows
+        .iter()
+        .map(|(show, tally)| (show.as_str(), tally))
+        .chain([("Total", &total)]);
+    for (show, tally) in rows {
+        // Padding counts chars, so wide names would still be off a little
+        table.push_str(&format!("{show:width$}  {:>6}", tally.videos));
+        for lang in languages {
+            table.push_str(&format!("  {:>4.0}%", tally.percent(*lang)));
+        }
+        table.push('\n');
+    }
+    table
+}
+
+pub fn to_json(
+    results: &[(Utf8PathBuf, anyhow::Result<Report>)],
+    languages: &[Language],
+) -> Value {
+    let (shows, total) = tally(results);
+    let entry = |tally: &Tally| {
+        Value::object([
+            ("videos", tally.videos.into()),
+            (
+                "languages",
+                Value::object(languages.iter().map(|lang| {
+                    (code(*lang), Value::Number(tally.percent(*lang)))
+                })),
+            ),
+        ])
+    };
+    Value::object([
+        (
+            "shows",
+            Value::object(
+                shows
+                    .iter()
+                    .map(|(show, tally)| (show.as_str(), entry(tally))),
+            ),
+        ),
+        ("total", entry(&total)),
+    ])
+}
@@ -0,0 +1,814 @@
+use std::{
+    collections::BTreeSet,
+    fs,
+    time::{Instant, SystemTime},
+};
+
+use camino::{Utf8Component, Utf8DirEntry, Utf8Path, Utf8PathBuf};
+use isolang::Language;
+use log::{debug, info, warn};
+use walkdir::WalkDir;
+
+use crate::{
+    canonicalize,
+    cli::Options,
+    content,
+    events::Event,
+    fsops::audit_language_override,
+    gzip,
+    ignore::IgnoreFiles,
+    lock::DIRECTORY_LOCK,
+    manifest::{Manifest, MANIFEST},
+    naming, nfo, parallel, predicates,
+    report::{Report, Skip, SkipReason},
+    series::{self, SeriesInfo},
+    subtitle::{language_from_tag, Source, Subtitle},
+    sync, timing,
+    video::Video,
+};
+
+pub fn discover_videos(
+    in_dir: impl AsRef<Utf8Path>,
+    options: &Options,
+    report: &mut Report,
+) -> Vec<Video> {
+    let mut files = 0;
+    let ignore = ignore_files(in_dir.as_ref(), options);
+    // Sorted so what's done (and reported) is in the same order whatever
+    // order the filesystem lists things in
+    let videos = WalkDir::new(in_dir.as_ref())
+        .min_depth(1)
+        .max_depth(1)
+        .sort_by_file_name()
+        .contents_first(true)
+        .into_iter()
+        .filter_map(|dir_entry| match dir_entry {
+            Ok(dir_entry) => {
+                files += 1;
+                Some(dir_entry)
+            },
+            Err(why) => {
+                warn!("{why}");
+                None
+            },
+        })
+        .filter(predicates::is_video)
+        .filter_map(|dir_entry| {
+            match Utf8PathBuf::try_from(dir_entry.path().to_owned()) {
+                Ok(path) if ignore.excluded_by(&path).is_some() => {
+                    skip_ignored(path, &ignore, report);
+                    None
+                },
+                Ok(path) => match Video::from_path(path, options.strict) {
+                    Ok(video) => {
+                        report.emit(Event::VideoFound {
+                            path: video.path.clone(),
+                        });
+                        Some(video)
+                    },
+                    Err(skip) => {
+                        report.warn_skip(
+                            format!(
+                                "skipped path {}: {}",
+                                skip.path, skip.detail
+                            ),
+                            skip,
+                        );
+                        None
+                    },
+                },
+                Err(_) => {
+                    report.warn_skip(
+                        format!(
+                            "skipped non-UTF-8 path {}",
+                            dir_entry.path().display()
+                        ),
+                        Skip::new(
+                            dir_entry.path().to_string_lossy().into_owned(),
+                            SkipReason::NonUtf8Path,
+                            "path isn't valid UTF-8",
+                        ),
+                    );
+                    None
+                },
+            }
+        })
+        .collect();
+    report.stats.files += files;
+    videos
+}
+
+pub fn discover_subtitles(
+    in_root_dir: impl AsRef<Utf8Path>,
+    videos: &[Video],
+    options: &Options,
+    report: &mut Report,
+) -> Vec<Subtitle> {
+    let in_root_dir = in_root_dir.as_ref();
+    let mut subtitles = Vec::new();
+    let mut found = Vec::new();
+    let parse_start = Instant::now();
+    let episode_titles = nfo::episode_titles(videos);
+    report.stats.parse += parse_start.elapsed();
+    // Links are followed, as other tools curate subtitle folders with them,
+    // but never back into what's being searched
+    let root = canonicalize(in_root_dir).ok();
+    let ignore = ignore_files(in_root_dir, options);
+    // Copies & moved subtitles an earlier run put next to the videos
+    let placed = Manifest::load(in_root_dir)
+        .map(|manifest| {
+            manifest
+                .entries()
+                .iter()
+                .map(|entry| entry.name.clone())
+                .collect::<BTreeSet<_>>()
+        })
+        .unwrap_or_default();
+    let mut walker = WalkDir::new(in_root_dir)
+        .min_depth(1)
+        .sort_by_file_name()
+        .follow_links(true)
+        .into_iter();
+    while let Some(dir_entry) = walker.next() {
+        let dir_entry = match dir_entry {
+            Ok(dir_entry) => {
+                report.stats.files += 1;
+                dir_entry
+            },
+            Err(why) => {
+                report.stats.files += 1;
+                let skip = match (why.path(), why.loop_ancestor()) {
+                    (Some(path), Some(ancestor)) => Some(Skip::new(
+                        path.to_string_lossy().into_owned(),
+                        SkipReason::SymlinkLoop,
+                        format!("links back to {}", ancestor.display()),
+                    )),
+                    (Some(path), None) if path.is_symlink() => Some(Skip::new(
+                        path.to_string_lossy().into_owned(),
+                        SkipReason::BrokenSymlink,
+                        why.io_error()
+                            .map(ToString::to_string)
+                            .unwrap_or_else(|| why.to_string()),
+                    )),
+                    _ => None,
+                };
+                match skip {
+                    Some(skip) => {
+                        report.warn_skip(
+                            format!(
+                                "skipping link {}: {}",
+                                skip.path, skip.detail
+                            ),
+                            skip,
+                        );
+                    },
+                    None => warn!("{why}"),
+                }
+                continue;
+            },
+        };
+        let path = match Utf8PathBuf::try_from(dir_entry.path().to_owned()) {
+            Ok(path) => path,
+            Err(_) => {
+                report.warn_skip(
+                    format!(
+                        "skipped non-UTF-8 path {}",
+                        dir_entry.path().display()
+                    ),
+                    Skip::new(
+                        dir_entry.path().to_string_lossy().into_owned(),
+                        SkipReason::NonUtf8Path,
+                        "path isn't valid UTF-8",
+                    ),
+                );
+                if dir_entry.file_type().is_dir() {
+                    walker.skip_current_dir();
+                }
+                continue;
+            },
+        };
+        if ignore.excluded_by(&path).is_some() {
+            skip_ignored(path, &ignore, report);
+            if dir_entry.file_type().is_dir() {
+                walker.skip_current_dir();
+            }
+            continue;
+        }
+        if dir_entry.path_is_symlink() {
+            // The links next to the videos are the ones subfix makes
+            if dir_entry.depth() == 1 && !dir_entry.file_type().is_dir() {
+                debug!("ignoring {path}, it's a link");
+                continue;
+            }
+            let target = canonicalize(&path).ok();
+            if let (Some(root), Some(target)) = (&root, target) {
+                if target.starts_with(root) {
+                    report.warn_skip(
+                        format!(
+                            "skipping link {path}, it leads back to {target}"
+                        ),
+                        Skip::new(
+                            path,
+                            SkipReason::SymlinkLoop,
+                            format!("leads back to {target}"),
+                        ),
+                    );
+                    if dir_entry.file_type().is_dir() {
+                        walker.skip_current_dir();
+                    }
+                    continue;
+                }
+            }
+        }
+        if dir_entry.file_type().is_dir() {
+            if !options.all_folders
+                && !predicates::is_searchable(
+                    &dir_entry,
+                    videos,
+                    &options.subtitle_folders,
+                )
+            {
+                report.skip(Skip::new(
+                    path,
+                    SkipReason::ExcludedPath,
+                    "not a known subtitle folder",
+                ));
+                walker.skip_current_dir();
+            }
+            continue;
+        }
+        if matches!(path.file_name(), Some(DIRECTORY_LOCK | MANIFEST)) {
+            continue;
+        }
+        if dir_entry.depth() == 1
+            && placed.contains(path.file_name().unwrap_or_default())
+        {
+            debug!("ignoring {path}, an earlier run put it there");
+            continue;
+        }
+        let path = match gzip::inner_subtitle(&path) {
+            Some(inner) if dir_entry.file_type().is_file() => {
+                // Sorted, so one decompressed before was found just now
+                if inner.exists() {
+                    debug!("ignoring {path}, it's been decompressed already");
+                    continue;
+                }
+                // Planned as the file it'll be, and only decompressed if
+                // it's kept (see gzip::decompress_found)
+                report.compressed.insert(inner.clone(), path);
+                inner
+            },
+            _ if timing::is_fixed_copy(&path) => {
+                debug!("ignoring {path}, it's made from another's timings");
+                continue;
+            },
+            _ if sync::is_synced_copy(&path) => {
+                debug!("ignoring {path}, it's another synced to its video");
+                continue;
+            },
+            _ if !predicates::is_subtitle(&dir_entry) => {
+                if !(dir_entry.depth() == 1 && predicates::is_video(&dir_entry))
+                {
+                    debug!("ignoring {path}, not a subtitle");
+                    report.skip(Skip::new(
+                        path,
+                        SkipReason::ExtensionFiltered,
+                        "extension isn't a known subtitle format",
+                    ));
+                }
+                continue;
+            },
+            _ => path,
+        };
+        info!("found {path}");
+        found.push(path);
+    }
+    // Reading them is what takes the time in a big season pack, and each is
+    // read on its own
+    let parse_start = Instant::now();
+    let parsed = parallel::map(&found, options.jobs, |path| {
+        parse(path.clone(), in_root_dir, videos, &episode_titles, options)
+    });
+    report.stats.parse += parse_start.elapsed();
+    for subtitle in parsed {
+        match subtitle {
+            Ok(sub) => subtitles.push(sub),
+            Err(skip) => {
+                report.warn_skip(
+                    format!(
+                        "failed to process {}, skipping: {}",
+                        skip.path, skip.detail
+                    ),
+                    skip,
+                );
+            },
+        }
+    }
+    subtitles
+}
+
+// What a subtitle is, going by its name and, as needed, its contents
+fn parse(
+    path: Utf8PathBuf,
+    in_root_dir: &Utf8Path,
+    videos: &[Video],
+    episode_titles: &[(String, SeriesInfo)],
+    options: &Options,
+) -> Result<Subtitle, Skip> {
+    let source = source_of(in_root_dir, &path, videos, options);
+    let given = match language_override(&path, in_root_dir, options) {
+        Some(lang) => {
+            info!("{path} was given as {}", lang.to_name());
+            audit_language_override(
+                &path,
+                lang.to_639_1().unwrap_or(lang.to_639_3()),
+            );
+            Some(lang)
+        },
+        None => options.assume_lang,
+    };
+    let bilingual = given.is_none()
+        && options.bilingual_label.is_some()
+        && content::looks_bilingual(&path);
+    // Whatever the name says, one language would be wrong
+    let subtitle = match (given, bilingual) {
+        (Some(lang), _) => Subtitle::with_language(
+            path,
+            in_root_dir,
+            lang,
+            source,
+            options.strict,
+        ),
+        (None, true) => {
+            info!("{path} looks like it has two languages");
+            Subtitle::with_language(
+                path,
+                in_root_dir,
+                Language::Mul,
+                source,
+                options.strict,
+            )
+        },
+        (None, false) => {
+            Subtitle::new(path, in_root_dir, source, options.strict)
+        },
+    };
+    let subtitle = match subtitle {
+        _ if episode_titles.is_empty() => subtitle,
+        Ok(mut sub) if sub.series_info.is_none() => {
+            // e.g. Subs/The One Where Monica Gets a Roommate/English.srt
+            sub.series_info = sub
+                .path
+                .parent()
+                .and_then(|folder| folder.file_name())
+                .and_then(|folder| nfo::match_title(folder, episode_titles));
+            Ok(sub)
+        },
+        Err(skip) if skip.reason == SkipReason::UnknownLanguage => {
+            by_title(skip, in_root_dir, source, episode_titles, options)
+        },
+        subtitle => subtitle,
+    };
+    let mut sub = match subtitle {
+        Err(skip)
+            if skip.reason == SkipReason::UnknownLanguage
+                && options.detect_language =>
+        {
+            by_content(skip, in_root_dir, source, options)
+        },
+        subtitle => subtitle,
+    }?;
+    if bilingual {
+        sub.label = options.bilingual_label.clone();
+    }
+    if options
+        .all_episodes
+        .iter()
+        .any(|given| is_given(&sub.path, in_root_dir, given))
+    {
+        info!("{} is for every episode", sub.path);
+        sub.shared = true;
+        // Tells it apart from each episode's own subtitle
+        if sub.title.is_none() {
+            sub.title = sub.path.file_stem().map(str::to_owned);
+        }
+    }
+    Ok(sub)
+}
+
+// Subtitles named like `The One Where Monica Gets a Roommate.en.srt`, which
+// don't have a language name to go on
+fn by_title(
+    skip: Skip,
+    root: &Utf8Path,
+    source: Source,
+    episode_titles: &[(String, SeriesInfo)],
+    options: &Options,
+) -> Result<Subtitle, Skip> {
+    let stem = skip.path.file_stem().unwrap_or_default();
+    let (title, lang) = match stem.rsplit_once('.') {
+        Some((title, tag)) => match language_from_tag(tag) {
+            Some(lang) => (title, Some(lang)),
+            None => (stem, None),
+        },
+        None => (stem, None),
+    };
+    let Some(series_info) = nfo::match_title(title, episode_titles) else {
+        return Err(skip);
+    };
+    let Some(lang) = lang else {
+        return Err(Skip::new(
+            skip.path,
+            SkipReason::UnknownLanguage,
+            format!("named after {series_info}, but has no language"),
+        ));
+    };
+    let mut sub =
+        Subtitle::with_language(skip.path, root, lang, source, options.strict)?;
+    sub.series_info = Some(series_info);
+    Ok(sub)
+}
+
+/// A subtitle whose name doesn't give its language, going by its text
+fn by_content(
+    skip: Skip,
+    root: &Utf8Path,
+    source: Source,
+    options: &Options,
+) -> Result<Subtitle, Skip> {
+    let Some(lang) = content::detect_language(&skip.path, options) else {
+        return Err(Skip::new(
+            skip.path,
+            SkipReason::UnknownLanguage,
+            format!("{}, and its text doesn't clearly say", skip.detail),
+        ));
+    };
+    info!("{} reads as {}", skip.path, lang.to_name());
+    Subtitle::with_language(skip.path, root, lang, source, options.strict)
+}
+
+fn language_override(
+    path: &Utf8Path,
+    root: &Utf8Path,
+    options: &Options,
+) -> Option<Language> {
+    options
+        .set_lang
+        .iter()
+        .find(|(given, _)| is_given(path, root, given))
+        .map(|&(_, lang)| lang)
+}
+
+/// Whether a path is one given on the command line, which are relative to
+/// the folder being processed unless they're absolute
+fn is_given(path: &Utf8Path, root: &Utf8Path, given: &Utf8Path) -> bool {
+    let without_dots = |path: &Utf8Path| {
+        path.components()
+            .filter(|component| *component != Utf8Component::CurDir)
+            .collect::<Utf8PathBuf>()
+    };
+    match given.is_absolute() {
+        true => without_dots(path) == without_dots(given),
+        false => {
+            let relative = path.strip_prefix(root).unwrap_or(path);
+            without_dots(given) == without_dots(relative)
+        },
+    }
+}
+
+pub(crate) fn source_of(
+    root: &Utf8Path,
+    path: &Utf8Path,
+    videos: &[Video],
+    options: &Options,
+) -> Source {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    let mut components = relative.components();
+    let top_level = components.next().map(|folder| folder.as_str());
+    match (top_level, components.next()) {
+        (Some(folder), Some(_)) => {
+            if videos
+                .iter()
+                .any(|video| video.path.file_stem() == Some(folder))
+            {
+                Source::VideoFolder
+            } else if predicates::is_subtitle_folder(
+                folder,
+                &[],
+                &options.subtitle_folders,
+            ) {
+                Source::SubsFolder
+            } else {
+                Source::Other
+            }
+        },
+        _ => Source::Adjacent,
+    }
+}
+
+/// The folder of a show for one of its seasons, like `Show/Season 02`
+pub fn season_folder(show: &Utf8Path, season: u8) -> Option<Utf8PathBuf> {
+    let mut folders = show
+        .read_dir_utf8()
+        .ok()?
+        .filter_map(Result::ok)
+        .filter(|entry| {
+            entry
+                .file_type()
+                .map(|kind| kind.is_dir())
+                .unwrap_or_default()
+                && series::season_of_folder(entry.file_name()) == Some(season)
+        })
+        .map(|entry| entry.path().to_owned())
+        .collect::<Vec<_>>();
+    folders.sort();
+    if folders.len() > 1 {
+        warn!(
+            "{show} has more than one folder for season {season}, using the \
+             first: {folders:?}"
+        );
+    }
+    folders.into_iter().next()
+}
+
+/// Folders Jellyfin keeps a title's extras in, which aren't titles themselves
+const EXTRAS_FOLDERS: &[&str] = &[
+    "behind the scenes",
+    "deleted scenes",
+    "interviews",
+    "scenes",
+    "samples",
+    "shorts",
+    "featurettes",
+    "clips",
+    "other",
+    "extras",
+    "trailers",
+];
+
+/// The ignore files that apply to what's in `dir`, if they're to be followed
+fn ignore_files(dir: &Utf8Path, options: &Options) -> IgnoreFiles {
+    match options.respect_ignore_files {
+        true => IgnoreFiles::above(dir),
+        false => IgnoreFiles::default(),
+    }
+}
+
+fn skip_ignored(path: Utf8PathBuf, ignore: &IgnoreFiles, report: &mut Report) {
+    let file = ignore.excluded_by(&path).unwrap_or(Utf8Path::new(""));
+    info!("skipping {path}, it's excluded by {file}");
+    report.skip(Skip::new(
+        path,
+        SkipReason::Ignored,
+        format!("excluded by {file}"),
+    ));
+}
+
+/// Every folder under a library root (like `/media/Movies`) with videos in
+/// it, each to be processed on its own.
+///
+/// Anything inside a title's folder is taken to be its subtitles, except
+/// season folders (`Show/Season 01`), which are titles of their own. Hidden
+/// folders, extras and symlinked folders are left out, the last so a link
+/// back up the tree can't go round forever. Given a season, other seasons'
+/// folders are left out too, as is anything ignore files exclude (with
+/// `--respect-ignore-files`)
+pub fn title_folders(root: &Utf8Path, options: &Options) -> Vec<Utf8PathBuf> {
+    let ignore = ignore_files(root, options);
+    let mut titles = Vec::new();
+    find_titles(root, options, &ignore, &mut titles);
+    titles
+}
+
+fn find_titles(
+    dir: &Utf8Path,
+    options: &Options,
+    ignore: &IgnoreFiles,
+    titles: &mut Vec<Utf8PathBuf>,
+) {
+    let mut entries = match dir.read_dir_utf8() {
+        Ok(entries) => entries.filter_map(Result::ok).collect::<Vec<_>>(),
+        Err(why) => {
+            warn!("couldn't read {dir}, skipping it: {why}");
+            return;
+        },
+    };
+    entries.sort_by(|a, b| a.file_name().cmp(b.file_name()));
+    let is_dir = |entry: &Utf8DirEntry| {
+        entry
+            .file_type()
+            .map(|file_type| file_type.is_dir())
+            .unwrap_or_default()
+    };
+    let has_videos = entries.iter().any(|entry| {
+        !is_dir(entry) && predicates::has_video_extension(entry.path())
+    });
+    if has_videos {
+        debug!("{dir} is a title");
+        titles.push(dir.to_owned());
+    }
+    for entry in entries.iter().filter(|entry| is_dir(entry)) {
+        let name = entry.file_name();
+        if name.starts_with('.')
+            || EXTRAS_FOLDERS.contains(&name.to_lowercase().as_str())
+        {
+            debug!("not looking for titles in {}", entry.path());
+            continue;
+        }
+        match (series::season_of_folder(name), options.season) {
+            (Some(folder), Some(season)) if folder != season => continue,
+            (None, _) if has_videos => continue,
+            _ => {},
+        }
+        if let Some(file) = ignore.excluded_by(entry.path()) {
+            info!("{} is excluded by {file}, leaving it out", entry.path());
+            continue;
+        }
+        match options.respect_ignore_files {
+            true => find_titles(
+                entry.path(),
+                options,
+                &ignore.descend(entry.path()),
+                titles,
+            ),
+            false => find_titles(entry.path(), options, ignore, titles),
+        }
+    }
+}
+
+/// `root` and every folder under it, in name order, for the commands that
+/// go through what earlier runs left (`undo`, `prune-langs`, `adopt` & co).
+/// Symlinked folders aren't followed, so a link back up the tree can't go
+/// round forever, and with `--respect-ignore-files` nor is anything ignore
+/// files exclude
+pub fn folders(
+    root: &Utf8Path,
+    options: &Options,
+) -> impl Iterator<Item = Utf8PathBuf> {
+    let respect_ignore_files = options.respect_ignore_files;
+    WalkDir::new(root)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(move |entry| {
+            let excluded = respect_ignore_files
+                && entry.file_type().is_dir()
+                && Utf8Path::from_path(entry.path())
+                    .map(|dir| {
+                        IgnoreFiles::above(dir).excluded_by(dir).is_some()
+                    })
+                    .unwrap_or_default();
+            if excluded {
+                info!(
+                    "{} is excluded by an ignore file, leaving it out",
+                    entry.path().display()
+                );
+            }
+            !excluded
+        })
+        .filter_map(|entry| match entry {
+            Ok(entry) => Some(entry),
+            Err(why) => {
+                warn!("{why}");
+                None
+            },
+        })
+        .filter(|entry| entry.file_type().is_dir())
+        .filter_map(|entry| {
+            match Utf8PathBuf::from_path_buf(entry.into_path()) {
+                Ok(dir) => Some(dir),
+                Err(path) => {
+                    warn!("skipped non-UTF-8 path {}", path.display());
+                    None
+                },
+            }
+        })
+}
+
+/// The most recent modification time of anything directly inside a directory
+///
+/// The directory's own time is left out, along with symlinks, the lock file
+/// and the manifest, as subfix changes those itself
+pub fn last_modified(dir: impl AsRef<Utf8Path>) -> Option<SystemTime> {
+    fs::read_dir(dir.as_ref())
+        .ok()?
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let meta = entry.metadata().ok()?;
+            if entry.file_name() == DIRECTORY_LOCK
+                || entry.file_name() == MANIFEST
+                || meta.file_type().is_symlink()
+            {
+                return None;
+            }
+            meta.modified().ok()
+        })
+        .max()
+}
+
+/// Languages of the subtitles already sitting next to a video, named the way
+/// Jellyfin expects (`Video.en.srt`, `Video.eng.forced.srt`, ...)
+pub fn existing_languages(video: &Video) -> BTreeSet<Language> {
+    Listing::around(video).existing_languages(video)
+}
+
+/// Subtitles next to a video that are named after it, like `Movie.en.srt`
+pub fn sidecars(
+    video: &Video,
+    keep: impl Fn(&Utf8DirEntry) -> bool,
+) -> Vec<Utf8PathBuf> {
+    Listing::around(video).sidecars(video, keep)
+}
+
+/// The language a sidecar is tagged with, going by the tags between the
+/// video's name and the extension (`Movie.default.en.srt` is English)
+pub fn sidecar_language(video: &Video, sidecar: &Utf8Path) -> Option<Language> {
+    naming::sidecar_language(video.path.file_stem()?, sidecar.file_stem()?)
+}
+
+/// The entries of a folder, read once so that finding the sidecars of every
+/// video in a folder of thousands doesn't mean reading it for each of them
+#[derive(Default)]
+pub struct Listing {
+    /// Sorted by name
+    entries: Vec<Utf8DirEntry>,
+}
+
+impl Listing {
+    pub fn read(dir: impl AsRef<Utf8Path>) -> Self {
+        let mut entries = dir
+            .as_ref()
+            .read_dir_utf8()
+            .map(|entries| entries.filter_map(Result::ok).collect::<Vec<_>>())
+            .unwrap_or_default();
+        entries.sort_by(|a, b| a.file_name().cmp(b.file_name()));
+        Listing { entries }
+    }
+
+    /// The folder a video is in
+    pub fn around(video: &Video) -> Self {
+        match video.path.parent() {
+            Some(dir) => Listing::read(dir),
+            None => Listing::default(),
+        }
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(Utf8DirEntry::file_name)
+    }
+
+    pub fn existing_languages(&self, video: &Video) -> BTreeSet<Language> {
+        self.sidecar_languages(video, |_| true)
+    }
+
+    /// Languages of subtitles next to a video that aren't symlinks, which is
+    /// how Bazarr leaves the ones it downloads
+    pub fn bazarr_languages(&self, video: &Video) -> BTreeSet<Language> {
+        self.sidecar_languages(video, |entry| {
+            entry
+                .file_type()
+                .map(|file_type| file_type.is_file())
+                .unwrap_or_default()
+        })
+    }
+
+    fn sidecar_languages(
+        &self,
+        video: &Video,
+        keep: impl Fn(&Utf8DirEntry) -> bool,
+    ) -> BTreeSet<Language> {
+        self.sidecars(video, keep)
+            .iter()
+            .filter_map(|path| sidecar_language(video, path))
+            .collect()
+    }
+
+    pub fn sidecars(
+        &self,
+        video: &Video,
+        keep: impl Fn(&Utf8DirEntry) -> bool,
+    ) -> Vec<Utf8PathBuf> {
+        let stem = video.path.file_stem().unwrap_or_default();
+        let prefix = format!("{stem}.");
+        // Everything starting with the prefix sorts together
+        let start = self
+            .entries
+            .partition_point(|entry| entry.file_name() < prefix.as_str());
+        self.entries[start..]
+            .iter()
+            .take_while(|entry| entry.file_name().starts_with(&prefix))
+            .filter(|entry| {
+                let path = entry.path();
+                // `Movie.srt` has nothing after the video's name but the
+                // extension
+                let named_after_video = path
+                    .file_stem()
+                    .map(|tags| tags.len() > stem.len())
+                    .unwrap_or_default();
+                predicates::has_subtitle_extension(path)
+                    && named_after_video
+                    && keep(entry)
+            })
+            .map(|entry| entry.path().to_owned())
+            .collect()
+    }
+}
@@ -0,0 +1,33 @@
+use once_cell::sync::Lazy;
+use regex::{Regex, RegexBuilder};
+
+// Jellyfin/Plex style `{edition-Director's Cut}`
+static EDITION_TAG_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\s*\{edition-(?P<edition>[^}]+)\}").unwrap());
+
+static EDITION_WORD_REGEX: Lazy<Regex> = Lazy::new(|| {
+    RegexBuilder::new(
+        r"\b(?P<edition>director'?s[ ._]cut|extended|theatrical|unrated|uncut|final[ ._]cut|special[ ._]edition|ultimate[ ._]edition|remastered|imax)\b",
+    )
+    .case_insensitive(true)
+    .build()
+    .unwrap()
+});
+
+/// Finds the edition a video (or subtitle) is for, normalised so that e.g.
+/// `Directors.Cut` and `{edition-Director's Cut}` compare equal
+pub fn detect(s: &str) -> Option<String> {
+    let edition = EDITION_TAG_REGEX
+        .captures(s)
+        .or_else(|| EDITION_WORD_REGEX.captures(s))?;
+    let edition = edition["edition"]
+        .to_lowercase()
+        .replace(['.', '_'], " ")
+        .replace("directors", "director's");
+    Some(edition.trim().to_owned())
+}
+
+/// Removes `{edition-...}` tags so names can be compared without them
+pub fn strip_tags(s: &str) -> String {
+    EDITION_TAG_REGEX.replace_all(s, "").into_owned()
+}
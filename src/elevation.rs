@@ -0,0 +1,73 @@
+//! Windows only lets administrators (or anyone, with Developer Mode on) make
+//! symlinks. Rather than leave people to work out what subfix wanted to do,
+//! the links it was refused are turned into commands to run elevated
+
+use camino::{Utf8Path, Utf8PathBuf};
+use log::warn;
+
+use crate::report::Report;
+
+/// A link that couldn't be made for lack of privilege
+#[derive(Debug)]
+pub struct DeniedLink {
+    pub dir: Utf8PathBuf,
+    /// Relative to `dir`
+    pub link: Utf8PathBuf,
+    /// Relative to `dir`, so the link is too
+    pub target: Utf8PathBuf,
+}
+
+pub fn denied_links(
+    results: &[(Utf8PathBuf, anyhow::Result<Report>)],
+) -> Vec<DeniedLink> {
+    results
+        .iter()
+        .filter_map(|(dir, result)| Some((dir, result.as_ref().ok()?)))
+        .flat_map(|(dir, report)| {
+            report.denied.iter().map(|record| DeniedLink {
+                dir: dir.to_owned(),
+                link: relative_to(dir, &record.link),
+                target: relative_to(dir, &record.subtitle),
+            })
+        })
+        .collect()
+}
+
+fn relative_to(dir: &Utf8Path, path: &Utf8Path) -> Utf8PathBuf {
+    path.strip_prefix(dir).unwrap_or(path).to_owned()
+}
+
+/// A batch file that makes the links
+pub fn script(links: &[DeniedLink]) -> String {
+    let mut script = String::from("@echo off\r\n");
+    let mut dir = None;
+    for link in links {
+        if dir != Some(&link.dir) {
+            script.push_str(&format!("cd /d \"{}\"\r\n", escape(&link.dir)));
+            dir = Some(&link.dir);
+        }
+        script.push_str(&format!(
+            "mklink \"{}\" \"{}\"\r\n",
+            escape(&link.link),
+            escape(&link.target),
+        ));
+    }
+    script
+}
+
+// Quotes cover everything bar `%`, which batch files expand even inside them.
+// Windows doesn't allow `"` in file names
+fn escape(path: &Utf8Path) -> String {
+    path.as_str().replace('%', "%%")
+}
+
+pub fn print_instructions(links: &[DeniedLink]) {
+    warn!(
+        "Windows refused to make {} links without administrator rights. \
+         Either turn on Developer Mode and run subfix again, run it again \
+         with --elevate, or save the following as a .cmd file and run it as \
+         administrator:\n{}",
+        links.len(),
+        script(links).replace("\r\n", "\n").trim_end(),
+    );
+}
@@ -0,0 +1,217 @@
+//! Putting the subtitles linked next to MKVs inside them instead, as soft
+//! tracks, with `mkvmerge`. For those who'd rather have one file per
+//! episode than a cluster of sidecars. The links are taken away once
+//! they're in, as Jellyfin would list each subtitle twice otherwise
+
+use std::{collections::BTreeMap, fs, process::Command};
+
+use anyhow::{bail, Context};
+use camino::{Utf8Path, Utf8PathBuf};
+use log::{error, info, warn};
+
+use crate::{
+    cli::Options,
+    discovery::folders,
+    fonts::{self, FONTS},
+    fsops::{
+        check_space, copy_permissions, remove_file, replace,
+        run_creating_despite_warnings,
+    },
+    interrupt::is_interrupted,
+    jellyfin,
+    lint::Sidecar,
+    lock::{Lock, DIRECTORY_LOCK},
+    manifest::{Entry, Manifest, Origin, MANIFEST},
+    naming::LanguageCodes,
+    report::dry_run_prefix,
+    time::Stamp,
+    undo::remove_managed,
+};
+
+/// Embeds the subtitles subfix linked to each MKV anywhere under `root`,
+/// returning how many went in. Adopted links were the user's before they
+/// were subfix's, so they're left alone
+pub fn embed_subtitles(
+    root: &Utf8Path,
+    options: &Options,
+) -> anyhow::Result<usize> {
+    if !options.force {
+        if let Some(why) = jellyfin::server_data_reason(root) {
+            bail!(
+                "not embedding subtitles in {root}, it looks like Jellyfin's \
+                 own data: {why} (see --force)"
+            );
+        }
+    }
+    let mut embedded = 0;
+    for dir in folders(root, options) {
+        if !dir.join(MANIFEST).exists() {
+            continue;
+        }
+        let _lock = Lock::acquire(dir.join(DIRECTORY_LOCK))?;
+        let mut manifest = Manifest::load(&dir)?;
+        let fonts = match options.fonts {
+            true => fonts::font_files(&dir.join(FONTS)),
+            false => Vec::new(),
+        };
+        let mut by_video = BTreeMap::<String, Vec<Entry>>::new();
+        for entry in manifest.entries() {
+            if entry.origin != Origin::Created {
+                continue;
+            }
+            if let Some(video) =
+                entry.video.as_ref().filter(|video| is_mkv(video))
+            {
+                if manifest.is_embedded(video, &entry.target) {
+                    info!(
+                        "not embedding {} into {video} again, it's there \
+                         already",
+                        entry.name
+                    );
+                    continue;
+                }
+                by_video
+                    .entry(video.clone())
+                    .or_default()
+                    .push(entry.clone());
+            }
+        }
+        for (video, entries) in by_video {
+            if is_interrupted() {
+                break;
+            }
+            let video = dir.join(video);
+            if !video.is_file() {
+                continue;
+            }
+            let sidecars = entries
+                .iter()
+                .filter_map(|entry| {
+                    let link = dir.join(&entry.name);
+                    // Broken ones are for the next run to clear up
+                    if !link.exists() {
+                        return None;
+                    }
+                    let sidecar = Sidecar::parse(&video, link);
+                    if sidecar.is_none() {
+                        warn!(
+                            "can't tell the language of {}, leaving it",
+                            entry.name
+                        );
+                    }
+                    sidecar.map(|sidecar| (entry, sidecar))
+                })
+                .collect::<Vec<_>>();
+            if sidecars.is_empty() {
+                continue;
+            }
+            let subtitles = sidecars
+                .iter()
+                .map(|(_, sidecar)| sidecar)
+                .collect::<Vec<_>>();
+            if let Err(why) = remux(&video, &subtitles, &fonts) {
+                error!("couldn't embed subtitles into {video}: {why:#}");
+                continue;
+            }
+            embedded += sidecars.len();
+            for (entry, sidecar) in sidecars {
+                if let Err(why) = remove_managed(&sidecar.path, entry) {
+                    error!("{why:#}");
+                }
+                // Whether or not the link's gone, the subtitle's in there now
+                manifest.record(Entry {
+                    origin: Origin::Embedded,
+                    recorded: Stamp::now(),
+                    ..entry.clone()
+                });
+            }
+        }
+        manifest.save()?;
+    }
+    Ok(embedded)
+}
+
+fn is_mkv(name: &str) -> bool {
+    Utf8Path::new(name)
+        .extension()
+        .map(|extension| extension.eq_ignore_ascii_case("mkv"))
+        .unwrap_or_default()
+}
+
+// Written next to the video then swapped in, so a failure part way through
+// leaves it as it was
+fn remux(
+    video: &Utf8Path,
+    subtitles: &[&Sidecar],
+    fonts: &[Utf8PathBuf],
+) -> anyhow::Result<()> {
+    info!(
+        "{}embedding {} into {video}",
+        dry_run_prefix(),
+        subtitles
+            .iter()
+            .map(|sidecar| sidecar.path.file_name().unwrap_or_default())
+            .collect::<Vec<_>>()
+            .join(", "),
+    );
+    let remuxed = remuxed_path(video);
+    if let Some(dir) = video.parent() {
+        let size = |path: &Utf8Path| {
+            fs::metadata(path)
+                .map(|metadata| metadata.len())
+                .unwrap_or_default()
+        };
+        let bytes = size(video)
+            + subtitles
+                .iter()
+                .map(|sidecar| size(&sidecar.path))
+                .sum::<u64>()
+            + fonts.iter().map(|font| size(font)).sum::<u64>();
+        check_space(dir, bytes, 1)?;
+    }
+    let mut mkvmerge = Command::new("mkvmerge");
+    mkvmerge.arg("--quiet").arg("-o").arg(&remuxed).arg(video);
+    for sidecar in subtitles {
+        // Each file's only track is track 0
+        mkvmerge
+            .arg("--language")
+            .arg(format!("0:{}", LanguageCodes::TwoLetter.code(sidecar.lang)))
+            .arg("--default-track-flag")
+            .arg(flag(sidecar.is_default()))
+            .arg("--forced-display-flag")
+            .arg(flag(sidecar.is_forced()));
+        if sidecar.is_hearing_impaired() {
+            mkvmerge.arg("--hearing-impaired-flag").arg(flag(true));
+        }
+        let title = sidecar.title();
+        if !title.is_empty() {
+            mkvmerge.arg("--track-name").arg(format!("0:{title}"));
+        }
+        mkvmerge.arg(&sidecar.path);
+    }
+    for font in fonts {
+        mkvmerge.arg("--attach-file").arg(font);
+    }
+    run_creating_despite_warnings(&mut mkvmerge, &remuxed)
+        .context("failed to run mkvmerge")?;
+    let swapped = copy_permissions(video, &remuxed)
+        .and_then(|()| Ok(replace(&remuxed, video)?));
+    if let Err(why) = swapped {
+        let _ = remove_file(&remuxed);
+        return Err(why.context(format!("failed to replace {video}")));
+    }
+    Ok(())
+}
+
+fn flag(on: bool) -> &'static str {
+    match on {
+        true => "0:1",
+        false => "0:0",
+    }
+}
+
+// e.g. Movie.mkv gives .Movie.embedding.mkv
+fn remuxed_path(video: &Utf8Path) -> Utf8PathBuf {
+    let stem = video.file_stem().unwrap_or_default();
+    video.with_file_name(format!(".{stem}.embedding.mkv"))
+}
@@ -0,0 +1,42 @@
+//! Progress reports for frontends, sent while a directory is being processed
+//! rather than only in the [`Report`](crate::report::Report) at the end
+
+use std::sync::mpsc::Sender;
+
+use camino::Utf8PathBuf;
+
+/// Paths other than [`Event::DiscoveryStarted`]'s are relative to the
+/// directory being processed, like the ones in reports
+#[derive(Debug, Clone)]
+pub enum Event {
+    /// Started looking for videos & subtitles in this directory
+    DiscoveryStarted {
+        dir: Utf8PathBuf,
+    },
+    VideoFound {
+        path: Utf8PathBuf,
+    },
+    /// A subtitle was found to be for a video, though it may still be skipped
+    Matched {
+        video: Utf8PathBuf,
+        subtitle: Utf8PathBuf,
+    },
+    LinkCreated {
+        video: Utf8PathBuf,
+        subtitle: Utf8PathBuf,
+        link: Utf8PathBuf,
+    },
+    /// Something went wrong with a file, or with the whole directory
+    Error {
+        path: Utf8PathBuf,
+        message: String,
+    },
+}
+
+/// Sends events if anyone is listening. A receiver that's gone away is fine,
+/// the frontend might have stopped caring
+pub(crate) fn emit(events: &Option<Sender<Event>>, event: Event) {
+    if let Some(events) = events {
+        let _ = events.send(event);
+    }
+}
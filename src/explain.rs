@@ -0,0 +1,288 @@
+use anyhow::{bail, Context};
+use camino::{Utf8Path, Utf8PathBuf};
+use isolang::Language;
+
+use crate::{
+    canonicalize,
+    cli::Options,
+    content::detect_language,
+    discovery::discover_videos,
+    matcher::Chain,
+    predicates,
+    report::{Report, SkipReason},
+    series::SeriesInfo,
+    subtitle::{
+        find_language, language_hint, split_flags, split_part_suffix,
+        LanguageSource, Source, Subtitle, AUDIO_DESCRIPTION,
+    },
+    video::Video,
+};
+
+/// Narrates how a single subtitle would be handled, step by step
+pub fn explain(
+    subtitle: impl AsRef<Utf8Path>,
+    options: &Options,
+) -> anyhow::Result<()> {
+    let subtitle = subtitle.as_ref();
+    if !subtitle.is_file() {
+        bail!("{subtitle} isn't a file");
+    }
+    let subtitle = canonicalize(subtitle)
+        .with_context(|| format!("failed to resolve {subtitle}"))?;
+    println!("explaining {subtitle}");
+
+    println!("\n1. file type");
+    match predicates::has_subtitle_extension(&subtitle) {
+        true => println!(
+            "   {:?} is a subtitle extension",
+            subtitle.extension().unwrap_or_default()
+        ),
+        false => {
+            println!(
+                "   {:?} isn't a subtitle extension, so the file would be \
+                 ignored",
+                subtitle.extension().unwrap_or_default()
+            );
+            return Ok(());
+        },
+    }
+
+    let found = find_root(&subtitle, options);
+    let root = match &found {
+        Some((root, _)) => root.as_path(),
+        None => subtitle.parent().unwrap_or(&subtitle),
+    };
+
+    println!("\n2. language");
+    if explain_language(&subtitle, root, options)?.is_none() {
+        return Ok(());
+    }
+
+    println!("\n3. series info");
+    let parsed =
+        Subtitle::new(subtitle.clone(), root, Source::Other, options.strict);
+    let parsed = match parsed {
+        Err(skip)
+            if skip.reason == SkipReason::UnknownLanguage
+                && options.detect_language =>
+        {
+            match detect_language(&subtitle, options) {
+                Some(lang) => Subtitle::with_language(
+                    subtitle.clone(),
+                    root,
+                    lang,
+                    Source::Other,
+                    options.strict,
+                ),
+                None => Err(skip),
+            }
+        },
+        parsed => parsed,
+    };
+    let mut parsed = match parsed {
+        Ok(parsed) => parsed,
+        Err(skip) => {
+            println!("   skipped ({}): {}", skip.reason.code(), skip.detail);
+            return Ok(());
+        },
+    };
+    if parsed.series_info.is_some() && !options.content_type.has_episodes() {
+        println!("   episode numbers are ignored for this --content-type");
+        parsed.series_info = None;
+    }
+    match parsed.series_info {
+        Some(series_info) => println!("   found {series_info}"),
+        None => println!("   none found, treating it as a movie subtitle"),
+    }
+
+    println!("\n4. videos");
+    let Some((root, videos)) = found else {
+        println!("   no folder above the subtitle contains any videos");
+        return Ok(());
+    };
+    println!("   {} videos found in {root}", videos.len());
+    let relative = subtitle.strip_prefix(&root).expect("root is an ancestor");
+    let mut components = relative.components();
+    let top_level = components.next().expect("subtitle has a file name");
+    if components.next().is_some() && !options.all_folders {
+        let folder = top_level.as_str();
+        match predicates::is_subtitle_folder(
+            folder,
+            &videos,
+            &options.subtitle_folders,
+        ) {
+            true => println!("   {folder:?} is searched for subtitles"),
+            false => {
+                println!(
+                    "   {folder:?} isn't a known subtitle folder, so it \
+                     wouldn't be searched (see --subs-folder or --all-folders)"
+                );
+                return Ok(());
+            },
+        }
+    }
+
+    println!("\n5. matching");
+    let chain = Chain::new(&options.matchers, &videos).needing_evidence(
+        !options.content_type.one_per_folder() && videos.len() > 1,
+    );
+    videos.iter().for_each(|video| {
+        let name = video.path.file_name().unwrap_or_default();
+        match chain.matches(video, &parsed) {
+            true => println!("   matches {name}"),
+            false => println!(
+                "   doesn't match {name} (it's {}, the subtitle is {})",
+                describe(video.series_info),
+                describe(parsed.series_info),
+            ),
+        }
+        for (kind, verdict) in chain.verdicts(video, &parsed) {
+            println!("     {kind}: {verdict:?}");
+        }
+    });
+    Ok(())
+}
+
+// The language the subtitle would be given, going the same way as
+// `Subtitle::new`, or `None` if it would be skipped
+fn explain_language(
+    subtitle: &Utf8Path,
+    root: &Utf8Path,
+    options: &Options,
+) -> anyhow::Result<Option<Language>> {
+    let stem = subtitle.file_stem().context("subtitle has no file name")?;
+    let (without_part, part) = split_part_suffix(stem);
+    if let Some(part) = part {
+        println!("   {stem:?} looks like part {part} of a split subtitle");
+    }
+    let hint = language_hint(without_part);
+    if hint != without_part {
+        println!("   stripped the number prefix from {without_part:?}");
+    }
+    let (without_flags, flags) = split_flags(hint);
+    if flags.forced {
+        println!("   {hint:?} says it's a forced track");
+    }
+    if flags.hearing_impaired {
+        println!("   {hint:?} says it's for the hearing impaired");
+    }
+    if flags.audio_description {
+        println!(
+            "   {hint:?} says it's an audio description, so it's titled \
+             {AUDIO_DESCRIPTION:?}"
+        );
+    }
+    let hint = without_flags.as_str();
+    let relative = subtitle.strip_prefix(root).unwrap_or(subtitle);
+    let name = |lang: Language| {
+        format!(
+            "{} ({})",
+            lang.to_name(),
+            lang.to_639_1().unwrap_or(lang.to_639_3())
+        )
+    };
+    let Some((lang, found)) = find_language(relative, without_part, hint)
+    else {
+        println!(
+            "   neither {hint:?} nor the folders it's in say what language it \
+             is"
+        );
+        if !options.detect_language {
+            println!("   so the subtitle would be skipped");
+            return Ok(None);
+        }
+        let detected = detect_language(subtitle, options);
+        match detected {
+            Some(lang) => println!("   its text reads as {}", name(lang)),
+            None => println!(
+                "   its text doesn't clearly say what language it is, so the \
+                 subtitle would be skipped"
+            ),
+        }
+        return Ok(detected);
+    };
+    match found {
+        LanguageSource::SignsAndSongs => println!(
+            "   {hint:?} is a signs & songs track, so it's forced {}",
+            name(lang)
+        ),
+        LanguageSource::Name => {
+            println!("   {hint:?} is the language {}", name(lang))
+        },
+        LanguageSource::ReleaseTag => {
+            println!("   {without_part:?} is tagged as {}", name(lang))
+        },
+        LanguageSource::Folder(folder) => {
+            println!("   it's in {folder:?}, so it's {}", name(lang))
+        },
+        LanguageSource::Parts => {
+            println!("   {hint:?} ends with the code for {}", name(lang))
+        },
+    }
+    Ok(Some(lang))
+}
+
+fn describe(series_info: Option<SeriesInfo>) -> String {
+    match series_info {
+        Some(series_info) => series_info.to_string(),
+        None => "a movie".to_owned(),
+    }
+}
+
+// The closest folder above the subtitle that has videos in it
+fn find_root(
+    subtitle: &Utf8Path,
+    options: &Options,
+) -> Option<(Utf8PathBuf, Vec<Video>)> {
+    subtitle.ancestors().skip(1).find_map(|dir| {
+        let mut videos = discover_videos(dir, options, &mut Report::default());
+        if !options.content_type.has_episodes() {
+            videos.iter_mut().for_each(|video| video.series_info = None);
+        }
+        #[cfg(feature = "probe")]
+        if options.probe {
+            crate::probe::measure(&mut videos);
+            if !options.include_short {
+                crate::probe::remove_short(
+                    &mut videos,
+                    options.min_video_duration,
+                    &mut Report::default(),
+                );
+            }
+        }
+        (!videos.is_empty()).then(|| (dir.to_owned(), videos))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn language_agrees_with_parsing() {
+        let root = Utf8Path::new("/library/Movie (2019)");
+        let options = Options::default();
+        for name in [
+            "Movie (2019).eng.srt",
+            "Movie (2019).en.srt",
+            "Movie (2019).fre.forced.srt",
+            "x.pt-BR.srt",
+            "Movie.2019.VOSTFR.srt",
+            "English.srt",
+            "3_French (SDH).srt",
+            "Signs & Songs.ass",
+            "Subs/eng/2.srt",
+            "Subs/By Language/French/sub.srt",
+            "Subs/Movie.2019.1080p.WEB.srt",
+            "The.srt",
+        ] {
+            let path = root.join(name);
+            let parsed =
+                Subtitle::new(path.clone(), root, Source::Other, false)
+                    .ok()
+                    .map(|subtitle| subtitle.lang);
+            let explained = explain_language(&path, root, &options).unwrap();
+            assert_eq!(explained, parsed, "{name}");
+        }
+    }
+}
@@ -0,0 +1,151 @@
+//! The fonts styled (ASS/SSA) subtitles are drawn with, which releases ship
+//! in a `fonts` folder of their own. Jellyfin only hands a player the fonts
+//! attached to an MKV (and its fallback fonts), so they're copied next to
+//! the video, for `embed` to attach, and for players that look there
+
+use std::{collections::BTreeSet, fs, io::Read};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use log::{debug, error, info};
+
+use crate::{
+    fsops::{copy_file, create_dir_all},
+    report::dry_run_prefix,
+    srt::MAX_SUBTITLE_SIZE,
+};
+
+/// Where fonts go, next to the videos
+pub const FONTS: &str = "fonts";
+
+/// What releases call the folder their fonts are in, lowercase
+const FOLDERS: &[&str] = &["fonts", "font", "attachments"];
+
+const EXTENSIONS: &[&str] = &["ttf", "otf", "ttc", "otc"];
+
+pub fn is_styled(path: &Utf8Path) -> bool {
+    path.extension()
+        .map(|extension| {
+            extension.eq_ignore_ascii_case("ass")
+                || extension.eq_ignore_ascii_case("ssa")
+        })
+        .unwrap_or_default()
+}
+
+/// The names of the fonts a styled subtitle's styles & `\fn` overrides use
+pub fn used(path: &Utf8Path) -> BTreeSet<String> {
+    let mut bytes = Vec::new();
+    let read = fs::File::open(path)
+        .and_then(|file| file.take(MAX_SUBTITLE_SIZE).read_to_end(&mut bytes));
+    if let Err(why) = read {
+        debug!("couldn't read the fonts {path} uses: {why}");
+        return BTreeSet::new();
+    }
+    let contents = String::from_utf8_lossy(&bytes);
+    let mut fonts = BTreeSet::new();
+    // The font is the second field of a style unless a format line says
+    // otherwise
+    let mut fontname = 1;
+    for line in contents.lines().map(str::trim) {
+        if let Some(format) = line.strip_prefix("Format:") {
+            if let Some(index) = format
+                .split(',')
+                .position(|field| field.trim().eq_ignore_ascii_case("fontname"))
+            {
+                fontname = index;
+            }
+        } else if let Some(style) = line.strip_prefix("Style:") {
+            fonts.extend(style.split(',').nth(fontname).map(font_name));
+        } else if line.starts_with("Dialogue:") {
+            fonts.extend(
+                line.split("\\fn")
+                    .skip(1)
+                    .filter_map(|rest| rest.split(['\\', '}']).next())
+                    .map(font_name),
+            );
+        }
+    }
+    fonts.remove("");
+    fonts
+}
+
+// `@` asks for the vertical version of the same font
+fn font_name(name: &str) -> String {
+    name.trim().trim_start_matches('@').to_owned()
+}
+
+/// Copies the fonts in font folders of the subtitles' folders, or the
+/// folders between them & `dir`, to a `fonts` folder in `dir`. Fonts
+/// already there are left as they are. Returns how many were copied
+pub fn bring(dir: &Utf8Path, subtitles: &[&Utf8Path]) -> usize {
+    let destination = dir.join(FONTS);
+    let mut folders = BTreeSet::new();
+    for subtitle in subtitles {
+        debug!(
+            "{subtitle} uses {}",
+            used(subtitle).into_iter().collect::<Vec<_>>().join(", ")
+        );
+        let above = subtitle
+            .ancestors()
+            .skip(1)
+            .take_while(|folder| folder.starts_with(dir));
+        folders.extend(above.flat_map(font_folders));
+    }
+    // Next to the videos already
+    folders.retain(|folder| folder.parent() != Some(dir));
+    let mut copied = 0;
+    for folder in folders {
+        for font in font_files(&folder) {
+            let Some(name) = font.file_name() else {
+                continue;
+            };
+            let to = destination.join(name);
+            if to.exists() {
+                continue;
+            }
+            info!("{}copying font {font} to {}", dry_run_prefix(), destination);
+            match create_dir_all(&destination)
+                .and_then(|()| copy_file(&font, &to))
+            {
+                Ok(()) => copied += 1,
+                Err(why) => error!("failed to copy {font} to {to}: {why}"),
+            }
+        }
+    }
+    copied
+}
+
+/// The fonts in a folder, for `embed` to attach
+pub fn font_files(folder: &Utf8Path) -> Vec<Utf8PathBuf> {
+    let Ok(entries) = folder.read_dir_utf8() else {
+        return Vec::new();
+    };
+    let mut fonts = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.into_path())
+        .filter(|path| path.is_file() && is_font(path))
+        .collect::<Vec<_>>();
+    fonts.sort();
+    fonts
+}
+
+fn font_folders(folder: &Utf8Path) -> Vec<Utf8PathBuf> {
+    let Ok(entries) = folder.read_dir_utf8() else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(Result::ok)
+        .filter(|entry| {
+            FOLDERS.contains(&entry.file_name().to_lowercase().as_str())
+        })
+        .map(|entry| entry.into_path())
+        .filter(|path| path.is_dir())
+        .collect()
+}
+
+fn is_font(path: &Utf8Path) -> bool {
+    path.extension()
+        .map(|extension| {
+            EXTENSIONS.contains(&extension.to_lowercase().as_str())
+        })
+        .unwrap_or_default()
+}
@@ -0,0 +1,798 @@
+//! Everything that changes the filesystem goes through here, so dry runs,
+//! read-only mode, and the audit log can't be bypassed
+
+use std::{
+    env,
+    fmt::Display,
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::{Component, Path, PathBuf},
+    process::{Command, ExitStatus},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+
+use anyhow::{bail, Context};
+use camino::Utf8Path;
+use log::{debug, info, warn};
+use once_cell::sync::OnceCell;
+
+use crate::{
+    elevation::{self, DeniedLink},
+    run,
+    time::Stamp,
+};
+
+// Tries to actually make a symlink, as Windows needs special privileges to do
+// so
+pub fn can_symlink() -> io::Result<()> {
+    let dir = env::temp_dir();
+    check_writable(&dir)?;
+    let target = dir.join(format!("subfix-{}-target", std::process::id()));
+    let link = dir.join(format!("subfix-{}-link", std::process::id()));
+    std::fs::write(&target, [])?;
+    #[cfg(unix)]
+    let result = std::os::unix::fs::symlink(&target, &link);
+    #[cfg(windows)]
+    let result = std::os::windows::fs::symlink_file(&target, &link);
+    let _ = std::fs::remove_file(&link);
+    let _ = std::fs::remove_file(&target);
+    result
+}
+
+// ERROR_PRIVILEGE_NOT_HELD, which std doesn't have its own kind for
+const PRIVILEGE_NOT_HELD: i32 = 1314;
+
+/// Whether making a symlink failed because the user isn't allowed to, rather
+/// than because of the paths involved
+pub fn needs_privilege(why: &io::Error) -> bool {
+    cfg!(windows) && why.raw_os_error() == Some(PRIVILEGE_NOT_HELD)
+}
+
+/// Makes the links with a batch file run as administrator, which Windows asks
+/// the user to allow first
+pub fn run_elevated(links: &[DeniedLink]) -> anyhow::Result<()> {
+    let script_path =
+        env::temp_dir().join(format!("subfix-{}.cmd", std::process::id()));
+    check_writable(&script_path)?;
+    std::fs::write(&script_path, elevation::script(links))
+        .context("failed to write batch file")?;
+    // Start-Process is the only built-in way to get the UAC prompt
+    let status = Command::new("powershell")
+        .args(["-NoProfile", "-NonInteractive", "-Command"])
+        .arg(format!(
+            "Start-Process -FilePath cmd.exe -ArgumentList '/c', '\"{}\"' \
+             -Verb RunAs -Wait",
+            script_path.display().to_string().replace('\'', "''"),
+        ))
+        .status();
+    let _ = std::fs::remove_file(&script_path);
+    let status = status.context("couldn't run powershell")?;
+    if !status.success() {
+        anyhow::bail!("elevated batch file wasn't run ({status})");
+    }
+    let mut made = 0;
+    for link in links {
+        let path = link.dir.join(&link.link);
+        if path.symlink_metadata().is_ok() {
+            audit("symlink", path.as_std_path(), Some(&link.target));
+            made += 1;
+        }
+    }
+    match made == links.len() {
+        true => Ok(()),
+        false => {
+            anyhow::bail!("only {made} of {} links were made", links.len())
+        },
+    }
+}
+
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+pub fn set_dry_run(dry_run: bool) {
+    DRY_RUN.store(dry_run, Ordering::Relaxed);
+}
+
+/// Whether changes are only being reported, never made
+pub fn is_dry_run() -> bool {
+    DRY_RUN.load(Ordering::Relaxed) || is_read_only()
+}
+
+static READ_ONLY: AtomicBool = AtomicBool::new(false);
+
+/// Makes every change fail from now on. There's deliberately no way to turn
+/// it off again, so nothing (like a server request) can undo it
+pub fn set_read_only() {
+    READ_ONLY.store(true, Ordering::Relaxed);
+}
+
+pub fn is_read_only() -> bool {
+    READ_ONLY.load(Ordering::Relaxed)
+}
+
+static ROOTS: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// Keeps every change from now on inside `roots`, the folders subfix was
+/// given. Until it's called, changes can be made anywhere
+pub fn set_roots(roots: &[impl AsRef<Path>]) {
+    let roots = roots
+        .iter()
+        .filter_map(|root| std::fs::canonicalize(root).ok())
+        .collect();
+    *ROOTS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = roots;
+}
+
+static OUTPUTS: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// Lets the files subfix was told to write, like the checkpoint or the HTML
+/// report, be written wherever they are, along with the lock & temporary
+/// files kept next to them
+pub fn allow_outputs(outputs: &[impl AsRef<Path>]) {
+    let outputs = outputs
+        .iter()
+        .filter_map(|output| resolve(output.as_ref(), true).ok())
+        .collect();
+    *OUTPUTS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = outputs;
+}
+
+fn is_output(resolved: &Path) -> bool {
+    let outputs = OUTPUTS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    outputs.iter().any(|output| {
+        let name = |path: &Path| {
+            path.file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        };
+        resolved == output
+            || resolved.parent() == output.parent()
+                && is_made_for(&name(resolved), &name(output))
+    })
+}
+
+// Whether `name` is one of the files made next to an output called `output`
+// while writing it: its lock (and the lock moved aside when stale), or its
+// temporary file
+fn is_made_for(name: &str, output: &str) -> bool {
+    let pid = |rest: &str| {
+        !rest.is_empty() && rest.bytes().all(|byte| byte.is_ascii_digit())
+    };
+    let lock = format!("{output}.lock");
+    let temporary = || {
+        let rest = name.strip_prefix('.')?.strip_prefix(output)?;
+        rest.strip_prefix('.')?.strip_suffix(".tmp")
+    };
+    name == lock
+        || name
+            .strip_prefix(&lock)
+            .and_then(|rest| rest.strip_prefix(".stale."))
+            .map(pid)
+            .unwrap_or_default()
+        || temporary().map(pid).unwrap_or_default()
+}
+
+/// Refuses to change anything outside the folders given, wherever `..` or a
+/// symlink a subtitle pack came with would otherwise lead. `follow` is for
+/// changes made to what a symlink at `path` points at, rather than the link
+pub fn check_contained(path: impl AsRef<Path>, follow: bool) -> io::Result<()> {
+    let path = path.as_ref();
+    let roots = ROOTS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if roots.is_empty() {
+        return Ok(());
+    }
+    let resolved = resolve(path, follow)?;
+    match roots.iter().any(|root| resolved.starts_with(root))
+        || is_output(&resolved)
+    {
+        true => Ok(()),
+        false => Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "refusing to change {}, it's outside the folders given{}",
+                path.display(),
+                match resolved.as_path() == path {
+                    true => String::new(),
+                    false => format!(" (it's really {})", resolved.display()),
+                },
+            ),
+        )),
+    }
+}
+
+// Where a change to `path` would really happen. Whatever doesn't exist yet
+// can't be a symlink, but can still climb out with `..`
+fn resolve(path: &Path, follow: bool) -> io::Result<PathBuf> {
+    if follow {
+        if let Ok(resolved) = std::fs::canonicalize(path) {
+            return Ok(resolved);
+        }
+    }
+    let mut rest = Vec::new();
+    let mut ancestors = path.ancestors();
+    // The last component itself is never followed
+    if let Some(name) = path.file_name() {
+        rest.push(name);
+        ancestors.next();
+    }
+    for ancestor in ancestors {
+        let existing = match ancestor.as_os_str().is_empty() {
+            true => Path::new("."),
+            false => ancestor,
+        };
+        let Ok(mut resolved) = std::fs::canonicalize(existing) else {
+            match ancestor.components().next_back() {
+                Some(Component::Normal(name)) => rest.push(name),
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::PermissionDenied,
+                        format!(
+                            "refusing to change {}, it goes up through a \
+                             folder that doesn't exist",
+                            path.display()
+                        ),
+                    ))
+                },
+            }
+            continue;
+        };
+        for component in rest.iter().rev() {
+            resolved.push(component);
+        }
+        return Ok(resolved);
+    }
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("couldn't resolve {}", path.display()),
+    ))
+}
+
+/// The last line of defence for `--read-only`: checked right before anything
+/// is changed, so code that forgets about dry runs still can't change things
+pub fn check_writable(path: impl AsRef<Path>) -> io::Result<()> {
+    match is_read_only() {
+        true => Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "refusing to change {} in read-only mode",
+                path.as_ref().display()
+            ),
+        )),
+        false => Ok(()),
+    }
+}
+
+/// Replaces a file's contents without readers ever seeing it half-written.
+/// Unlike other changes, it's made in dry runs too (a report's worth having
+/// then), so it's for callers to check
+pub fn write_atomically(
+    path: impl AsRef<Utf8Path>,
+    contents: impl AsRef<[u8]>,
+) -> io::Result<()> {
+    let path = path.as_ref();
+    check_contained(path, false)?;
+    check_writable(path)?;
+    let temp = path.with_file_name(format!(
+        ".{}.{}.tmp",
+        path.file_name().unwrap_or_default(),
+        std::process::id()
+    ));
+    check_contained(&temp, false)?;
+    std::fs::write(&temp, contents)?;
+    if let Err(why) = std::fs::rename(&temp, path) {
+        let _ = std::fs::remove_file(&temp);
+        return Err(why);
+    }
+    audit("write", path.as_std_path(), None);
+    Ok(())
+}
+
+/// Takes a lock by creating its file, which fails if it's there already,
+/// i.e. someone else has it. Never taken in a dry run, as nothing's changed
+/// then for anything to conflict with
+pub fn create_lock(path: impl AsRef<Path>) -> io::Result<()> {
+    let path = path.as_ref();
+    check_contained(path, false)?;
+    check_writable(path)?;
+    let mut file =
+        OpenOptions::new().write(true).create_new(true).open(path)?;
+    let _ = writeln!(file, "{}", std::process::id());
+    audit("lock", path, None);
+    Ok(())
+}
+
+/// Writes a held lock's file again, so its age is how long since it was last
+/// known to be held, not since it was taken
+pub fn refresh_lock(path: impl AsRef<Path>) -> io::Result<()> {
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    writeln!(file, "{}", std::process::id())
+}
+
+// Nothing is written during a dry run. No mode is given, so the usual umask
+// and any default ACLs of the directory apply to the new file
+pub fn create_file(path: impl AsRef<Path>) -> io::Result<Box<dyn Write>> {
+    check_contained(path.as_ref(), true)?;
+    match is_dry_run() {
+        false => {
+            check_writable(path.as_ref())?;
+            let file = File::create(path.as_ref())?;
+            audit("create", path.as_ref(), None);
+            Ok(Box::new(file))
+        },
+        true => Ok(Box::new(io::sink())),
+    }
+}
+
+// Nothing is created during a dry run
+pub fn create_dir_all(path: impl AsRef<Path>) -> io::Result<()> {
+    check_contained(path.as_ref(), false)?;
+    let path = path.as_ref();
+    if is_dry_run() || path.is_dir() {
+        return Ok(());
+    }
+    check_writable(path)?;
+    std::fs::create_dir_all(path)?;
+    audit("mkdir", path, None);
+    Ok(())
+}
+
+/// Gives a file subfix wrote the same permissions as the file it came from
+pub fn copy_permissions(
+    from: impl AsRef<Utf8Path>,
+    to: impl AsRef<Utf8Path>,
+) -> anyhow::Result<()> {
+    check_contained(to.as_ref(), true)?;
+    let (from, to) = (from.as_ref(), to.as_ref());
+    if is_dry_run() {
+        return Ok(());
+    }
+    check_writable(to)?;
+    let permissions = std::fs::metadata(from)
+        .with_context(|| format!("failed to read permissions of {from}"))?
+        .permissions();
+    std::fs::set_permissions(to, permissions)
+        .with_context(|| format!("failed to set permissions of {to}"))?;
+    audit("chmod", to.as_std_path(), Some(&from));
+    Ok(())
+}
+
+// Nothing is removed during a dry run
+pub fn remove_file(path: impl AsRef<Path>) -> io::Result<()> {
+    check_contained(path.as_ref(), false)?;
+    match is_dry_run() {
+        false => {
+            check_writable(path.as_ref())?;
+            std::fs::remove_file(path.as_ref())?;
+            audit("delete", path.as_ref(), None);
+            Ok(())
+        },
+        true => Ok(()),
+    }
+}
+
+// Nothing is symlinked during a dry run
+#[cfg(unix)]
+pub fn symlink(
+    actual_file: impl AsRef<Path>,
+    link_here: impl AsRef<Path>,
+) -> io::Result<()> {
+    check_contained(link_here.as_ref(), false)?;
+    use std::os::unix::fs;
+    match is_dry_run() {
+        false => {
+            check_writable(link_here.as_ref())?;
+            fs::symlink(actual_file.as_ref(), link_here.as_ref())?;
+            audit(
+                "symlink",
+                link_here.as_ref(),
+                Some(&actual_file.as_ref().display()),
+            );
+            Ok(())
+        },
+        true => Ok(()),
+    }
+}
+
+// Nothing is symlinked during a dry run
+#[cfg(windows)]
+pub fn symlink(
+    actual_file: impl AsRef<Path>,
+    link_here: impl AsRef<Path>,
+) -> io::Result<()> {
+    check_contained(link_here.as_ref(), false)?;
+    use std::os::windows::fs;
+    assert!(std::fs::metadata(actual_file.as_ref())?.is_file());
+    match is_dry_run() {
+        false => {
+            check_writable(link_here.as_ref())?;
+            fs::symlink_file(actual_file.as_ref(), link_here.as_ref())?;
+            audit(
+                "symlink",
+                link_here.as_ref(),
+                Some(&actual_file.as_ref().display()),
+            );
+            Ok(())
+        },
+        true => Ok(()),
+    }
+}
+
+// Nothing is linked during a dry run
+pub fn hard_link(
+    original: impl AsRef<Path>,
+    link: impl AsRef<Path>,
+) -> io::Result<()> {
+    check_contained(link.as_ref(), false)?;
+    if is_dry_run() {
+        return Ok(());
+    }
+    check_writable(link.as_ref())?;
+    std::fs::hard_link(original.as_ref(), link.as_ref())?;
+    audit(
+        "hardlink",
+        link.as_ref(),
+        Some(&original.as_ref().display()),
+    );
+    Ok(())
+}
+
+// Nothing is copied during a dry run
+pub fn copy_file(
+    from: impl AsRef<Path>,
+    to: impl AsRef<Path>,
+) -> io::Result<()> {
+    check_contained(to.as_ref(), false)?;
+    if is_dry_run() {
+        return Ok(());
+    }
+    check_writable(to.as_ref())?;
+    let mut source = File::open(from.as_ref())?;
+    // Never over the top of something, like the other ways of placing
+    // files. Made like any other new file, rather than with the source's
+    // permissions as `fs::copy` would, so the umask applies
+    let mut copy =
+        new_file()
+            .open(to.as_ref())
+            .map_err(|why| match why.kind() {
+                io::ErrorKind::AlreadyExists => io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{} already exists", to.as_ref().display()),
+                ),
+                _ => why,
+            })?;
+    if let Err(why) = io::copy(&mut source, &mut copy) {
+        drop(copy);
+        let _ = std::fs::remove_file(to.as_ref());
+        return Err(why);
+    }
+    audit("copy", to.as_ref(), Some(&from.as_ref().display()));
+    Ok(())
+}
+
+fn new_file() -> OpenOptions {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o666);
+    }
+    options
+}
+
+/// Moves a file, copying it over (permissions and all) and removing the
+/// original when it's going to another filesystem
+pub fn move_file(
+    from: impl AsRef<Path>,
+    to: impl AsRef<Path>,
+) -> io::Result<()> {
+    let (from, to) = (from.as_ref(), to.as_ref());
+    match rename(from, to) {
+        Err(why) if crosses_devices(&why) => {
+            info!(
+                "{} is on another filesystem, copying it over",
+                from.display()
+            );
+            let permissions = std::fs::metadata(from)?.permissions();
+            copy_file(from, to)?;
+            std::fs::set_permissions(to, permissions)?;
+            remove_file(from)
+        },
+        moved => moved,
+    }
+}
+
+// Nothing is moved during a dry run
+pub fn rename(from: impl AsRef<Path>, to: impl AsRef<Path>) -> io::Result<()> {
+    check_contained(from.as_ref(), false)?;
+    check_contained(to.as_ref(), false)?;
+    if is_dry_run() {
+        return Ok(());
+    }
+    check_writable(from.as_ref())?;
+    check_writable(to.as_ref())?;
+    // `rename` replaces whatever's there
+    if to.as_ref().symlink_metadata().is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", to.as_ref().display()),
+        ));
+    }
+    std::fs::rename(from.as_ref(), to.as_ref())?;
+    audit("rename", to.as_ref(), Some(&from.as_ref().display()));
+    Ok(())
+}
+
+// Nothing is replaced during a dry run
+pub fn replace(from: impl AsRef<Path>, to: impl AsRef<Path>) -> io::Result<()> {
+    check_contained(from.as_ref(), false)?;
+    check_contained(to.as_ref(), false)?;
+    if is_dry_run() {
+        return Ok(());
+    }
+    check_writable(from.as_ref())?;
+    check_writable(to.as_ref())?;
+    std::fs::rename(from.as_ref(), to.as_ref())?;
+    audit("replace", to.as_ref(), Some(&from.as_ref().display()));
+    Ok(())
+}
+
+// EXDEV & ERROR_NOT_SAME_DEVICE, as `io::ErrorKind::CrossesDevices` is newer
+// than the MSRV
+#[cfg(unix)]
+const CROSSES_DEVICES: i32 = 18;
+#[cfg(windows)]
+const CROSSES_DEVICES: i32 = 17;
+
+/// Whether a hard link or rename failed because the two paths are on
+/// different filesystems
+pub fn crosses_devices(why: &io::Error) -> bool {
+    why.raw_os_error() == Some(CROSSES_DEVICES)
+}
+
+/// Whether two paths are hard links to the same file. A symlink isn't the
+/// same file as what it points at
+#[cfg(unix)]
+pub fn is_same_file(a: impl AsRef<Path>, b: impl AsRef<Path>) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (a.as_ref().symlink_metadata(), b.as_ref().symlink_metadata()) {
+        (Ok(a), Ok(b)) => (a.dev(), a.ino()) == (b.dev(), b.ino()),
+        _ => false,
+    }
+}
+
+// There's no stable way to tell on Windows, where nothing's marked anyway
+#[cfg(not(unix))]
+pub fn is_same_file(_: impl AsRef<Path>, _: impl AsRef<Path>) -> bool {
+    false
+}
+
+/// Whether two paths are on the same filesystem, so a hard link or move
+/// between them doesn't turn into a copy
+#[cfg(unix)]
+pub fn same_filesystem(a: impl AsRef<Path>, b: impl AsRef<Path>) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (a.as_ref().metadata(), b.as_ref().metadata()) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev(),
+        _ => true,
+    }
+}
+
+#[cfg(not(unix))]
+pub fn same_filesystem(_: impl AsRef<Path>, _: impl AsRef<Path>) -> bool {
+    true
+}
+
+/// Fails if the filesystem `dir` is on hasn't room for `bytes` more, or
+/// `files` more files (each link takes one), so a run stops before starting
+/// rather than part way through, leaving half of what it meant to do.
+/// Filesystems that can't say, or don't have a fixed number of files (like
+/// btrfs), are taken to have room
+pub fn check_space(
+    dir: &Utf8Path,
+    bytes: u64,
+    files: u64,
+) -> anyhow::Result<()> {
+    let (free_bytes, free_files) = match free_space(dir) {
+        Ok(free) => free,
+        Err(why) => {
+            debug!("couldn't tell how much space there is in {dir}: {why}");
+            return Ok(());
+        },
+    };
+    debug!(
+        "{dir} needs {bytes} bytes and {files} files, there's {free_bytes} \
+         bytes and {free_files:?} files free"
+    );
+    if bytes > free_bytes {
+        bail!(
+            "not enough space in {dir}: this needs about {}, but only {} is \
+             free",
+            human_size(bytes),
+            human_size(free_bytes)
+        );
+    }
+    if let Some(free_files) = free_files {
+        if files > free_files {
+            bail!(
+                "the filesystem {dir} is on has run out of inodes: this makes \
+                 {files} files, but there's only room for {free_files} more"
+            );
+        }
+    }
+    Ok(())
+}
+
+// Bytes available to an unprivileged user, and files if there's a limit
+#[cfg(unix)]
+fn free_space(dir: &Utf8Path) -> io::Result<(u64, Option<u64>)> {
+    let path = std::ffi::CString::new(dir.as_str())
+        .map_err(|why| io::Error::new(io::ErrorKind::InvalidInput, why))?;
+    let mut stats = std::mem::MaybeUninit::<libc::statvfs>::zeroed();
+    // SAFETY: the path is NUL-terminated and outlives the call, and `stats`
+    // is big enough for what's written to it
+    if unsafe { libc::statvfs(path.as_ptr(), stats.as_mut_ptr()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: filled in by the successful call
+    let stats = unsafe { stats.assume_init() };
+    #[allow(clippy::useless_conversion)]
+    let bytes = u64::from(stats.f_bavail) * u64::from(stats.f_frsize);
+    #[allow(clippy::useless_conversion)]
+    let files = (stats.f_files != 0).then(|| u64::from(stats.f_favail));
+    Ok((bytes, files))
+}
+
+#[cfg(not(unix))]
+fn free_space(_: &Utf8Path) -> io::Result<(u64, Option<u64>)> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "free space isn't checked on this platform",
+    ))
+}
+
+fn human_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["bytes", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{bytes} bytes"),
+        _ => format!("{size:.1} {}", UNITS[unit]),
+    }
+}
+
+/// Runs a program that writes `output`, removing anything it left behind if
+/// it fails
+pub fn run_creating(
+    command: &mut Command,
+    output: impl AsRef<Path>,
+) -> io::Result<()> {
+    run_creating_if(command, output.as_ref(), ExitStatus::success)
+}
+
+/// Like [`run_creating`], for programs like `mkvmerge` that exit with 1 when
+/// they've only warned about something
+pub fn run_creating_despite_warnings(
+    command: &mut Command,
+    output: impl AsRef<Path>,
+) -> io::Result<()> {
+    run_creating_if(command, output.as_ref(), |status| {
+        matches!(status.code(), Some(0 | 1))
+    })
+}
+
+fn run_creating_if(
+    command: &mut Command,
+    output: &Path,
+    succeeded: impl Fn(&ExitStatus) -> bool,
+) -> io::Result<()> {
+    check_contained(output, true)?;
+    if is_dry_run() {
+        return Ok(());
+    }
+    check_writable(output)?;
+    let status = command.status()?;
+    if !succeeded(&status) {
+        let _ = std::fs::remove_file(output);
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("{:?} exited with {status}", command.get_program()),
+        ));
+    }
+    audit("create", output, None);
+    Ok(())
+}
+
+static AUDIT_LOG: OnceCell<Mutex<File>> = OnceCell::new();
+
+/// Appends a record of every change made to `path`
+pub fn open_audit_log(path: impl AsRef<Utf8Path>) -> anyhow::Result<()> {
+    let path = path.as_ref();
+    check_writable(path)?;
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("failed to open audit log {path}"))?;
+    AUDIT_LOG
+        .set(Mutex::new(file))
+        .map_err(|_| anyhow::anyhow!("audit log is already open"))
+}
+
+/// Records that a subtitle's language was given by hand rather than detected,
+/// as that decides what it's linked as
+pub fn audit_language_override(path: impl AsRef<Path>, tag: &str) {
+    if !is_dry_run() {
+        audit("set-lang", path.as_ref(), Some(&tag));
+    }
+}
+
+fn audit(action: &str, path: &Path, other: Option<&dyn Display>) {
+    let Some(log) = AUDIT_LOG.get() else {
+        return;
+    };
+    // Relative paths are no use once the run is over
+    let absolute = |path: &Path| match env::current_dir() {
+        Ok(cwd) if path.is_relative() => {
+            cwd.join(path.strip_prefix(".").unwrap_or(path))
+        },
+        _ => path.to_owned(),
+    };
+    let stamp = Stamp::now();
+    let mut line = format!(
+        "{}\tseq={}\trun={}\tpid={}\t{action}\t{}",
+        stamp.at,
+        stamp.seq,
+        run::id(),
+        std::process::id(),
+        absolute(path).display(),
+    );
+    if let Some(other) = other {
+        line.push('\t');
+        line.push_str(&other.to_string());
+    }
+    line.push('\n');
+    // One write per line so concurrent runs don't interleave records
+    let mut file = log.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Err(why) = file.write_all(line.as_bytes()) {
+        warn!("failed to write to audit log: {why}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn made_for_outputs() {
+        for name in [
+            "Movie.srt.lock",
+            "Movie.srt.lock.stale.123",
+            ".Movie.srt.123.tmp",
+        ] {
+            assert!(is_made_for(name, "Movie.srt"), "{name:?}");
+        }
+        for name in [
+            "Movie.srt.bak",
+            "Other Movie.srt",
+            "Movie.srt.lockout",
+            "Movie.srt.lock.stale.",
+            ".Movie.srt.tmp",
+            ".Movie.srt.1.2.tmp",
+        ] {
+            assert!(!is_made_for(name, "Movie.srt"), "{name:?}");
+        }
+    }
+}
@@ -0,0 +1,84 @@
+//! Gzipped subtitles, which some archives ship and Jellyfin can't read
+
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    process::Command,
+    time::Instant,
+};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use log::info;
+
+use crate::{
+    fsops::{check_space, run_creating},
+    predicates,
+    report::{dry_run_prefix, Report, Skip, SkipReason},
+    subtitle::Subtitle,
+};
+
+/// The subtitle in a gzipped one, like `English.srt` for `English.srt.gz`
+pub fn inner_subtitle(path: &Utf8Path) -> Option<Utf8PathBuf> {
+    let ext = path.extension()?;
+    if !ext.eq_ignore_ascii_case("gz") {
+        return None;
+    }
+    let inner = path.with_extension("");
+    predicates::has_subtitle_extension(&inner).then_some(inner)
+}
+
+/// Decompresses the gzipped subtitles that were found and are still wanted,
+/// before anything needs what's in them. The ones that can't be are skipped.
+/// In a dry run they're left as they are, and planned for as though they
+/// had been
+pub fn decompress_found(subtitles: &mut Vec<Subtitle>, report: &mut Report) {
+    subtitles.retain(|subtitle| {
+        let Some(path) = report.compressed.get(&subtitle.path).cloned() else {
+            return true;
+        };
+        match decompress(&path, &subtitle.path, report) {
+            Ok(()) => true,
+            Err(why) => {
+                report.compressed.remove(&subtitle.path);
+                report.warn_skip(
+                    format!("couldn't decompress {path}, skipping: {why}"),
+                    Skip::new(
+                        path,
+                        SkipReason::DecompressFailed,
+                        why.to_string(),
+                    ),
+                );
+                false
+            },
+        }
+    });
+}
+
+/// Decompresses next to the original with `gzip`, keeping it, so the next
+/// run finds the plain file and leaves the compressed one be
+fn decompress(
+    path: &Utf8Path,
+    inner: &Utf8Path,
+    report: &mut Report,
+) -> anyhow::Result<()> {
+    if let (Some(dir), Some(size)) = (inner.parent(), decompressed_size(path)) {
+        check_space(dir, size, 1)?;
+    }
+    info!("{}decompressing {path} to {inner}", dry_run_prefix());
+    let mut gzip = Command::new("gzip");
+    gzip.args(["--decompress", "--keep", "--"]).arg(path);
+    let start = Instant::now();
+    let decompressed = run_creating(&mut gzip, inner);
+    report.stats.execute += start.elapsed();
+    Ok(decompressed?)
+}
+
+/// What gzip says the file was before it was compressed, from the end of it.
+/// It's only kept to 4 GiB, but no subtitle is that big
+fn decompressed_size(path: &Utf8Path) -> Option<u64> {
+    let mut file = File::open(path).ok()?;
+    file.seek(SeekFrom::End(-4)).ok()?;
+    let mut size = [0; 4];
+    file.read_exact(&mut size).ok()?;
+    Some(u32::from_le_bytes(size).into())
+}
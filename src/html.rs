@@ -0,0 +1,207 @@
+//! A report of a run as one HTML page, with nothing to fetch, so it can be
+//! opened from anywhere or attached to a ticket. Each folder gets tables
+//! that sort by a column when its heading is clicked, and skips that need a
+//! person to choose between subtitles are highlighted
+
+use std::fmt::Write;
+
+use camino::Utf8PathBuf;
+
+use crate::{fsops::is_dry_run, report::Report, run, time::Stamp};
+
+const STYLE: &str = "\
+body { font-family: sans-serif; margin: 2em; }
+table { border-collapse: collapse; margin-bottom: 1em; }
+th, td { border: 1px solid #ccc; padding: 0.2em 0.5em; text-align: left; }
+th { background: #eee; cursor: pointer; }
+code { word-break: break-all; }
+.error { color: #b00; }
+.conflict { background: #fdd; }
+.review { background: #ffd; }";
+
+const SCRIPT: &str = include_str!("sort.js");
+
+pub fn render(results: &[(Utf8PathBuf, anyhow::Result<Report>)]) -> String {
+    let mut html = String::new();
+    let title = match is_dry_run() {
+        true => "subfix report (dry run)",
+        false => "subfix report",
+    };
+    let _ = write!(
+        html,
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta \
+         charset=\"utf-8\">\n<title>{title}</title>\n<style>\n{STYLE}\n</\
+         style>\n</head>\n<body>\n<h1>{title}</h1>\n<p>Run <code>{}</code>, \
+         written {}</p>\n",
+        escape(&run::id()),
+        escape(&Stamp::now().at),
+    );
+    summary(&mut html, results);
+    for (path, result) in results {
+        let _ =
+            writeln!(html, "<h2><code>{}</code></h2>", escape(path.as_str()));
+        match result {
+            Ok(report) => directory(&mut html, report),
+            Err(why) => {
+                let _ = writeln!(
+                    html,
+                    "<p class=\"error\">Failed: {}</p>",
+                    escape(&format!("{why:#}"))
+                );
+            },
+        }
+    }
+    let _ = write!(html, "<script>\n{SCRIPT}\n</script>\n</body>\n</html>\n");
+    html
+}
+
+fn summary(
+    html: &mut String,
+    results: &[(Utf8PathBuf, anyhow::Result<Report>)],
+) {
+    let reports = results
+        .iter()
+        .filter_map(|(_, result)| result.as_ref().ok())
+        .collect::<Vec<_>>();
+    let count = |of: fn(&Report) -> usize| {
+        reports.iter().map(|report| of(report)).sum::<usize>()
+    };
+    let conflicts = count(|report| {
+        report
+            .skipped
+            .iter()
+            .filter(|skip| skip.reason.is_conflict())
+            .count()
+    });
+    let _ = writeln!(
+        html,
+        "<p>{} folders ({} failed), {} links {}, {} skipped ({} to decide \
+         about), {} worth checking</p>",
+        results.len(),
+        results.len() - reports.len(),
+        count(|report| report.links.len()),
+        match is_dry_run() {
+            true => "would be made",
+            false => "made",
+        },
+        count(|report| report.skipped.len()),
+        conflicts,
+        count(|report| report.review.len()),
+    );
+}
+
+fn directory(html: &mut String, report: &Report) {
+    if report.interrupted {
+        html.push_str("<p class=\"error\">Interrupted part way through</p>\n");
+    }
+    if !report.review.is_empty() {
+        html.push_str("<ul>\n");
+        for why in &report.review {
+            let _ = writeln!(
+                html,
+                "<li class=\"review\">Worth checking: {}</li>",
+                escape(why)
+            );
+        }
+        html.push_str("</ul>\n");
+    }
+    table(
+        html,
+        "Links",
+        &["Video", "Subtitle", "Link", "Language"],
+        report.links.iter().map(|link| {
+            ("", vec![
+                link.video.to_string(),
+                link.subtitle.to_string(),
+                link.link.to_string(),
+                link.lang.to_name().to_owned(),
+            ])
+        }),
+    );
+    table(
+        html,
+        "Extracted",
+        &["Video", "Track", "Subtitle", "Language"],
+        report.extracted.iter().map(|extraction| {
+            ("", vec![
+                extraction.video.to_string(),
+                extraction.track.to_string(),
+                extraction.path.to_string(),
+                extraction.lang.to_name().to_owned(),
+            ])
+        }),
+    );
+    table(
+        html,
+        "Removed",
+        &["Link"],
+        report
+            .removed
+            .iter()
+            .map(|path| ("", vec![path.to_string()])),
+    );
+    table(
+        html,
+        "Skipped",
+        &["Path", "Reason", "Detail"],
+        report.skipped.iter().map(|skip| {
+            (
+                match skip.reason.is_conflict() {
+                    true => "conflict",
+                    false => "",
+                },
+                vec![
+                    skip.path.to_string(),
+                    skip.reason.describe().to_owned(),
+                    skip.detail.clone(),
+                ],
+            )
+        }),
+    );
+}
+
+// Left out if there are no rows
+fn table<'a>(
+    html: &mut String,
+    caption: &str,
+    headings: &[&str],
+    rows: impl Iterator<Item = (&'a str, Vec<String>)>,
+) {
+    let mut rows = rows.peekable();
+    if rows.peek().is_none() {
+        return;
+    }
+    let _ = write!(html, "<table>\n<caption>{caption}</caption>\n<thead><tr>");
+    for heading in headings {
+        let _ = write!(html, "<th>{heading}</th>");
+    }
+    html.push_str("</tr></thead>\n<tbody>\n");
+    for (class, cells) in rows {
+        match class.is_empty() {
+            true => html.push_str("<tr>"),
+            false => {
+                let _ = write!(html, "<tr class=\"{class}\">");
+            },
+        }
+        for cell in cells {
+            let _ = write!(html, "<td>{}</td>", escape(&cell));
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</tbody>\n</table>\n");
+}
+
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
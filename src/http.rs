@@ -0,0 +1,246 @@
+//! The bare minimum of HTTP/1.1 needed to serve a local API, and to ask a
+//! local Jellyfin server things, one request per connection
+
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    time::Duration,
+};
+
+use anyhow::{bail, Context};
+
+/// Anything bigger isn't a request subfix would ever be sent
+#[cfg(feature = "serve")]
+const MAX_BODY: usize = 1024 * 1024;
+const MAX_HEADER_LINE: usize = 8 * 1024;
+/// How long to wait on the other end of a connection before giving up on it
+pub const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[cfg(feature = "serve")]
+#[derive(Debug)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    /// Names are lowercase
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+#[cfg(feature = "serve")]
+impl Request {
+    pub fn read(stream: &TcpStream) -> anyhow::Result<Self> {
+        let mut reader = BufReader::new(stream);
+        let request_line = read_line(&mut reader)?;
+        let mut parts = request_line.split_whitespace();
+        let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+            bail!("malformed request line {request_line:?}");
+        };
+        let mut content_length = 0;
+        let mut headers = Vec::new();
+        loop {
+            let header = read_line(&mut reader)?;
+            if header.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                let name = name.trim().to_ascii_lowercase();
+                if name == "content-length" {
+                    content_length = value
+                        .trim()
+                        .parse()
+                        .context("invalid Content-Length")?;
+                }
+                headers.push((name, value.trim().to_owned()));
+            }
+        }
+        if content_length > MAX_BODY {
+            bail!("request body is too big");
+        }
+        let mut body = vec![0; content_length];
+        reader
+            .read_exact(&mut body)
+            .context("failed to read body")?;
+        Ok(Request {
+            method: method.to_owned(),
+            // Query strings aren't used for anything
+            path: path.split('?').next().unwrap_or_default().to_owned(),
+            headers,
+            body: String::from_utf8(body).context("body isn't UTF-8")?,
+        })
+    }
+
+    /// The value of the first header called `name`, given in lowercase
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+fn read_line(reader: &mut impl BufRead) -> anyhow::Result<String> {
+    let mut line = Vec::new();
+    reader
+        .take(MAX_HEADER_LINE as u64)
+        .read_until(b'\n', &mut line)
+        .context("failed to read request")?;
+    if !line.ends_with(b"\n") {
+        bail!("request line or header is too long or cut off");
+    }
+    let line = String::from_utf8(line).context("header isn't UTF-8")?;
+    Ok(line.trim_end_matches(['\r', '\n']).to_owned())
+}
+
+#[cfg(feature = "serve")]
+#[derive(Debug)]
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+#[cfg(feature = "serve")]
+impl Response {
+    pub fn json(status: u16, body: impl ToString) -> Self {
+        Response {
+            status,
+            content_type: "application/json",
+            body: body.to_string(),
+        }
+    }
+
+    pub fn write(&self, mut stream: &TcpStream) -> std::io::Result<()> {
+        write!(
+            stream,
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: \
+             {}\r\nConnection: close\r\n\r\n{}",
+            self.status,
+            reason(self.status),
+            self.content_type,
+            self.body.len(),
+            self.body
+        )?;
+        stream.flush()
+    }
+}
+
+#[cfg(feature = "serve")]
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        202 => "Accepted",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        415 => "Unsupported Media Type",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    }
+}
+
+/// Jellyfin's answers for big libraries are big, but not this big
+#[cfg(feature = "jellyfin")]
+const MAX_RESPONSE: u64 = 64 * 1024 * 1024;
+
+/// Fetches `url`, which has to be plain `http://` as there's no TLS here
+#[cfg(feature = "jellyfin")]
+pub fn get(url: &str, headers: &[(&str, &str)]) -> anyhow::Result<String> {
+    let Some(rest) = url.strip_prefix("http://") else {
+        bail!("only http:// URLs are supported, not {url:?}");
+    };
+    let (host, path) = match rest.find('/') {
+        Some(slash) => rest.split_at(slash),
+        None => (rest, "/"),
+    };
+    let address = match host.contains(':') {
+        true => host.to_owned(),
+        false => format!("{host}:80"),
+    };
+    let mut stream = TcpStream::connect(&address)
+        .with_context(|| format!("couldn't connect to {address}"))?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    write!(stream, "GET {path} HTTP/1.1\r\nHost: {host}\r\n")?;
+    for (name, value) in headers {
+        write!(stream, "{name}: {value}\r\n")?;
+    }
+    write!(stream, "Connection: close\r\n\r\n")?;
+    stream.flush()?;
+    let mut reader = BufReader::new(stream);
+    let status_line = read_line(&mut reader)?;
+    let status = status_line
+        .split_whitespace()
+        .nth(1)
+        .context("malformed status line")?;
+    let mut chunked = false;
+    loop {
+        let header = read_line(&mut reader)?;
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("transfer-encoding") {
+                chunked = value.trim().eq_ignore_ascii_case("chunked");
+            }
+        }
+    }
+    // The connection's closed afterwards, so the end of the body is the end
+    // of the stream (bar chunk framing)
+    let mut body = Vec::new();
+    reader
+        .take(MAX_RESPONSE)
+        .read_to_end(&mut body)
+        .context("failed to read response")?;
+    if chunked {
+        body = dechunk(&body)?;
+    }
+    let body = String::from_utf8(body).context("response isn't UTF-8")?;
+    if status != "200" {
+        bail!("{url} answered {status_line:?}");
+    }
+    Ok(body)
+}
+
+#[cfg(feature = "jellyfin")]
+fn dechunk(mut body: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(body.len());
+    loop {
+        let line_end = body
+            .windows(2)
+            .position(|pair| pair == b"\r\n")
+            .context("chunked response is cut off")?;
+        let size = std::str::from_utf8(&body[..line_end])?;
+        // Chunk extensions come after a ';'
+        let size = size.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size, 16)
+            .with_context(|| format!("invalid chunk size {size:?}"))?;
+        body = &body[line_end + 2..];
+        if size == 0 {
+            return Ok(out);
+        }
+        if body.len() < size {
+            bail!("chunked response is cut off");
+        }
+        out.extend_from_slice(&body[..size]);
+        body = body[size..].strip_prefix(b"\r\n").unwrap_or(&body[size..]);
+    }
+}
+
+/// Escapes everything but unreserved characters, for query strings
+#[cfg(feature = "jellyfin")]
+pub fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z'
+            | b'a'..=b'z'
+            | b'0'..=b'9'
+            | b'-'
+            | b'.'
+            | b'_'
+            | b'~' => (byte as char).to_string(),
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
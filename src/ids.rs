@@ -0,0 +1,98 @@
+//! IMDb & TMDB ids, from video names like `Movie (2010) [imdbid-tt1234567]`,
+//! the `.nfo`s media managers leave behind, and subtitle folders named after
+//! them like `Subs/tt1234567/`
+
+use std::fs;
+
+use camino::Utf8Path;
+use log::debug;
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use crate::{stats, video::Video};
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ExternalId {
+    Imdb(String),
+    Tmdb(String),
+}
+
+// Bare, or in Jellyfin's `[imdbid-tt...]` & `{imdb-tt...}` tags
+static IMDB_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\b(tt\d{7,9})\b").unwrap());
+
+// TMDB ids are plain numbers, so only trusted in a tag
+static TMDB_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?i)[\[{]tmdb(?:id)?[-=](\d+)[\]}]").unwrap());
+
+static NFO_IMDB_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r#"(?i)<(?:imdbid|imdb_id|id)>\s*(tt\d+)\s*<|<uniqueid[^>]*type="imdb"[^>]*>\s*(tt\d+)\s*<"#,
+    )
+    .unwrap()
+});
+
+static NFO_TMDB_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r#"(?i)<tmdbid>\s*(\d+)\s*<|<uniqueid[^>]*type="tmdb"[^>]*>\s*(\d+)\s*<"#,
+    )
+    .unwrap()
+});
+
+/// The ids mentioned in a file or folder name
+pub fn in_name(name: &str) -> Vec<ExternalId> {
+    let imdb = IMDB_REGEX
+        .captures_iter(name)
+        .map(|captures| ExternalId::Imdb(captures[1].to_owned()));
+    let tmdb = TMDB_REGEX
+        .captures_iter(name)
+        .map(|captures| ExternalId::Tmdb(captures[1].to_owned()));
+    imdb.chain(tmdb).collect()
+}
+
+/// The ids in a video's name, or its `.nfo` (`Movie.nfo` or `movie.nfo`)
+pub fn of_video(video: &Video) -> Vec<ExternalId> {
+    let mut ids = in_name(video.path.file_stem().unwrap_or_default());
+    let nfos = [
+        Some(video.path.with_extension("nfo")),
+        video.path.parent().map(|dir| dir.join("movie.nfo")),
+    ];
+    if let Some(contents) = nfos
+        .into_iter()
+        .flatten()
+        .find_map(|nfo| fs::read_to_string(nfo).ok())
+    {
+        stats::add_bytes_read(contents.len());
+        ids.extend(in_nfo(&contents));
+    }
+    if !ids.is_empty() {
+        debug!("{} has ids {ids:?}", video.path);
+    }
+    ids
+}
+
+fn in_nfo(contents: &str) -> Vec<ExternalId> {
+    let found = |regex: &Regex| {
+        regex
+            .captures_iter(contents)
+            .filter_map(|captures| {
+                Some(captures.get(1).or_else(|| captures.get(2))?.as_str())
+            })
+            .map(str::to_owned)
+            .collect::<Vec<_>>()
+    };
+    let imdb = found(&NFO_IMDB_REGEX).into_iter().map(ExternalId::Imdb);
+    let tmdb = found(&NFO_TMDB_REGEX).into_iter().map(ExternalId::Tmdb);
+    imdb.chain(tmdb).collect()
+}
+
+/// The ids in the folders (and name) of a subtitle below `dir`
+pub fn in_path_below(path: &Utf8Path, dir: &Utf8Path) -> Vec<ExternalId> {
+    let Ok(below) = path.strip_prefix(dir) else {
+        return Vec::new();
+    };
+    below
+        .components()
+        .flat_map(|component| in_name(component.as_str()))
+        .collect()
+}
@@ -0,0 +1,239 @@
+//! Exclusions from the ignore files of sync tools, so what's deliberately
+//! left out of a Syncthing (`.stignore`), Resilio (`.syncignore`) or rsync
+//! (`.rsync-filter`) setup is left out here too.
+//!
+//! Only the common ground of their formats is understood: globs with `*`,
+//! `**`, `?` & `[...]`, a leading `/` to anchor to the ignore file's folder,
+//! and ways of saying a match is kept rather than excluded. Like all three,
+//! the first pattern to match decides
+
+use std::fs;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use log::{debug, warn};
+use regex::{Regex, RegexBuilder};
+
+const IGNORE_FILES: &[&str] = &[".stignore", ".syncignore", ".rsync-filter"];
+
+#[derive(Clone)]
+struct Rule {
+    regex: Regex,
+    exclude: bool,
+}
+
+/// The ignore files in a folder and those above it, nearest first
+#[derive(Clone, Default)]
+pub struct IgnoreFiles {
+    files: Vec<(Utf8PathBuf, Vec<Rule>)>,
+}
+
+impl IgnoreFiles {
+    pub fn above(dir: &Utf8Path) -> Self {
+        let files = dir.ancestors().flat_map(read_in).collect();
+        IgnoreFiles { files }
+    }
+
+    /// These, with any ignore files in `dir` (a folder below them), for
+    /// walking down a tree without reading every ancestor again
+    pub fn descend(&self, dir: &Utf8Path) -> Self {
+        let mut files = read_in(dir);
+        files.extend(self.files.iter().cloned());
+        IgnoreFiles { files }
+    }
+
+    /// The ignore files found, nearest first
+    pub fn files(&self) -> impl Iterator<Item = &Utf8Path> {
+        self.files.iter().map(|(file, _)| file.as_path())
+    }
+
+    /// The ignore file that excludes `path`, if any does
+    pub fn excluded_by(&self, path: &Utf8Path) -> Option<&Utf8Path> {
+        for (file, rules) in &self.files {
+            let Some(relative) =
+                file.parent().and_then(|dir| path.strip_prefix(dir).ok())
+            else {
+                continue;
+            };
+            if relative.as_str().is_empty() {
+                continue;
+            }
+            let rule = rules
+                .iter()
+                .find(|rule| rule.regex.is_match(relative.as_str()));
+            if let Some(rule) = rule {
+                return rule.exclude.then_some(file.as_path());
+            }
+        }
+        None
+    }
+}
+
+fn read_in(dir: &Utf8Path) -> Vec<(Utf8PathBuf, Vec<Rule>)> {
+    IGNORE_FILES
+        .iter()
+        .map(|name| dir.join(name))
+        .filter_map(|file| {
+            let contents = fs::read_to_string(&file).ok()?;
+            debug!("following ignore file {file}");
+            let rules = parse(&file, &contents);
+            Some((file, rules))
+        })
+        .collect()
+}
+
+fn parse(file: &Utf8Path, contents: &str) -> Vec<Rule> {
+    let rsync = file.file_name() == Some(".rsync-filter");
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| {
+            !(line.is_empty()
+                || line.starts_with("//")
+                || (line.starts_with('#') && !line.starts_with("#include"))
+                || (rsync && line.starts_with(';')))
+        })
+        .filter_map(|line| {
+            let (pattern, exclude) = match rsync {
+                true => match line.split_once(' ') {
+                    Some(("-" | "exclude", pattern)) => (pattern, true),
+                    Some(("+" | "include", pattern)) => (pattern, false),
+                    _ => {
+                        warn!("{file}: not following {line:?}");
+                        return None;
+                    },
+                },
+                false if line.starts_with("#include") => {
+                    warn!("{file}: includes aren't followed, {line:?}");
+                    return None;
+                },
+                false => match line.strip_prefix('!') {
+                    Some(pattern) => (pattern, false),
+                    None => (line, true),
+                },
+            };
+            match to_regex(pattern.trim()) {
+                Some(regex) => Some(Rule { regex, exclude }),
+                None => {
+                    warn!("{file}: couldn't understand {line:?}");
+                    None
+                },
+            }
+        })
+        .collect()
+}
+
+/// Matches the path (relative to the ignore file) of anything the glob names,
+/// or anything inside it
+fn to_regex(pattern: &str) -> Option<Regex> {
+    let mut pattern = pattern;
+    let mut case_insensitive = false;
+    // Syncthing's prefixes, for matching without case and for files that
+    // are fine to delete
+    loop {
+        if let Some(rest) = pattern.strip_prefix("(?i)") {
+            case_insensitive = true;
+            pattern = rest;
+        } else if let Some(rest) = pattern.strip_prefix("(?d)") {
+            pattern = rest;
+        } else {
+            break;
+        }
+    }
+    // Only directories, in rsync's syntax, but the difference rarely matters
+    let pattern = pattern.trim_end_matches('/');
+    if pattern.is_empty() {
+        return None;
+    }
+    let (anchored, pattern) = match pattern.strip_prefix('/') {
+        Some(pattern) => (true, pattern),
+        None => (false, pattern),
+    };
+    let mut regex = String::from(match anchored {
+        true => "^",
+        false => "(^|/)",
+    });
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                regex.push_str(".*");
+            },
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '[' => {
+                regex.push('[');
+                if chars.next_if(|&c| c == '!' || c == '^').is_some() {
+                    regex.push('^');
+                }
+                for c in chars.by_ref() {
+                    match c {
+                        ']' => break,
+                        // Ranges are the only syntax kept in a class
+                        '-' => regex.push('-'),
+                        c => regex.push_str(&regex::escape(&c.to_string())),
+                    }
+                }
+                regex.push(']');
+            },
+            '\\' => {
+                let escaped = chars.next()?;
+                regex.push_str(&regex::escape(&escaped.to_string()));
+            },
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push_str("(/.*)?$");
+    RegexBuilder::new(&regex)
+        .case_insensitive(case_insensitive)
+        // Names can have newlines in, and `**` still matches across them
+        .dot_matches_new_line(true)
+        .build()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hostile_names() {
+        for (pattern, path, matches) in [
+            ("C++ (2020).*", "C++ (2020).en.srt", true),
+            ("C++ (2020).*", "CC (2020).en.srt", false),
+            ("(1)_*", "Subs/(1)_English.srt", true),
+            ("(1)_*", "Subs/1_English.srt", false),
+            ("Heat \\[1995\\]*", "Heat [1995].en.srt", true),
+            ("Heat \\[1995\\]*", "Heat 1.en.srt", false),
+            ("Heat [1995]*", "Heat 9.en.srt", true),
+            ("Heat [1995]*", "Heat [1995].en.srt", false),
+            ("[a&&b]*", "&.srt", true),
+            ("[a-c]*", "b.srt", true),
+            ("[a-c]*", "-.srt", false),
+            ("[!a-c]*", "-.srt", true),
+            ("[~+]*", "+.srt", true),
+            ("🎬*", "Extras/🎬 Behind the scenes.srt", true),
+            ("/Amélie*", "Amélie 🎬.mkv", true),
+            ("/Amélie*", "Extras/Amélie 🎬.mkv", false),
+            (
+                "ترجمة \u{202e}txt",
+                "Subs/ترجمة \u{202e}txt/2_Arabic.srt",
+                true,
+            ),
+            ("ترجمة txt", "Subs/ترجمة \u{202e}txt/2_Arabic.srt", false),
+            ("Extras", "Extras/Line\nBreak.srt", true),
+            ("*.srt", "Line\nBreak.srt", true),
+            ("**.srt", "Subs/Line\nBreak/English.srt", true),
+            ("Line?Break*", "Line\nBreak.srt", true),
+            ("$HOME .+ ^{1}*", "$HOME .+ ^{1}.srt", true),
+            ("$HOME .+ ^{1}*", "$HOME xx ^1.srt", false),
+        ] {
+            let regex = to_regex(pattern).unwrap();
+            assert_eq!(
+                regex.is_match(path),
+                matches,
+                "{pattern:?} against {path:?} ({regex})"
+            );
+        }
+    }
+}
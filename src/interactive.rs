@@ -0,0 +1,83 @@
+//! Asking whoever's running subfix to settle what it can't work out itself
+
+use std::io::{self, BufRead, Write};
+
+use anyhow::{bail, Context};
+
+use crate::{predicates, series::SeriesInfo, video::Video};
+
+/// Shows whether each video was taken to be an episode or a movie, and lets
+/// the user correct them until they're all one or the other
+pub fn reclassify(videos: &mut [Video]) -> anyhow::Result<()> {
+    // Prompts go to stderr so they don't end up in `--json` output
+    eprintln!("these videos look like a mix of episodes and movies:");
+    loop {
+        for (index, video) in videos.iter().enumerate() {
+            eprintln!(
+                "  {}. {} ({})",
+                index + 1,
+                video.path.file_name().unwrap_or_default(),
+                match video.series_info {
+                    Some(series_info) => format!("episode {series_info}"),
+                    None => "movie".to_owned(),
+                },
+            );
+        }
+        let answer = ask("number of a video to change, or done/quit: ")?;
+        match answer.as_str() {
+            "done" | "d" | "" => {
+                if predicates::no_series(videos.iter())
+                    || predicates::all_a_series(videos.iter())
+                {
+                    return Ok(());
+                }
+                eprintln!("they're still a mix, which can't be linked");
+            },
+            "quit" | "q" => bail!("can't mix series and movies"),
+            number => {
+                let Some(video) = number
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|number| videos.get_mut(number.checked_sub(1)?))
+                else {
+                    eprintln!("{number:?} isn't one of the videos");
+                    continue;
+                };
+                let episode = ask(&format!(
+                    "episode {} is (e.g. S01E03, or nothing for a movie): ",
+                    video.path.file_name().unwrap_or_default()
+                ))?;
+                video.series_info = match episode.is_empty() {
+                    true => None,
+                    false => match episode.parse::<SeriesInfo>() {
+                        Ok(series_info) => Some(series_info),
+                        Err(why) => {
+                            eprintln!("{episode:?} {why}");
+                            continue;
+                        },
+                    },
+                };
+            },
+        }
+    }
+}
+
+/// A yes or no question, where anything but yes is no
+pub fn confirm(question: &str) -> anyhow::Result<bool> {
+    let answer = ask(&format!("{question} [y/N] "))?;
+    Ok(matches!(answer.to_lowercase().as_str(), "y" | "yes"))
+}
+
+fn ask(prompt: &str) -> anyhow::Result<String> {
+    eprint!("{prompt}");
+    io::stderr().flush().context("failed to show prompt")?;
+    let mut answer = String::new();
+    let read = io::stdin()
+        .lock()
+        .read_line(&mut answer)
+        .context("failed to read answer")?;
+    if read == 0 {
+        bail!("no answer given");
+    }
+    Ok(answer.trim().to_owned())
+}
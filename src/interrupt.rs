@@ -0,0 +1,38 @@
+//! Stopping cleanly on Ctrl-C: the link being made is finished, the rest
+//! aren't started, and what was done is still recorded (in the manifest,
+//! the audit log & checkpoint) and summarised, so the next run can pick up
+//! where this one stopped. A second Ctrl-C stops straight away
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Whether the run's been asked to stop
+pub fn is_interrupted() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed)
+}
+
+#[cfg(unix)]
+extern "C" fn on_interrupt(_: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::Relaxed);
+    // SAFETY: `signal` is async-signal-safe, and this puts back the default
+    // of being killed for the next one
+    unsafe {
+        libc::signal(libc::SIGINT, libc::SIG_DFL);
+    }
+}
+
+/// Catches Ctrl-C (and `kill -INT`) from here on
+#[cfg(unix)]
+pub fn install() {
+    let handler = on_interrupt as extern "C" fn(libc::c_int);
+    // SAFETY: the handler only touches an atomic and calls `signal`, both
+    // of which are fine in a signal handler
+    unsafe {
+        libc::signal(libc::SIGINT, handler as libc::sighandler_t);
+    }
+}
+
+// Ctrl-C still stops the run straight away elsewhere
+#[cfg(not(unix))]
+pub fn install() {}
@@ -0,0 +1,168 @@
+//! Asks a Jellyfin server which folders the items of a collection or playlist
+//! are in, so a run can be limited to them. Also knows what the server's own
+//! folders look like, so they're not mistaken for a library
+
+use camino::Utf8Path;
+// Without the jellyfin feature, only the server's own folders are known
+#[cfg(feature = "jellyfin")]
+use {
+    crate::{
+        http::{self, percent_encode},
+        json::Value,
+    },
+    anyhow::{bail, Context},
+    camino::Utf8PathBuf,
+    log::{debug, info, warn},
+    std::collections::BTreeSet,
+};
+
+/// Where the API key is read from, so it isn't visible to everyone in `ps`
+#[cfg(feature = "jellyfin")]
+pub const API_KEY_VAR: &str = "JELLYFIN_API_KEY";
+
+/// Files only found in the server's data folder
+const DATABASE_FILES: &[&str] = &["jellyfin.db", "library.db"];
+
+/// Folders inside `metadata/` where the server keeps the images and `.nfo`s
+/// it downloads, some of which are named after videos
+const METADATA_FOLDERS: &[&str] = &[
+    "library",
+    "People",
+    "Studio",
+    "Genre",
+    "MusicArtist",
+    "artists",
+];
+
+/// Why `path` looks like one of Jellyfin's own config or data folders rather
+/// than a library, if it does. Links made in there are garbage at best
+pub fn server_data_reason(path: &Utf8Path) -> Option<String> {
+    let components = path
+        .components()
+        .map(|component| component.as_str())
+        .collect::<Vec<_>>();
+    if components.contains(&".jellyfin") {
+        return Some("it's in a .jellyfin folder".to_owned());
+    }
+    for pair in components.windows(2) {
+        match pair {
+            ["config", "data"] => {
+                return Some("it's in a config/data folder".to_owned())
+            },
+            ["metadata", folder] if METADATA_FOLDERS.contains(folder) => {
+                return Some(format!("it's in a metadata/{folder} folder"))
+            },
+            _ => {},
+        }
+    }
+    // The config folder has the data folder inside it, and a folder inside
+    // the data folder has the database above it
+    let config = DATABASE_FILES
+        .iter()
+        .map(|file| path.join("data").join(file));
+    let data = path
+        .ancestors()
+        .flat_map(|dir| DATABASE_FILES.iter().map(move |file| dir.join(file)));
+    config
+        .chain(data)
+        .find(|database| database.is_file())
+        .map(|database| format!("it has Jellyfin's database {database}"))
+}
+
+/// A Jellyfin server, like `http://localhost:8096`
+#[cfg(feature = "jellyfin")]
+#[derive(Debug)]
+pub struct Server {
+    url: String,
+    api_key: String,
+}
+
+#[cfg(feature = "jellyfin")]
+impl Server {
+    pub fn new(url: &str, api_key: String) -> Self {
+        Server {
+            url: url.trim_end_matches('/').to_owned(),
+            api_key,
+        }
+    }
+
+    /// The folders with the videos of the collection or playlist called
+    /// `name`, as the server sees them
+    pub fn collection_folders(
+        &self,
+        name: &str,
+    ) -> anyhow::Result<Vec<Utf8PathBuf>> {
+        let found = self.items(&format!(
+            "Recursive=true&IncludeItemTypes=BoxSet,Playlist&SearchTerm={}",
+            percent_encode(name)
+        ))?;
+        let matching = found
+            .iter()
+            .filter(|item| {
+                item.get("Name")
+                    .and_then(Value::as_str)
+                    .map(|found| found.to_lowercase() == name.to_lowercase())
+                    .unwrap_or_default()
+            })
+            .filter_map(|item| item.get("Id")?.as_str())
+            .collect::<Vec<_>>();
+        let id = match matching[..] {
+            [id] => id,
+            [] => {
+                bail!("Jellyfin has no collection or playlist called {name:?}")
+            },
+            _ => bail!(
+                "Jellyfin has {} collections or playlists called {name:?}",
+                matching.len()
+            ),
+        };
+        let mut folders = BTreeSet::new();
+        for item in self.items(&format!("ParentId={id}&Fields=Path"))? {
+            let kind = item.get("Type").and_then(Value::as_str);
+            // A show's own path is above its season folders, which are
+            // where the videos are
+            if let (Some("Series" | "Season"), Some(id)) =
+                (kind, item.get("Id").and_then(Value::as_str))
+            {
+                let episodes = self.items(&format!(
+                    "ParentId={id}&Recursive=true&IncludeItemTypes=Episode&\
+                     Fields=Path"
+                ))?;
+                folders.extend(episodes.iter().filter_map(folder_of));
+                continue;
+            }
+            match folder_of(&item) {
+                Some(folder) => {
+                    folders.insert(folder);
+                },
+                None => warn!(
+                    "Jellyfin didn't give a path for {:?}, skipping it",
+                    item.get("Name").and_then(Value::as_str).unwrap_or("?")
+                ),
+            }
+        }
+        info!("{name:?} has videos in {} folders", folders.len());
+        debug!("folders in {name:?}: {folders:#?}");
+        Ok(folders.into_iter().collect())
+    }
+
+    fn items(&self, query: &str) -> anyhow::Result<Vec<Value>> {
+        let url = format!("{}/Items?{query}", self.url);
+        debug!("asking Jellyfin for {url}");
+        let body = http::get(&url, &[("X-Emby-Token", &self.api_key)])?;
+        let response = body.parse::<Value>().with_context(|| {
+            format!("Jellyfin's answer to {url} isn't JSON")
+        })?;
+        Ok(response
+            .get("Items")
+            .and_then(Value::as_array)
+            .context("Jellyfin's answer has no \"Items\"")?
+            .to_vec())
+    }
+}
+
+#[cfg(feature = "jellyfin")]
+fn folder_of(item: &Value) -> Option<Utf8PathBuf> {
+    let path = Utf8Path::new(item.get("Path")?.as_str()?);
+    Some(path.parent()?.to_owned())
+}
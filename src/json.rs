@@ -0,0 +1,303 @@
+use std::{
+    fmt,
+    iter::Peekable,
+    str::{Chars, FromStr},
+};
+
+use anyhow::{anyhow, bail, Context};
+
+/// Just enough JSON to read & write machine-readable messages without pulling
+/// in serde
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    pub fn object<K: Into<String>>(
+        pairs: impl IntoIterator<Item = (K, Value)>,
+    ) -> Self {
+        Value::Object(
+            pairs
+                .into_iter()
+                .map(|(key, value)| (key.into(), value))
+                .collect(),
+        )
+    }
+
+    /// Looks up a key of an object
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(pairs) => pairs
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for Value {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chars = s.chars().peekable();
+        let value = parse_value(&mut chars, 0)?;
+        skip_whitespace(&mut chars);
+        match chars.next() {
+            Some(c) => bail!("unexpected {c:?} after JSON value"),
+            None => Ok(value),
+        }
+    }
+}
+
+// Nesting deeper than this is an attack, not a request
+const MAX_DEPTH: usize = 64;
+
+fn parse_value(
+    chars: &mut Peekable<Chars>,
+    depth: usize,
+) -> anyhow::Result<Value> {
+    if depth > MAX_DEPTH {
+        bail!("JSON is nested too deeply");
+    }
+    skip_whitespace(chars);
+    match chars.peek().copied() {
+        Some('n') => expect_word(chars, "null").map(|_| Value::Null),
+        Some('t') => expect_word(chars, "true").map(|_| Value::Bool(true)),
+        Some('f') => expect_word(chars, "false").map(|_| Value::Bool(false)),
+        Some('"') => parse_string(chars).map(Value::String),
+        Some('[') => {
+            chars.next();
+            let mut values = Vec::new();
+            skip_whitespace(chars);
+            if chars.next_if_eq(&']').is_some() {
+                return Ok(Value::Array(values));
+            }
+            loop {
+                values.push(parse_value(chars, depth + 1)?);
+                skip_whitespace(chars);
+                match chars.next() {
+                    Some(',') => continue,
+                    Some(']') => return Ok(Value::Array(values)),
+                    other => bail!("expected ',' or ']', found {other:?}"),
+                }
+            }
+        },
+        Some('{') => {
+            chars.next();
+            let mut pairs = Vec::new();
+            skip_whitespace(chars);
+            if chars.next_if_eq(&'}').is_some() {
+                return Ok(Value::Object(pairs));
+            }
+            loop {
+                skip_whitespace(chars);
+                let key = parse_string(chars)?;
+                skip_whitespace(chars);
+                if chars.next() != Some(':') {
+                    bail!("expected ':' after key {key:?}");
+                }
+                pairs.push((key, parse_value(chars, depth + 1)?));
+                skip_whitespace(chars);
+                match chars.next() {
+                    Some(',') => continue,
+                    Some('}') => return Ok(Value::Object(pairs)),
+                    other => bail!("expected ',' or '}}', found {other:?}"),
+                }
+            }
+        },
+        Some(c) if c == '-' || c.is_ascii_digit() => {
+            let mut number = String::new();
+            while let Some(c) = chars.next_if(|&c| {
+                c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E')
+            }) {
+                number.push(c);
+            }
+            number
+                .parse()
+                .map(Value::Number)
+                .with_context(|| format!("invalid number {number:?}"))
+        },
+        Some(c) => bail!("unexpected {c:?}"),
+        None => bail!("unexpected end of JSON"),
+    }
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+}
+
+fn expect_word(chars: &mut Peekable<Chars>, word: &str) -> anyhow::Result<()> {
+    for expected in word.chars() {
+        if chars.next() != Some(expected) {
+            bail!("expected {word}");
+        }
+    }
+    Ok(())
+}
+
+fn parse_string(chars: &mut Peekable<Chars>) -> anyhow::Result<String> {
+    if chars.next() != Some('"') {
+        bail!("expected a string");
+    }
+    let mut s = String::new();
+    loop {
+        match chars.next().ok_or_else(|| anyhow!("unterminated string"))? {
+            '"' => return Ok(s),
+            '\\' => match chars.next() {
+                Some('"') => s.push('"'),
+                Some('\\') => s.push('\\'),
+                Some('/') => s.push('/'),
+                Some('b') => s.push('\u{8}'),
+                Some('f') => s.push('\u{c}'),
+                Some('n') => s.push('\n'),
+                Some('r') => s.push('\r'),
+                Some('t') => s.push('\t'),
+                Some('u') => {
+                    let high = parse_hex4(chars)?;
+                    // Characters outside the BMP come as surrogate pairs
+                    let code = match high {
+                        0xD800..=0xDBFF => {
+                            expect_word(chars, "\\u")?;
+                            let low = parse_hex4(chars)?;
+                            if !(0xDC00..=0xDFFF).contains(&low) {
+                                bail!("unpaired surrogate in \\u escape");
+                            }
+                            0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+                        },
+                        _ => high,
+                    };
+                    s.push(
+                        char::from_u32(code)
+                            .ok_or_else(|| anyhow!("invalid \\u escape"))?,
+                    );
+                },
+                other => bail!("invalid escape {other:?}"),
+            },
+            c => s.push(c),
+        }
+    }
+}
+
+fn parse_hex4(chars: &mut Peekable<Chars>) -> anyhow::Result<u32> {
+    let hex = chars.take(4).collect::<String>();
+    u32::from_str_radix(&hex, 16)
+        .with_context(|| format!("invalid \\u escape {hex:?}"))
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => f.write_str("null"),
+            Value::Bool(b) => write!(f, "{b}"),
+            Value::Number(n) if !n.is_finite() => f.write_str("null"),
+            Value::Number(n) if n.fract() == 0.0 && n.abs() < 1e15 => {
+                write!(f, "{}", *n as i64)
+            },
+            Value::Number(n) => write!(f, "{n}"),
+            Value::String(s) => write_escaped(f, s),
+            Value::Array(values) => {
+                f.write_str("[")?;
+                for (index, value) in values.iter().enumerate() {
+                    if index != 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{value}")?;
+                }
+                f.write_str("]")
+            },
+            Value::Object(pairs) => {
+                f.write_str("{")?;
+                for (index, (key, value)) in pairs.iter().enumerate() {
+                    if index != 0 {
+                        f.write_str(",")?;
+                    }
+                    write_escaped(f, key)?;
+                    write!(f, ":{value}")?;
+                }
+                f.write_str("}")
+            },
+        }
+    }
+}
+
+fn write_escaped(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    f.write_str("\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{c}")?,
+        }
+    }
+    f.write_str("\"")
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+impl From<usize> for Value {
+    fn from(n: usize) -> Self {
+        Value::Number(n as f64)
+    }
+}
+
+impl From<u64> for Value {
+    fn from(n: u64) -> Self {
+        Value::Number(n as f64)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::String(s.to_owned())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::String(s)
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(option: Option<T>) -> Self {
+        match option {
+            Some(value) => value.into(),
+            None => Value::Null,
+        }
+    }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(values: Vec<T>) -> Self {
+        Value::Array(values.into_iter().map(Into::into).collect())
+    }
+}
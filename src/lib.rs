@@ -0,0 +1,353 @@
+//! Finds subtitles for Jellyfin & links them next to the videos they're for.
+//! The `subfix` binary is a thin command line wrapper around [`process`]
+
+use std::{collections::BTreeMap, env, io, sync::mpsc::Sender, time::Instant};
+
+use anyhow::{bail, Context};
+use camino::{Utf8Path, Utf8PathBuf};
+use log::{debug, info, warn};
+
+use crate::{
+    cli::Options,
+    discovery::{discover_subtitles, discover_videos},
+    events::Event,
+    ignore::IgnoreFiles,
+    link::create_symlinks,
+    lock::{Lock, DIRECTORY_LOCK},
+    matcher::Chain,
+    report::{Report, Skip, SkipReason},
+    series::SeriesInfo,
+    split::merge_split_parts,
+    subtitle::{flag_small_as_forced, remove_duplicate_languages, Subtitle},
+    time::Stamp,
+    video::Video,
+};
+
+pub mod adopt;
+pub mod census;
+pub mod checkpoint;
+pub mod cli;
+pub mod config;
+mod content;
+pub mod coverage;
+pub mod discovery;
+mod edition;
+pub mod elevation;
+#[cfg(feature = "embed")]
+pub mod embed;
+pub mod events;
+pub mod explain;
+mod fonts;
+pub mod fsops;
+mod gzip;
+mod html;
+#[cfg(any(feature = "serve", feature = "jellyfin"))]
+mod http;
+mod ids;
+mod ignore;
+mod interactive;
+pub mod interrupt;
+pub mod jellyfin;
+mod json;
+mod link;
+pub mod lint;
+pub mod lock;
+pub mod manifest;
+mod manual;
+mod matcher;
+pub mod naming;
+mod nfo;
+mod normalize;
+mod numbered;
+#[cfg(feature = "ocr")]
+mod ocr;
+mod parallel;
+mod predicates;
+#[cfg(feature = "probe")]
+mod probe;
+pub mod prune;
+pub mod rebuild;
+mod release;
+pub mod report;
+mod review;
+pub mod run;
+mod series;
+#[cfg(feature = "serve")]
+pub mod serve;
+mod split;
+mod srt;
+mod stack;
+mod stats;
+mod subtitle;
+mod sync;
+mod time;
+mod timing;
+pub mod torrent;
+mod transliterate;
+pub mod undo;
+mod video;
+pub mod xattr;
+
+pub fn current_dir() -> anyhow::Result<Utf8PathBuf> {
+    let cwd = env::current_dir().context("couldn't get current directory")?;
+    Utf8PathBuf::try_from(cwd).context("current directory isn't valid UTF-8")
+}
+
+/// Resolves a path like [`Utf8Path::canonicalize_utf8`], but on Windows
+/// gives it back as it'd be typed, like `\\NAS\media\Show` or `D:\Show`,
+/// rather than with the `\\?\` prefix that turns up in names & messages
+pub fn canonicalize(path: &Utf8Path) -> io::Result<Utf8PathBuf> {
+    let path = path.canonicalize_utf8()?;
+    if !cfg!(windows) {
+        return Ok(path);
+    }
+    Ok(strip_verbatim(path))
+}
+
+// `\\?\UNC\NAS\media` is `\\NAS\media` and `\\?\D:\` is `D:\`. The
+// other kinds, like volume GUIDs, have nothing simpler to be
+fn strip_verbatim(path: Utf8PathBuf) -> Utf8PathBuf {
+    let Some(rest) = path.as_str().strip_prefix(r"\\?\") else {
+        return path;
+    };
+    if let Some(share) = rest.strip_prefix(r"UNC\") {
+        return Utf8PathBuf::from(format!(r"\\{share}"));
+    }
+    let mut chars = rest.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some(drive), Some(':'), Some('\\')) if drive.is_ascii_alphabetic() => {
+            Utf8PathBuf::from(rest)
+        },
+        _ => path,
+    }
+}
+
+/// Links the subtitles for the videos in a directory, which should be an
+/// absolute path
+pub fn process(
+    path: impl AsRef<Utf8Path>,
+    options: &Options,
+) -> anyhow::Result<Report> {
+    process_inner(path.as_ref(), options, None)
+}
+
+/// Like [`process`], but also sends [`Event`]s as things happen
+pub fn process_with_events(
+    path: impl AsRef<Utf8Path>,
+    options: &Options,
+    events: Sender<Event>,
+) -> anyhow::Result<Report> {
+    process_inner(path.as_ref(), options, Some(events))
+}
+
+fn process_inner(
+    path: &Utf8Path,
+    options: &Options,
+    events: Option<Sender<Event>>,
+) -> anyhow::Result<Report> {
+    let bytes_before = stats::bytes_read();
+    let mut report = Report {
+        started: Stamp::now(),
+        run: run::id(),
+        events,
+        ..Report::default()
+    };
+    if let Err(why) = find_and_link(path, options, &mut report) {
+        report.emit(Event::Error {
+            path: path.to_owned(),
+            message: format!("{why:#}"),
+        });
+        return Err(why);
+    }
+    report.stats.bytes_read = stats::bytes_read() - bytes_before;
+    report.finished = Stamp::now();
+    Ok(report)
+}
+
+fn find_and_link(
+    path: &Utf8Path,
+    options: &Options,
+    report: &mut Report,
+) -> anyhow::Result<()> {
+    if !options.force {
+        if let Some(why) = jellyfin::server_data_reason(path) {
+            bail!(
+                "not processing {path}, it looks like Jellyfin's own data: \
+                 {why} (see --force)"
+            );
+        }
+    }
+    if options.respect_ignore_files {
+        if let Some(file) = IgnoreFiles::above(path).excluded_by(path) {
+            bail!("not processing {path}, it's excluded by {file}");
+        }
+    }
+    info!("discovering video files in {path}");
+    report.emit(Event::DiscoveryStarted {
+        dir: path.to_owned(),
+    });
+    let _lock = Lock::acquire(path.join(DIRECTORY_LOCK))?;
+    if let Some(matches) = manual::load(path)? {
+        return manual::link(path, matches, options, report);
+    }
+    let here = path;
+    let walk_start = Instant::now();
+    let mut videos = discover_videos(here, options, report);
+    if !options.content_type.has_episodes() {
+        videos.iter_mut().for_each(|video| video.series_info = None);
+    }
+    #[cfg(feature = "probe")]
+    if options.probe {
+        probe::measure(&mut videos);
+        if !options.include_short {
+            probe::remove_short(
+                &mut videos,
+                options.min_video_duration,
+                report,
+            );
+        }
+    }
+    if let Some(season) = options.season {
+        videos.retain(|video| in_season(video.series_info, season));
+        if videos.is_empty() {
+            bail!("didn't find any episodes of season {season} in {path}");
+        }
+    }
+    match videos.len() {
+        0 => bail!("didn't find any videos in {}", path),
+        1 => info!("found {}", &videos[0].path),
+        _ => {
+            info!("videos in {path}: {videos:#?}");
+            if !(predicates::no_series(videos.iter())
+                || predicates::all_a_series(videos.iter()))
+            {
+                match options.interactive {
+                    true => interactive::reclassify(&mut videos)?,
+                    false => {
+                        bail!("can't mix series and movies (see --interactive)")
+                    },
+                }
+            }
+            if options.content_type.one_per_folder()
+                && !predicates::different_versions_same_media(videos.iter())
+            {
+                bail!(
+                    "unsure that all videos are different versions of the \
+                     same thing"
+                );
+            }
+            debug!(
+                "verified all videos are different versions of the same thing"
+            );
+        },
+    }
+    #[cfg(feature = "probe")]
+    if options.extract_embedded {
+        probe::extract_embedded(&videos, report);
+    }
+    let mut subs = discover_subtitles(here, &videos, options, report);
+    if !options.content_type.has_episodes() {
+        subs.iter_mut().for_each(|sub| sub.series_info = None);
+    }
+    if let Some(season) = options.season {
+        subs.retain(|sub| {
+            let keep = in_season(sub.series_info, season);
+            if !keep {
+                debug!("{} isn't from season {season}, skipping", sub.path);
+                report.skip(Skip::new(
+                    &sub.path,
+                    SkipReason::OtherSeason,
+                    format!("not from season {season}"),
+                ));
+            }
+            keep
+        });
+    }
+    report.stats.walk = walk_start.elapsed().saturating_sub(report.stats.parse);
+    if subs.is_empty() {
+        info!("no subtitles found in {path}");
+        #[cfg(feature = "probe")]
+        if options.probe {
+            probe::handle_embedded(
+                &videos,
+                &options.extract_if_missing,
+                report,
+            );
+        }
+        coverage::record(&videos, report);
+        return Ok(());
+    }
+    info!("subtitles in {path}: {subs:#?}");
+    gzip::decompress_found(&mut subs, report);
+    numbered::align(&videos, &mut subs, options.interactive, report)?;
+    let matching_start = Instant::now();
+    #[cfg(feature = "ocr")]
+    if options.ocr {
+        ocr::convert_vobsubs(&mut subs, report);
+    }
+    if let Some(split_point) = options.merge_split {
+        match videos.len() {
+            1 => merge_split_parts(&mut subs, split_point, options, report),
+            _ => warn!(
+                "not merging split subtitles, there's more than one video"
+            ),
+        }
+    }
+    match options.content_type.stacks() && stack::is_stacked(&videos) {
+        true => stack::sort_into_parts(&videos, &mut subs, options, report),
+        // One part on its own isn't stacked with anything
+        false => videos.iter_mut().for_each(|video| video.part = None),
+    }
+    flag_small_as_forced(&mut subs);
+    match options.content_type.one_per_folder() {
+        true => {
+            remove_duplicate_languages(&mut subs, &options.source_order, report)
+        },
+        false => {
+            remove_duplicates_per_video(&videos, &mut subs, options, report)
+        },
+    }
+    if options.fix_timing {
+        timing::fix_timings(&mut subs, options, report);
+    }
+    #[cfg(feature = "sync")]
+    if let Some(provider) = &options.sync_provider {
+        sync::sync_subtitles(&videos, &mut subs, provider, options, report);
+    }
+    create_symlinks(here, &videos, &subs, options, report)?;
+    coverage::record(&videos, report);
+    review::flag_suspicious(&videos, report);
+    report.stats.matching = matching_start
+        .elapsed()
+        .saturating_sub(report.stats.execute);
+    info!("done!");
+    Ok(())
+}
+
+/// Anything that isn't an episode is left for the rest of processing to
+/// decide about
+fn in_season(series_info: Option<SeriesInfo>, season: u8) -> bool {
+    series_info
+        .map(|series_info| series_info.season() == season)
+        .unwrap_or(true)
+}
+
+/// Drops repeated languages per video, for folders where each is its own
+pub(crate) fn remove_duplicates_per_video(
+    videos: &[Video],
+    subs: &mut Vec<Subtitle>,
+    options: &Options,
+    report: &mut Report,
+) {
+    let chain = Chain::new(&options.matchers, videos)
+        .needing_evidence(videos.len() > 1);
+    let mut by_video = BTreeMap::<Option<usize>, Vec<Subtitle>>::new();
+    for sub in subs.drain(..) {
+        let video = videos.iter().position(|video| chain.matches(video, &sub));
+        by_video.entry(video).or_default().push(sub);
+    }
+    for (_, mut group) in by_video {
+        remove_duplicate_languages(&mut group, &options.source_order, report);
+        subs.extend(group);
+    }
+}
@@ -0,0 +1,967 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt, fs, io,
+    str::FromStr,
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::bail;
+use camino::{Utf8Path, Utf8PathBuf};
+use isolang::Language;
+use log::{debug, error, info, warn};
+
+use crate::{
+    cli::Options,
+    content::hash_file,
+    discovery::Listing,
+    events::Event,
+    fonts,
+    fsops::{
+        check_space, copy_file, crosses_devices, hard_link, is_dry_run,
+        is_same_file, move_file, needs_privilege, same_filesystem, symlink,
+    },
+    interrupt,
+    manifest::{Entry, Manifest, Origin, Written},
+    matcher::Chain,
+    naming::{self, Tags},
+    parallel,
+    report::{dry_run_prefix, LinkRecord, Report, Skip, SkipReason},
+    stack::same_part,
+    subtitle::{Flags, Subtitle},
+    time::Stamp,
+    transliterate,
+    undo::{remove_managed, remove_stale},
+    video::Video,
+    xattr,
+};
+
+/// How a subtitle is put next to its video
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum Mode {
+    /// A relative symlink, leaving the subtitle where it is
+    #[default]
+    Symlink,
+    /// A hard link, or a copy if the subtitle's on another filesystem
+    Hardlink,
+    /// A copy, for setups symlinks don't survive, like Jellyfin in Docker
+    /// seeing the library somewhere else, or SMB shares
+    Copy,
+    /// The subtitle itself, moved next to the video (copied and removed if
+    /// it's on another filesystem). Other versions of the video get copies
+    Rename,
+}
+
+impl Mode {
+    fn noun(self) -> &'static str {
+        match self {
+            Mode::Symlink => "symlink",
+            Mode::Hardlink => "hard link",
+            Mode::Copy => "copy",
+            Mode::Rename => "subtitle",
+        }
+    }
+}
+
+impl FromStr for Mode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "symlink" => Ok(Mode::Symlink),
+            "hardlink" | "hard-link" => Ok(Mode::Hardlink),
+            "copy" => Ok(Mode::Copy),
+            "rename" | "move" => Ok(Mode::Rename),
+            _ => bail!(
+                "unknown mode {s:?}, expected symlink, hardlink, copy or \
+                 rename"
+            ),
+        }
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Mode::Symlink => "symlink",
+            Mode::Hardlink => "hardlink",
+            Mode::Copy => "copy",
+            Mode::Rename => "rename",
+        })
+    }
+}
+
+/// What a hard link becomes when the subtitle's on another filesystem to
+/// the video, where it can't be made
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum Fallback {
+    #[default]
+    Copy,
+    Symlink,
+    /// Nothing, but a warning
+    Skip,
+}
+
+impl Fallback {
+    fn mode(self) -> Option<Mode> {
+        match self {
+            Fallback::Copy => Some(Mode::Copy),
+            Fallback::Symlink => Some(Mode::Symlink),
+            Fallback::Skip => None,
+        }
+    }
+}
+
+impl FromStr for Fallback {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "copy" => Ok(Fallback::Copy),
+            "symlink" => Ok(Fallback::Symlink),
+            "skip" => Ok(Fallback::Skip),
+            _ => bail!(
+                "unknown fallback mode {s:?}, expected copy, symlink or skip"
+            ),
+        }
+    }
+}
+
+impl fmt::Display for Fallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Fallback::Copy => "copy",
+            Fallback::Symlink => "symlink",
+            Fallback::Skip => "skip",
+        })
+    }
+}
+
+pub fn create_symlinks(
+    in_root_dir: impl AsRef<Utf8Path>,
+    videos: &[Video],
+    subtitles: &[Subtitle],
+    options: &Options,
+    report: &mut Report,
+) -> anyhow::Result<()> {
+    let mut manifest = Manifest::load(in_root_dir.as_ref())?;
+    remove_stale(in_root_dir.as_ref(), &mut manifest, report);
+    let same_edition = same_edition(videos, options);
+    // Looked up before anything is linked so new links don't count
+    let listing = Listing::read(in_root_dir.as_ref());
+    let existing = match options.only_missing {
+        true => videos
+            .iter()
+            .map(|video| {
+                (video.path.as_str(), listing.existing_languages(video))
+            })
+            .collect(),
+        false => BTreeMap::new(),
+    };
+    let bazarr = match options.defer_to_bazarr {
+        true => videos
+            .iter()
+            .map(|video| (video.path.as_str(), listing.bazarr_languages(video)))
+            .collect(),
+        false => BTreeMap::new(),
+    };
+    let mut names = case_folded_names(&listing, report);
+    // Where subtitles have been moved to, to copy them from there for any
+    // other versions of the video
+    let mut moved = BTreeMap::new();
+    let chain = Chain::new(&options.matchers, videos).needing_evidence(
+        !options.content_type.one_per_folder() && videos.len() > 1,
+    );
+    subtitles.iter().for_each(|subtitle| {
+        let same_episode = videos
+            .iter()
+            .filter(|video| {
+                (subtitle.shared || chain.matches(video, subtitle))
+                    && same_part(video, subtitle)
+            })
+            .collect::<Vec<_>>();
+        if same_episode.is_empty() {
+            report.warn_skip(
+                format!("no video matches {}, skipping", subtitle.path),
+                Skip::new(
+                    &subtitle.path,
+                    SkipReason::NoMatchingVideo,
+                    "no video matches",
+                ),
+            );
+        } else if !same_episode
+            .iter()
+            .any(|video| same_edition(video, subtitle))
+        {
+            let message = match different_cuts(videos, options) {
+                Some(spread) => format!(
+                    "can't tell which cut {} is for, the versions' lengths \
+                     differ by {}s and it isn't named after one, skipping \
+                     (see --ignore-editions)",
+                    subtitle.path,
+                    spread.as_secs()
+                ),
+                None => format!(
+                    "can't tell which edition {} is for, skipping (see \
+                     --ignore-editions)",
+                    subtitle.path
+                ),
+            };
+            report.warn_skip(
+                message,
+                Skip::new(
+                    &subtitle.path,
+                    SkipReason::EditionMismatch,
+                    "no video is the same edition",
+                ),
+            );
+        }
+    });
+    // Hashes of the subtitles each video has, including ones linked this run
+    let mut contents = BTreeMap::new();
+    let pairs = videos
+        .iter()
+        .flat_map(|video| {
+            subtitles.iter().map(move |subtitle| (video, subtitle))
+        })
+        .filter(|(video, subtitle)| {
+            (subtitle.shared || chain.matches(video, subtitle))
+                && same_part(video, subtitle)
+                && same_edition(video, subtitle)
+        })
+        .collect::<Vec<_>>();
+    check_room(in_root_dir.as_ref(), &pairs, options)?;
+    // Including ones linked by earlier runs, which may not have had `--fonts`
+    let mut styled = pairs
+        .iter()
+        .map(|(_, subtitle)| subtitle.path.as_path())
+        .filter(|path| fonts::is_styled(path))
+        .collect::<Vec<_>>();
+    styled.sort();
+    styled.dedup();
+    let planned = subtitles
+        .iter()
+        .map(|subtitle| {
+            (subtitle.path.as_path(), FileState::of(&subtitle.path))
+        })
+        .collect::<BTreeMap<_, _>>();
+    // Each read once, however many videos it's for, and all at once
+    let identical = match options.skip_identical {
+        true => {
+            let hashes = parallel::map(subtitles, options.jobs, |subtitle| {
+                hash_file(&subtitle.path)
+            });
+            subtitles
+                .iter()
+                .map(|subtitle| subtitle.path.as_path())
+                .zip(hashes)
+                .collect()
+        },
+        false => BTreeMap::new(),
+    };
+    for (video, subtitle) in pairs {
+        if interrupt::is_interrupted() {
+            warn!(
+                "interrupted, not linking anything else in {}",
+                in_root_dir.as_ref()
+            );
+            report.interrupted = true;
+            break;
+        }
+        report.emit(Event::Matched {
+            video: video.path.clone(),
+            subtitle: subtitle.path.clone(),
+        });
+        if options.only_missing
+            && existing[video.path.as_str()].contains(&subtitle.lang)
+        {
+            info!(
+                "{} already has a {} subtitle, skipping {}",
+                video.path,
+                subtitle.lang.to_name(),
+                subtitle.path
+            );
+            report.skip(Skip::new(
+                &subtitle.path,
+                SkipReason::AlreadyHasLanguage,
+                format!("{} already has this language", video.path),
+            ));
+            continue;
+        }
+        if options.defer_to_bazarr
+            && bazarr[video.path.as_str()].contains(&subtitle.lang)
+        {
+            info!(
+                "Bazarr manages {} subtitles for {}, skipping {}",
+                subtitle.lang.to_name(),
+                video.path,
+                subtitle.path
+            );
+            report.skip(Skip::new(
+                &subtitle.path,
+                SkipReason::ManagedByBazarr,
+                format!("{} has a subtitle from Bazarr", video.path),
+            ));
+            continue;
+        }
+        let hash = match identical.get(subtitle.path.as_path()) {
+            Some(Ok(hash)) => Some(*hash),
+            Some(Err(why)) => {
+                warn!("couldn't read {}: {why}", subtitle.path);
+                None
+            },
+            None => None,
+        };
+        if let Some(hash) = hash {
+            let hashes = contents
+                .entry(video.path.as_str())
+                .or_insert_with(|| existing_hashes(&listing, video));
+            if !hashes.insert(hash) {
+                info!(
+                    "{} already has a subtitle identical to {}, skipping",
+                    video.path, subtitle.path
+                );
+                report.skip(Skip::new(
+                    &subtitle.path,
+                    SkipReason::IdenticalContent,
+                    format!(
+                        "{} already has a subtitle with this content",
+                        video.path
+                    ),
+                ));
+                continue;
+            }
+        }
+        // Looked at again right before it's linked, as packs still being
+        // downloaded can change under a run. One moved for another version
+        // was fine then, and one a dry run would have written by now (like
+        // a decompressed or merged subtitle) was never there
+        let planned = planned[subtitle.path.as_path()];
+        let unfit = match moved.contains_key(&subtitle.path)
+            || (is_dry_run() && planned.is_none())
+        {
+            true => None,
+            false => unfit(&subtitle.path, planned),
+        };
+        if let Some(why) = unfit {
+            report.warn_skip(
+                format!("not linking {}, {why}", subtitle.path),
+                Skip::new(&subtitle.path, SkipReason::Incomplete, why),
+            );
+            continue;
+        }
+        let mut flagged = vec![true];
+        // Clients that ignore `.default` still pick the plain language link
+        if options.also_unflagged
+            && subtitle.lang == Language::Eng
+            && !subtitle.flags.forced
+        {
+            flagged.push(false);
+        }
+        let title = safe_title(subtitle, options, report);
+        let link_names = flagged
+            .into_iter()
+            .filter_map(|flagged| {
+                link_name(
+                    in_root_dir.as_ref(),
+                    video,
+                    subtitle,
+                    title.as_deref(),
+                    flagged,
+                    options,
+                )
+            })
+            .collect::<Vec<_>>();
+        if link_names.is_empty() {
+            report.warn_skip(
+                format!(
+                    "the name of {} is too long to add a language to, \
+                     skipping {}",
+                    video.path, subtitle.path
+                ),
+                Skip::new(
+                    &subtitle.path,
+                    SkipReason::NameTooLong,
+                    format!(
+                        "a link for {} would be over {} bytes",
+                        video.path, options.max_name_length
+                    ),
+                ),
+            );
+            continue;
+        }
+        for subtitle_name in link_names {
+            let name = subtitle_name.file_name().unwrap_or_default();
+            let folded = name.to_lowercase();
+            match names.get(&folded) {
+                Some(other) if other != name => {
+                    report.warn_skip(
+                        format!(
+                            "not linking {} as {name}, {other} is already \
+                             there and only differs in case",
+                            subtitle.path
+                        ),
+                        Skip::new(
+                            &subtitle.path,
+                            SkipReason::CaseConflict,
+                            format!("{name} would clash with {other}"),
+                        ),
+                    );
+                    continue;
+                },
+                _ => {
+                    names.insert(folded, name.to_owned());
+                },
+            }
+            link(
+                video,
+                subtitle,
+                subtitle_name,
+                options,
+                &mut moved,
+                &mut manifest,
+                report,
+            );
+        }
+    }
+    if options.fonts {
+        fonts::bring(in_root_dir.as_ref(), &styled);
+    }
+    manifest.save()
+}
+
+/// Makes sure there's room for every link that could be made, before any
+/// are. Some pairs are skipped later on, so this can overestimate
+fn check_room(
+    dir: &Utf8Path,
+    pairs: &[(&Video, &Subtitle)],
+    options: &Options,
+) -> anyhow::Result<()> {
+    let mut files = 0;
+    let mut bytes = 0;
+    let mut moved = BTreeSet::new();
+    for (_, subtitle) in pairs {
+        files += match options.also_unflagged {
+            true => 2,
+            false => 1,
+        };
+        let copied = match options.mode {
+            Mode::Symlink => false,
+            Mode::Copy => true,
+            Mode::Hardlink => {
+                options.fallback_mode == Fallback::Copy
+                    && !same_filesystem(&subtitle.path, dir)
+            },
+            // Only the first is moved, the rest are copies of it
+            Mode::Rename => {
+                !moved.insert(&subtitle.path)
+                    || !same_filesystem(&subtitle.path, dir)
+            },
+        };
+        if copied {
+            bytes += fs::metadata(&subtitle.path)
+                .map(|metadata| metadata.len())
+                .unwrap_or_default();
+        }
+    }
+    check_space(dir, bytes, files)
+}
+
+/// The names in a folder by their lowercase form. Case-insensitive shares
+/// (like SMB, or most Windows & macOS disks) can only hold one of each, so
+/// what happens to a second depends on where the folder's copied to
+fn case_folded_names(
+    listing: &Listing,
+    report: &mut Report,
+) -> BTreeMap<String, String> {
+    let mut names = BTreeMap::new();
+    for name in listing.names() {
+        if let Some(other) = names.insert(name.to_lowercase(), name.to_owned())
+        {
+            warn!("{other} and {name} only differ in case");
+            report.review.push(format!(
+                "{other} and {name} only differ in case, so only one would \
+                 survive being copied somewhere case-insensitive"
+            ));
+        }
+    }
+    names
+}
+
+/// A subtitle's size, when it was last changed, and which file it is, as the
+/// links were planned
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+struct FileState {
+    len: u64,
+    modified: Option<SystemTime>,
+    /// The device & inode, which change when it's replaced by another file
+    id: Option<(u64, u64)>,
+}
+
+impl FileState {
+    fn of(path: &Utf8Path) -> Option<Self> {
+        let metadata = fs::metadata(path).ok()?;
+        Some(FileState {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+            id: file_id(&metadata),
+        })
+    }
+}
+
+#[cfg(unix)]
+fn file_id(metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_id(_: &fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+// Why a subtitle shouldn't be linked after all, if it's now empty, can't be
+// read, or isn't as it was planned
+fn unfit(path: &Utf8Path, planned: Option<FileState>) -> Option<String> {
+    let now = match fs::metadata(path) {
+        Ok(_) => FileState::of(path),
+        Err(why) => return Some(format!("it can't be read: {why}")),
+    };
+    if now.map(|now| now.len) == Some(0) {
+        return Some("it's empty".to_owned());
+    }
+    if planned.is_some() && now != planned {
+        return Some(
+            "it changed after it was looked at, it may still be being written"
+                .to_owned(),
+        );
+    }
+    match fs::File::open(path) {
+        Ok(_) => None,
+        Err(why) => Some(format!("it can't be read: {why}")),
+    }
+}
+
+fn existing_hashes(listing: &Listing, video: &Video) -> BTreeSet<u64> {
+    listing
+        .sidecars(video, |_| true)
+        .iter()
+        .filter_map(|path| hash_file(path).ok())
+        .collect()
+}
+
+fn link(
+    video: &Video,
+    subtitle: &Subtitle,
+    subtitle_name: Utf8PathBuf,
+    options: &Options,
+    moved: &mut BTreeMap<Utf8PathBuf, Utf8PathBuf>,
+    manifest: &mut Manifest,
+    report: &mut Report,
+) {
+    if subtitle.path == subtitle_name {
+        debug!("{subtitle_name} is already where it'd go");
+        return;
+    }
+    let dir = video.path.parent().unwrap_or(&video.path);
+    let mode = match options.mode {
+        Mode::Hardlink if !same_filesystem(&subtitle.path, dir) => {
+            match options.fallback_mode.mode() {
+                Some(fallback) => {
+                    info!(
+                        "{}{} is on another filesystem to {dir}, so making a \
+                         {} rather than a hard link",
+                        dry_run_prefix(),
+                        subtitle.path,
+                        fallback.noun(),
+                    );
+                    fallback
+                },
+                None => {
+                    warn!(
+                        "not linking {}, it's on another filesystem to {dir} \
+                         (see --fallback-mode)",
+                        subtitle.path
+                    );
+                    report.skip(Skip::new(
+                        &subtitle.path,
+                        SkipReason::OtherFilesystem,
+                        format!("it's on another filesystem to {dir}"),
+                    ));
+                    return;
+                },
+            }
+        },
+        mode => mode,
+    };
+    let moved_to = moved.get(&subtitle.path).cloned();
+    // What's recorded in the manifest. The copies made for other versions
+    // of a video are of the subtitle that was moved, so they're recorded as
+    // being of where it was, like it is
+    let target = match mode {
+        Mode::Symlink => symlink_target(&subtitle.path, &subtitle_name),
+        Mode::Hardlink | Mode::Copy | Mode::Rename => subtitle.path.clone(),
+    };
+    let origin = match (mode, &moved_to) {
+        (Mode::Rename, None) => Origin::Moved,
+        _ => Origin::Created,
+    };
+    let name = subtitle_name.file_name().unwrap_or_default().to_owned();
+    let video_name = video.path.file_name().unwrap_or_default();
+    if manifest.is_embedded(video_name, &target) {
+        debug!("{} was embedded in {video_name} already", subtitle.path);
+        return;
+    }
+    if let Some(entry) = manifest.get(&name).cloned() {
+        if entry.target == target
+            && fs::symlink_metadata(&subtitle_name).is_ok()
+        {
+            debug!("{subtitle_name} was already made by an earlier run");
+            return;
+        }
+        match entry.origin {
+            // A different subtitle to the one inside the video, which is
+            // linked beside it
+            Origin::Embedded => manifest.forget(&name),
+            Origin::Moved => {
+                warn!(
+                    "not replacing {subtitle_name} with {}, it's the subtitle \
+                     an earlier run moved from {}",
+                    subtitle.path, entry.target
+                );
+                report.skip(Skip::new(
+                    &subtitle.path,
+                    SkipReason::LinkFailed,
+                    format!(
+                        "{subtitle_name} was moved there by an earlier run"
+                    ),
+                ));
+                return;
+            },
+            // An earlier run's, for a subtitle that's been replaced since
+            Origin::Created | Origin::Adopted => {
+                info!(
+                    "{}replacing {subtitle_name}, which was for {}",
+                    dry_run_prefix(),
+                    entry.target,
+                );
+                match remove_managed(&subtitle_name, &entry) {
+                    Ok(_) => manifest.forget(&name),
+                    Err(why) => error!("{why:#}"),
+                }
+            },
+        }
+    }
+    info!(
+        "{}naming {} {} for {} to {}",
+        dry_run_prefix(),
+        subtitle.lang.to_name(),
+        mode.noun(),
+        video.path.file_name().unwrap(),
+        subtitle_name.file_name().unwrap(),
+    );
+    let start = Instant::now();
+    let placed = match &moved_to {
+        Some(moved_to) => copy_file(moved_to, &subtitle_name),
+        None => {
+            place(mode, options.fallback_mode, &subtitle.path, &subtitle_name)
+        },
+    };
+    report.stats.execute += start.elapsed();
+    match placed {
+        Ok(()) => {
+            if mode == Mode::Rename {
+                moved
+                    .entry(subtitle.path.clone())
+                    .or_insert_with(|| subtitle_name.clone());
+            }
+            // A moved subtitle is still the user's, and a hard link is the
+            // same file as theirs
+            if options.xattr
+                && origin == Origin::Created
+                && !is_same_file(&subtitle.path, &subtitle_name)
+            {
+                xattr::mark_created(&subtitle_name);
+            }
+            manifest.record(Entry {
+                name,
+                target,
+                video: video.path.file_name().map(str::to_owned),
+                origin,
+                recorded: Stamp::now(),
+                written: Written::copy(&subtitle_name, &subtitle.path),
+            });
+            report.emit(Event::LinkCreated {
+                video: video.path.clone(),
+                subtitle: subtitle.path.clone(),
+                link: subtitle_name.clone(),
+            });
+            report.links.push(LinkRecord {
+                video: video.path.clone(),
+                subtitle: subtitle.path.clone(),
+                link: subtitle_name,
+                lang: subtitle.lang,
+            })
+        },
+        Err(why) => {
+            report.emit(Event::Error {
+                path: subtitle.path.clone(),
+                message: format!("couldn't link to {subtitle_name}: {why}"),
+            });
+            error!(
+                "failed to create {} {} -> {subtitle_name}: {why}",
+                mode.noun(),
+                &subtitle.path
+            );
+            report.skip(Skip::new(
+                &subtitle.path,
+                SkipReason::LinkFailed,
+                why.to_string(),
+            ));
+            if mode == Mode::Symlink && needs_privilege(&why) {
+                report.denied.push(LinkRecord {
+                    video: video.path.clone(),
+                    subtitle: subtitle.path.clone(),
+                    link: subtitle_name,
+                    lang: subtitle.lang,
+                });
+            }
+        },
+    }
+}
+
+/// Puts `subtitle` at `name`, falling back to copying when a hard link or
+/// move would cross filesystems
+// Filesystems are told apart when planning, but some (like bind mounts of
+// the same one) only turn out to be different when linking
+fn place(
+    mode: Mode,
+    fallback: Fallback,
+    subtitle: &Utf8Path,
+    name: &Utf8Path,
+) -> io::Result<()> {
+    match mode {
+        Mode::Symlink => symlink(symlink_target(subtitle, name), name),
+        Mode::Hardlink => match hard_link(subtitle, name) {
+            Err(why) if crosses_devices(&why) => match fallback.mode() {
+                Some(instead) => {
+                    info!(
+                        "{subtitle} is on another filesystem, making a {} \
+                         instead",
+                        instead.noun()
+                    );
+                    place(instead, fallback, subtitle, name)
+                },
+                None => Err(why),
+            },
+            linked => linked,
+        },
+        Mode::Copy => copy_file(subtitle, name),
+        Mode::Rename => move_file(subtitle, name),
+    }
+}
+
+/// Where a symlink at `name` points to get to `subtitle`. Relative, so
+/// links still work with the library mounted elsewhere
+fn symlink_target(subtitle: &Utf8Path, name: &Utf8Path) -> Utf8PathBuf {
+    name.parent()
+        .and_then(|dir| subtitle.strip_prefix(dir).ok())
+        .map(|relative| Utf8Path::new(".").join(relative))
+        .unwrap_or_else(|| subtitle.to_owned())
+}
+
+/// Whether a subtitle is for the same edition of a video, given the editions
+/// of all the videos in the folder
+pub(crate) fn same_edition<'a>(
+    videos: &'a [Video],
+    options: &'a Options,
+) -> impl Fn(&Video, &Subtitle) -> bool + 'a {
+    let editions = videos
+        .iter()
+        .map(|video| video.edition.clone())
+        .collect::<BTreeSet<_>>();
+    let different_cuts = different_cuts(videos, options).is_some();
+    move |video, subtitle| {
+        if options.ignore_editions {
+            return true;
+        }
+        match &subtitle.edition {
+            Some(_) if editions != BTreeSet::from([None]) => {
+                video.edition == subtitle.edition
+            },
+            // Versions that run for different lengths are timed differently,
+            // whatever they're called
+            _ if different_cuts => {
+                named_after(videos, subtitle) == video.path.file_stem()
+            },
+            // Can't tell which cut it's timed for if there's a choice
+            _ => editions.len() == 1,
+        }
+    }
+}
+
+/// How much longer the longest version runs than the shortest, if that's
+/// more than `--cut-threshold`, so they're probably different cuts
+fn different_cuts(videos: &[Video], options: &Options) -> Option<Duration> {
+    if !options.content_type.one_per_folder() {
+        return None;
+    }
+    let runtimes = videos.iter().filter_map(|video| video.runtime);
+    let spread = runtimes.clone().max()?.checked_sub(runtimes.min()?)?;
+    (spread > options.cut_threshold).then_some(spread)
+}
+
+// The longest of the videos' names the subtitle's starts with, or the folder
+// it's in below them is, like `Movie.Extended.en.srt` or `Movie/en.srt`
+fn named_after<'a>(
+    videos: &'a [Video],
+    subtitle: &Subtitle,
+) -> Option<&'a str> {
+    videos
+        .iter()
+        .filter_map(|video| {
+            let stem = video.path.file_stem()?;
+            let relative =
+                subtitle.path.strip_prefix(video.path.parent()?).ok()?;
+            let first = relative.components().next()?.as_str();
+            let named = match relative.components().count() {
+                1 => first.strip_prefix(stem)?.starts_with('.'),
+                _ => first == stem,
+            };
+            named.then_some(stem)
+        })
+        .max_by_key(|stem| stem.len())
+}
+
+/// A subtitle's title, changed if it would make the link look like one of
+/// Jellyfin's reserved names
+pub(crate) fn safe_title(
+    subtitle: &Subtitle,
+    options: &Options,
+    report: &mut Report,
+) -> Option<String> {
+    let title = subtitle.title.as_deref()?;
+    let latin = match options.transliterate {
+        true => transliterate::to_latin(title),
+        false => None,
+    };
+    if options.transliterate && latin.is_none() {
+        debug!("can't write {title:?} in Latin letters, leaving it as it is");
+    }
+    let title = latin.as_deref().unwrap_or(title);
+    if !naming::is_reserved(title) {
+        return Some(title.to_owned());
+    }
+    let safe = naming::unreserved(title);
+    let flag = format!(
+        "{} is titled {title:?}, which Jellyfin reserves, so it's linked {}",
+        subtitle.path,
+        match &safe {
+            Some(safe) => format!("as {safe:?}"),
+            None => "without a title".to_owned(),
+        }
+    );
+    if !report.review.contains(&flag) {
+        warn!("{flag}");
+        report.review.push(flag);
+    }
+    safe
+}
+
+// `None` if the name would be too long even without a title
+pub(crate) fn link_name(
+    in_root_dir: &Utf8Path,
+    video: &Video,
+    subtitle: &Subtitle,
+    title: Option<&str>,
+    flagged: bool,
+    options: &Options,
+) -> Option<Utf8PathBuf> {
+    let tags = Tags {
+        lang: naming::language_tag(
+            subtitle.lang,
+            subtitle.label.as_deref(),
+            options.language_codes,
+        ),
+        default: flagged
+            && subtitle.lang == Language::Eng
+            && subtitle.flags == Flags::default()
+            && !subtitle.shared,
+        flags: subtitle.flags,
+        extension: subtitle.path.extension().unwrap(),
+    };
+    let stem = video.path.file_stem().unwrap();
+    let file_name = naming::file_name(
+        stem,
+        title,
+        tags,
+        options.naming,
+        options.max_name_length,
+    )?;
+    Some(in_root_dir.join(file_name))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs::OpenOptions};
+
+    use super::*;
+
+    fn subtitle(name: &str) -> Utf8PathBuf {
+        let dir = Utf8PathBuf::try_from(env::temp_dir())
+            .unwrap()
+            .join(format!("subfix-link-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("English.srt");
+        fs::write(&path, "1\n00:00:01,000 --> 00:00:02,000\nHello\n").unwrap();
+        path
+    }
+
+    #[test]
+    fn unchanged_is_fit() {
+        let path = subtitle("unchanged");
+        assert_eq!(unfit(&path, FileState::of(&path)), None);
+    }
+
+    #[test]
+    fn empty_is_unfit() {
+        let path = subtitle("empty");
+        fs::write(&path, "").unwrap();
+        let why = unfit(&path, FileState::of(&path)).unwrap();
+        assert!(why.contains("empty"), "{why}");
+    }
+
+    #[test]
+    fn truncated_after_planning_is_unfit() {
+        let path = subtitle("truncated");
+        let planned = FileState::of(&path);
+        OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .and_then(|file| file.set_len(10))
+            .unwrap();
+        let why = unfit(&path, planned).unwrap();
+        assert!(why.contains("changed"), "{why}");
+    }
+
+    #[test]
+    fn replaced_after_planning_is_unfit() {
+        let path = subtitle("replaced");
+        let planned = FileState::of(&path);
+        // The same size, and maybe the same modification time too
+        let other = path.with_file_name("other.srt");
+        fs::copy(&path, &other).unwrap();
+        fs::rename(&other, &path).unwrap();
+        let why = unfit(&path, planned).unwrap();
+        assert!(why.contains("changed"), "{why}");
+    }
+
+    #[test]
+    fn removed_after_planning_is_unfit() {
+        let path = subtitle("removed");
+        let planned = FileState::of(&path);
+        fs::remove_file(&path).unwrap();
+        let why = unfit(&path, planned).unwrap();
+        assert!(why.contains("can't be read"), "{why}");
+    }
+}
@@ -0,0 +1,511 @@
+//! Looking over the subtitles already next to videos for what keeps Jellyfin
+//! from showing them as well as it could, each with the command that would
+//! fix it. Nothing's changed, so a library can be checked over before
+//! letting subfix (or anything else) loose on it
+
+use std::{
+    collections::HashSet,
+    fmt,
+    fs::{self, File},
+    io::Read,
+};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use isolang::Language;
+
+use crate::{
+    canonicalize,
+    cli::Options,
+    discovery::{discover_videos, folders, Listing},
+    json::Value,
+    naming::{flags, LanguageCodes},
+    predicates,
+    report::Report,
+    srt::MAX_SUBTITLE_SIZE,
+    subtitle::language_from_tag,
+    video::Video,
+};
+
+/// How much a finding matters, most first
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Priority {
+    /// Jellyfin won't show the subtitle, or will show it garbled
+    High,
+    /// It's shown, but not picked the way it should be
+    Medium,
+    /// Only untidy
+    Low,
+}
+
+impl Priority {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Priority::High => "high",
+            Priority::Medium => "medium",
+            Priority::Low => "low",
+        }
+    }
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+#[derive(Debug)]
+pub struct Finding {
+    pub priority: Priority,
+    /// Stable and kebab-case, for scripts
+    pub kind: &'static str,
+    pub path: Utf8PathBuf,
+    pub message: String,
+    /// A shell command that would fix it
+    pub fix: String,
+}
+
+/// `{"findings": [...]}`, for `--json`
+pub fn to_json(findings: &[Finding]) -> Value {
+    Value::object([(
+        "findings",
+        Value::Array(findings.iter().map(Finding::to_json).collect()),
+    )])
+}
+
+impl Finding {
+    fn to_json(&self) -> Value {
+        Value::object([
+            ("priority", self.priority.as_str().into()),
+            ("kind", self.kind.into()),
+            ("path", self.path.as_str().into()),
+            ("message", self.message.as_str().into()),
+            ("fix", self.fix.as_str().into()),
+        ])
+    }
+}
+
+/// Everything worth fixing in the folders anywhere under `root`, most
+/// important first
+pub fn lint(root: &Utf8Path, options: &Options) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    for dir in folders(root, options) {
+        lint_dir(&dir, options, &mut findings);
+    }
+    // Stable, so each priority stays in folder order
+    findings.sort_by_key(|finding| finding.priority);
+    findings
+}
+
+fn lint_dir(dir: &Utf8Path, options: &Options, findings: &mut Vec<Finding>) {
+    // Skips are only of interest when linking
+    let videos = discover_videos(dir, options, &mut Report::default());
+    if videos.is_empty() {
+        return;
+    }
+    let listing = Listing::read(dir);
+    let sidecars = videos
+        .iter()
+        .map(|video| {
+            let sidecars = listing
+                .sidecars(video, |_| true)
+                .into_iter()
+                .filter_map(|path| Sidecar::parse(&video.path, path))
+                .collect::<Vec<_>>();
+            (video, sidecars)
+        })
+        .collect::<Vec<_>>();
+    orphans(dir, &listing, &sidecars, findings);
+    let mut checked = HashSet::new();
+    for (video, sidecars) in &sidecars {
+        for sidecar in sidecars {
+            broken_link(sidecar, findings);
+            bad_encoding(sidecar, &mut checked, findings);
+        }
+        duplicate_languages(sidecars, findings);
+        missing_default(video, sidecars, findings);
+    }
+    match options.language_codes {
+        LanguageCodes::TwoLetter => mixed_codes(&sidecars, findings),
+        codes => other_codes(&sidecars, codes, findings),
+    }
+}
+
+// Every way Jellyfin takes the flags, not just the ones subfix writes
+const FORCED: &[&str] = &[flags::FORCED, "foreign"];
+const HEARING_IMPAIRED: &[&str] = &[flags::HEARING_IMPAIRED, "sdh", "hi"];
+
+/// A subtitle named after a video, split into the tags Jellyfin reads
+pub(crate) struct Sidecar {
+    pub path: Utf8PathBuf,
+    pub lang: Language,
+    /// Between the dots after the video's name, without the extension
+    tags: Vec<String>,
+    /// Which of `tags` is the language
+    lang_index: usize,
+}
+
+impl Sidecar {
+    pub fn parse(video: &Utf8Path, path: Utf8PathBuf) -> Option<Self> {
+        let stem = video.file_stem()?;
+        let tags = path
+            .file_stem()?
+            .strip_prefix(stem)?
+            .strip_prefix('.')?
+            .split('.')
+            .map(str::to_owned)
+            .collect::<Vec<_>>();
+        let (lang_index, lang) = tags
+            .iter()
+            .enumerate()
+            .find_map(|(index, tag)| Some((index, language_from_tag(tag)?)))?;
+        Some(Sidecar {
+            path,
+            lang,
+            tags,
+            lang_index,
+        })
+    }
+
+    // Flags can be anywhere after the video's name
+    fn has_tag(&self, names: &[&str]) -> bool {
+        self.tags
+            .iter()
+            .enumerate()
+            .filter(|&(index, _)| index != self.lang_index)
+            .any(|(_, tag)| {
+                names.iter().any(|name| tag.eq_ignore_ascii_case(name))
+            })
+    }
+
+    pub fn is_default(&self) -> bool {
+        self.has_tag(&[flags::DEFAULT])
+    }
+
+    pub fn is_forced(&self) -> bool {
+        self.has_tag(FORCED)
+    }
+
+    pub fn is_hearing_impaired(&self) -> bool {
+        self.has_tag(HEARING_IMPAIRED)
+    }
+
+    /// What Jellyfin shows as the title, like `Commentary`
+    pub fn title(&self) -> String {
+        self.tags
+            .iter()
+            .enumerate()
+            .filter(|&(index, tag)| {
+                index != self.lang_index
+                    && !FORCED
+                        .iter()
+                        .chain(HEARING_IMPAIRED)
+                        .chain(&[flags::DEFAULT])
+                        .any(|name| tag.eq_ignore_ascii_case(name))
+            })
+            .map(|(_, tag)| tag.as_str())
+            .collect::<Vec<_>>()
+            .join(".")
+    }
+
+    fn lang_tag(&self) -> &str {
+        &self.tags[self.lang_index]
+    }
+
+    fn is_symlink(&self) -> bool {
+        fs::symlink_metadata(&self.path)
+            .map(|metadata| metadata.file_type().is_symlink())
+            .unwrap_or_default()
+    }
+
+    /// The same file, with different tags
+    fn renamed(&self, tags: &[String]) -> Utf8PathBuf {
+        let stem = self.path.file_stem().unwrap_or_default();
+        let video_stem = &stem[..stem.len() - self.tags.join(".").len() - 1];
+        let mut name = format!("{video_stem}.{}", tags.join("."));
+        if let Some(extension) = self.path.extension() {
+            name.push('.');
+            name.push_str(extension);
+        }
+        self.path.with_file_name(name)
+    }
+}
+
+// Subtitles right next to the videos but not named after any of them, which
+// Jellyfin passes over, unless a link to them already is
+fn orphans(
+    dir: &Utf8Path,
+    listing: &Listing,
+    sidecars: &[(&Video, Vec<Sidecar>)],
+    findings: &mut Vec<Finding>,
+) {
+    let linked = sidecars
+        .iter()
+        .flat_map(|(_, sidecars)| sidecars)
+        .filter_map(|sidecar| canonicalize(&sidecar.path).ok())
+        .collect::<HashSet<_>>();
+    for name in listing.names() {
+        let path = dir.join(name);
+        let is_sidecar = sidecars
+            .iter()
+            .flat_map(|(_, sidecars)| sidecars)
+            .any(|sidecar| sidecar.path == path);
+        let named_after_video = sidecars.iter().any(|(video, _)| {
+            video
+                .path
+                .file_stem()
+                .map(|stem| name.starts_with(&format!("{stem}.")))
+                .unwrap_or_default()
+        });
+        if is_sidecar
+            || named_after_video
+            || !predicates::has_subtitle_extension(&path)
+            || !path.is_file()
+        {
+            continue;
+        }
+        let is_linked = canonicalize(&path)
+            .map(|target| linked.contains(&target))
+            .unwrap_or_default();
+        if is_linked {
+            continue;
+        }
+        findings.push(Finding {
+            priority: Priority::High,
+            kind: "orphan",
+            message: format!(
+                "{path} isn't named after a video, so Jellyfin won't show it"
+            ),
+            fix: format!("subfix {}", quote(dir.as_str())),
+            path,
+        });
+    }
+}
+
+fn broken_link(sidecar: &Sidecar, findings: &mut Vec<Finding>) {
+    if !sidecar.is_symlink() || sidecar.path.exists() {
+        return;
+    }
+    let target = fs::read_link(&sidecar.path)
+        .map(|target| target.display().to_string())
+        .unwrap_or_default();
+    findings.push(Finding {
+        priority: Priority::High,
+        kind: "broken-link",
+        path: sidecar.path.clone(),
+        message: format!("{} is broken, {target} isn't there", sidecar.path),
+        fix: format!("rm -- {}", quote(sidecar.path.as_str())),
+    });
+}
+
+// Jellyfin reads text subtitles as UTF-8 (or UTF-16 with a BOM, which some
+// clients still trip over), so anything else comes out garbled
+fn bad_encoding(
+    sidecar: &Sidecar,
+    checked: &mut HashSet<Utf8PathBuf>,
+    findings: &mut Vec<Finding>,
+) {
+    if !matches!(sidecar.path.extension(), Some("srt" | "vtt" | "ass")) {
+        return;
+    }
+    // The file itself, once, for links to it (which `mv` would replace)
+    let Ok(path) = canonicalize(&sidecar.path) else {
+        return;
+    };
+    if !checked.insert(path.clone()) {
+        return;
+    }
+    let mut bytes = Vec::new();
+    let read = File::open(&path)
+        .and_then(|file| file.take(MAX_SUBTITLE_SIZE).read_to_end(&mut bytes));
+    if read.is_err() {
+        return;
+    }
+    let from = match bytes.as_slice() {
+        [0xFF, 0xFE, ..] | [0xFE, 0xFF, ..] => "UTF-16",
+        bytes if std::str::from_utf8(bytes).is_err() => "WINDOWS-1252",
+        _ => return,
+    };
+    let message = match from {
+        "UTF-16" => format!("{path} is UTF-16, which some clients can't read"),
+        _ => format!(
+            "{path} isn't UTF-8, so Jellyfin will show it garbled (it's \
+             guessed to be Windows-1252, check it reads right after \
+             converting)"
+        ),
+    };
+    let converted = format!("{path}.utf8");
+    findings.push(Finding {
+        priority: Priority::High,
+        kind: "bad-encoding",
+        message,
+        fix: format!(
+            "iconv -f {from} -t UTF-8 {} > {} && mv -- {} {}",
+            quote(path.as_str()),
+            quote(&converted),
+            quote(&converted),
+            quote(path.as_str())
+        ),
+        path,
+    });
+}
+
+// Two subtitles of a video Jellyfin can't tell apart, as they've the same
+// language, flags & title. Real files are kept over links, and the tidier
+// name over the other
+fn duplicate_languages(sidecars: &[Sidecar], findings: &mut Vec<Finding>) {
+    let mut sorted = sidecars.iter().collect::<Vec<_>>();
+    sorted.sort_by_key(|sidecar| {
+        (sidecar.is_symlink(), sidecar.lang_tag().len() != 2)
+    });
+    let mut seen = Vec::new();
+    for sidecar in sorted {
+        let key = (
+            sidecar.lang,
+            sidecar.is_forced(),
+            sidecar.is_hearing_impaired(),
+            sidecar.title().to_lowercase(),
+        );
+        match seen.iter().find(|(seen, _)| *seen == key) {
+            Some((_, kept)) => findings.push(Finding {
+                priority: Priority::Medium,
+                kind: "duplicate-language",
+                path: sidecar.path.clone(),
+                message: format!(
+                    "{} is another {} subtitle like {kept}, Jellyfin will \
+                     list both the same way",
+                    sidecar.path,
+                    sidecar.lang.to_name()
+                ),
+                fix: format!("rm -- {}", quote(sidecar.path.as_str())),
+            }),
+            None => seen.push((key, &sidecar.path)),
+        }
+    }
+}
+
+// Without a default, players fall back on their own order, which rarely puts
+// the full track of the main language first. English is picked, as when
+// linking, otherwise the first full track
+fn missing_default(
+    video: &Video,
+    sidecars: &[Sidecar],
+    findings: &mut Vec<Finding>,
+) {
+    if sidecars.iter().any(Sidecar::is_default) {
+        return;
+    }
+    let mut full = sidecars.iter().filter(|sidecar| {
+        !sidecar.is_forced() && !sidecar.is_hearing_impaired()
+    });
+    let pick = full
+        .clone()
+        .find(|sidecar| sidecar.lang == Language::Eng)
+        .or_else(|| full.next());
+    let Some(pick) = pick else {
+        return;
+    };
+    let mut tags = pick.tags.clone();
+    tags.insert(pick.lang_index + 1, flags::DEFAULT.to_owned());
+    findings.push(Finding {
+        priority: Priority::Medium,
+        kind: "missing-default",
+        path: pick.path.clone(),
+        message: format!(
+            "none of the subtitles for {} are marked default",
+            video.path
+        ),
+        fix: format!(
+            "mv -- {} {}",
+            quote(pick.path.as_str()),
+            quote(pick.renamed(&tags).as_str())
+        ),
+    });
+}
+
+// A folder naming some subtitles `en` and others `eng` works, but is hard to
+// keep track of. The two letter codes are what subfix uses
+fn mixed_codes(
+    sidecars: &[(&Video, Vec<Sidecar>)],
+    findings: &mut Vec<Finding>,
+) {
+    let all = sidecars
+        .iter()
+        .flat_map(|(_, sidecars)| sidecars)
+        .filter(|sidecar| sidecar.lang.to_639_1().is_some())
+        .collect::<Vec<_>>();
+    let is_short = |sidecar: &Sidecar| sidecar.lang_tag().len() == 2;
+    let is_long = |sidecar: &Sidecar| sidecar.lang_tag().len() == 3;
+    if !all.iter().any(|sidecar| is_short(sidecar)) {
+        return;
+    }
+    for sidecar in all.into_iter().filter(|sidecar| is_long(sidecar)) {
+        let Some(code) = sidecar.lang.to_639_1() else {
+            continue;
+        };
+        let mut tags = sidecar.tags.clone();
+        tags[sidecar.lang_index] = code.to_owned();
+        let renamed = sidecar.renamed(&tags);
+        // Then it's a duplicate, which is its own finding
+        if fs::symlink_metadata(&renamed).is_ok() {
+            continue;
+        }
+        findings.push(Finding {
+            priority: Priority::Low,
+            kind: "mixed-codes",
+            path: sidecar.path.clone(),
+            message: format!(
+                "{} is tagged {:?} where others in its folder use two letter \
+                 codes",
+                sidecar.path,
+                sidecar.lang_tag()
+            ),
+            fix: format!(
+                "mv -- {} {}",
+                quote(sidecar.path.as_str()),
+                quote(renamed.as_str())
+            ),
+        });
+    }
+}
+
+// With three letter codes asked for, every code that's not of that kind, be
+// it two letters or the other three
+fn other_codes(
+    sidecars: &[(&Video, Vec<Sidecar>)],
+    codes: LanguageCodes,
+    findings: &mut Vec<Finding>,
+) {
+    let all = sidecars.iter().flat_map(|(_, sidecars)| sidecars);
+    for sidecar in all.filter(|sidecar| sidecar.lang_tag().len() <= 3) {
+        let code = codes.code(sidecar.lang);
+        if sidecar.lang_tag().eq_ignore_ascii_case(code) {
+            continue;
+        }
+        let mut tags = sidecar.tags.clone();
+        tags[sidecar.lang_index] = code.to_owned();
+        let renamed = sidecar.renamed(&tags);
+        if fs::symlink_metadata(&renamed).is_ok() {
+            continue;
+        }
+        findings.push(Finding {
+            priority: Priority::Low,
+            kind: "other-codes",
+            path: sidecar.path.clone(),
+            message: format!(
+                "{} is tagged {:?} rather than the {codes} code {code:?}",
+                sidecar.path,
+                sidecar.lang_tag()
+            ),
+            fix: format!(
+                "mv -- {} {}",
+                quote(sidecar.path.as_str()),
+                quote(renamed.as_str())
+            ),
+        });
+    }
+}
+
+// For a POSIX shell
+fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
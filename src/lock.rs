@@ -0,0 +1,233 @@
+use std::{
+    fs, io,
+    sync::{Mutex, MutexGuard, Once},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{bail, Context};
+use camino::{Utf8Path, Utf8PathBuf};
+use log::{debug, warn};
+
+use crate::fsops::{
+    create_lock, hard_link, is_dry_run, refresh_lock, remove_file, rename,
+};
+
+/// Held in each directory while it's being processed
+pub const DIRECTORY_LOCK: &str = ".subfix.lock";
+
+/// How long to wait for another subfix to finish before giving up
+const WAIT_FOR: Duration = Duration::from_secs(60);
+/// Lock files older than this were probably left behind by a crash
+const STALE_AFTER: Duration = Duration::from_secs(60 * 60);
+/// How often the locks held are written again, well within `STALE_AFTER`,
+/// so a long run's lock isn't taken to be one left behind
+const REFRESH_EVERY: Duration = Duration::from_secs(5 * 60);
+
+/// The locks this process holds, for the thread that keeps them fresh
+static HELD: Mutex<Vec<Utf8PathBuf>> = Mutex::new(Vec::new());
+static REFRESHER: Once = Once::new();
+
+/// An advisory lock held by creating a file, so that subfix processes
+/// triggered at the same time (e.g. by the *arr apps) take turns instead of
+/// making conflicting changes. Released when dropped
+#[derive(Debug)]
+pub struct Lock {
+    path: Utf8PathBuf,
+    /// Not taken in dry runs (or read-only mode), as that would mean
+    /// creating a file. Nothing's changed then, so there's nothing to
+    /// conflict
+    held: bool,
+}
+
+impl Lock {
+    pub fn acquire(path: impl Into<Utf8PathBuf>) -> anyhow::Result<Self> {
+        let path = path.into();
+        if is_dry_run() {
+            debug!("dry run, not taking lock {path}");
+            return Ok(Lock { path, held: false });
+        }
+        let start = SystemTime::now();
+        let mut warned = false;
+        loop {
+            match create_lock(&path) {
+                Ok(()) => {
+                    debug!("acquired lock {path}");
+                    hold(&path);
+                    return Ok(Lock { path, held: true });
+                },
+                Err(why) if why.kind() == io::ErrorKind::AlreadyExists => {
+                    if is_stale(&path) {
+                        break_stale(&path).with_context(|| {
+                            format!("failed to remove stale lock {path}")
+                        })?;
+                        continue;
+                    }
+                    if start.elapsed().unwrap_or_default() > WAIT_FOR {
+                        bail!(
+                            "another subfix is still holding {path} (delete \
+                             it if that's not the case)"
+                        );
+                    }
+                    if !warned {
+                        warn!("waiting for another subfix to release {path}");
+                        warned = true;
+                    }
+                    thread::sleep(Duration::from_millis(200));
+                },
+                Err(why) => {
+                    return Err(why)
+                        .with_context(|| format!("failed to create {path}"))
+                },
+            }
+        }
+    }
+
+    /// Lock for a file, kept next to it
+    pub fn for_file(file: impl AsRef<Utf8Path>) -> anyhow::Result<Self> {
+        Lock::acquire(format!("{}.lock", file.as_ref()))
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        if !self.held {
+            return;
+        }
+        // First, so it isn't written again once it's gone
+        held().retain(|path| *path != self.path);
+        if let Err(why) = remove_file(&self.path) {
+            warn!("failed to release lock {}: {why}", self.path);
+        }
+    }
+}
+
+fn held() -> MutexGuard<'static, Vec<Utf8PathBuf>> {
+    HELD.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn hold(path: &Utf8Path) {
+    held().push(path.to_owned());
+    REFRESHER.call_once(|| {
+        thread::spawn(|| loop {
+            thread::sleep(REFRESH_EVERY);
+            refresh_held();
+        });
+    });
+}
+
+fn refresh_held() {
+    for path in held().iter() {
+        if let Err(why) = refresh_lock(path) {
+            warn!("failed to refresh lock {path}: {why}");
+        }
+    }
+}
+
+fn is_stale(path: &Utf8Path) -> bool {
+    fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+        .and_then(|modified| modified.elapsed().ok())
+        .map(|age| age > STALE_AFTER)
+        .unwrap_or_default()
+}
+
+/// Moves a stale lock aside before removing it, so of the runs that notice
+/// it's stale, only one removes it, and none removes the lock another takes
+/// in its place
+fn break_stale(path: &Utf8Path) -> io::Result<()> {
+    let aside = format!("{path}.stale.{}", std::process::id());
+    match rename(path, &aside) {
+        // Another run got to it first
+        Err(why) if why.kind() == io::ErrorKind::NotFound => return Ok(()),
+        moved => moved?,
+    }
+    // Broken and taken again by another run between looking and moving it
+    if !is_stale(Utf8Path::new(&aside)) {
+        debug!("{path} was taken again, putting it back");
+        let restored = hard_link(&aside, path);
+        remove_file(&aside)?;
+        return match restored {
+            Err(why) if why.kind() == io::ErrorKind::AlreadyExists => Ok(()),
+            restored => restored,
+        };
+    }
+    warn!("removed stale lock {path}");
+    remove_file(&aside)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process::Command};
+
+    use super::*;
+
+    fn lock_path(name: &str) -> Utf8PathBuf {
+        let dir = Utf8PathBuf::try_from(env::temp_dir())
+            .unwrap()
+            .join(format!("subfix-lock-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir.join(DIRECTORY_LOCK)
+    }
+
+    fn entries(path: &Utf8Path) -> usize {
+        path.parent().unwrap().read_dir_utf8().unwrap().count()
+    }
+
+    #[test]
+    fn released_when_dropped() {
+        let path = lock_path("released");
+        let lock = Lock::acquire(&path).unwrap();
+        assert!(path.is_file());
+        drop(lock);
+        assert!(!path.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stale_locks_are_broken() {
+        let path = lock_path("stale");
+        fs::write(&path, "1\n").unwrap();
+        let touched = Command::new("touch")
+            .args(["-d", "3 hours ago"])
+            .arg(&path)
+            .status()
+            .unwrap();
+        assert!(touched.success());
+        let lock = Lock::acquire(&path).unwrap();
+        let holder = fs::read_to_string(&path).unwrap();
+        assert_eq!(holder.trim(), std::process::id().to_string());
+        drop(lock);
+        assert_eq!(entries(&path), 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn held_locks_are_kept_fresh() {
+        let path = lock_path("held");
+        let lock = Lock::acquire(&path).unwrap();
+        let touched = Command::new("touch")
+            .args(["-d", "3 hours ago"])
+            .arg(&path)
+            .status()
+            .unwrap();
+        assert!(touched.success());
+        refresh_held();
+        assert!(!is_stale(&path));
+        drop(lock);
+        assert_eq!(entries(&path), 0);
+    }
+
+    #[test]
+    fn fresh_locks_are_put_back() {
+        // As if another run took it between it being seen to be stale and
+        // it being moved aside
+        let path = lock_path("fresh");
+        fs::write(&path, "1\n").unwrap();
+        break_stale(&path).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "1\n");
+        assert_eq!(entries(&path), 1);
+    }
+}
@@ -1,432 +1,630 @@
-use std::{env, io, num::NonZeroU8, path::Path, str::FromStr};
+use std::{cmp::Reverse, env, io::Write, time::Instant};
 
-use anyhow::{anyhow, bail, Context};
-use camino::{Utf8Path, Utf8PathBuf};
+use camino::Utf8PathBuf;
 use env_logger::Env;
 use isolang::Language;
-use log::{debug, error, info, trace, warn, LevelFilter};
-use once_cell::sync::Lazy;
-use regex::{Regex, RegexBuilder};
-use walkdir::WalkDir;
+use log::{debug, error, info, warn, LevelFilter};
+use subfix::{
+    adopt::adopt_links,
+    canonicalize, census,
+    checkpoint::Checkpoint,
+    cli::{Command, Options},
+    config, coverage, current_dir,
+    discovery::{last_modified, season_folder, title_folders},
+    elevation,
+    explain::explain,
+    fsops::{
+        allow_outputs, can_symlink, is_dry_run, is_read_only, open_audit_log,
+        run_elevated, set_dry_run, set_read_only, set_roots, write_atomically,
+    },
+    interrupt, lint, process,
+    prune::prune_languages,
+    rebuild::rebuild_manifests,
+    report, run, torrent,
+    undo::undo_links,
+};
 
 fn main() {
+    let parsed = Options::from_args(env::args().skip(1));
+    let verbose = parsed
+        .as_ref()
+        .map(|(options, _)| options.verbose)
+        .unwrap_or_default();
     env_logger::builder()
-        .filter_level(LevelFilter::Info)
+        .filter_level(match verbose {
+            true => LevelFilter::Debug,
+            false => LevelFilter::Info,
+        })
         .parse_env(Env::new().filter("SUBFIX_LOG"))
-        .format_timestamp(None)
+        // As env_logger's own, with the run's ID so lines from different
+        // runs can be told apart
+        .format(|buf, record| {
+            writeln!(
+                buf,
+                "[{:<5} {} {}] {}",
+                buf.default_styled_level(record.level()),
+                record.module_path().unwrap_or_default(),
+                run::id(),
+                record.args(),
+            )
+        })
         .init();
-    let mut no_args = true;
-    env::args().skip(1).for_each(|arg| {
-        no_args = false;
-        let path = Utf8PathBuf::from(arg);
-        if path.is_dir() {
-            if let Err(why) = process(&path) {
-                error!("failed to process {path}: {why}");
+    let (options, paths) = match parsed {
+        Ok(parsed) => parsed,
+        Err(why) => {
+            error!("{why}");
+            std::process::exit(1);
+        },
+    };
+    if options.read_only {
+        set_read_only();
+    }
+    if options.show_version {
+        print_version(options.verbose);
+        return;
+    }
+    set_dry_run(options.dry_run);
+    if let Some(audit_log) = &options.audit_log {
+        if let Err(why) = open_audit_log(audit_log) {
+            error!("{why:#}");
+            std::process::exit(1);
+        }
+    }
+    if let Command::Explain(subtitle) = &options.command {
+        if let Err(why) = explain(subtitle, &options) {
+            error!("{why:#}");
+            std::process::exit(1);
+        }
+        return;
+    }
+    if let Command::Torrent(torrent) = &options.command {
+        if let Err(why) = torrent::show(torrent, &options) {
+            error!("{why:#}");
+            std::process::exit(1);
+        }
+        return;
+    }
+    #[cfg(feature = "serve")]
+    if let Command::Serve { transport, base } = options.command {
+        let token = options.token.or_else(|| env::var("SUBFIX_TOKEN").ok());
+        if let Err(why) = subfix::serve::serve(transport, base, token) {
+            error!("{why:#}");
+            std::process::exit(1);
+        }
+        return;
+    }
+    #[cfg(feature = "jellyfin")]
+    let paths = match &options.jellyfin_collection {
+        Some(collection) => {
+            match collection_folders(&options.jellyfin_url, collection) {
+                Ok(folders) => paths.into_iter().chain(folders).collect(),
+                Err(why) => {
+                    error!("{why:#}");
+                    std::process::exit(1);
+                },
             }
-        } else {
-            error!("{path} is not a folder, ignoring");
+        },
+        None => paths,
+    };
+    let paths = match paths.is_empty() && options.jellyfin_collection.is_none()
+    {
+        true => {
+            info!("assuming current directory");
+            vec![Utf8PathBuf::from(".")]
+        },
+        false => paths,
+    };
+    // Reports, checkpoints & the Bazarr map need to make sense from anywhere
+    let cwd = match current_dir() {
+        Ok(cwd) => cwd,
+        Err(why) => {
+            error!("{why:#}");
+            std::process::exit(1);
+        },
+    };
+    // Collecting the components drops any `.` ones
+    let absolute = |path: Utf8PathBuf| match path.is_absolute() {
+        true => path,
+        false => cwd.join(path).components().collect(),
+    };
+    let paths = paths.into_iter().map(absolute);
+    let paths = match options.library {
+        true => paths
+            .flat_map(|root| {
+                let titles = title_folders(&root, &options);
+                info!("found {} titles in {root}", titles.len());
+                titles
+            })
+            .collect::<Vec<_>>(),
+        false => paths.collect(),
+    };
+    let paths = paths.into_iter().map(|path| {
+        // A show's folder stands for the season's folder inside it
+        let season_folder = options
+            .season
+            .and_then(|season| season_folder(&path, season));
+        match season_folder {
+            Some(folder) => {
+                info!("doing {folder} for {path}");
+                folder
+            },
+            None => path,
         }
     });
-    if no_args {
-        info!("assuming current directory");
-        if let Err(why) = process(Utf8Path::new(".")) {
-            error!("failed to process this directory: {why}");
-        }
+    let mut paths = canonical_roots(paths, &options);
+    set_roots(&paths);
+    if let Command::PruneLangs(keep) = &options.command {
+        prune(&paths, keep, &options);
+        return;
     }
-}
-
-fn process(path: impl AsRef<Utf8Path>) -> anyhow::Result<()> {
-    info!("discovering video files in {}", path.as_ref());
-    let path = path.as_ref();
-    env::set_current_dir(path).context("failed to move into directory")?;
-    let videos = discover_videos(path);
-    match videos.len() {
-        0 => bail!("didn't find any videos in {}", path),
-        1 => info!("found {}", &videos[0].path),
-        _ => {
-            info!("videos in {path}: {videos:#?}");
-            if !(predicates::no_series(videos.iter())
-                || predicates::all_a_series(videos.iter()))
+    if let Command::Adopt = options.command {
+        adopt(&paths, &options);
+        return;
+    }
+    if let Command::RebuildJournal = options.command {
+        rebuild_journal(&paths, &options);
+        return;
+    }
+    if let Command::Undo = options.command {
+        undo(&paths, &options);
+        return;
+    }
+    if let Command::Stats = options.command {
+        stats(&paths, &options);
+        return;
+    }
+    #[cfg(feature = "embed")]
+    if let Command::Embed = options.command {
+        embed(&paths, &options);
+        return;
+    }
+    if let Command::Lint = options.command {
+        lint(&paths, &options);
+        return;
+    }
+    if let Command::ShowConfig { effective } = options.command {
+        show_config(&paths, &options, effective);
+        return;
+    }
+    let bazarr_map = options.bazarr_map.clone().map(absolute);
+    let html_report = options.html_report.clone().map(absolute);
+    allow_outputs(
+        &[&bazarr_map, &html_report, &options.checkpoint]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>(),
+    );
+    if options.newest_first {
+        paths.sort_by_cached_key(|path| Reverse(last_modified(path)));
+        debug!("processing order: {paths:#?}");
+    }
+    let mut checkpoint = match options.checkpoint.as_ref().map(Checkpoint::load)
+    {
+        Some(Ok(checkpoint)) => Some(checkpoint),
+        Some(Err(why)) => {
+            error!("{why:#}");
+            std::process::exit(1);
+        },
+        None => None,
+    };
+    let start = Instant::now();
+    let mut results = Vec::new();
+    let mut out_of_time = false;
+    let mut interrupted = false;
+    interrupt::install();
+    for (index, path) in paths.iter().enumerate() {
+        if interrupt::is_interrupted() {
+            warn!(
+                "interrupted, {} directories left unprocessed: {}",
+                paths.len() - index,
+                paths[index..]
+                    .iter()
+                    .map(|path| path.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+            interrupted = true;
+            break;
+        }
+        if let Some(max_duration) = options.max_duration {
+            if start.elapsed() >= max_duration {
+                warn!(
+                    "ran out of time, {} directories left unprocessed",
+                    paths.len() - index
+                );
+                out_of_time = true;
+                break;
+            }
+        }
+        if !path.is_dir() {
+            error!("{path} is not a folder, ignoring");
+            continue;
+        }
+        if let Some(since) = options.since {
+            if last_modified(path)
+                .map(|modified| modified < since)
+                .unwrap_or(true)
             {
-                bail!("can't mix series and movies");
+                info!("{path} hasn't changed since the given time, skipping");
+                continue;
             }
-            if !predicates::different_versions_same_media(videos.iter()) {
-                bail!(
-                    "unsure that all videos are different versions of the \
-                     same thing"
-                );
+        }
+        if let Some(checkpoint) = &checkpoint {
+            if checkpoint.is_done(path) {
+                info!("{path} was done on a previous run, skipping");
+                continue;
             }
-            debug!(
-                "verified all videos are different versions of the same thing"
-            );
-        },
+        }
+        let result = process(path, &options);
+        let partial = match &result {
+            Ok(report) => report.interrupted,
+            Err(_) => interrupt::is_interrupted(),
+        };
+        if let Some(checkpoint) = &mut checkpoint {
+            // Left to be gone through again, where the links already made
+            // are left be
+            if partial {
+                info!("{path} was only partly done");
+            } else if let Err(why) = checkpoint.mark_done(path) {
+                error!("couldn't update checkpoint: {why:#}");
+            }
+        }
+        results.push((path.to_owned(), result));
     }
-    let mut subs = discover_subtitles(path);
-    if subs.is_empty() {
-        info!("no subtitles found in {path}, nothing to do");
-        return Ok(());
+    if let Some(checkpoint) = checkpoint {
+        if !(out_of_time || interrupted || interrupt::is_interrupted()) {
+            if let Err(why) = checkpoint.finish() {
+                error!("couldn't clear checkpoint: {why:#}");
+            }
+        }
     }
-    info!("subtitles in {path}: {subs:#?}");
-    remove_duplicate_languages(&mut subs);
-    create_symlinks(path, &videos, &subs);
-    info!("done!");
-    Ok(())
-}
-
-fn discover_videos(in_dir: impl AsRef<Utf8Path>) -> Vec<Video> {
-    WalkDir::new(in_dir.as_ref())
-        .min_depth(1)
-        .max_depth(1)
-        .contents_first(true)
-        .into_iter()
-        .filter_map(|dir_entry| match dir_entry {
-            Ok(dir_entry) => Some(dir_entry),
-            Err(why) => {
-                warn!("{why}");
-                None
-            },
-        })
-        .filter(predicates::is_video)
-        .filter_map(|dir_entry| {
-            match Utf8PathBuf::try_from(dir_entry.path().to_owned()) {
-                Ok(path) => match Video::from_path(path) {
-                    Ok(video) => Some(video),
-                    Err(why) => {
-                        warn!(
-                            "skipped path {}: {why}",
-                            dir_entry.path().display()
-                        );
-                        None
-                    },
-                },
-                Err(_) => {
-                    warn!(
-                        "skipped non-UTF-8 path {}",
-                        dir_entry.path().display()
-                    );
-                    None
+    report::log_summary(&results);
+    let denied = elevation::denied_links(&results);
+    if !denied.is_empty() {
+        match options.elevate {
+            true => match run_elevated(&denied) {
+                Ok(()) => info!("made {} links as administrator", denied.len()),
+                Err(why) => {
+                    error!("{why:#}");
+                    elevation::print_instructions(&denied);
                 },
-            }
-        })
-        .collect()
-}
-
-fn discover_subtitles(in_root_dir: impl AsRef<Utf8Path>) -> Vec<Subtitle> {
-    WalkDir::new(in_root_dir.as_ref())
-        .min_depth(1)
-        .sort_by_file_name()
-        .follow_links(false)
-        .into_iter()
-        .filter_map(|dir_entry| match dir_entry {
-            Ok(dir_entry) => Some(dir_entry),
-            Err(why) => {
-                warn!("{why}");
-                None
             },
-        })
-        .filter(predicates::is_subtitle)
-        .filter_map(|dir_entry| {
-            match Utf8PathBuf::try_from(dir_entry.path().to_owned()) {
-                Ok(path) => {
-                    info!("found {path}");
-                    Some(path)
-                },
-                Err(_) => {
-                    warn!(
-                        "skipped non-UTF-8 path {}",
-                        dir_entry.path().display()
-                    );
-                    None
-                },
-            }
-        })
-        .filter_map(|path| match Subtitle::new(path.clone()) {
-            Ok(sub) => Some(sub),
-            Err(why) => {
-                warn!("failed to process {path}, skipping: {why}");
-                None
+            false => elevation::print_instructions(&denied),
+        }
+    }
+    match options.json {
+        true => println!("{}", report::to_json(&results, &options.coverage)),
+        false if !options.coverage.is_empty() => {
+            print!("{}", coverage::table(&results, &options.coverage))
+        },
+        false => {},
+    }
+    // Written on dry runs too, as that's when there's most to look over
+    if let Some(html_report) = &html_report {
+        match write_atomically(html_report, report::to_html(&results)) {
+            Ok(()) => info!("wrote report to {html_report}"),
+            Err(why) => error!("couldn't write report: {why:#}"),
+        }
+    }
+    if let Some(bazarr_map) = &bazarr_map {
+        match is_dry_run() {
+            true => info!("(dry run) not writing {bazarr_map}"),
+            false => {
+                let map = report::to_bazarr_map(&results).to_string();
+                if let Err(why) = write_atomically(bazarr_map, map) {
+                    error!("couldn't write Bazarr map: {why:#}");
+                }
             },
-        })
-        .collect()
-}
-
-fn create_symlinks(
-    in_root_dir: impl AsRef<Utf8Path>,
-    videos: &[Video],
-    subtitles: &[Subtitle],
-) {
-    videos
-        .iter()
-        .flat_map(|video| {
-            subtitles.iter().map(move |subtitle| (video, subtitle))
-        })
-        .filter(|(video, subtitle)| video.series_info == subtitle.series_info)
-        .for_each(|(video, subtitle)| {
-            let subtitle_name = {
-                let mut path = in_root_dir.as_ref().to_owned();
-                let file_name = {
-                    let mut file_name =
-                        video.path.file_stem().unwrap().to_owned();
-                    file_name.push('.');
-                    file_name.push_str(
-                        subtitle
-                            .lang
-                            .to_639_1()
-                            .unwrap_or(subtitle.lang.to_639_3()),
-                    );
-                    if subtitle.lang == Language::Eng {
-                        file_name.push('.');
-                        file_name.push_str(jellyfin_flags::DEFAULT)
-                    }
-                    file_name.push('.');
-                    file_name.push_str(subtitle.path.extension().unwrap());
-                    file_name
-                };
-                path.push(file_name);
-                path
-            };
-            info!(
-                "naming {} symlink for {} to {}",
-                subtitle.lang.to_name(),
-                video.path.file_name().unwrap(),
-                subtitle_name.file_name().unwrap(),
-            );
-            if let Err(why) = symlink(&subtitle.path, &subtitle_name) {
-                error!(
-                    "failed to create symlink {} -> {subtitle_name}: {why}",
-                    &subtitle.path
-                );
-            }
+        }
+    }
+    if interrupted || interrupt::is_interrupted() {
+        warn!("stopped early, run again{} to finish", match options
+            .checkpoint
+            .is_some()
+        {
+            true => " with the same checkpoint",
+            false => "",
         });
+        std::process::exit(INTERRUPTED_EXIT_CODE);
+    }
+    // Like `terraform plan`, let scripts know whether a real run would do
+    // anything
+    if is_dry_run() && report::would_change(&results) {
+        std::process::exit(DRY_RUN_CHANGES_EXIT_CODE);
+    }
 }
 
-fn remove_duplicate_languages(subs: &mut Vec<Subtitle>) {
-    let mut seen = Vec::new();
-    subs.retain(|sub| {
-        if seen.contains(&(sub.lang, sub.series_info)) {
-            warn!(
-                "skipping duplicate {} subtitle {}",
-                sub.lang.to_name(),
-                &sub.path
-            );
-            false
-        } else {
-            seen.push((sub.lang, sub.series_info));
-            true
+const DRY_RUN_CHANGES_EXIT_CODE: i32 = 2;
+// As shells give a program killed by SIGINT
+const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+fn prune(paths: &[Utf8PathBuf], keep: &[Language], options: &Options) {
+    let mut removed = 0;
+    for path in paths {
+        if !path.is_dir() {
+            error!("{path} is not a folder, ignoring");
+            continue;
         }
-    });
+        match prune_languages(path, keep, options) {
+            Ok(count) => removed += count,
+            Err(why) => error!("{why:#}"),
+        }
+    }
+    match is_dry_run() {
+        true => info!("(dry run) would remove {removed} links"),
+        false => info!("removed {removed} links"),
+    }
+    if is_dry_run() && removed > 0 {
+        std::process::exit(DRY_RUN_CHANGES_EXIT_CODE);
+    }
 }
 
-#[derive(Debug)]
-pub struct Video {
-    path: Utf8PathBuf,
-    series_info: Option<SeriesInfo>,
+fn adopt(paths: &[Utf8PathBuf], options: &Options) {
+    let mut adopted = 0;
+    for path in paths {
+        if !path.is_dir() {
+            error!("{path} is not a folder, ignoring");
+            continue;
+        }
+        match adopt_links(path, options) {
+            Ok(count) => adopted += count,
+            Err(why) => error!("{why:#}"),
+        }
+    }
+    match is_dry_run() {
+        true => info!("(dry run) would adopt {adopted} links"),
+        false => info!("adopted {adopted} links"),
+    }
+    if is_dry_run() && adopted > 0 {
+        std::process::exit(DRY_RUN_CHANGES_EXIT_CODE);
+    }
 }
 
-impl Video {
-    fn from_path(path: Utf8PathBuf) -> anyhow::Result<Self> {
-        let series_info = match SERIES_INFO_REGEX.find(path.as_str()) {
-            Some(series_info) => {
-                info!("found series info in {path}");
-                series_info.as_str().parse::<SeriesInfo>()?.into()
+fn rebuild_journal(paths: &[Utf8PathBuf], options: &Options) {
+    let mut recorded = 0;
+    let mut unproven = 0;
+    for path in paths {
+        if !path.is_dir() {
+            error!("{path} is not a folder, ignoring");
+            continue;
+        }
+        match rebuild_manifests(path, options) {
+            Ok(rebuilt) => {
+                recorded += rebuilt.recorded;
+                unproven += rebuilt.unproven;
             },
-            None => None,
-        };
-        Ok(Video { path, series_info })
+            Err(why) => error!("{why:#}"),
+        }
     }
-
-    fn part_of_series(&self) -> bool {
-        self.series_info.is_some()
+    match is_dry_run() {
+        true => info!("(dry run) would record {recorded} links"),
+        false => info!("recorded {recorded} links"),
     }
-}
-
-impl AsRef<Utf8Path> for Video {
-    fn as_ref(&self) -> &Utf8Path {
-        self.path.as_ref()
+    if unproven > 0 {
+        info!(
+            "{unproven} subtitles next to videos aren't recorded, as nothing \
+             shows subfix made them (see adopt)"
+        );
+    }
+    if is_dry_run() && recorded > 0 {
+        std::process::exit(DRY_RUN_CHANGES_EXIT_CODE);
     }
 }
 
-static SERIES_INFO_REGEX: Lazy<Regex> = Lazy::new(|| {
-    RegexBuilder::new(r"S\d{2}E\d{2}")
-        .case_insensitive(true)
-        .build()
-        .unwrap()
-});
-
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
-struct SeriesInfo {
-    season: NonZeroU8,
-    episode: NonZeroU8,
-}
-
-impl FromStr for SeriesInfo {
-    type Err = anyhow::Error;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s.len() != 6 || !SERIES_INFO_REGEX.is_match(s) {
-            bail!("doesn't match pattern S01E01");
+fn undo(paths: &[Utf8PathBuf], options: &Options) {
+    let mut removed = 0;
+    for path in paths {
+        if !path.is_dir() {
+            error!("{path} is not a folder, ignoring");
+            continue;
+        }
+        match undo_links(path, options) {
+            Ok(count) => removed += count,
+            Err(why) => error!("{why:#}"),
         }
-        let season = s[1..3].parse().context("couldn't parse season")?;
-        let episode = s[4..6].parse().context("couldn't parse episode")?;
-        Ok(SeriesInfo { season, episode })
+    }
+    match is_dry_run() {
+        true => info!("(dry run) would remove {removed} links"),
+        false => info!("removed {removed} links"),
+    }
+    if is_dry_run() && removed > 0 {
+        std::process::exit(DRY_RUN_CHANGES_EXIT_CODE);
     }
 }
 
-#[derive(Debug)]
-struct Subtitle {
-    path: Utf8PathBuf,
-    lang: Language,
-    series_info: Option<SeriesInfo>,
-}
-
-static NUMBER_PREFIX_REGEX: Lazy<Regex> =
-    Lazy::new(|| Regex::new(r"^\d+_").unwrap());
-
-impl Subtitle {
-    fn new(path: Utf8PathBuf) -> anyhow::Result<Self> {
-        let file_name =
-            path.file_stem().expect("subtitle should have file name");
-        trace!("regexing {file_name:?}");
-        let language = NUMBER_PREFIX_REGEX.splitn(file_name, 2).last().unwrap();
-        info!("guessing language is {language:?}");
-        let lang = Language::from_name(language)
-            .ok_or_else(|| anyhow!("couldn't find language {:?}", language))?;
-
-        let series_info = match SERIES_INFO_REGEX.find(path.as_str()) {
-            Some(series_info) => {
-                info!("found series info in {path}");
-                series_info.as_str().parse::<SeriesInfo>()?.into()
-            },
-            None => None,
-        };
-
-        Ok(Self {
-            path,
-            lang,
-            series_info,
+fn stats(paths: &[Utf8PathBuf], options: &Options) {
+    let libraries = paths
+        .iter()
+        .filter(|path| {
+            let is_dir = path.is_dir();
+            if !is_dir {
+                error!("{path} is not a folder, ignoring");
+            }
+            is_dir
         })
+        .map(|path| (path.to_owned(), census::census(path, options)))
+        .collect::<Vec<_>>();
+    match options.json {
+        true => println!("{}", census::to_json(&libraries)),
+        false => {
+            for (root, census) in &libraries {
+                print!("{}", census::summary(root, census));
+            }
+        },
     }
 }
 
-mod predicates {
-    use std::ffi::OsStr;
-
-    use camino::Utf8Path;
-    use log::{error, info, trace};
-    use once_cell::sync::Lazy;
-    use regex::{Regex, RegexBuilder};
-    use walkdir::DirEntry;
-
-    use crate::Video;
-
-    const VIDEO_EXTENSIONS: &[&str] = &["mp4", "mkv", "avi"];
-    const SUBTITLE_EXTENSIONS: &[&str] = &["srt", "vtt", "idx", "ass", "dts"];
-
-    static SEASON_AND_QUALITY_SUFFIX_REGEX: Lazy<Regex> = Lazy::new(|| {
-        RegexBuilder::new(r"( S\d{2}E\d{2})? - ((720p)|(1080p)|(4K( HDR)?))$")
-            .case_insensitive(true)
-            .build()
-            .unwrap()
-    });
-
-    fn ext_in(ext: &OsStr, group: &[&str]) -> bool {
-        group
-            .iter()
-            .any(|acceptable| ext.eq_ignore_ascii_case(acceptable))
+#[cfg(feature = "embed")]
+fn embed(paths: &[Utf8PathBuf], options: &Options) {
+    let mut embedded = 0;
+    for path in paths {
+        if !path.is_dir() {
+            error!("{path} is not a folder, ignoring");
+            continue;
+        }
+        match subfix::embed::embed_subtitles(path, options) {
+            Ok(count) => embedded += count,
+            Err(why) => error!("{why:#}"),
+        }
     }
-
-    pub fn is_video(dir_entry: &DirEntry) -> bool {
-        dir_entry.file_type().is_file()
-            && dir_entry
-                .path()
-                .extension()
-                .map(|ext| {
-                    trace!("seeing if {ext:?} is a video extension");
-                    ext_in(ext, VIDEO_EXTENSIONS)
-                })
-                .unwrap_or_default()
+    match is_dry_run() {
+        true => info!("(dry run) would embed {embedded} subtitles"),
+        false => info!("embedded {embedded} subtitles"),
     }
-
-    pub fn is_subtitle(dir_entry: &DirEntry) -> bool {
-        trace!("testing {dir_entry:?}");
-        dir_entry.file_type().is_file()
-            && dir_entry
-                .path()
-                .extension()
-                .map(|ext| {
-                    trace!("seeing if {ext:?} is a subtitle extension");
-                    ext_in(ext, SUBTITLE_EXTENSIONS)
-                })
-                .unwrap_or_default()
+    if is_dry_run() && embedded > 0 {
+        std::process::exit(DRY_RUN_CHANGES_EXIT_CODE);
     }
+}
 
-    pub fn all_a_series<'a>(
-        videos: impl IntoIterator<Item = &'a Video>,
-    ) -> bool {
-        videos.into_iter().all(|vid| vid.part_of_series())
+fn lint(paths: &[Utf8PathBuf], options: &Options) {
+    let mut findings = Vec::new();
+    for path in paths {
+        if !path.is_dir() {
+            error!("{path} is not a folder, ignoring");
+            continue;
+        }
+        findings.extend(lint::lint(path, options));
     }
-
-    pub fn no_series<'a>(videos: impl IntoIterator<Item = &'a Video>) -> bool {
-        videos.into_iter().all(|vid| !vid.part_of_series())
+    findings.sort_by_key(|finding| finding.priority);
+    match options.json {
+        true => println!("{}", lint::to_json(&findings)),
+        false => {
+            for finding in &findings {
+                println!("{:<6} {}", finding.priority, finding.message);
+                println!("       {}", finding.fix);
+            }
+        },
+    }
+    match findings.len() {
+        0 => info!("nothing to fix"),
+        count => info!("found {count} things that could be fixed"),
     }
+    // Like a dry run that would change things
+    if !findings.is_empty() {
+        std::process::exit(DRY_RUN_CHANGES_EXIT_CODE);
+    }
+}
 
-    // Assumes files has 2 or more elements
-    pub fn different_versions_same_media(
-        files: impl IntoIterator<Item = impl AsRef<Utf8Path>>,
-    ) -> bool {
-        let mut files = files.into_iter();
-        let first = files
-            .next()
-            .expect("files iter should have at least two elements");
-        let first = first.as_ref();
-        let first_name = first.file_stem().expect("file has no name");
-        trace!("regexing {first_name:?}");
-        let Some(name_prefix) =
-            SEASON_AND_QUALITY_SUFFIX_REGEX.splitn(first_name, 2).next()
-        else {
-            error!("couldn't find quality suffix in {first}");
-            return false;
-        };
-        info!("guessing movie/episode name is {name_prefix:?}");
-        files.all(|file| {
-            file.as_ref()
-                .file_stem()
-                .map(|name| name.starts_with(name_prefix))
-                .unwrap_or_default()
-        })
+fn show_config(paths: &[Utf8PathBuf], options: &Options, effective: bool) {
+    println!("config files: none, subfix doesn't read any");
+    let settings = config::settings(options);
+    if !effective {
+        match settings.iter().any(|setting| setting.given) {
+            true => println!("settings given:"),
+            false => println!("settings given: none, all are the defaults"),
+        }
+        for setting in settings.iter().filter(|setting| setting.given) {
+            println!("  {:<30} {}", setting.flag, setting.value);
+        }
+        return;
+    }
+    println!("settings:");
+    for setting in &settings {
+        println!("  {:<30} {}{}", setting.flag, setting.value, match setting
+            .given
+        {
+            true => " (given)",
+            false => "",
+        });
+    }
+    for path in paths {
+        println!("{path}:");
+        match config::refusal(path, options) {
+            Some(why) => println!("  not processed, {why}"),
+            None => println!("  processed with the settings above"),
+        }
+        for marker in config::markers(path, options) {
+            println!("  {}: {}", marker.path, marker.effect);
+        }
     }
 }
 
-#[allow(unused)]
-mod jellyfin_flags {
-    pub const DEFAULT: &str = "default";
-    pub const FORCED: &str = "forced";
-    pub const HEARING_IMPAIRED: &str = "cc";
+#[cfg(feature = "jellyfin")]
+fn collection_folders(
+    url: &str,
+    collection: &str,
+) -> anyhow::Result<Vec<Utf8PathBuf>> {
+    use anyhow::Context;
+    use subfix::jellyfin::{Server, API_KEY_VAR};
+
+    let api_key = env::var(API_KEY_VAR).with_context(|| {
+        format!("--jellyfin-collection needs an API key in {API_KEY_VAR}")
+    })?;
+    Server::new(url, api_key).collection_folders(collection)
 }
 
-// Nothing is symlinked except in release builds
-#[cfg(unix)]
-fn symlink(
-    actual_file: impl AsRef<Path>,
-    link_here: impl AsRef<Path>,
-) -> io::Result<()> {
-    use std::os::unix::fs;
-    match cfg!(debug_assertions) {
-        false => fs::symlink(actual_file, link_here),
-        true => Ok(()),
+/// Resolves symlinks and `..` in the folders given, so the same folder given
+/// two ways is only processed once
+fn canonical_roots(
+    paths: impl IntoIterator<Item = Utf8PathBuf>,
+    options: &Options,
+) -> Vec<Utf8PathBuf> {
+    let mut roots: Vec<Utf8PathBuf> = Vec::new();
+    for path in paths {
+        // Ones that can't be resolved don't exist, which is reported later
+        let path = canonicalize(&path).unwrap_or(path);
+        match roots.contains(&path) {
+            true => {
+                info!("{path} was given more than once, only doing it once")
+            },
+            false => roots.push(path),
+        }
+    }
+    // Subtitle folders are searched in full, so one given as a root of its
+    // own would have its subtitles linked from both
+    for inner in &roots {
+        for outer in &roots {
+            let Ok(relative) = inner.strip_prefix(outer) else {
+                continue;
+            };
+            let Some(first) = relative.components().next() else {
+                continue;
+            };
+            let searched = options.all_folders
+                || options.subtitle_folders.iter().any(|folder| {
+                    folder.to_lowercase() == first.as_str().to_lowercase()
+                });
+            if searched {
+                warn!(
+                    "{inner} is also searched for subtitles when doing \
+                     {outer}, so they may be linked to the videos of both"
+                );
+            }
+        }
     }
+    roots
 }
 
-// Nothing is symlinked except in release builds
-#[cfg(windows)]
-fn symlink(
-    actual_file: impl AsRef<Path>,
-    link_here: impl AsRef<Path>,
-) -> io::Result<()> {
-    use std::os::windows::fs;
-    assert!(std::fs::metadata(actual_file.as_ref())?.is_file());
-    match cfg!(debug_assertions) {
-        false => fs::symlink_file(actual_file, link_here),
-        true => Ok(()),
+fn print_version(verbose: bool) {
+    println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    if !verbose {
+        return;
     }
+    println!("platform: {}-{}", env::consts::OS, env::consts::ARCH);
+    println!("build profile: {}", match cfg!(debug_assertions) {
+        true => "debug",
+        false => "release",
+    });
+    println!("symlinks: {}", match can_symlink() {
+        Ok(()) => "supported".to_owned(),
+        Err(_) if is_read_only() => "not checked in read-only mode".to_owned(),
+        Err(why) => format!("unavailable ({why})"),
+    });
+    let features = [
+        ("ocr", cfg!(feature = "ocr")),
+        ("serve", cfg!(feature = "serve")),
+        ("embed", cfg!(feature = "embed")),
+        ("sync", cfg!(feature = "sync")),
+        ("jellyfin", cfg!(feature = "jellyfin")),
+        ("probe", cfg!(feature = "probe")),
+    ]
+    .into_iter()
+    .filter(|(_, on)| *on)
+    .map(|(feature, _)| feature)
+    .collect::<Vec<_>>();
+    println!("optional features: {}", match features.is_empty() {
+        true => "none".to_owned(),
+        false => features.join(", "),
+    });
+    println!("config files: none consulted");
 }
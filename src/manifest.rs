@@ -0,0 +1,273 @@
+//! The record kept in each folder of the links subfix manages there, so
+//! they can be told apart from files that are the user's own
+
+use std::{fs, io, time::UNIX_EPOCH};
+
+use anyhow::{bail, Context};
+use camino::{Utf8Path, Utf8PathBuf};
+use log::debug;
+
+use crate::{
+    fsops::{is_dry_run, is_same_file, remove_file, write_atomically},
+    json::Value,
+    lock::Lock,
+    time::Stamp,
+};
+
+pub const MANIFEST: &str = ".subfix.json";
+
+const VERSION: usize = 1;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Origin {
+    /// Made by subfix
+    Created,
+    /// Made some other way, and taken on with `subfix adopt`
+    Adopted,
+    /// The user's own subtitle, moved from `target` by `--mode rename`. It's
+    /// the only copy, so it's put back rather than removed
+    Moved,
+    /// Put inside the video by `embed`, and the link taken away. Recorded so
+    /// the subtitle isn't linked, and then embedded, again
+    Embedded,
+}
+
+impl Origin {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Origin::Created => "created",
+            Origin::Adopted => "adopted",
+            Origin::Moved => "moved",
+            Origin::Embedded => "embedded",
+        }
+    }
+
+    fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "created" => Some(Origin::Created),
+            "adopted" => Some(Origin::Adopted),
+            "moved" => Some(Origin::Moved),
+            "embedded" => Some(Origin::Embedded),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Entry {
+    /// The link's file name, in the manifest's folder
+    pub name: String,
+    /// Where it points, as it was when recorded. For a copy, or a moved
+    /// subtitle (and the copies of it), where the subtitle was
+    pub target: Utf8PathBuf,
+    /// The file name of the video it's for, if that's known (it isn't for
+    /// links recorded by older versions)
+    pub video: Option<String>,
+    pub origin: Origin,
+    pub recorded: Stamp,
+    /// For a copy, what it was like once written. Links are left out, as
+    /// what they point at is the user's to change
+    pub written: Option<Written>,
+}
+
+/// A copy's size & modification time (in milliseconds since the epoch), so
+/// one that's been edited since isn't removed as if it were still subfix's
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Written {
+    pub size: u64,
+    pub modified: u64,
+}
+
+impl Written {
+    /// What `link` is like now, if it's a copy of `target` rather than a
+    /// symlink or hard link to it
+    pub fn copy(link: &Utf8Path, target: &Utf8Path) -> Option<Self> {
+        if is_same_file(link, target) {
+            return None;
+        }
+        Self::of(&fs::symlink_metadata(link).ok()?)
+    }
+
+    /// What a file is like now, or `None` if it isn't a file
+    pub fn of(metadata: &fs::Metadata) -> Option<Self> {
+        if !metadata.is_file() {
+            return None;
+        }
+        let modified = metadata
+            .modified()
+            .ok()?
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        Some(Written {
+            size: metadata.len(),
+            modified: u64::try_from(modified.as_millis()).ok()?,
+        })
+    }
+
+    fn to_json(self) -> Value {
+        Value::object([
+            ("size", self.size.into()),
+            ("modified", self.modified.into()),
+        ])
+    }
+
+    fn from_json(value: &Value) -> Option<Self> {
+        let number = |key| match value.get(key)? {
+            Value::Number(number) if *number >= 0.0 => Some(*number as u64),
+            _ => None,
+        };
+        Some(Written {
+            size: number("size")?,
+            modified: number("modified")?,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Manifest {
+    path: Utf8PathBuf,
+    entries: Vec<Entry>,
+    changed: bool,
+}
+
+impl Manifest {
+    /// The manifest of a folder, empty if there isn't one yet
+    pub fn load(dir: impl AsRef<Utf8Path>) -> anyhow::Result<Self> {
+        let path = dir.as_ref().join(MANIFEST);
+        let entries = match fs::read_to_string(&path) {
+            Ok(contents) => parse(&contents)
+                .with_context(|| format!("failed to read {path}"))?,
+            Err(why) if why.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(why) => {
+                return Err(why)
+                    .with_context(|| format!("failed to read {path}"))
+            },
+        };
+        debug!("manifest {path} has {} links", entries.len());
+        Ok(Manifest {
+            path,
+            entries,
+            changed: false,
+        })
+    }
+
+    /// A manifest for a folder that records nothing, whatever's there
+    pub fn empty(dir: impl AsRef<Utf8Path>) -> Self {
+        Manifest {
+            path: dir.as_ref().join(MANIFEST),
+            entries: Vec::new(),
+            changed: false,
+        }
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Entry> {
+        self.entries.iter().find(|entry| entry.name == name)
+    }
+
+    /// Whether `embed` has put the subtitle at `target` inside `video` (a
+    /// file name in the manifest's folder) already
+    pub fn is_embedded(&self, video: &str, target: &Utf8Path) -> bool {
+        self.entries.iter().any(|entry| {
+            entry.origin == Origin::Embedded
+                && entry.video.as_deref() == Some(video)
+                && entry.target == target
+        })
+    }
+
+    /// Records a link, replacing what was known about one of the same name
+    pub fn record(&mut self, entry: Entry) {
+        self.forget(&entry.name);
+        self.entries.push(entry);
+        self.changed = true;
+    }
+
+    pub fn forget(&mut self, name: &str) {
+        let before = self.entries.len();
+        self.entries.retain(|entry| entry.name != name);
+        self.changed |= self.entries.len() != before;
+    }
+
+    /// Writes the manifest back if anything changed, removing it once it's
+    /// empty. Nothing's written during a dry run
+    pub fn save(self) -> anyhow::Result<()> {
+        if !self.changed || is_dry_run() {
+            return Ok(());
+        }
+        let _lock = Lock::for_file(&self.path)?;
+        if self.entries.is_empty() {
+            return match remove_file(&self.path) {
+                Ok(()) => Ok(()),
+                Err(why) if why.kind() == io::ErrorKind::NotFound => Ok(()),
+                Err(why) => Err(why)
+                    .with_context(|| format!("failed to remove {}", self.path)),
+            };
+        }
+        let mut entries = self.entries;
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        let links = entries
+            .iter()
+            .map(|entry| {
+                Value::object([
+                    ("name", entry.name.as_str().into()),
+                    ("target", entry.target.as_str().into()),
+                    ("video", entry.video.as_deref().into()),
+                    ("origin", entry.origin.as_str().into()),
+                    ("recorded", entry.recorded.to_json()),
+                    ("written", entry.written.map(Written::to_json).into()),
+                ])
+            })
+            .collect::<Vec<_>>();
+        let contents = Value::object([
+            ("version", VERSION.into()),
+            ("links", links.into()),
+        ]);
+        write_atomically(&self.path, format!("{contents}\n"))
+            .with_context(|| format!("failed to write {}", self.path))
+    }
+}
+
+fn parse(contents: &str) -> anyhow::Result<Vec<Entry>> {
+    let value = contents.parse::<Value>()?;
+    match value.get("version") {
+        Some(Value::Number(version)) if *version == VERSION as f64 => {},
+        Some(Value::Number(version)) => {
+            bail!("version {version} isn't understood by this subfix")
+        },
+        _ => bail!("no version"),
+    }
+    let links = value
+        .get("links")
+        .and_then(Value::as_array)
+        .context("no links")?;
+    links
+        .iter()
+        .map(|link| {
+            let field = |key| {
+                link.get(key)
+                    .and_then(Value::as_str)
+                    .with_context(|| format!("a link has no {key}"))
+            };
+            let origin = field("origin")?;
+            Ok(Entry {
+                name: field("name")?.to_owned(),
+                target: field("target")?.into(),
+                video: link
+                    .get("video")
+                    .and_then(Value::as_str)
+                    .map(str::to_owned),
+                origin: Origin::from_str(origin)
+                    .with_context(|| format!("unknown origin {origin:?}"))?,
+                recorded: link
+                    .get("recorded")
+                    .and_then(Stamp::from_json)
+                    .context("a link has no time it was recorded")?,
+                // Not there for links, or in manifests from before it was
+                written: link.get("written").and_then(Written::from_json),
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,266 @@
+//! Matches written by hand in a folder's `subfix.matches.toml`, for the
+//! folders no amount of guessing gets right. When there's one, it's all
+//! that's linked there: nothing else is looked for or worked out. Only as
+//! much TOML is read as the file needs, a `[[match]]` table per subtitle:
+//!
+//! ```toml
+//! [[match]]
+//! subtitle = "Extras/subs/track3.srt"
+//! video = "Movie (2020).mkv"
+//! language = "en"
+//! flags = ["forced"]
+//! title = "Signs"
+//! ```
+
+use std::{collections::BTreeMap, fs, io};
+
+use anyhow::{anyhow, bail, Context};
+use camino::{Utf8Path, Utf8PathBuf};
+use isolang::Language;
+use log::info;
+
+use crate::{
+    cli::Options,
+    coverage,
+    link::create_symlinks,
+    report::Report,
+    subtitle::{language_from_tag, Flags, Source, Subtitle},
+    video::Video,
+};
+
+pub const MATCHES: &str = "subfix.matches.toml";
+
+/// A subtitle to link to a video, whatever their names say
+#[derive(Debug)]
+pub struct Match {
+    pub subtitle: Utf8PathBuf,
+    pub video: Utf8PathBuf,
+    pub lang: Language,
+    pub flags: Flags,
+    pub title: Option<String>,
+}
+
+/// The matches in `dir`'s matches file, or `None` if it hasn't got one. The
+/// paths in it are relative to `dir`
+pub fn load(dir: &Utf8Path) -> anyhow::Result<Option<Vec<Match>>> {
+    let path = dir.join(MATCHES);
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(why) if why.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(why) => {
+            return Err(why).with_context(|| format!("failed to read {path}"))
+        },
+    };
+    // All in one, as only the outermost context of a folder's error is
+    // reported
+    let matches = parse(dir, &contents)
+        .map_err(|why| anyhow!("failed to read {path}: {why:#}"))?;
+    Ok(Some(matches))
+}
+
+fn parse(dir: &Utf8Path, contents: &str) -> anyhow::Result<Vec<Match>> {
+    let mut tables = Vec::<(usize, BTreeMap<String, Value>)>::new();
+    for (number, line) in contents.lines().enumerate() {
+        let number = number + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line == "[[match]]" {
+            tables.push((number, BTreeMap::new()));
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            bail!("line {number} isn't `key = value` or `[[match]]`");
+        };
+        let Some((_, table)) = tables.last_mut() else {
+            bail!("line {number} comes before the first `[[match]]`");
+        };
+        let value = Value::parse(value.trim())
+            .with_context(|| format!("line {number} has a bad value"))?;
+        if table.insert(key.trim().to_owned(), value).is_some() {
+            bail!("line {number} gives {} again", key.trim());
+        }
+    }
+    tables
+        .into_iter()
+        .map(|(number, table)| {
+            to_match(dir, table)
+                .with_context(|| format!("the match on line {number}"))
+        })
+        .collect()
+}
+
+fn to_match(
+    dir: &Utf8Path,
+    mut table: BTreeMap<String, Value>,
+) -> anyhow::Result<Match> {
+    let mut string = |key: &str| match table.remove(key) {
+        Some(Value::String(value)) => Ok(Some(value)),
+        Some(Value::List(_)) => bail!("{key} should be a string"),
+        None => Ok(None),
+    };
+    let subtitle = dir.join(string("subtitle")?.context("no subtitle")?);
+    let video = dir.join(string("video")?.context("no video")?);
+    let language = string("language")?.context("no language")?;
+    let lang = language_from_tag(&language)
+        .with_context(|| format!("unknown language {language:?}"))?;
+    let title = string("title")?;
+    let mut flags = Flags::default();
+    match table.remove("flags") {
+        Some(Value::List(list)) => {
+            for flag in list {
+                match flag.to_ascii_lowercase().as_str() {
+                    "forced" => flags.forced = true,
+                    "sdh" | "hi" | "cc" | "hearing-impaired" => {
+                        flags.hearing_impaired = true
+                    },
+                    "ad" | "audio-description" => {
+                        flags.audio_description = true
+                    },
+                    _ => bail!(
+                        "unknown flag {flag:?}, expected forced, sdh or ad"
+                    ),
+                }
+            }
+        },
+        Some(Value::String(_)) => bail!("flags should be a list"),
+        None => {},
+    }
+    if let Some(key) = table.keys().next() {
+        bail!("unknown key {key}");
+    }
+    if !subtitle.is_file() {
+        bail!("{subtitle} doesn't exist");
+    }
+    if !video.is_file() || video.parent() != Some(dir) {
+        bail!("{video} isn't a video in {dir}");
+    }
+    Ok(Match {
+        subtitle,
+        video,
+        lang,
+        flags,
+        title,
+    })
+}
+
+enum Value {
+    String(String),
+    List(Vec<String>),
+}
+
+impl Value {
+    fn parse(s: &str) -> anyhow::Result<Self> {
+        let s = without_comment(s);
+        if let Some(list) = s.strip_prefix('[') {
+            let Some(list) = list.strip_suffix(']') else {
+                bail!("lists have to be on one line");
+            };
+            return list
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(string)
+                .collect::<anyhow::Result<_>>()
+                .map(Value::List);
+        }
+        string(s).map(Value::String)
+    }
+}
+
+// A `#` outside of a string starts a comment
+fn without_comment(s: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        match (quote, c) {
+            (Some('"'), '\\') if !escaped => {
+                escaped = true;
+                continue;
+            },
+            (Some(open), _) if c == open && !escaped => quote = None,
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '#') => return s[..i].trim_end(),
+            _ => {},
+        }
+        escaped = false;
+    }
+    s
+}
+
+// Basic strings, with the common escapes, and literal ones
+fn string(s: &str) -> anyhow::Result<String> {
+    if let Some(literal) =
+        s.strip_prefix('\'').and_then(|s| s.strip_suffix('\''))
+    {
+        return Ok(literal.to_owned());
+    }
+    let Some(basic) = s.strip_prefix('"').and_then(|s| s.strip_suffix('"'))
+    else {
+        bail!("expected a quoted string, not {s}");
+    };
+    let mut unescaped = String::with_capacity(basic.len());
+    let mut chars = basic.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('\\') => unescaped.push('\\'),
+            Some('"') => unescaped.push('"'),
+            Some('t') => unescaped.push('\t'),
+            other => bail!(
+                "unsupported escape \\{} (use a '...' string for Windows \
+                 paths)",
+                other.map(String::from).unwrap_or_default()
+            ),
+        }
+    }
+    Ok(unescaped)
+}
+
+/// Links the matches in `dir`, and nothing else
+pub fn link(
+    dir: &Utf8Path,
+    matches: Vec<Match>,
+    options: &Options,
+    report: &mut Report,
+) -> anyhow::Result<()> {
+    info!(
+        "linking the {} matches in {}, not looking for any others",
+        matches.len(),
+        dir.join(MATCHES)
+    );
+    let mut by_video = BTreeMap::<Utf8PathBuf, Vec<Subtitle>>::new();
+    for manual in matches {
+        by_video.entry(manual.video).or_default().push(Subtitle {
+            path: manual.subtitle,
+            lang: manual.lang,
+            series_info: None,
+            part: None,
+            edition: None,
+            source: Source::Other,
+            label: None,
+            flags: manual.flags,
+            title: manual.title,
+            shared: false,
+        });
+    }
+    let mut videos = Vec::new();
+    for (video, subtitles) in by_video {
+        // Nothing about the video matters but where it is
+        let video = vec![Video {
+            path: video,
+            series_info: None,
+            edition: None,
+            part: None,
+            runtime: None,
+        }];
+        create_symlinks(dir, &video, &subtitles, options, report)?;
+        videos.extend(video);
+    }
+    coverage::record(&videos, report);
+    Ok(())
+}
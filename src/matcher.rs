@@ -0,0 +1,386 @@
+//! The evidence used to decide which videos a subtitle is for. Each matcher
+//! gives a verdict (or abstains), and the verdicts are weighed up in order
+
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    str::FromStr,
+};
+
+use anyhow::{anyhow, bail, Context};
+use camino::Utf8PathBuf;
+use log::trace;
+
+use crate::{
+    content::hash_file,
+    discovery::Listing,
+    ids::{self, ExternalId},
+    predicates::title_of,
+    subtitle::Subtitle,
+    video::Video,
+};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Verdict {
+    Match,
+    Mismatch,
+}
+
+pub trait Matcher {
+    /// Whether `subtitle` is for `video`, or `None` if this matcher can't
+    /// tell
+    fn judge(&self, video: &Video, subtitle: &Subtitle) -> Option<Verdict>;
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum MatcherKind {
+    /// Subtitles and videos need the same season & episode
+    SeriesInfo,
+    /// Subtitles in a folder named after a video are only for that video
+    PerVideoFolder,
+    /// Subtitles named after a video, like `Movie.en.srt`, are for it
+    ExactStem,
+    /// Subtitles whose path mentions the video's title are for it
+    FuzzyTitle,
+    /// Subtitles identical to one a video already has are for that video,
+    /// and not the others
+    ContentHash,
+    /// Subtitles in a folder named by an IMDb or TMDB id are for the video
+    /// with that id
+    ExternalId,
+}
+
+impl MatcherKind {
+    const ALL: [MatcherKind; 6] = [
+        MatcherKind::SeriesInfo,
+        MatcherKind::PerVideoFolder,
+        MatcherKind::ExactStem,
+        MatcherKind::FuzzyTitle,
+        MatcherKind::ContentHash,
+        MatcherKind::ExternalId,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MatcherKind::SeriesInfo => "series-info",
+            MatcherKind::PerVideoFolder => "per-video-folder",
+            MatcherKind::ExactStem => "exact-stem",
+            MatcherKind::FuzzyTitle => "fuzzy-title",
+            MatcherKind::ContentHash => "content-hash",
+            MatcherKind::ExternalId => "external-id",
+        }
+    }
+
+    fn default_weight(self) -> i32 {
+        match self {
+            MatcherKind::SeriesInfo => 10,
+            MatcherKind::ExternalId => 9,
+            MatcherKind::ContentHash => 8,
+            MatcherKind::PerVideoFolder => 5,
+            MatcherKind::ExactStem => 2,
+            MatcherKind::FuzzyTitle => 1,
+        }
+    }
+}
+
+impl fmt::Display for MatcherKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A matcher to run and how much its verdict counts for
+#[derive(Debug, Copy, Clone)]
+pub struct MatcherConfig {
+    pub kind: MatcherKind,
+    pub weight: i32,
+}
+
+impl MatcherConfig {
+    pub fn defaults() -> Vec<Self> {
+        [
+            MatcherKind::SeriesInfo,
+            MatcherKind::ExternalId,
+            MatcherKind::PerVideoFolder,
+            MatcherKind::ExactStem,
+        ]
+        .into_iter()
+        .map(|kind| MatcherConfig {
+            kind,
+            weight: kind.default_weight(),
+        })
+        .collect()
+    }
+}
+
+/// Parses `name` or `name:weight`
+impl FromStr for MatcherConfig {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, weight) = match s.split_once(':') {
+            Some((name, weight)) => (name, Some(weight)),
+            None => (s, None),
+        };
+        let kind = MatcherKind::ALL
+            .into_iter()
+            .find(|kind| kind.as_str() == name)
+            .ok_or_else(|| {
+                anyhow!(
+                    "unknown matcher {name:?}, expected one of {}",
+                    MatcherKind::ALL.map(MatcherKind::as_str).join(", ")
+                )
+            })?;
+        let weight = match weight {
+            Some(weight) => weight
+                .parse()
+                .with_context(|| format!("invalid weight {weight:?}"))?,
+            None => kind.default_weight(),
+        };
+        if weight <= 0 {
+            bail!("{name} needs a positive weight");
+        }
+        Ok(MatcherConfig { kind, weight })
+    }
+}
+
+/// As it's parsed, `name:weight`
+impl fmt::Display for MatcherConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.kind, self.weight)
+    }
+}
+
+/// The configured matchers, ready to judge the videos of one directory
+pub struct Chain {
+    matchers: Vec<(MatcherKind, Box<dyn Matcher>, i32)>,
+    /// Whether it takes a matcher saying so for a subtitle to match, rather
+    /// than just none saying otherwise
+    evidence_needed: bool,
+}
+
+impl Chain {
+    pub fn new(config: &[MatcherConfig], videos: &[Video]) -> Self {
+        let matchers = config
+            .iter()
+            .map(|config| {
+                let matcher: Box<dyn Matcher> = match config.kind {
+                    MatcherKind::SeriesInfo => Box::new(SeriesInfoMatcher),
+                    MatcherKind::PerVideoFolder => {
+                        Box::new(PerVideoFolder::new(videos))
+                    },
+                    MatcherKind::ExactStem => Box::new(ExactStem),
+                    MatcherKind::FuzzyTitle => Box::new(FuzzyTitle),
+                    MatcherKind::ContentHash => {
+                        Box::new(ContentHash::new(videos))
+                    },
+                    MatcherKind::ExternalId => {
+                        Box::new(ExternalIdMatcher::new(videos))
+                    },
+                };
+                (config.kind, matcher, config.weight)
+            })
+            .collect();
+        Chain {
+            matchers,
+            evidence_needed: false,
+        }
+    }
+
+    /// Only matches pairs some matcher vouches for, for folders of videos
+    /// that each have their own subtitles
+    pub fn needing_evidence(mut self, needed: bool) -> Self {
+        self.evidence_needed = needed;
+        self
+    }
+
+    /// What each matcher made of the pair, in order
+    pub fn verdicts(
+        &self,
+        video: &Video,
+        subtitle: &Subtitle,
+    ) -> Vec<(MatcherKind, Verdict)> {
+        self.matchers
+            .iter()
+            .filter_map(|(kind, matcher, _)| {
+                Some((*kind, matcher.judge(video, subtitle)?))
+            })
+            .collect()
+    }
+
+    /// Matchers that abstain don't count either way, so with no evidence at
+    /// all (like a single movie) everything matches, unless evidence is
+    /// needed
+    pub fn matches(&self, video: &Video, subtitle: &Subtitle) -> bool {
+        let score = self
+            .matchers
+            .iter()
+            .filter_map(|(kind, matcher, weight)| {
+                let verdict = matcher.judge(video, subtitle)?;
+                trace!("{kind}: {} {verdict:?} {}", subtitle.path, video.path);
+                Some(match verdict {
+                    Verdict::Match => *weight,
+                    Verdict::Mismatch => -weight,
+                })
+            })
+            .sum::<i32>();
+        match self.evidence_needed {
+            true => score > 0,
+            false => score >= 0,
+        }
+    }
+}
+
+struct SeriesInfoMatcher;
+
+impl Matcher for SeriesInfoMatcher {
+    fn judge(&self, video: &Video, subtitle: &Subtitle) -> Option<Verdict> {
+        match (video.series_info, subtitle.series_info) {
+            (None, None) => None,
+            (video, subtitle) if video == subtitle => Some(Verdict::Match),
+            _ => Some(Verdict::Mismatch),
+        }
+    }
+}
+
+struct PerVideoFolder {
+    stems: BTreeSet<String>,
+}
+
+impl PerVideoFolder {
+    fn new(videos: &[Video]) -> Self {
+        let stems = videos
+            .iter()
+            .filter_map(|video| Some(video.path.file_stem()?.to_owned()))
+            .collect();
+        PerVideoFolder { stems }
+    }
+}
+
+impl Matcher for PerVideoFolder {
+    fn judge(&self, video: &Video, subtitle: &Subtitle) -> Option<Verdict> {
+        let dir = video.path.parent()?;
+        let folder = subtitle
+            .path
+            .strip_prefix(dir)
+            .ok()?
+            .components()
+            .next()?
+            .as_str();
+        if !self.stems.contains(folder) {
+            return None;
+        }
+        match video.path.file_stem() == Some(folder) {
+            true => Some(Verdict::Match),
+            false => Some(Verdict::Mismatch),
+        }
+    }
+}
+
+struct ExternalIdMatcher {
+    ids: BTreeMap<Utf8PathBuf, Vec<ExternalId>>,
+}
+
+impl ExternalIdMatcher {
+    fn new(videos: &[Video]) -> Self {
+        let ids = videos
+            .iter()
+            .map(|video| (video.path.clone(), ids::of_video(video)))
+            .collect();
+        ExternalIdMatcher { ids }
+    }
+}
+
+impl Matcher for ExternalIdMatcher {
+    fn judge(&self, video: &Video, subtitle: &Subtitle) -> Option<Verdict> {
+        let video_ids = self.ids.get(&video.path)?;
+        let subtitle_ids =
+            ids::in_path_below(&subtitle.path, video.path.parent()?);
+        if video_ids.is_empty() || subtitle_ids.is_empty() {
+            return None;
+        }
+        match subtitle_ids.iter().any(|id| video_ids.contains(id)) {
+            true => Some(Verdict::Match),
+            false => Some(Verdict::Mismatch),
+        }
+    }
+}
+
+struct ExactStem;
+
+impl Matcher for ExactStem {
+    fn judge(&self, video: &Video, subtitle: &Subtitle) -> Option<Verdict> {
+        let stem = video.path.file_stem()?;
+        let tags = subtitle.path.file_stem()?.strip_prefix(stem)?;
+        (tags.is_empty() || tags.starts_with('.')).then_some(Verdict::Match)
+    }
+}
+
+struct FuzzyTitle;
+
+impl Matcher for FuzzyTitle {
+    fn judge(&self, video: &Video, subtitle: &Subtitle) -> Option<Verdict> {
+        let title = normalise(&title_of(video.path.file_stem()?));
+        if title.is_empty() {
+            return None;
+        }
+        normalise(subtitle.path.as_str())
+            .contains(&title)
+            .then_some(Verdict::Match)
+    }
+}
+
+fn normalise(s: &str) -> String {
+    s.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+struct ContentHash {
+    /// The hashes of each video's existing subtitles
+    hashes: Vec<(String, BTreeSet<u64>)>,
+    /// Candidates are judged against every video, so only read them once
+    candidates: RefCell<BTreeMap<Utf8PathBuf, Option<u64>>>,
+}
+
+impl ContentHash {
+    fn new(videos: &[Video]) -> Self {
+        let listing = videos.first().map(Listing::around).unwrap_or_default();
+        let hashes = videos
+            .iter()
+            .map(|video| {
+                let hashes = listing
+                    .sidecars(video, |_| true)
+                    .iter()
+                    .filter_map(|path| hash_file(path).ok())
+                    .collect();
+                (video.path.to_string(), hashes)
+            })
+            .collect();
+        ContentHash {
+            hashes,
+            candidates: RefCell::default(),
+        }
+    }
+}
+
+impl Matcher for ContentHash {
+    fn judge(&self, video: &Video, subtitle: &Subtitle) -> Option<Verdict> {
+        let hash = *self
+            .candidates
+            .borrow_mut()
+            .entry(subtitle.path.clone())
+            .or_insert_with(|| hash_file(&subtitle.path).ok());
+        let hash = hash?;
+        let (owner, _) = self
+            .hashes
+            .iter()
+            .find(|(_, hashes)| hashes.contains(&hash))?;
+        match owner == video.path.as_str() {
+            true => Some(Verdict::Match),
+            false => Some(Verdict::Mismatch),
+        }
+    }
+}
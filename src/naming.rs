@@ -0,0 +1,383 @@
+//! How Jellyfin reads the names of external subtitles, and so how links are
+//! named: `<video stem>[.<title>].<language>[.default][.forced][.cc].<ext>`,
+//! as in its docs. The video's whole stem has to come first, the language
+//! is an ISO 639 code (or a name), the flags can be in any order, and
+//! anything else between the dots is shown as the track's title. Kept apart
+//! from matching so the rules can be checked (and fixed) on their own
+
+use std::{fmt, str::FromStr};
+
+use anyhow::bail;
+use isolang::Language;
+use log::warn;
+
+use crate::subtitle::{language_from_tag, Flags};
+
+/// How links are named. Jellyfin finds the language and flags wherever they
+/// are, but other players are fussier
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum Naming {
+    /// `Movie.en.default.srt`, as in Jellyfin's docs
+    #[default]
+    Jellyfin,
+    /// `Movie.default.en.srt`, for mpv & Kodi too, which only look for the
+    /// language right before the extension
+    MultiPlayer,
+}
+
+impl FromStr for Naming {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "jellyfin" => Ok(Naming::Jellyfin),
+            "multi-player" | "mpv" | "kodi" => Ok(Naming::MultiPlayer),
+            _ => {
+                bail!("unknown naming {s:?}, expected jellyfin or multi-player")
+            },
+        }
+    }
+}
+
+impl fmt::Display for Naming {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Naming::Jellyfin => "jellyfin",
+            Naming::MultiPlayer => "multi-player",
+        })
+    }
+}
+
+/// How languages are written in names, where there's a choice. Jellyfin
+/// reads all of them
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum LanguageCodes {
+    /// `en`, or three letters for languages without a two letter code
+    #[default]
+    TwoLetter,
+    /// ISO 639-2/T, like `deu` & `fra`, which is what ISO 639-3 uses too
+    Terminological,
+    /// ISO 639-2/B, like `ger` & `fre`, for the few languages it differs in
+    Bibliographic,
+}
+
+impl LanguageCodes {
+    pub fn code(self, lang: Language) -> &'static str {
+        match self {
+            LanguageCodes::TwoLetter => {
+                lang.to_639_1().unwrap_or(lang.to_639_3())
+            },
+            LanguageCodes::Terminological => lang.to_639_3(),
+            LanguageCodes::Bibliographic => {
+                let terminological = lang.to_639_3();
+                BIBLIOGRAPHIC
+                    .iter()
+                    .find(|(t, _)| *t == terminological)
+                    .map(|(_, b)| *b)
+                    .unwrap_or(terminological)
+            },
+        }
+    }
+}
+
+impl FromStr for LanguageCodes {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "two-letter" | "639-1" => Ok(LanguageCodes::TwoLetter),
+            "terminological" | "639-2t" => Ok(LanguageCodes::Terminological),
+            "bibliographic" | "639-2b" => Ok(LanguageCodes::Bibliographic),
+            _ => bail!(
+                "unknown language codes {s:?}, expected two-letter, \
+                 terminological or bibliographic"
+            ),
+        }
+    }
+}
+
+impl fmt::Display for LanguageCodes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LanguageCodes::TwoLetter => "two-letter",
+            LanguageCodes::Terminological => "terminological",
+            LanguageCodes::Bibliographic => "bibliographic",
+        })
+    }
+}
+
+/// The languages whose ISO 639-2 bibliographic code isn't the terminological
+/// one, as `(terminological, bibliographic)`
+pub const BIBLIOGRAPHIC: &[(&str, &str)] = &[
+    ("bod", "tib"),
+    ("ces", "cze"),
+    ("cym", "wel"),
+    ("deu", "ger"),
+    ("ell", "gre"),
+    ("eus", "baq"),
+    ("fas", "per"),
+    ("fra", "fre"),
+    ("hye", "arm"),
+    ("isl", "ice"),
+    ("kat", "geo"),
+    ("mkd", "mac"),
+    ("mri", "mao"),
+    ("msa", "may"),
+    ("mya", "bur"),
+    ("nld", "dut"),
+    ("ron", "rum"),
+    ("slk", "slo"),
+    ("sqi", "alb"),
+    ("zho", "chi"),
+];
+
+/// The flags Jellyfin understands, as they're written in names
+pub mod flags {
+    pub const DEFAULT: &str = "default";
+    pub const FORCED: &str = "forced";
+    /// Jellyfin also takes `sdh` & `hi`, but `cc` is what it documents
+    pub const HEARING_IMPAIRED: &str = "cc";
+}
+
+/// What goes after the video's stem in a subtitle's name
+#[derive(Debug, Clone, Copy)]
+pub struct Tags<'a> {
+    /// From [`language_tag`]
+    pub lang: &'a str,
+    /// Picked by players when nothing else is
+    pub default: bool,
+    pub flags: Flags,
+    /// Without the `.`
+    pub extension: &'a str,
+}
+
+/// How a language is written in a name: the subtitle's own label if it has
+/// one, otherwise its code
+pub fn language_tag(
+    lang: Language,
+    label: Option<&str>,
+    codes: LanguageCodes,
+) -> &str {
+    match label {
+        Some(label) => label,
+        None => codes.code(lang),
+    }
+}
+
+/// The name for a subtitle of a video with the given stem, with the title
+/// shortened to fit in `max_length` bytes. `None` if it wouldn't fit even
+/// without a title
+pub fn file_name(
+    video_stem: &str,
+    title: Option<&str>,
+    tags: Tags,
+    naming: Naming,
+    max_length: usize,
+) -> Option<String> {
+    let mut suffix = String::new();
+    if naming == Naming::Jellyfin {
+        suffix.push('.');
+        suffix.push_str(tags.lang);
+    }
+    if tags.default {
+        suffix.push('.');
+        suffix.push_str(flags::DEFAULT)
+    }
+    if tags.flags.forced {
+        suffix.push('.');
+        suffix.push_str(flags::FORCED)
+    }
+    if tags.flags.hearing_impaired {
+        suffix.push('.');
+        suffix.push_str(flags::HEARING_IMPAIRED)
+    }
+    if naming == Naming::MultiPlayer {
+        suffix.push('.');
+        suffix.push_str(tags.lang);
+    }
+    suffix.push('.');
+    suffix.push_str(tags.extension);
+    // Jellyfin needs the whole video name, so only the title can give way
+    let room = max_length.checked_sub(video_stem.len() + suffix.len())?;
+    let full_title = title;
+    let title = title
+        .map(|title| truncate(title, room.saturating_sub(1)))
+        .filter(|title| !title.is_empty());
+    if let Some(full_title) = full_title {
+        if title.map(str::len) != Some(full_title.len()) {
+            warn!(
+                "shortening the title {full_title:?} so the name of the link \
+                 for {video_stem} isn't too long"
+            );
+        }
+    }
+    let mut file_name = video_stem.to_owned();
+    if let Some(title) = title {
+        file_name.push('.');
+        file_name.push_str(title);
+    }
+    file_name.push_str(&suffix);
+    Some(file_name)
+}
+
+// Cuts between words where it can, and at least between characters, without
+// leaving a dangling separator
+fn truncate(s: &str, max_length: usize) -> &str {
+    if s.len() <= max_length {
+        return s;
+    }
+    let mut end = max_length;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    let mut cut = &s[..end];
+    if !s[end..].starts_with(char::is_whitespace) {
+        if let Some((words, _)) = cut.rsplit_once(char::is_whitespace) {
+            cut = words;
+        }
+    }
+    cut.trim_end_matches(|c: char| {
+        c.is_whitespace() || matches!(c, '.' | '-' | '_' | '&' | ',')
+    })
+}
+
+/// The language of a subtitle named after a video, the way Jellyfin would
+/// read it: the first tag after the stem that's a language, so
+/// `Movie.Director's Cut.fr.forced.srt` is French for `Movie`
+pub fn sidecar_language(
+    video_stem: &str,
+    sidecar_stem: &str,
+) -> Option<Language> {
+    let tags = sidecar_stem.strip_prefix(video_stem)?.strip_prefix('.')?;
+    tags.split('.').find_map(language_from_tag)
+}
+
+/// Names Jellyfin gives a meaning of their own when they're next to a video:
+/// images, extras, and the folder trickplay thumbnails are kept in
+const RESERVED_SUFFIXES: &[&str] = &[
+    "-thumb",
+    "-fanart",
+    "-poster",
+    "-banner",
+    "-logo",
+    "-clearart",
+    "-landscape",
+    "-disc",
+    "-trailer",
+    "-sample",
+    "-featurette",
+    "-behindthescenes",
+    "-deleted",
+    "-deletedscene",
+    "-interview",
+    "-scene",
+    "-clip",
+    "-short",
+    "-extra",
+    "-other",
+];
+const TRICKPLAY: &str = "trickplay";
+
+/// Whether a title would make the name look like one of Jellyfin's reserved
+/// ones, which can trip up library scans
+pub fn is_reserved(title: &str) -> bool {
+    let lowercase = title.to_lowercase();
+    lowercase == TRICKPLAY
+        || RESERVED_SUFFIXES
+            .iter()
+            .any(|suffix| lowercase.ends_with(suffix))
+}
+
+/// A title to use instead of a reserved one, with the suffix's `-` made a
+/// space (`Commentary-Trailer` becomes `Commentary Trailer`), or `None` if
+/// there's nothing to keep
+pub fn unreserved(title: &str) -> Option<String> {
+    let lowercase = title.to_lowercase();
+    if lowercase == TRICKPLAY {
+        return None;
+    }
+    let safe = match RESERVED_SUFFIXES
+        .iter()
+        .find(|suffix| lowercase.ends_with(*suffix))
+    {
+        Some(suffix) => {
+            let (name, rest) = title.split_at(title.len() - suffix.len());
+            format!("{name} {}", &rest[1..]).trim().to_owned()
+        },
+        None => title.to_owned(),
+    };
+    Some(safe).filter(|safe| !safe.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Names from `tests/naming`, with what they're made from
+    const CORPUS: &str = include_str!("../tests/naming/jellyfin.txt");
+
+    #[test]
+    fn jellyfin_names() {
+        let mut cases = 0;
+        for line in CORPUS.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let columns = line.split('|').map(str::trim).collect::<Vec<_>>();
+            let [stem, title, lang, codes, flags, naming, extension, expected] =
+                columns[..]
+            else {
+                panic!("{line:?} should have 8 columns");
+            };
+            let lang = Language::from_639_3(lang).unwrap();
+            let flag = |name| flags.split(',').any(|flag| flag.trim() == name);
+            let tags = Tags {
+                lang: language_tag(lang, None, codes.parse().unwrap()),
+                default: flag(flags::DEFAULT),
+                flags: Flags {
+                    forced: flag(flags::FORCED),
+                    hearing_impaired: flag(flags::HEARING_IMPAIRED),
+                    audio_description: false,
+                },
+                extension,
+            };
+            let title = Some(title).filter(|title| !title.is_empty());
+            let name =
+                file_name(stem, title, tags, naming.parse().unwrap(), 255);
+            assert_eq!(name.as_deref(), Some(expected), "{line}");
+            let expected_stem = expected.strip_suffix(extension).unwrap();
+            let expected_stem = expected_stem.strip_suffix('.').unwrap();
+            assert_eq!(
+                sidecar_language(stem, expected_stem),
+                Some(lang),
+                "{line}"
+            );
+            cases += 1;
+        }
+        assert!(cases > 20);
+    }
+
+    #[test]
+    fn only_the_title_gives_way() {
+        let tags = Tags {
+            lang: "en",
+            default: true,
+            flags: Flags::default(),
+            extension: "srt",
+        };
+        let name = |max| {
+            file_name(
+                "Film",
+                Some("A Long Commentary"),
+                tags,
+                Naming::Jellyfin,
+                max,
+            )
+        };
+        assert_eq!(name(255).unwrap(), "Film.A Long Commentary.en.default.srt");
+        assert_eq!(name(27).unwrap(), "Film.A Long.en.default.srt");
+        assert_eq!(name(19).unwrap(), "Film.en.default.srt");
+        assert_eq!(name(18), None);
+    }
+}
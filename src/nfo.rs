@@ -0,0 +1,71 @@
+//! Episode titles from the `.nfo` files media managers leave next to videos,
+//! for matching subtitles named only by title
+
+use std::fs;
+
+use log::{debug, info};
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use crate::{series::SeriesInfo, stats, video::Video};
+
+static TITLE_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?s)<title>\s*(.*?)\s*</title>").unwrap());
+
+/// Short titles (like "Pilot") are only trusted as the whole name
+const MIN_SUFFIX_TITLE: usize = 8;
+
+/// The title of each episode with a `.nfo` alongside it
+pub fn episode_titles(videos: &[Video]) -> Vec<(String, SeriesInfo)> {
+    videos
+        .iter()
+        .filter_map(|video| {
+            let series_info = video.series_info?;
+            let nfo = video.path.with_extension("nfo");
+            let contents = fs::read_to_string(&nfo).ok()?;
+            stats::add_bytes_read(contents.len());
+            let title = TITLE_REGEX.captures(&contents)?.get(1)?.as_str();
+            let title = unescape(title);
+            debug!("{nfo} says {series_info} is {title:?}");
+            Some((normalise(&title), series_info))
+        })
+        .filter(|(title, _)| !title.is_empty())
+        .collect()
+}
+
+/// Finds the episode a subtitle is named after, e.g. `Friends - The One
+/// Where Monica Gets a Roommate`
+pub fn match_title(
+    stem: &str,
+    titles: &[(String, SeriesInfo)],
+) -> Option<SeriesInfo> {
+    let stem = normalise(stem);
+    let (title, series_info) = titles.iter().find(|(title, _)| {
+        stem == *title
+            || (title.len() >= MIN_SUFFIX_TITLE
+                && stem.ends_with(&format!(" {title}")))
+    })?;
+    info!("{stem:?} is named after {series_info}, {title:?}");
+    Some(*series_info)
+}
+
+// Case, punctuation & spacing vary between where names come from
+fn normalise(s: &str) -> String {
+    s.chars()
+        .map(|c| match c.is_alphanumeric() {
+            true => c.to_lowercase().next().unwrap_or(c),
+            false => ' ',
+        })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn unescape(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
@@ -0,0 +1,47 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::bail;
+
+/// Line endings for subtitle files subfix writes. Whatever the choice, the
+/// UTF-8 BOM is always dropped as some Jellyfin web clients show it as a stray
+/// character in the first cue
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+}
+
+impl FromStr for LineEnding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "lf" | "unix" => Ok(LineEnding::Lf),
+            "crlf" | "windows" => Ok(LineEnding::CrLf),
+            _ => bail!("unknown line ending {s:?}, expected lf or crlf"),
+        }
+    }
+}
+
+impl fmt::Display for LineEnding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LineEnding::Lf => "lf",
+            LineEnding::CrLf => "crlf",
+        })
+    }
+}
+
+pub fn strip_bom(s: &str) -> &str {
+    s.strip_prefix('\u{feff}').unwrap_or(s)
+}
@@ -0,0 +1,104 @@
+//! Subtitles named only `1.srt` to `N.srt`, for a folder of `N` episodes,
+//! which can only be matched up by counting
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use camino::Utf8Path;
+use log::{info, warn};
+
+use crate::{
+    interactive, predicates, report::Report, subtitle::Subtitle, video::Video,
+};
+
+/// Gives numbered subtitles the episode at the same place in the order.
+/// That's only trusted without asking when the numbers are the episode
+/// numbers, i.e. the videos are episodes 1 to `N` of one season. Otherwise,
+/// it's only done if the user confirms it with `interactive`
+pub fn align(
+    videos: &[Video],
+    subs: &mut [Subtitle],
+    interactive: bool,
+    report: &mut Report,
+) -> anyhow::Result<()> {
+    if !predicates::all_a_series(videos) {
+        return Ok(());
+    }
+    // Versions of the same episode only count once
+    let episodes = videos
+        .iter()
+        .filter_map(|video| video.series_info)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect::<Vec<_>>();
+    let mut folders = BTreeMap::<&Utf8Path, Vec<(usize, usize)>>::new();
+    for (index, sub) in subs.iter().enumerate() {
+        if sub.series_info.is_some() {
+            continue;
+        }
+        let (Some(folder), Some(number)) = (sub.path.parent(), number(sub))
+        else {
+            continue;
+        };
+        folders.entry(folder).or_default().push((number, index));
+    }
+    let mut aligned = Vec::new();
+    for (folder, mut numbered) in folders {
+        numbered.sort_unstable();
+        let numbers = numbered.iter().map(|&(number, _)| number);
+        if numbered.len() != episodes.len() || !numbers.eq(1..=episodes.len()) {
+            continue;
+        }
+        let first = episodes[0];
+        let lines_up = episodes.iter().enumerate().all(|(index, episode)| {
+            episode.season() == first.season()
+                && usize::from(episode.episode()) == index + 1
+        });
+        let last = episodes[episodes.len() - 1];
+        if !lines_up {
+            let question = format!(
+                "{folder} has subtitles numbered 1 to {}, and there are {} \
+                 episodes ({first} to {last}). Match them up in order?",
+                numbered.len(),
+                episodes.len(),
+            );
+            if !interactive {
+                info!(
+                    "{folder} has as many numbered subtitles as there are \
+                     episodes, but they don't line up, so not guessing (see \
+                     --interactive)"
+                );
+                continue;
+            }
+            if !interactive::confirm(&question)? {
+                continue;
+            }
+        }
+        warn!(
+            "matching the numbered subtitles in {folder} to {first} to {last} \
+             by counting"
+        );
+        aligned.extend(
+            numbered
+                .into_iter()
+                .zip(&episodes)
+                .map(|((_, index), episode)| (index, *episode)),
+        );
+    }
+    for (index, episode) in aligned {
+        let sub = &mut subs[index];
+        sub.series_info = Some(episode);
+        report.review.push(format!(
+            "{} was taken to be {episode} by counting, not from its name",
+            sub.path
+        ));
+    }
+    Ok(())
+}
+
+fn number(sub: &Subtitle) -> Option<usize> {
+    let stem = sub.path.file_stem()?;
+    if stem.is_empty() || !stem.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    stem.parse().ok()
+}
@@ -0,0 +1,52 @@
+//! Turns image-based subtitles into text ones, which Jellyfin can search and
+//! burn in without transcoding the whole picture
+
+use std::{process::Command, time::Instant};
+
+use log::{info, warn};
+
+use crate::{
+    fsops::run_creating,
+    report::{dry_run_prefix, Report},
+    subtitle::Subtitle,
+};
+
+/// Replaces VobSub (`.idx`/`.sub`) subtitles with SubRip ones read by
+/// `vobsub2srt`, which uses tesseract. Ones that already have a text version
+/// with the same name are dropped in favour of it
+pub fn convert_vobsubs(subs: &mut Vec<Subtitle>, report: &mut Report) {
+    subs.retain_mut(|sub| {
+        if !sub
+            .path
+            .extension()
+            .map(|ext| ext.eq_ignore_ascii_case("idx"))
+            .unwrap_or_default()
+        {
+            return true;
+        }
+        let srt = sub.path.with_extension("srt");
+        if srt.is_file() {
+            info!("{} already has a text version, using that", sub.path);
+            return false;
+        }
+        info!(
+            "{}reading {} with OCR into {srt}",
+            dry_run_prefix(),
+            sub.path
+        );
+        let mut vobsub2srt = Command::new("vobsub2srt");
+        vobsub2srt
+            .args(["--tesseract-lang", sub.lang.to_639_3()])
+            // It wants the name without the extension, and adds `.srt`
+            .arg(sub.path.with_extension(""));
+        let start = Instant::now();
+        let converted = run_creating(&mut vobsub2srt, &srt);
+        report.stats.execute += start.elapsed();
+        match converted {
+            Ok(()) => sub.path = srt,
+            // The image-based subtitle is still better than nothing
+            Err(why) => warn!("couldn't OCR {}: {why}", sub.path),
+        }
+        true
+    });
+}
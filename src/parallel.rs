@@ -0,0 +1,40 @@
+//! Spreading the work on each file of a folder over threads, for season
+//! packs with a thousand subtitles to read
+
+use std::{num::NonZeroUsize, thread};
+
+/// How many threads to use when `--jobs` isn't given
+pub fn default_jobs() -> usize {
+    thread::available_parallelism()
+        .map(NonZeroUsize::get)
+        .unwrap_or(1)
+}
+
+/// `items.iter().map(f)`, with the items split between up to `jobs`
+/// threads. The results are in the same order as the items
+pub fn map<T, R>(items: &[T], jobs: usize, f: impl Fn(&T) -> R + Sync) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+{
+    if jobs <= 1 || items.len() <= 1 {
+        return items.iter().map(f).collect();
+    }
+    let chunk = (items.len() + jobs - 1) / jobs;
+    let f = &f;
+    thread::scope(|scope| {
+        let workers = items
+            .chunks(chunk)
+            .map(|items| {
+                scope.spawn(move || items.iter().map(f).collect::<Vec<R>>())
+            })
+            .collect::<Vec<_>>();
+        workers
+            .into_iter()
+            .flat_map(|worker| match worker.join() {
+                Ok(results) => results,
+                Err(panic) => std::panic::resume_unwind(panic),
+            })
+            .collect::<Vec<R>>()
+    })
+}
@@ -0,0 +1,244 @@
+use std::ffi::OsStr;
+
+use camino::Utf8Path;
+use isolang::Language;
+use log::{debug, error, info, trace};
+use once_cell::sync::Lazy;
+use regex::{Regex, RegexBuilder};
+use walkdir::DirEntry;
+
+use crate::{
+    edition, series::normalise_width, subtitle::split_part_suffix, video::Video,
+};
+
+const VIDEO_EXTENSIONS: &[&str] = &["mp4", "mkv", "avi"];
+const SUBTITLE_EXTENSIONS: &[&str] = &["srt", "vtt", "idx", "ass", "dts"];
+
+// Scene tags for the language of a release, e.g. `Movie.2010.MULTi.1080p`.
+// Only matched in capitals (bar the odd `i`), so titles like "The French
+// Connection" aren't mistaken for them. Languages are given where a subtitle
+// tagged this way would be in it
+const LANGUAGE_TAGS: &[(&str, Option<Language>)] = &[
+    ("MULTi", None),
+    ("MULTI", None),
+    ("TRUEFRENCH", Some(Language::Fra)),
+    ("FRENCH", Some(Language::Fra)),
+    ("SUBFRENCH", Some(Language::Fra)),
+    ("VOSTFR", Some(Language::Fra)),
+    ("VFF", Some(Language::Fra)),
+    ("VFQ", Some(Language::Fra)),
+    ("VFI", Some(Language::Fra)),
+    ("VF2", Some(Language::Fra)),
+    ("GERMAN", Some(Language::Deu)),
+    ("DL", None),
+    ("iTALiAN", Some(Language::Ita)),
+    ("ITALIAN", Some(Language::Ita)),
+    ("ITA", Some(Language::Ita)),
+    ("SPANiSH", Some(Language::Spa)),
+    ("SPANISH", Some(Language::Spa)),
+    ("CASTELLANO", Some(Language::Spa)),
+    ("LATINO", Some(Language::Spa)),
+    ("NORDiC", None),
+    ("DUBBED", None),
+    ("SUBBED", None),
+];
+
+static LANGUAGE_TAGS_PATTERN: Lazy<String> = Lazy::new(|| {
+    let tags = LANGUAGE_TAGS
+        .iter()
+        .map(|(tag, _)| regex::escape(tag))
+        .collect::<Vec<_>>();
+    format!(r"(?-i:[ ._](?P<tag>{})\b)", tags.join("|"))
+});
+
+static LANGUAGE_TAG_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(&LANGUAGE_TAGS_PATTERN).unwrap());
+
+static TRAILING_LANGUAGE_TAG_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(&format!("{}$", LANGUAGE_TAGS_PATTERN.as_str())).unwrap()
+});
+
+// Language tags before the quality are part of the suffix, so versions of a
+// movie in different languages still have the same name
+static SEASON_AND_QUALITY_SUFFIX_REGEX: Lazy<Regex> = Lazy::new(|| {
+    RegexBuilder::new(&format!(
+        r"([ .]S\d{{2}}E\d{{2}}|\s*第\s*\d+\s*[集话話])?{}*( - |[ ._])((480p)|(576p)|(720p)|(1080p)|(2160p)|(4K( HDR)?)|(UHD))\b",
+        LANGUAGE_TAGS_PATTERN.as_str(),
+    ))
+    .case_insensitive(true)
+    .build()
+    .unwrap()
+});
+
+// Trailing `[GROUP]`, `{edition-...}`, or scene-style `.x265-GROUP` tokens.
+// The hyphenated form needs a dotted token before it so titles like
+// "Spider-Man" are left alone
+static RELEASE_TAG_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(\s*\[[^\]]*\]|\s*\{[^}]*\}|\.[^.\s-]+-[[:alnum:]]+)$")
+        .unwrap()
+});
+
+fn ext_in(ext: &OsStr, group: &[&str]) -> bool {
+    group
+        .iter()
+        .any(|acceptable| ext.eq_ignore_ascii_case(acceptable))
+}
+
+pub fn is_video(dir_entry: &DirEntry) -> bool {
+    dir_entry.file_type().is_file()
+        && dir_entry
+            .path()
+            .extension()
+            .map(|ext| {
+                trace!("seeing if {ext:?} is a video extension");
+                ext_in(ext, VIDEO_EXTENSIONS)
+            })
+            .unwrap_or_default()
+}
+
+pub fn is_subtitle(dir_entry: &DirEntry) -> bool {
+    trace!("testing {dir_entry:?}");
+    dir_entry.file_type().is_file()
+        && dir_entry
+            .path()
+            .extension()
+            .map(|ext| {
+                trace!("seeing if {ext:?} is a subtitle extension");
+                ext_in(ext, SUBTITLE_EXTENSIONS)
+            })
+            .unwrap_or_default()
+}
+
+// Only top level folders are filtered, anything inside a subtitle folder
+// is fair game
+pub fn is_searchable(
+    dir_entry: &DirEntry,
+    videos: &[Video],
+    subtitle_folders: &[String],
+) -> bool {
+    if dir_entry.depth() != 1 || !dir_entry.file_type().is_dir() {
+        return true;
+    }
+    let Some(name) = dir_entry.file_name().to_str() else {
+        return false;
+    };
+    let searchable = is_subtitle_folder(name, videos, subtitle_folders);
+    if !searchable {
+        debug!("not searching {name:?} for subtitles");
+    }
+    searchable
+}
+
+pub fn is_subtitle_folder(
+    name: &str,
+    videos: &[Video],
+    subtitle_folders: &[String],
+) -> bool {
+    let lowercase_name = name.to_lowercase();
+    subtitle_folders
+        .iter()
+        .any(|folder| folder.to_lowercase() == lowercase_name)
+        || videos
+            .iter()
+            .any(|video| video.path.file_stem() == Some(name))
+}
+
+pub fn has_video_extension(path: &Utf8Path) -> bool {
+    path.extension()
+        .map(|ext| ext_in(OsStr::new(ext), VIDEO_EXTENSIONS))
+        .unwrap_or_default()
+}
+
+pub fn has_subtitle_extension(path: &Utf8Path) -> bool {
+    path.extension()
+        .map(|ext| ext_in(OsStr::new(ext), SUBTITLE_EXTENSIONS))
+        .unwrap_or_default()
+}
+
+pub fn all_a_series<'a>(videos: impl IntoIterator<Item = &'a Video>) -> bool {
+    videos.into_iter().all(|vid| vid.part_of_series())
+}
+
+pub fn no_series<'a>(videos: impl IntoIterator<Item = &'a Video>) -> bool {
+    videos.into_iter().all(|vid| !vid.part_of_series())
+}
+
+// Assumes files has 2 or more elements
+pub fn different_versions_same_media(
+    files: impl IntoIterator<Item = impl AsRef<Utf8Path>>,
+) -> bool {
+    let mut files = files.into_iter();
+    let first = files
+        .next()
+        .expect("files iter should have at least two elements");
+    let first = first.as_ref();
+    // Parts of a stacked movie are one version of it between them
+    let first_name = edition::strip_tags(strip_release_tags(&normalise_width(
+        split_part_suffix(first.file_stem().expect("file has no name")).0,
+    )));
+    let first_name = first_name.as_str();
+    trace!("regexing {first_name:?}");
+    let Some(name_prefix) =
+        SEASON_AND_QUALITY_SUFFIX_REGEX.splitn(first_name, 2).next()
+    else {
+        error!("couldn't find quality suffix in {first}");
+        return false;
+    };
+    info!("guessing movie/episode name is {name_prefix:?}");
+    files.all(|file| {
+        file.as_ref()
+            .file_stem()
+            .map(|name| {
+                edition::strip_tags(strip_release_tags(&normalise_width(
+                    split_part_suffix(name).0,
+                )))
+                .starts_with(name_prefix)
+            })
+            .unwrap_or_default()
+    })
+}
+
+/// The movie or show name at the start of a video's file stem, before any
+/// series info or quality
+pub fn title_of(stem: &str) -> String {
+    let stem = edition::strip_tags(strip_release_tags(&normalise_width(stem)));
+    SEASON_AND_QUALITY_SUFFIX_REGEX
+        .splitn(&stem, 2)
+        .next()
+        .unwrap_or_default()
+        .to_owned()
+}
+
+/// Removes release group and edition tags from the end of a file stem
+pub fn strip_release_tags(mut stem: &str) -> &str {
+    while let Some(tag) = RELEASE_TAG_REGEX.find(stem) {
+        // Keep the token before a scene group, e.g. `.x265` in `.x265-GROUP`
+        stem = match tag.as_str().starts_with('.') {
+            true => {
+                let hyphen = tag.as_str().rfind('-').expect("regex has '-'");
+                &stem[..tag.start() + hyphen]
+            },
+            false => &stem[..tag.start()],
+        };
+        trace!("stripped release tag, left with {stem:?}");
+    }
+    while let Some(tag) = TRAILING_LANGUAGE_TAG_REGEX.find(stem) {
+        stem = &stem[..tag.start()];
+        trace!("stripped language tag, left with {stem:?}");
+    }
+    stem
+}
+
+/// The language implied by a scene tag like `VOSTFR`, for subtitles named
+/// after their release
+pub fn language_of_release(stem: &str) -> Option<Language> {
+    LANGUAGE_TAG_REGEX
+        .captures_iter(stem)
+        .filter_map(|captures| {
+            LANGUAGE_TAGS
+                .iter()
+                .find(|(tag, _)| *tag == &captures["tag"])?
+                .1
+        })
+        .last()
+}
@@ -0,0 +1,406 @@
+//! Subtitle tracks embedded in videos, found with ffprobe and extracted with
+//! ffmpeg
+
+use std::{
+    process::{Command, Stdio},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context};
+use camino::{Utf8Path, Utf8PathBuf};
+use isolang::Language;
+use log::{debug, info, warn};
+
+use crate::{
+    discovery::existing_languages,
+    fsops::{create_dir_all, run_creating},
+    interrupt,
+    report::{dry_run_prefix, Extraction, Report, Skip, SkipReason},
+    subtitle::{language_from_tag, split_flags},
+    video::Video,
+};
+
+/// Codecs ffmpeg can turn into SubRip. Image-based ones (like PGS) would need
+/// OCR
+const TEXT_CODECS: &[&str] =
+    &["subrip", "srt", "ass", "ssa", "mov_text", "webvtt", "text"];
+
+#[derive(Debug)]
+pub struct Track {
+    /// Stream index within the file, as ffmpeg's `-map 0:<index>` wants
+    pub index: u32,
+    pub codec: String,
+    pub lang: Option<Language>,
+    pub forced: bool,
+    pub hearing_impaired: bool,
+    /// Describes what's on screen, for the visually impaired
+    pub visual_impaired: bool,
+    /// Often says what sets it apart, like `SDH` or `Signs & Songs`
+    pub title: Option<String>,
+}
+
+impl Track {
+    pub fn is_text(&self) -> bool {
+        TEXT_CODECS.contains(&self.codec.as_str())
+    }
+}
+
+pub fn subtitle_tracks(video: &Utf8Path) -> anyhow::Result<Vec<Track>> {
+    let output = Command::new("ffprobe")
+        .args(["-v", "error", "-select_streams", "s"])
+        .args([
+            "-show_entries",
+            "stream=index,codec_name:stream_tags=language,title:\
+             stream_disposition=forced,hearing_impaired,visual_impaired",
+        ])
+        .args(["-of", "compact=p=0"])
+        .arg(video)
+        .stdin(Stdio::null())
+        .output()
+        .context("couldn't run ffprobe, is it installed?")?;
+    if !output.status.success() {
+        bail!(
+            "ffprobe failed on {video}: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    // Each line is like `index=2|codec_name=subrip|disposition:forced=0|
+    // disposition:hearing_impaired=0|tag:language=eng|tag:title=SDH`
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let field = |name: &str| {
+                line.split('|')
+                    .find_map(|pair| pair.strip_prefix(name)?.strip_prefix('='))
+            };
+            Ok(Track {
+                index: field("index")
+                    .context("ffprobe didn't give a stream index")?
+                    .parse()
+                    .context("ffprobe gave an invalid stream index")?,
+                codec: field("codec_name").unwrap_or_default().to_owned(),
+                lang: field("tag:language").and_then(language_from_tag),
+                forced: field("disposition:forced") == Some("1"),
+                hearing_impaired: field("disposition:hearing_impaired")
+                    == Some("1"),
+                visual_impaired: field("disposition:visual_impaired")
+                    == Some("1"),
+                title: field("tag:title")
+                    .filter(|title| !title.is_empty())
+                    .map(str::to_owned),
+            })
+        })
+        .collect()
+}
+
+/// How long a video runs for
+pub fn duration(video: &Utf8Path) -> anyhow::Result<Duration> {
+    let output = Command::new("ffprobe")
+        .args(["-v", "error", "-show_entries", "format=duration"])
+        .args(["-of", "default=noprint_wrappers=1:nokey=1"])
+        .arg(video)
+        .stdin(Stdio::null())
+        .output()
+        .context("couldn't run ffprobe, is it installed?")?;
+    if !output.status.success() {
+        bail!(
+            "ffprobe failed on {video}: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let seconds = String::from_utf8_lossy(&output.stdout);
+    let seconds = seconds.trim();
+    parse_duration(seconds).with_context(|| {
+        format!("ffprobe gave {video} an invalid duration {seconds:?}")
+    })
+}
+
+// Seconds, as ffprobe gives them. `Duration::from_secs_f64` panics on ones
+// too big for a `Duration`
+fn parse_duration(seconds: &str) -> Option<Duration> {
+    seconds
+        .parse::<f64>()
+        .ok()
+        .filter(|seconds| {
+            seconds.is_finite()
+                && *seconds > 0.0
+                && *seconds < Duration::MAX.as_secs_f64()
+        })
+        .map(Duration::from_secs_f64)
+}
+
+/// Finds how long each video runs for, leaving it unknown for ones ffprobe
+/// can't read
+pub fn measure(videos: &mut [Video]) {
+    for video in videos {
+        match duration(&video.path) {
+            Ok(duration) => video.runtime = Some(duration),
+            Err(why) => {
+                warn!("couldn't tell how long {} is: {why:#}", video.path)
+            },
+        }
+    }
+}
+
+/// Leaves out videos shorter than `shorter_than`, like samples & trailers,
+/// which would otherwise look like other versions of the feature or take its
+/// subtitles. Ones that haven't been measured are kept, and so are all of
+/// them if they're all short, as there's then nothing for them to be extras
+/// of
+pub fn remove_short(
+    videos: &mut Vec<Video>,
+    shorter_than: Duration,
+    report: &mut Report,
+) {
+    let is_short = |video: &Video| {
+        video
+            .runtime
+            .map(|duration| duration < shorter_than)
+            .unwrap_or_default()
+    };
+    if videos.iter().all(is_short) {
+        debug!("every video is shorter than {shorter_than:?}, keeping them");
+        return;
+    }
+    videos.retain(|video| {
+        if !is_short(video) {
+            return true;
+        }
+        let duration = video.runtime.unwrap_or_default();
+        info!(
+            "leaving out {}, it's only {}s long so it's probably a sample or \
+             an extra",
+            video.path,
+            duration.as_secs()
+        );
+        report.skip(Skip::new(
+            &video.path,
+            SkipReason::ShortVideo,
+            format!("only {}s long", duration.as_secs()),
+        ));
+        false
+    });
+}
+
+/// Logs the embedded tracks of videos that have no external subtitles, and
+/// extracts ones in the given languages that videos don't already have
+pub fn handle_embedded(
+    videos: &[Video],
+    extract: &[Language],
+    report: &mut Report,
+) {
+    for video in videos {
+        let tracks = match subtitle_tracks(&video.path) {
+            Ok(tracks) => tracks,
+            Err(why) => {
+                warn!("{why:#}");
+                continue;
+            },
+        };
+        if tracks.is_empty() {
+            info!("{} has no embedded subtitles either", video.path);
+            continue;
+        }
+        for track in &tracks {
+            info!(
+                "{} has an embedded {} subtitle ({}{})",
+                video.path,
+                track
+                    .lang
+                    .map(|lang| lang.to_name())
+                    .unwrap_or("unlabelled"),
+                track.codec,
+                match track.forced {
+                    true => ", forced",
+                    false => "",
+                },
+            );
+        }
+        let existing = existing_languages(video);
+        for &lang in extract.iter().filter(|lang| !existing.contains(lang)) {
+            let Some(track) = tracks
+                .iter()
+                .find(|track| track.lang == Some(lang) && !track.forced)
+            else {
+                continue;
+            };
+            if !track.is_text() {
+                warn!(
+                    "can't extract the {} subtitle from {}, {} is image-based",
+                    lang.to_name(),
+                    video.path,
+                    track.codec
+                );
+                continue;
+            }
+            let path = extracted_path(video, lang);
+            info!(
+                "{}extracting {} subtitle from {} to {path}",
+                dry_run_prefix(),
+                lang.to_name(),
+                video.path,
+            );
+            let mut ffmpeg = Command::new("ffmpeg");
+            ffmpeg
+                .args(["-nostdin", "-v", "error", "-n", "-i"])
+                .arg(&video.path)
+                .args(["-map", &format!("0:{}", track.index), "-c:s", "srt"])
+                .arg(&path);
+            let start = Instant::now();
+            let extracted = run_creating(&mut ffmpeg, &path);
+            report.stats.execute += start.elapsed();
+            match extracted {
+                Ok(()) => report.extracted.push(Extraction {
+                    video: video.path.clone(),
+                    track: track.index,
+                    path,
+                    lang,
+                }),
+                Err(why) => warn!("failed to extract to {path}: {why}"),
+            }
+        }
+    }
+}
+
+/// Extracts every text subtitle track of the videos into a folder named
+/// after each (`Movie/3_English (Forced).srt` next to `Movie.mkv`), where
+/// they're found like any other per-video subtitles, their flags & language
+/// carried over in their names. Ones extracted on earlier runs are kept
+pub fn extract_embedded(videos: &[Video], report: &mut Report) {
+    for video in videos {
+        if interrupt::is_interrupted() {
+            report.interrupted = true;
+            return;
+        }
+        let tracks = match subtitle_tracks(&video.path) {
+            Ok(tracks) => tracks,
+            Err(why) => {
+                warn!("{why:#}");
+                continue;
+            },
+        };
+        let (Some(dir), Some(stem)) =
+            (video.path.parent(), video.path.file_stem())
+        else {
+            continue;
+        };
+        let folder = dir.join(stem);
+        for track in tracks {
+            let Some(lang) = track.lang else {
+                info!(
+                    "not extracting track {} of {}, it has no language",
+                    track.index, video.path
+                );
+                continue;
+            };
+            let Some((codec, ext)) = output_format(&track.codec) else {
+                debug!(
+                    "not extracting track {} of {}, {} isn't text",
+                    track.index, video.path, track.codec
+                );
+                continue;
+            };
+            let Some(name) = track_name(&track, lang) else {
+                warn!(
+                    "not extracting track {} of {}, {} can't be put in a file \
+                     name",
+                    track.index,
+                    video.path,
+                    lang.to_name()
+                );
+                continue;
+            };
+            let path = folder.join(format!("{name}.{ext}"));
+            if path.exists() {
+                debug!("{path} was extracted already");
+                continue;
+            }
+            info!(
+                "{}extracting track {} of {} to {path}",
+                dry_run_prefix(),
+                track.index,
+                video.path,
+            );
+            let mut ffmpeg = Command::new("ffmpeg");
+            ffmpeg
+                .args(["-nostdin", "-v", "error", "-n", "-i"])
+                .arg(&video.path)
+                .args(["-map", &format!("0:{}", track.index), "-c:s", codec])
+                .arg(&path);
+            let start = Instant::now();
+            let extracted = create_dir_all(&folder)
+                .and_then(|()| run_creating(&mut ffmpeg, &path));
+            report.stats.execute += start.elapsed();
+            match extracted {
+                Ok(()) => report.extracted.push(Extraction {
+                    video: video.path.clone(),
+                    track: track.index,
+                    path,
+                    lang,
+                }),
+                Err(why) => warn!("failed to extract to {path}: {why}"),
+            }
+        }
+    }
+}
+
+/// The codec to have ffmpeg write a track as, and its extension. ASS is
+/// kept as it is, to keep its styling
+fn output_format(codec: &str) -> Option<(&'static str, &'static str)> {
+    match codec {
+        "ass" | "ssa" => Some(("copy", "ass")),
+        "subrip" | "srt" => Some(("copy", "srt")),
+        codec if TEXT_CODECS.contains(&codec) => Some(("srt", "srt")),
+        _ => None,
+    }
+}
+
+// Named like `3_English (Forced)`, so what reads subtitle names finds the
+// language & flags in it, numbered so tracks of the same language don't
+// clash
+fn track_name(track: &Track, lang: Language) -> Option<String> {
+    let language = lang.to_name();
+    if Language::from_name(language) != Some(lang)
+        || language.contains(['/', '\\'])
+    {
+        return None;
+    }
+    let mut name = format!("{}_{language}", track.index);
+    // A title often says it's forced or SDH when the disposition doesn't
+    let (_, flags) =
+        track.title.as_deref().map(split_flags).unwrap_or_default();
+    if track.forced || flags.forced {
+        name.push_str(" (Forced)");
+    }
+    if track.hearing_impaired || flags.hearing_impaired {
+        name.push_str(" (SDH)");
+    }
+    if track.visual_impaired || flags.audio_description {
+        name.push_str(" (AD)");
+    }
+    Some(name)
+}
+
+fn extracted_path(video: &Video, lang: Language) -> Utf8PathBuf {
+    video.path.with_extension(format!(
+        "{}.srt",
+        lang.to_639_1().unwrap_or(lang.to_639_3())
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations() {
+        assert_eq!(
+            parse_duration("5400.25"),
+            Some(Duration::from_millis(5_400_250))
+        );
+        for bad in ["", "N/A", "0", "-1", "inf", "NaN", "1e30"] {
+            assert_eq!(parse_duration(bad), None, "{bad:?}");
+        }
+    }
+}
@@ -0,0 +1,104 @@
+//! Removing links made by earlier runs, for languages that aren't wanted any
+//! more
+
+use anyhow::{bail, Context};
+use camino::{Utf8Path, Utf8PathBuf};
+use isolang::Language;
+use log::{debug, error, info};
+
+use crate::{
+    cli::Options,
+    discovery::{discover_videos, folders, sidecar_language, Listing},
+    fsops::remove_file,
+    jellyfin,
+    lock::{Lock, DIRECTORY_LOCK},
+    manifest::Manifest,
+    report::{dry_run_prefix, Report},
+    xattr,
+};
+
+/// Removes the subtitle links next to videos anywhere under `root` whose
+/// language isn't in `keep`, returning how many went.
+///
+/// Links are recognised by being symlinks named after a video, like
+/// `Movie.de.srt`, which is how every run has made them, or by being marked
+/// as subfix's with [`xattr`](crate::xattr) (for copies made by `--mode`).
+/// Subtitles that are other real files (downloaded ones, or the source files
+/// the links point to) are never touched, nor are links whose language
+/// can't be told from the name
+pub fn prune_languages(
+    root: &Utf8Path,
+    keep: &[Language],
+    options: &Options,
+) -> anyhow::Result<usize> {
+    if !options.force {
+        if let Some(why) = jellyfin::server_data_reason(root) {
+            bail!(
+                "not pruning {root}, it looks like Jellyfin's own data: {why} \
+                 (see --force)"
+            );
+        }
+    }
+    let mut removed = 0;
+    for dir in folders(root, options) {
+        let unwanted = unwanted_links(&dir, keep, options);
+        if unwanted.is_empty() {
+            continue;
+        }
+        let _lock = Lock::acquire(dir.join(DIRECTORY_LOCK))?;
+        let mut manifest = Manifest::load(&dir)?;
+        for (link, lang) in unwanted {
+            info!(
+                "{}removing {} link {link}",
+                dry_run_prefix(),
+                lang.to_name(),
+            );
+            match remove_file(&link)
+                .with_context(|| format!("failed to remove {link}"))
+            {
+                Ok(()) => {
+                    removed += 1;
+                    manifest.forget(link.file_name().unwrap_or_default());
+                },
+                Err(why) => error!("{why:#}"),
+            }
+        }
+        manifest.save()?;
+    }
+    Ok(removed)
+}
+
+fn unwanted_links(
+    dir: &Utf8Path,
+    keep: &[Language],
+    options: &Options,
+) -> Vec<(Utf8PathBuf, Language)> {
+    // Skips are only of interest when linking
+    let videos = discover_videos(dir, options, &mut Report::default());
+    if videos.is_empty() {
+        return Vec::new();
+    }
+    let listing = Listing::read(dir);
+    videos
+        .iter()
+        .flat_map(|video| {
+            listing
+                .sidecars(video, |entry| {
+                    entry
+                        .file_type()
+                        .map(|file_type| file_type.is_symlink())
+                        .unwrap_or_default()
+                        || xattr::is_marked_created(entry.path())
+                })
+                .into_iter()
+                .filter_map(move |link| {
+                    let lang = sidecar_language(video, &link);
+                    if lang.is_none() {
+                        debug!("can't tell the language of {link}, keeping it");
+                    }
+                    Some((link, lang?))
+                })
+        })
+        .filter(|(_, lang)| !keep.contains(lang))
+        .collect()
+}
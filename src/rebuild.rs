@@ -0,0 +1,211 @@
+//! Making a folder's manifest again from what's on disk, for when it's been
+//! deleted or can't be read, so `--undo`, `prune-langs` & `embed` still know
+//! which links are subfix's. Only links with something to show subfix made
+//! them are recorded: the `--xattr` mark, a target written the way subfix
+//! writes them (`./Subs/English.srt`), or one of the hidden copies only
+//! subfix makes. Anything else is left for `adopt`
+
+use std::{collections::BTreeMap, fs};
+
+use anyhow::bail;
+use camino::{Utf8Path, Utf8PathBuf};
+use log::{debug, error, info, warn};
+use walkdir::WalkDir;
+
+use crate::{
+    adopt::subtitle_target,
+    cli::Options,
+    content::hash_file,
+    discovery::{discover_videos, folders, Listing},
+    fsops::{is_same_file, rename},
+    jellyfin,
+    manifest::{Entry, Manifest, Origin, Written, MANIFEST},
+    predicates,
+    report::{dry_run_prefix, Report},
+    sync,
+    time::Stamp,
+    timing,
+    video::Video,
+    xattr,
+};
+
+/// What a manifest that can't be read is moved aside to, rather than lost
+const CORRUPT_SUFFIX: &str = ".corrupt";
+
+/// What was found while rebuilding
+#[derive(Debug, Default)]
+pub struct Rebuilt {
+    /// Links recorded that weren't before
+    pub recorded: usize,
+    /// Links next to videos with nothing to show they're subfix's
+    pub unproven: usize,
+}
+
+/// Rebuilds the manifest of every folder with videos anywhere under `root`,
+/// adding the links found to be subfix's to what's still recorded
+pub fn rebuild_manifests(
+    root: &Utf8Path,
+    options: &Options,
+) -> anyhow::Result<Rebuilt> {
+    if !options.force {
+        if let Some(why) = jellyfin::server_data_reason(root) {
+            bail!(
+                "not rebuilding manifests in {root}, it looks like Jellyfin's \
+                 own data: {why} (see --force)"
+            );
+        }
+    }
+    let mut rebuilt = Rebuilt::default();
+    for dir in folders(root, options) {
+        if let Err(why) = rebuild_in(&dir, options, &mut rebuilt) {
+            error!("{why:#}");
+        }
+    }
+    Ok(rebuilt)
+}
+
+fn rebuild_in(
+    dir: &Utf8Path,
+    options: &Options,
+    rebuilt: &mut Rebuilt,
+) -> anyhow::Result<()> {
+    // Skips are only of interest when linking
+    let videos = discover_videos(dir, options, &mut Report::default());
+    if videos.is_empty() {
+        return Ok(());
+    }
+    let mut manifest = match Manifest::load(dir) {
+        Ok(manifest) => manifest,
+        Err(why) => {
+            let path = dir.join(MANIFEST);
+            let aside = dir.join(format!("{MANIFEST}{CORRUPT_SUFFIX}"));
+            warn!(
+                "{}{why:#}, moving it to {aside} and starting again",
+                dry_run_prefix(),
+            );
+            rename(&path, &aside)?;
+            Manifest::empty(dir)
+        },
+    };
+    let listing = Listing::read(dir);
+    let mut originals = Originals::new(dir, &videos, &listing);
+    for video in &videos {
+        for sidecar in listing.sidecars(video, |_| true) {
+            let Some(name) = sidecar.file_name() else {
+                continue;
+            };
+            if manifest.get(name).is_some() {
+                debug!("{sidecar} is already recorded");
+                continue;
+            }
+            let Some(target) = evidence(&sidecar, &mut originals) else {
+                debug!("nothing shows subfix made {sidecar}");
+                rebuilt.unproven += 1;
+                continue;
+            };
+            info!(
+                "{}recording {sidecar}, which subfix made from {target}",
+                dry_run_prefix(),
+            );
+            manifest.record(Entry {
+                name: name.to_owned(),
+                written: Written::copy(&sidecar, &target),
+                target,
+                video: video.path.file_name().map(str::to_owned),
+                origin: Origin::Created,
+                recorded: Stamp::now(),
+            });
+            rebuilt.recorded += 1;
+        }
+    }
+    manifest.save()
+}
+
+// What the manifest would have said the sidecar was made from, if it's
+// subfix's
+fn evidence(
+    sidecar: &Utf8Path,
+    originals: &mut Originals,
+) -> Option<Utf8PathBuf> {
+    let metadata = fs::symlink_metadata(sidecar).ok()?;
+    let marked = xattr::is_marked_created(sidecar);
+    if metadata.file_type().is_symlink() {
+        let target = subtitle_target(sidecar)?;
+        let derived = sidecar
+            .parent()
+            .map(|dir| is_derived_copy(&dir.join(&target)))
+            .unwrap_or_default();
+        return (marked || derived || target.starts_with("."))
+            .then_some(target);
+    }
+    // Hard links aren't marked, as they're the user's file too, but one
+    // named after a video for a subtitle elsewhere in the folder is subfix's
+    if let Some(original) = originals.same_file(sidecar) {
+        return Some(original);
+    }
+    match marked {
+        true => originals.same_contents(sidecar),
+        false => None,
+    }
+}
+
+// The hidden copies subfix makes to link instead of what it found
+fn is_derived_copy(path: &Utf8Path) -> bool {
+    timing::is_fixed_copy(path) || sync::is_synced_copy(path)
+}
+
+/// The subtitles in a folder that aren't next to a video already, which
+/// copies & hard links would have been made from
+struct Originals {
+    paths: Vec<Utf8PathBuf>,
+    /// Read as they're needed
+    hashes: BTreeMap<Utf8PathBuf, Option<u64>>,
+}
+
+impl Originals {
+    fn new(dir: &Utf8Path, videos: &[Video], listing: &Listing) -> Self {
+        let sidecars = videos
+            .iter()
+            .flat_map(|video| listing.sidecars(video, |_| true))
+            .collect::<Vec<_>>();
+        let paths = WalkDir::new(dir)
+            .min_depth(1)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_type().is_file())
+            .filter_map(|entry| {
+                Utf8PathBuf::from_path_buf(entry.into_path()).ok()
+            })
+            .filter(|path| {
+                predicates::has_subtitle_extension(path)
+                    && !sidecars.contains(path)
+            })
+            .collect();
+        Originals {
+            paths,
+            hashes: BTreeMap::new(),
+        }
+    }
+
+    fn same_file(&self, sidecar: &Utf8Path) -> Option<Utf8PathBuf> {
+        self.paths
+            .iter()
+            .find(|path| is_same_file(path, sidecar))
+            .cloned()
+    }
+
+    fn same_contents(&mut self, sidecar: &Utf8Path) -> Option<Utf8PathBuf> {
+        let hash = hash_file(sidecar).ok()?;
+        let hashes = &mut self.hashes;
+        self.paths
+            .iter()
+            .find(|path| {
+                *hashes
+                    .entry(path.to_path_buf())
+                    .or_insert_with(|| hash_file(path).ok())
+                    == Some(hash)
+            })
+            .cloned()
+    }
+}
@@ -0,0 +1,114 @@
+//! The release a file is from, going by scene-style tokens in its name like
+//! `Movie.2010.1080p.BluRay.x264-GROUP`, so tools scoring subtitles (like
+//! Bazarr) can tell whether one was made for that exact release
+
+use camino::Utf8Path;
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use crate::{json::Value, subtitle::language_from_tag};
+
+static RESOLUTION_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)\b(480p|576p|720p|1080p|2160p|4k|uhd)\b").unwrap()
+});
+
+static SOURCE_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"(?i)\b(blu-?ray|bdremux|remux|bdrip|brrip|web-?dl|webrip|web|hdtv|dvdrip|dvd|hdrip)\b",
+    )
+    .unwrap()
+});
+
+// `[GROUP] Show - 01` for anime, `Movie.x264-GROUP` otherwise
+static LEADING_GROUP_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^\[([^\]]+)\]").unwrap());
+static TRAILING_GROUP_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\.[^.\s-]+-([[:alnum:]]+)$").unwrap());
+
+/// Tags subtitles have after the release's name, like `.en.forced`
+const SUBTITLE_TAGS: &[&str] = &["forced", "sdh", "cc", "hi", "default"];
+
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct Release {
+    pub group: Option<String>,
+    pub source: Option<String>,
+    pub resolution: Option<String>,
+}
+
+impl Release {
+    /// Looks at the file's name first, then the folders it's in, nearest
+    /// first, for each token it's missing (subtitles are often `English.srt`
+    /// in a folder named after the release)
+    pub fn of(path: &Utf8Path) -> Self {
+        let mut release = Release::default();
+        let stem = path.file_stem().into_iter();
+        let folders = path
+            .ancestors()
+            .skip(1)
+            .filter_map(Utf8Path::file_name)
+            .filter(|name| *name != "." && *name != "..");
+        for name in stem.chain(folders) {
+            release.fill_from(name);
+        }
+        release
+    }
+
+    fn fill_from(&mut self, name: &str) {
+        if self.resolution.is_none() {
+            self.resolution = RESOLUTION_REGEX.find(name).map(|found| {
+                match found.as_str().to_lowercase().as_str() {
+                    "4k" | "uhd" => "2160p".to_owned(),
+                    resolution => resolution.to_owned(),
+                }
+            });
+        }
+        if self.source.is_none() {
+            self.source = SOURCE_REGEX
+                .find(name)
+                .map(|found| canonical_source(found.as_str()).to_owned());
+        }
+        if self.group.is_none() {
+            self.group = group_of(name);
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        Value::object([
+            ("group", self.group.clone().into()),
+            ("source", self.source.clone().into()),
+            ("resolution", self.resolution.clone().into()),
+        ])
+    }
+}
+
+fn canonical_source(source: &str) -> &'static str {
+    match source.to_lowercase().replace('-', "").as_str() {
+        "bluray" => "BluRay",
+        "bdremux" | "remux" => "Remux",
+        "bdrip" | "brrip" => "BDRip",
+        "webdl" => "WEB-DL",
+        "webrip" => "WEBRip",
+        "web" => "WEB",
+        "hdtv" => "HDTV",
+        "dvdrip" | "dvd" => "DVDRip",
+        _ => "HDRip",
+    }
+}
+
+fn group_of(name: &str) -> Option<String> {
+    if let Some(captures) = LEADING_GROUP_REGEX.captures(name) {
+        return Some(captures[1].to_owned());
+    }
+    // Subtitles named after the release have their own tags on the end
+    let mut name = name;
+    while let Some((rest, tag)) = name.rsplit_once('.') {
+        let is_tag = SUBTITLE_TAGS.contains(&tag.to_lowercase().as_str())
+            || (tag.len() <= 3 && language_from_tag(tag).is_some());
+        if !is_tag {
+            break;
+        }
+        name = rest;
+    }
+    let captures = TRAILING_GROUP_REGEX.captures(name)?;
+    Some(captures[1].to_owned())
+}
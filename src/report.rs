@@ -0,0 +1,512 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+    sync::mpsc::Sender,
+};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use isolang::Language;
+use log::{debug, error, info, log_enabled, warn, Level};
+
+use crate::{
+    content, coverage,
+    events::{self, Event},
+    fsops::is_dry_run,
+    html,
+    json::Value,
+    release::Release,
+    run,
+    stats::Stats,
+    time::Stamp,
+};
+
+/// How many skips of each kind in a directory are warned about as they
+/// happen
+const WARNINGS_PER_REASON: usize = 3;
+
+/// What happened while processing one directory
+#[derive(Debug, Default)]
+pub struct Report {
+    pub links: Vec<LinkRecord>,
+    pub extracted: Vec<Extraction>,
+    /// Links from earlier runs that were removed as they'd gone stale
+    pub removed: Vec<Utf8PathBuf>,
+    pub skipped: Vec<Skip>,
+    /// How many skips of each kind weren't warned about as they happened,
+    /// past the first few in the directory
+    pub quieted: BTreeMap<SkipReason, usize>,
+    pub stats: Stats,
+    /// Links that weren't made because it takes more privilege than subfix
+    /// has, also skipped as [`SkipReason::LinkFailed`]
+    pub denied: Vec<LinkRecord>,
+    /// Guesses and patterns that usually mean something was misdetected,
+    /// worth someone checking
+    pub review: Vec<String>,
+    /// Every video in the directory and the languages it has subtitles for
+    pub videos: Vec<VideoLanguages>,
+    /// When processing the directory started & finished
+    pub started: Stamp,
+    pub finished: Stamp,
+    /// The [run](crate::run) it was processed in
+    pub run: String,
+    /// Stopped part way through by Ctrl-C, so not everything was linked
+    pub interrupted: bool,
+    /// Where to send progress as it happens
+    pub(crate) events: Option<Sender<Event>>,
+    /// Gzipped subtitles that were found, by the name they're decompressed
+    /// to, which is the one they're known by until then
+    pub(crate) compressed: BTreeMap<Utf8PathBuf, Utf8PathBuf>,
+}
+
+impl Report {
+    pub fn skip(&mut self, skip: Skip) {
+        self.skipped.push(skip);
+    }
+
+    /// Records a skip, warning about it with `message` unless there have
+    /// already been a few of the same kind. The rest are counted in the
+    /// summary instead, so a season pack doesn't bury everything else in
+    /// the same warning. With `--verbose`, every one is warned about
+    pub fn warn_skip(&mut self, message: impl Display, skip: Skip) {
+        let before = self
+            .skipped
+            .iter()
+            .filter(|skipped| skipped.reason == skip.reason)
+            .count();
+        match before < WARNINGS_PER_REASON || log_enabled!(Level::Debug) {
+            true => warn!("{message}"),
+            false => *self.quieted.entry(skip.reason).or_default() += 1,
+        }
+        self.skip(skip);
+    }
+
+    pub(crate) fn emit(&self, event: Event) {
+        events::emit(&self.events, event);
+    }
+}
+
+#[derive(Debug)]
+pub struct VideoLanguages {
+    pub path: Utf8PathBuf,
+    /// The show an episode is from, by its name
+    pub show: Option<String>,
+    pub languages: BTreeSet<Language>,
+}
+
+#[derive(Debug)]
+pub struct LinkRecord {
+    pub video: Utf8PathBuf,
+    pub subtitle: Utf8PathBuf,
+    pub link: Utf8PathBuf,
+    pub lang: Language,
+}
+
+impl LinkRecord {
+    // Folders further up are about the library, not the release
+    fn video_name(&self) -> &Utf8Path {
+        Utf8Path::new(self.video.file_name().unwrap_or_default())
+    }
+
+    fn subtitle_below(&self) -> &Utf8Path {
+        self.video
+            .parent()
+            .and_then(|dir| self.subtitle.strip_prefix(dir).ok())
+            .unwrap_or(&self.subtitle)
+    }
+
+    fn to_json(&self) -> Value {
+        Value::object([
+            ("video", self.video.as_str().into()),
+            ("subtitle", self.subtitle.as_str().into()),
+            ("link", self.link.as_str().into()),
+            ("video_release", Release::of(self.video_name()).to_json()),
+            (
+                "subtitle_release",
+                Release::of(self.subtitle_below()).to_json(),
+            ),
+        ])
+    }
+}
+
+/// A subtitle track copied out of a video into its own file
+#[derive(Debug)]
+pub struct Extraction {
+    pub video: Utf8PathBuf,
+    pub track: u32,
+    pub path: Utf8PathBuf,
+    pub lang: Language,
+}
+
+impl Extraction {
+    fn to_json(&self) -> Value {
+        Value::object([
+            ("video", self.video.as_str().into()),
+            ("track", (self.track as usize).into()),
+            ("path", self.path.as_str().into()),
+            (
+                "language",
+                self.lang.to_639_1().unwrap_or(self.lang.to_639_3()).into(),
+            ),
+        ])
+    }
+}
+
+/// Something that was left out, and why
+#[derive(Debug)]
+pub struct Skip {
+    pub path: Utf8PathBuf,
+    pub reason: SkipReason,
+    pub detail: String,
+}
+
+impl Skip {
+    pub fn new(
+        path: impl Into<Utf8PathBuf>,
+        reason: SkipReason,
+        detail: impl Into<String>,
+    ) -> Self {
+        Skip {
+            path: path.into(),
+            reason,
+            detail: detail.into(),
+        }
+    }
+
+    pub(crate) fn to_json(&self) -> Value {
+        Value::object([
+            ("path", self.path.as_str().into()),
+            ("reason", self.reason.code().into()),
+            ("detail", self.detail.as_str().into()),
+        ])
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum SkipReason {
+    DuplicateLanguage,
+    UnknownLanguage,
+    NoMatchingVideo,
+    ExtensionFiltered,
+    ExcludedPath,
+    NonUtf8Path,
+    BadSeriesInfo,
+    LinkFailed,
+    MergedPart,
+    EditionMismatch,
+    AlreadyHasLanguage,
+    ManagedByBazarr,
+    IdenticalContent,
+    NameTooLong,
+    SymlinkLoop,
+    BrokenSymlink,
+    DecompressFailed,
+    OtherSeason,
+    CaseConflict,
+    Ignored,
+    StackedMovie,
+    SplitIntoParts,
+    ShortVideo,
+    Incomplete,
+    OtherFilesystem,
+}
+
+impl SkipReason {
+    /// Stable identifier used in JSON output
+    pub fn code(self) -> &'static str {
+        use SkipReason::*;
+        match self {
+            DuplicateLanguage => "duplicate-language",
+            UnknownLanguage => "unknown-language",
+            NoMatchingVideo => "no-matching-video",
+            ExtensionFiltered => "extension-filtered",
+            ExcludedPath => "excluded-path",
+            NonUtf8Path => "non-utf8-path",
+            BadSeriesInfo => "bad-series-info",
+            LinkFailed => "link-failed",
+            MergedPart => "merged-part",
+            EditionMismatch => "edition-mismatch",
+            AlreadyHasLanguage => "already-has-language",
+            ManagedByBazarr => "managed-by-bazarr",
+            IdenticalContent => "identical-content",
+            NameTooLong => "name-too-long",
+            SymlinkLoop => "symlink-loop",
+            BrokenSymlink => "broken-symlink",
+            DecompressFailed => "decompress-failed",
+            OtherSeason => "other-season",
+            CaseConflict => "case-conflict",
+            Ignored => "ignored",
+            StackedMovie => "stacked-movie",
+            SplitIntoParts => "split-into-parts",
+            ShortVideo => "short-video",
+            Incomplete => "incomplete",
+            OtherFilesystem => "other-filesystem",
+        }
+    }
+
+    /// Whether it's one subtitle losing out to another, which someone might
+    /// want to choose between themselves
+    pub fn is_conflict(self) -> bool {
+        use SkipReason::*;
+        matches!(
+            self,
+            DuplicateLanguage | EditionMismatch | CaseConflict | StackedMovie
+        )
+    }
+
+    pub fn describe(self) -> &'static str {
+        use SkipReason::*;
+        match self {
+            DuplicateLanguage => "duplicate language",
+            UnknownLanguage => "unknown language",
+            NoMatchingVideo => "no matching video",
+            ExtensionFiltered => "not a video or subtitle",
+            ExcludedPath => "folder not searched",
+            NonUtf8Path => "path isn't UTF-8",
+            BadSeriesInfo => "couldn't parse series info",
+            LinkFailed => "couldn't create link",
+            MergedPart => "merged into another subtitle",
+            EditionMismatch => "no video of the same edition or cut",
+            AlreadyHasLanguage => "video already has this language",
+            ManagedByBazarr => "language is managed by Bazarr",
+            IdenticalContent => "video already has this subtitle",
+            NameTooLong => "link name would be too long",
+            SymlinkLoop => "link leads back into the search",
+            BrokenSymlink => "link can't be followed",
+            DecompressFailed => "couldn't decompress",
+            OtherSeason => "not the season asked for",
+            CaseConflict => "name only differs from another in case",
+            Ignored => "excluded by an ignore file",
+            StackedMovie => "for the whole of a stacked movie",
+            SplitIntoParts => "split into parts",
+            ShortVideo => "too short to be the feature",
+            Incomplete => "empty, unreadable or still being written",
+            OtherFilesystem => "can't be hard linked from another filesystem",
+        }
+    }
+}
+
+pub fn log_summary(results: &[(Utf8PathBuf, anyhow::Result<Report>)]) {
+    let mut links = 0;
+    let mut extracted = 0;
+    let mut removed = 0;
+    let mut stats = Stats::default();
+    let mut reasons = BTreeMap::new();
+    let mut quieted = BTreeMap::new();
+    let mut review = Vec::new();
+    for (path, result) in results {
+        match result {
+            Ok(report) => {
+                links += report.links.len();
+                extracted += report.extracted.len();
+                removed += report.removed.len();
+                stats.add(&report.stats);
+                for skip in &report.skipped {
+                    *reasons.entry(skip.reason).or_insert(0usize) += 1;
+                }
+                for (reason, count) in &report.quieted {
+                    *quieted.entry(*reason).or_insert(0usize) += count;
+                }
+                review.extend(report.review.iter().map(|why| (path, why)));
+                if report.interrupted {
+                    warn!("{path} was interrupted part way through");
+                }
+            },
+            Err(why) => error!("failed to process {path}: {why}"),
+        }
+    }
+    if results.len() > 1 {
+        let failed = results.iter().filter(|(_, result)| result.is_err());
+        info!(
+            "processed {} folders, {} failed",
+            results.len(),
+            failed.count()
+        );
+    }
+    match is_dry_run() {
+        true => info!("would create {links} links"),
+        false => info!("created {links} links"),
+    }
+    if extracted != 0 {
+        match is_dry_run() {
+            true => info!("would extract {extracted} embedded subtitles"),
+            false => info!("extracted {extracted} embedded subtitles"),
+        }
+    }
+    if removed != 0 {
+        match is_dry_run() {
+            true => info!("would remove {removed} stale links"),
+            false => info!("removed {removed} stale links"),
+        }
+    }
+    for (reason, count) in reasons {
+        match quieted.get(&reason) {
+            Some(quieted) => info!(
+                "skipped {count}: {} ({}), {quieted} of them not warned about \
+                 (see --verbose, or the report, for the list)",
+                reason.describe(),
+                reason.code()
+            ),
+            None => info!(
+                "skipped {count}: {} ({})",
+                reason.describe(),
+                reason.code()
+            ),
+        }
+    }
+    for (path, why) in review {
+        warn!("worth checking in {path}: {why}");
+    }
+    debug!(
+        "looked at {} files and read {} bytes; walking took {:?}, parsing \
+         {:?}, matching {:?}, and executing {:?}",
+        stats.files,
+        stats.bytes_read,
+        stats.walk,
+        stats.parse,
+        stats.matching,
+        stats.execute,
+    );
+    content::log_detection_stats();
+}
+
+/// What log lines about changes start with, so a dry run's say they weren't
+/// made
+pub fn dry_run_prefix() -> &'static str {
+    match is_dry_run() {
+        true => "(dry run) ",
+        false => "",
+    }
+}
+
+pub fn would_change(results: &[(Utf8PathBuf, anyhow::Result<Report>)]) -> bool {
+    results.iter().any(|(_, result)| {
+        result
+            .as_ref()
+            .map(|report| {
+                !(report.links.is_empty()
+                    && report.extracted.is_empty()
+                    && report.removed.is_empty())
+            })
+            .unwrap_or_default()
+    })
+}
+
+/// Includes [`coverage`](crate::coverage) if there are languages to give it
+/// for
+pub fn to_json(
+    results: &[(Utf8PathBuf, anyhow::Result<Report>)],
+    coverage: &[Language],
+) -> Value {
+    let mut json = vec![(
+        "directories",
+        Value::Array(
+            results
+                .iter()
+                .map(|(path, result)| directory_json(path, result))
+                .collect(),
+        ),
+    )];
+    if !coverage.is_empty() {
+        json.push(("coverage", coverage::to_json(results, coverage)));
+    }
+    Value::object(json)
+}
+
+pub fn directory_json(
+    path: &Utf8Path,
+    result: &anyhow::Result<Report>,
+) -> Value {
+    match result {
+        Ok(report) => Value::object([
+            ("path", path.as_str().into()),
+            ("run", report.run.as_str().into()),
+            ("error", Value::Null),
+            (
+                "links",
+                Value::Array(
+                    report.links.iter().map(LinkRecord::to_json).collect(),
+                ),
+            ),
+            (
+                "extracted",
+                Value::Array(
+                    report.extracted.iter().map(Extraction::to_json).collect(),
+                ),
+            ),
+            (
+                "removed",
+                report
+                    .removed
+                    .iter()
+                    .map(|path| path.as_str())
+                    .collect::<Vec<_>>()
+                    .into(),
+            ),
+            (
+                "skipped",
+                Value::Array(
+                    report.skipped.iter().map(Skip::to_json).collect(),
+                ),
+            ),
+            (
+                "review",
+                Value::Array(
+                    report
+                        .review
+                        .iter()
+                        .map(|why| why.as_str().into())
+                        .collect(),
+                ),
+            ),
+            ("stats", report.stats.to_json()),
+            ("started", report.started.to_json()),
+            ("finished", report.finished.to_json()),
+            ("interrupted", report.interrupted.into()),
+        ]),
+        Err(why) => Value::object([
+            ("path", path.as_str().into()),
+            ("run", run::id().into()),
+            ("error", why.to_string().into()),
+        ]),
+    }
+}
+
+/// A page to look over in a browser, see [`html`](crate::html)
+pub fn to_html(results: &[(Utf8PathBuf, anyhow::Result<Report>)]) -> String {
+    html::render(results)
+}
+
+/// Every link subfix made, with absolute paths, so Bazarr (or anything else)
+/// can tell them apart from subtitles it manages itself
+pub fn to_bazarr_map(
+    results: &[(Utf8PathBuf, anyhow::Result<Report>)],
+) -> Value {
+    let absolute = |dir: &Utf8Path, path: &Utf8Path| {
+        dir.join(path.strip_prefix(".").unwrap_or(path))
+    };
+    let subtitles = results
+        .iter()
+        .filter_map(|(dir, result)| Some((dir, result.as_ref().ok()?)))
+        .flat_map(|(dir, report)| {
+            report.links.iter().map(move |link| {
+                Value::object([
+                    ("video", absolute(dir, &link.video).as_str().into()),
+                    ("path", absolute(dir, &link.link).as_str().into()),
+                    (
+                        "language",
+                        link.lang
+                            .to_639_1()
+                            .unwrap_or(link.lang.to_639_3())
+                            .into(),
+                    ),
+                    ("forced", false.into()),
+                    ("hi", false.into()),
+                ])
+            })
+        })
+        .collect();
+    Value::object([
+        ("managed_by", "subfix".into()),
+        ("subtitles", Value::Array(subtitles)),
+    ])
+}
@@ -0,0 +1,50 @@
+//! Patterns in what gets linked that almost always mean a subtitle's language
+//! or episode was misread, to point out for someone to check
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use isolang::Language;
+
+use crate::{predicates, report::Report, video::Video};
+
+/// More languages than this for one episode is more likely a misread tag
+/// (or the wrong episode) than a generous release
+const MANY_LANGUAGES: usize = 15;
+/// Fewer episodes than this and a language only one of them has isn't odd
+const SEASON_PACK: usize = 4;
+
+/// Needs [`coverage::record`](crate::coverage::record) to have been run
+pub fn flag_suspicious(videos: &[Video], report: &mut Report) {
+    let languages = report
+        .videos
+        .iter()
+        .map(|video| (video.path.as_path(), &video.languages))
+        .collect::<BTreeMap<_, _>>();
+    let mut review = Vec::new();
+    for (video, languages) in &languages {
+        if languages.len() >= MANY_LANGUAGES {
+            review.push(format!(
+                "{video} has subtitles in {} languages",
+                languages.len()
+            ));
+        }
+    }
+    if videos.len() >= SEASON_PACK && predicates::all_a_series(videos) {
+        let mut havers = BTreeMap::<Language, BTreeSet<_>>::new();
+        for (video, languages) in &languages {
+            for lang in *languages {
+                havers.entry(*lang).or_default().insert(*video);
+            }
+        }
+        for (lang, havers) in havers {
+            if havers.len() == 1 {
+                let video = havers.iter().next().expect("has one");
+                review.push(format!(
+                    "{video} is the only episode with a {} subtitle",
+                    lang.to_name()
+                ));
+            }
+        }
+    }
+    report.review.extend(review);
+}
@@ -0,0 +1,47 @@
+//! An ID for each run, put in log lines, the audit log and JSON output, so
+//! what one run did can be picked out from others going on at the same time
+//! (several roots being watched, or a server's requests)
+
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    sync::{Mutex, MutexGuard},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+static RUN_ID: Mutex<String> = Mutex::new(String::new());
+
+/// Starts a new run, giving it a new ID, which is returned
+pub fn start() -> String {
+    let new = generate();
+    *lock() = new.clone();
+    new
+}
+
+/// The current run's ID, starting one if none has been yet
+pub fn id() -> String {
+    let mut current = lock();
+    if current.is_empty() {
+        *current = generate();
+    }
+    current.clone()
+}
+
+fn generate() -> String {
+    // `RandomState` is seeded randomly, which is all that's needed of it
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u128(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos(),
+    );
+    hasher.write_u32(std::process::id());
+    format!("{:012x}", hasher.finish() >> 16)
+}
+
+fn lock() -> MutexGuard<'static, String> {
+    RUN_ID
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
@@ -0,0 +1,182 @@
+use std::{collections::BTreeSet, fmt, num::NonZeroU8, str::FromStr};
+
+use anyhow::{bail, Context};
+use camino::Utf8Path;
+use log::{debug, warn};
+use once_cell::sync::Lazy;
+use regex::{Regex, RegexBuilder};
+
+pub static SERIES_INFO_REGEX: Lazy<Regex> = Lazy::new(|| {
+    RegexBuilder::new(r"S[0-9]{2}E[0-9]{2}")
+        .case_insensitive(true)
+        .build()
+        .unwrap()
+});
+
+// Chinese releases number episodes like `第01集` (or `第01话` for anime),
+// with the season, if at all, given separately as `第2季`
+static CJK_EPISODE_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"第\s*(?P<episode>[0-9]+)\s*[集话話]").unwrap());
+
+static CJK_SEASON_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"第\s*(?P<season>[0-9]+)\s*季").unwrap());
+
+// Folders for a season of a show, like `Season 02`, `S02`, or `第2季`
+static SEASON_FOLDER_REGEX: Lazy<Regex> = Lazy::new(|| {
+    RegexBuilder::new(
+        r"^(?:(?:season|series|saison|staffel|temporada|stagione|s)[\s._-]*0*(?P<season>[0-9]+)|第\s*0*(?P<cjk>[0-9]+)\s*季)$",
+    )
+    .case_insensitive(true)
+    .build()
+    .unwrap()
+});
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub struct SeriesInfo {
+    season: NonZeroU8,
+    episode: NonZeroU8,
+}
+
+impl FromStr for SeriesInfo {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() != 6 || !SERIES_INFO_REGEX.is_match(s) {
+            bail!("doesn't match pattern S01E01");
+        }
+        let season = s[1..3].parse().context("couldn't parse season")?;
+        let episode = s[4..6].parse().context("couldn't parse episode")?;
+        Ok(SeriesInfo { season, episode })
+    }
+}
+
+impl SeriesInfo {
+    pub fn season(self) -> u8 {
+        self.season.get()
+    }
+
+    pub fn episode(self) -> u8 {
+        self.episode.get()
+    }
+}
+
+impl fmt::Display for SeriesInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "S{:02}E{:02}", self.season, self.episode)
+    }
+}
+
+/// Finds series info in the path component nearest the file, so a folder like
+/// `S01E01-S01E10 Pack/` doesn't decide the episode of everything inside it.
+/// Components naming more than one episode are ignored. If a folder further
+/// up names a different episode the nearer one wins, unless `strict` is set,
+/// in which case it's an error
+pub fn find_in_path(
+    path: &Utf8Path,
+    strict: bool,
+) -> anyhow::Result<Option<SeriesInfo>> {
+    let mut nearest = None;
+    let components = path
+        .components()
+        .map(|component| normalise_width(component.as_str()))
+        .collect::<Vec<_>>();
+    for component in components.iter().rev() {
+        let mut found = SERIES_INFO_REGEX
+            .find_iter(component)
+            .map(|series_info| series_info.as_str().parse::<SeriesInfo>())
+            .collect::<Result<BTreeSet<_>, _>>()?;
+        if found.is_empty() {
+            found = cjk_episodes(component, &components)?;
+        }
+        if found.len() > 1 {
+            debug!(
+                "ignoring {component:?} in {path}, it names more than one \
+                 episode"
+            );
+            continue;
+        }
+        let Some(series_info) = found.into_iter().next() else {
+            continue;
+        };
+        match nearest {
+            None => nearest = Some(series_info),
+            Some(nearest) if nearest != series_info => {
+                if strict {
+                    bail!(
+                        "{component:?} says {series_info} but closer to the \
+                         file says {nearest}"
+                    );
+                }
+                warn!(
+                    "{path}: {component:?} says {series_info}, going with \
+                     {nearest} from closer to the file"
+                );
+                break;
+            },
+            Some(_) => {},
+        }
+    }
+    Ok(nearest)
+}
+
+fn cjk_episodes(
+    component: &str,
+    components: &[String],
+) -> anyhow::Result<BTreeSet<SeriesInfo>> {
+    let episodes = CJK_EPISODE_REGEX
+        .captures_iter(component)
+        .map(|captures| {
+            captures["episode"].parse().with_context(|| {
+                format!("couldn't parse episode in {component:?}")
+            })
+        })
+        .collect::<anyhow::Result<Vec<NonZeroU8>>>()?;
+    if episodes.is_empty() {
+        return Ok(BTreeSet::new());
+    }
+    // The same folder or file first, then anywhere else in the path
+    let season = CJK_SEASON_REGEX
+        .captures(component)
+        .or_else(|| {
+            components
+                .iter()
+                .rev()
+                .find_map(|other| CJK_SEASON_REGEX.captures(other))
+        })
+        .map(|captures| {
+            captures["season"].parse().with_context(|| {
+                format!("couldn't parse season in {component:?}")
+            })
+        })
+        .transpose()?
+        .unwrap_or_else(|| NonZeroU8::new(1).unwrap());
+    Ok(episodes
+        .into_iter()
+        .map(|episode| SeriesInfo { season, episode })
+        .collect())
+}
+
+/// The season a folder is for, going by its name
+pub fn season_of_folder(name: &str) -> Option<u8> {
+    let name = normalise_width(name);
+    let captures = SEASON_FOLDER_REGEX.captures(name.trim())?;
+    captures
+        .name("season")
+        .or_else(|| captures.name("cjk"))?
+        .as_str()
+        .parse()
+        .ok()
+}
+
+/// Turns full-width letters & digits, like in `Ｓ０１Ｅ０１`, into ASCII
+pub fn normalise_width(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            '\u{FF01}'..='\u{FF5E}' => {
+                char::from_u32(c as u32 - 0xFEE0).unwrap_or(c)
+            },
+            '\u{3000}' => ' ',
+            c => c,
+        })
+        .collect()
+}
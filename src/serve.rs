@@ -0,0 +1,520 @@
+//! Lets other programs drive subfix as a long-lived subprocess, rather than
+//! starting it over for every folder
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    io::{self, BufRead, Write},
+    net::{IpAddr, SocketAddr, TcpListener},
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    thread,
+};
+
+use anyhow::{anyhow, bail, Context};
+use camino::Utf8PathBuf;
+use log::{info, warn};
+
+use crate::{
+    cli::{Command, Options, Transport},
+    current_dir,
+    fsops::{set_dry_run, set_roots},
+    http::{Request, Response, TIMEOUT},
+    json::Value,
+    process,
+    report::{self, Report, SkipReason},
+    run,
+};
+
+/// The options a request can give, as switches & as options with a value.
+/// Anything that names a program or a path, or writes somewhere other than
+/// the folder, can only be given when the server's started, so a request
+/// can't get anything run but processing
+const REQUEST_SWITCHES: &[&str] = &[
+    "--dry-run",
+    "-n",
+    "--verbose",
+    "-v",
+    "--detect-language",
+    "--only-missing",
+    "--skip-identical",
+    "--ignore-editions",
+    "--all-folders",
+    "--all-episodes",
+    "--split-stacked",
+    "--transliterate",
+    "--also-unflagged",
+    "--strict",
+    "--include-short",
+];
+const REQUEST_OPTIONS: &[&str] = &[
+    "--mode",
+    "--fallback-mode",
+    "--naming",
+    "--language-codes",
+    "--subs-folder",
+    "--assume-lang",
+    "--set-lang",
+    "--detect-min-words",
+    "--detect-confidence",
+    "--detect-languages",
+    "--bilingual-label",
+    "--source-order",
+    "--matchers",
+    "--content-type",
+    "--season",
+    "--max-name-length",
+    "--cut-threshold",
+    "--min-video-duration",
+    "--since",
+];
+
+/// What every request is handled with, decided when the server starts
+#[derive(Debug)]
+struct Server {
+    /// Where relative paths in requests are from
+    cwd: Utf8PathBuf,
+    /// The options the server was started with, which a request's own go on
+    /// top of
+    base: Vec<String>,
+    /// What HTTP requests have to send as `Authorization: Bearer <token>`
+    token: Option<String>,
+}
+
+pub fn serve(
+    transport: Transport,
+    base: Vec<String>,
+    token: Option<String>,
+) -> anyhow::Result<()> {
+    let server = Server {
+        cwd: current_dir()?,
+        base,
+        token,
+    };
+    match transport {
+        Transport::Stdio => serve_stdio(&server),
+        Transport::Http(addr) => serve_http(addr, server),
+    }
+}
+
+fn serve_stdio(server: &Server) -> anyhow::Result<()> {
+    let stdout = io::stdout();
+    for line in io::stdin().lock().lines() {
+        let line = line.context("failed to read request")?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = handle(&line, server);
+        let mut stdout = stdout.lock();
+        writeln!(stdout, "{response}").context("failed to write response")?;
+        stdout.flush().context("failed to write response")?;
+    }
+    Ok(())
+}
+
+/// Answers a request like `{"id": 1, "path": "/movies/Heat (1995)",
+/// "options": ["--only-missing"]}` with the same JSON `--json` gives for a
+/// folder, plus the request's ID
+fn handle(request: &str, server: &Server) -> Value {
+    let request = match request.parse::<Value>() {
+        Ok(request) => request,
+        Err(why) => return error_response(Value::Null, why),
+    };
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+    match run(&request, server) {
+        Ok((path, result)) => {
+            let mut response = report::directory_json(&path, &result);
+            if let Value::Object(pairs) = &mut response {
+                pairs.insert(0, ("id".to_owned(), id));
+            }
+            response
+        },
+        Err(why) => error_response(id, why),
+    }
+}
+
+fn run(
+    request: &Value,
+    server: &Server,
+) -> anyhow::Result<(Utf8PathBuf, anyhow::Result<Report>)> {
+    let path = request
+        .get("path")
+        .and_then(Value::as_str)
+        .context("request needs a \"path\" string")?;
+    let args = match request.get("options") {
+        Some(args) => args
+            .as_array()
+            .context("\"options\" should be an array")?
+            .iter()
+            .map(|arg| {
+                arg.as_str()
+                    .map(str::to_owned)
+                    .context("\"options\" should only contain strings")
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?,
+        None => Vec::new(),
+    };
+    check_request_options(&args)?;
+    let (options, paths) =
+        Options::from_args(server.base.iter().cloned().chain(args))?;
+    if !paths.is_empty() {
+        bail!("folders go in \"path\", not the server's options");
+    }
+    if !matches!(options.command, Command::Process) {
+        bail!("only processing folders is supported");
+    }
+    if options.interactive {
+        bail!("nobody's there to answer questions for --interactive");
+    }
+    if options.jellyfin_collection.is_some() {
+        bail!("folders go in \"path\", not --jellyfin-collection");
+    }
+    if options.library {
+        bail!("requests are for one folder, --library isn't supported");
+    }
+    set_dry_run(options.dry_run);
+    // Each request is a run of its own
+    run::start();
+    // Relative to where the server was started, not wherever it is now
+    let path = server.cwd.join(path).components().collect::<Utf8PathBuf>();
+    if !path.is_dir() {
+        bail!("{path} is not a folder");
+    }
+    set_roots(&[&path]);
+    let result = process(&path, &options);
+    Ok((path, result))
+}
+
+// Only what's in the allowlists, so nothing that isn't known to be safe gets
+// through, whatever flags are added later
+fn check_request_options(args: &[String]) -> anyhow::Result<()> {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if REQUEST_SWITCHES.contains(&arg.as_str()) {
+            continue;
+        }
+        if REQUEST_OPTIONS.contains(&arg.as_str()) {
+            if args.next().is_none() {
+                bail!("{arg} needs a value");
+            }
+            continue;
+        }
+        match arg.starts_with('-') {
+            true => bail!(
+                "{} can't be given in a request, only when starting the server",
+                arg.split('=').next().unwrap_or_default()
+            ),
+            false => bail!("folders go in \"path\", not \"options\""),
+        }
+    }
+    Ok(())
+}
+
+fn error_response(id: Value, why: anyhow::Error) -> Value {
+    Value::object([("id", id), ("error", format!("{why:#}").into())])
+}
+
+/// What the HTTP server is up to, shared with the thread doing the work
+#[derive(Debug, Default)]
+struct State {
+    /// The request being worked on
+    running: Option<Value>,
+    /// Responses to the requests that finished most recently, newest first
+    runs: VecDeque<Value>,
+    /// Folders with subtitles that couldn't be matched to an edition, and
+    /// those subtitles, waiting for someone to decide whether to link them
+    /// to every edition
+    pending: BTreeMap<String, Vec<String>>,
+}
+
+const RECENT_RUNS: usize = 20;
+
+/// Connections being answered at once, past which more are turned away
+const MAX_CONNECTIONS: usize = 32;
+
+impl State {
+    fn finished(&mut self, response: Value) {
+        self.running = None;
+        if let Some(path) = response.get("path").and_then(Value::as_str) {
+            let ambiguous = response
+                .get("skipped")
+                .and_then(Value::as_array)
+                .unwrap_or_default()
+                .iter()
+                .filter(|skip| {
+                    skip.get("reason").and_then(Value::as_str)
+                        == Some(SkipReason::EditionMismatch.code())
+                })
+                .filter_map(|skip| skip.get("path")?.as_str())
+                .map(str::to_owned)
+                .collect::<Vec<_>>();
+            match ambiguous.is_empty() {
+                true => self.pending.remove(path),
+                false => self.pending.insert(path.to_owned(), ambiguous),
+            };
+        }
+        self.runs.push_front(response);
+        self.runs.truncate(RECENT_RUNS);
+    }
+
+    fn pending_json(&self) -> Value {
+        Value::Array(
+            self.pending
+                .iter()
+                .map(|(path, subtitles)| {
+                    Value::object([
+                        ("path", path.as_str().into()),
+                        (
+                            "subtitles",
+                            subtitles
+                                .iter()
+                                .map(String::as_str)
+                                .collect::<Vec<_>>()
+                                .into(),
+                        ),
+                    ])
+                })
+                .collect(),
+        )
+    }
+}
+
+fn serve_http(addr: SocketAddr, server: Server) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(addr)
+        .with_context(|| format!("failed to listen on {addr}"))?;
+    info!("listening on http://{addr}");
+    if server.token.is_none() {
+        warn!(
+            "there's no --token, so anything that can reach {addr} can use it"
+        );
+    }
+    let server = Arc::new(server);
+    let state = Arc::new(Mutex::new(State::default()));
+    let connections = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(why) => {
+                warn!("failed to accept connection: {why}");
+                continue;
+            },
+        };
+        // Each connection gets a thread of its own, so a client that's slow
+        // to send its request (or read the answer) only holds itself up
+        let timeouts = stream
+            .set_read_timeout(Some(TIMEOUT))
+            .and_then(|()| stream.set_write_timeout(Some(TIMEOUT)));
+        if let Err(why) = timeouts {
+            warn!("failed to set up connection: {why}");
+            continue;
+        }
+        if connections.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+            connections.fetch_sub(1, Ordering::SeqCst);
+            let busy = http_error(503, "too many connections");
+            if let Err(why) = busy.write(&stream) {
+                warn!("failed to send response: {why}");
+            }
+            continue;
+        }
+        let (server, state, connections) = (
+            Arc::clone(&server),
+            Arc::clone(&state),
+            Arc::clone(&connections),
+        );
+        thread::spawn(move || {
+            let response = match Request::read(&stream) {
+                Ok(request) => route(request, addr, &server, &state),
+                Err(why) => http_error(400, format!("{why:#}")),
+            };
+            if let Err(why) = response.write(&stream) {
+                warn!("failed to send response: {why}");
+            }
+            connections.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+    Ok(())
+}
+
+// GET / is a page for people, the rest is for programs:
+// - POST /process starts processing a folder (the body is the same as a
+//   `--stdio` request)
+// - GET /status says whether it's still going
+// - GET /report gives the result of the last one to finish, and GET /runs the
+//   last few
+// - GET /pending lists subtitles waiting on a decision, which are resolved by
+//   POSTing `{"path": ...}` to /pending/approve (link them to every edition) or
+//   /pending/reject (leave them be)
+fn route(
+    request: Request,
+    addr: SocketAddr,
+    server: &Arc<Server>,
+    state: &Arc<Mutex<State>>,
+) -> Response {
+    // The page is the same for everyone, and has to load before it can send
+    // the token
+    let page = request.method == "GET" && request.path == "/";
+    if let (false, Err(refusal)) = (page, check_caller(&request, addr, server))
+    {
+        return refusal;
+    }
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/") => Response {
+            status: 200,
+            content_type: "text/html; charset=utf-8",
+            body: include_str!("ui.html").to_owned(),
+        },
+        ("POST", "/process") => start(request.body, server, state),
+        ("GET", "/status") => {
+            let current = lock(state);
+            Response::json(
+                200,
+                Value::object([
+                    ("running", current.running.is_some().into()),
+                    ("request", current.running.clone().into()),
+                ]),
+            )
+        },
+        ("GET", "/report") => match lock(state).runs.front().cloned() {
+            Some(last) => Response::json(200, last),
+            None => http_error(404, "nothing has been processed yet"),
+        },
+        ("GET", "/runs") => Response::json(
+            200,
+            Value::Array(lock(state).runs.iter().cloned().collect()),
+        ),
+        ("GET", "/pending") => Response::json(200, lock(state).pending_json()),
+        ("POST", decision @ ("/pending/approve" | "/pending/reject")) => {
+            let path = match request.body.parse::<Value>() {
+                Ok(body) => match body.get("path").and_then(Value::as_str) {
+                    Some(path) => path.to_owned(),
+                    None => return http_error(400, "expected a \"path\""),
+                },
+                Err(why) => return http_error(400, format!("{why:#}")),
+            };
+            if !lock(state).pending.contains_key(&path) {
+                return http_error(404, "nothing is pending for that folder");
+            }
+            match decision {
+                "/pending/approve" => {
+                    let request = Value::object([
+                        ("path", path.as_str().into()),
+                        ("options", vec!["--ignore-editions"].into()),
+                    ]);
+                    start(request.to_string(), server, state)
+                },
+                _ => {
+                    lock(state).pending.remove(&path);
+                    Response::json(200, Value::object([("ok", true.into())]))
+                },
+            }
+        },
+        (
+            _,
+            "/" | "/process" | "/status" | "/report" | "/runs" | "/pending"
+            | "/pending/approve" | "/pending/reject",
+        ) => http_error(405, "method not allowed"),
+        _ => http_error(404, "no such endpoint"),
+    }
+}
+
+fn lock(state: &Mutex<State>) -> MutexGuard<'_, State> {
+    state
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// Processing moves between directories, so it's one folder at a time
+fn start(
+    body: String,
+    server: &Arc<Server>,
+    state: &Arc<Mutex<State>>,
+) -> Response {
+    let parsed = match body.parse::<Value>() {
+        Ok(parsed) => parsed,
+        Err(why) => return http_error(400, format!("{why:#}")),
+    };
+    let mut current = lock(state);
+    if current.running.is_some() {
+        return http_error(409, "already processing a folder");
+    }
+    let id = parsed.get("id").cloned().unwrap_or(Value::Null);
+    current.running = Some(parsed);
+    drop(current);
+    let (server, state) = (Arc::clone(server), Arc::clone(state));
+    thread::spawn(move || {
+        // Caught, or the server would think it's busy with this request
+        // until it's restarted
+        let response =
+            panic::catch_unwind(AssertUnwindSafe(|| handle(&body, &server)))
+                .unwrap_or_else(|_| {
+                    error_response(id, anyhow!("processing panicked"))
+                });
+        lock(&state).finished(response);
+    });
+    Response::json(202, Value::object([("accepted", true.into())]))
+}
+
+/// Turns away requests that didn't come from something trusted: ones without
+/// the token, if there is one, and ones a web page could have sent. Pages on
+/// other sites can only make a browser send plain text, or forms, without
+/// asking, and their requests say where they came from in `Origin`
+fn check_caller(
+    request: &Request,
+    addr: SocketAddr,
+    server: &Server,
+) -> Result<(), Response> {
+    if let Some(token) = &server.token {
+        let given = request
+            .header("authorization")
+            .and_then(|value| value.strip_prefix("Bearer "));
+        if given != Some(token.as_str()) {
+            return Err(http_error(401, "missing or wrong token"));
+        }
+    }
+    if let Some(origin) = request.header("origin") {
+        let host = request.header("host").unwrap_or_default();
+        let allowed = origin.strip_prefix("http://") == Some(host)
+            && is_own_host(host, addr);
+        if !allowed {
+            return Err(http_error(
+                403,
+                format!("requests from {origin} aren't allowed"),
+            ));
+        }
+    }
+    if request.method == "POST" {
+        let json = request
+            .header("content-type")
+            .and_then(|value| value.split(';').next())
+            .map(|value| value.trim().eq_ignore_ascii_case("application/json"))
+            .unwrap_or_default();
+        if !json {
+            return Err(http_error(415, "the body has to be application/json"));
+        }
+    }
+    Ok(())
+}
+
+// Whether `Host` names the server itself, rather than some other site's name
+// pointed at it
+fn is_own_host(host: &str, addr: SocketAddr) -> bool {
+    let port = addr.port();
+    if host == addr.to_string() || host == format!("localhost:{port}") {
+        return true;
+    }
+    // Listening everywhere, so any of the machine's addresses will do
+    addr.ip().is_unspecified()
+        && host
+            .rsplit_once(':')
+            .map(|(ip, given)| {
+                given == port.to_string()
+                    && ip.trim_matches(['[', ']']).parse::<IpAddr>().is_ok()
+            })
+            .unwrap_or_default()
+}
+
+fn http_error(status: u16, message: impl Into<String>) -> Response {
+    Response::json(status, Value::object([("error", message.into().into())]))
+}
@@ -0,0 +1,13 @@
+// Sorts a report's table by the clicked column, the other way on a second
+// click
+document.querySelectorAll("th").forEach(th => th.addEventListener("click", () => {
+  const table = th.closest("table");
+  const column = [...th.parentNode.children].indexOf(th);
+  const rows = [...table.tBodies[0].rows];
+  const ascending = table.dataset.sorted !== String(column);
+  table.dataset.sorted = ascending ? column : "";
+  rows.sort((a, b) => a.cells[column].textContent.localeCompare(
+    b.cells[column].textContent, undefined, { numeric: true }));
+  if (!ascending) rows.reverse();
+  table.tBodies[0].append(...rows);
+}));
@@ -0,0 +1,193 @@
+use std::{
+    fmt, fs,
+    io::{BufWriter, Write},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context};
+use camino::Utf8PathBuf;
+use log::{info, warn};
+
+use crate::{
+    cli::Options,
+    fsops::{check_space, copy_permissions, create_file, remove_file},
+    report::{Report, Skip, SkipReason},
+    srt,
+    subtitle::{Subtitle, PART_SUFFIX_REGEX},
+};
+
+/// Where the second part of a split subtitle starts in the single video
+#[derive(Debug, Copy, Clone)]
+pub enum SplitPoint {
+    At(Duration),
+    /// Estimated from the end of the first part's last cue
+    Auto,
+}
+
+impl SplitPoint {
+    pub fn parse(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "auto" => Ok(SplitPoint::Auto),
+            _ => srt::parse_timestamp(s).map(SplitPoint::At),
+        }
+    }
+}
+
+impl fmt::Display for SplitPoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SplitPoint::At(at) => f.write_str(&srt::format_timestamp(*at)),
+            SplitPoint::Auto => f.write_str("auto"),
+        }
+    }
+}
+
+/// Merges `.cd1`/`.cd2` style SRT pairs into one subtitle per language,
+/// replacing the parts in `subs`
+pub fn merge_split_parts(
+    subs: &mut Vec<Subtitle>,
+    split_point: SplitPoint,
+    options: &Options,
+    report: &mut Report,
+) {
+    let mut index = 0;
+    while index < subs.len() {
+        if subs[index].part != Some(1) {
+            index += 1;
+            continue;
+        }
+        let first = &subs[index];
+        let Some(second_index) = subs.iter().position(|sub| {
+            sub.part == Some(2)
+                && sub.lang == first.lang
+                && sub.series_info == first.series_info
+        }) else {
+            warn!("{} has no second part to merge with", first.path);
+            index += 1;
+            continue;
+        };
+        let start = Instant::now();
+        let merged = merge(first, &subs[second_index], split_point, options);
+        report.stats.execute += start.elapsed();
+        match merged {
+            Ok(merged) => {
+                info!(
+                    "merged {} and {} into {}",
+                    first.path, subs[second_index].path, merged.path
+                );
+                subs[index] = merged;
+                let second = subs.remove(second_index);
+                report.skip(Skip::new(
+                    second.path,
+                    SkipReason::MergedPart,
+                    "merged into the first part",
+                ));
+                if second_index < index {
+                    index -= 1;
+                }
+            },
+            Err(why) => {
+                warn!("couldn't merge split subtitle {}: {why:#}", first.path);
+            },
+        }
+        index += 1;
+    }
+}
+
+fn merge(
+    first: &Subtitle,
+    second: &Subtitle,
+    split_point: SplitPoint,
+    options: &Options,
+) -> anyhow::Result<Subtitle> {
+    let line_ending = options.line_ending;
+    if first.path.extension() != Some("srt")
+        || second.path.extension() != Some("srt")
+    {
+        bail!("only SRT subtitles can be merged");
+    }
+    let path = merged_path(first);
+    let size = |sub: &Subtitle| {
+        fs::metadata(&sub.path)
+            .map(|metadata| metadata.len())
+            .unwrap_or_default()
+    };
+    if let Some(dir) = path.parent() {
+        check_space(dir, size(first) + size(second), 1)?;
+    }
+    let mut out = BufWriter::new(
+        create_file(&path)
+            .with_context(|| format!("failed to create {path}"))?,
+    );
+    let written = (|| {
+        let mut index = 0;
+        let mut offset = Duration::ZERO;
+        for cue in srt::open(&first.path)?.strict() {
+            let cue = cue.with_context(|| format!("in {}", first.path))?;
+            offset = offset.max(cue.end);
+            index += 1;
+            srt::write_cue(&mut out, index, &cue, line_ending)?;
+        }
+        let offset = match split_point {
+            SplitPoint::At(offset) => offset,
+            SplitPoint::Auto => {
+                warn!(
+                    "estimating split point as {} from the end of {}, pass an \
+                     exact time if the second part is out of sync",
+                    srt::format_timestamp(offset),
+                    first.path
+                );
+                offset
+            },
+        };
+        for cue in srt::open(&second.path)?.strict() {
+            let mut cue = cue.with_context(|| format!("in {}", second.path))?;
+            let (Some(start), Some(end)) =
+                (cue.start.checked_add(offset), cue.end.checked_add(offset))
+            else {
+                bail!(
+                    "the cue at {} in {} is too late to move on by {}",
+                    srt::format_timestamp(cue.start),
+                    second.path,
+                    srt::format_timestamp(offset)
+                );
+            };
+            (cue.start, cue.end) = (start, end);
+            index += 1;
+            srt::write_cue(&mut out, index, &cue, line_ending)?;
+        }
+        out.flush()?;
+        anyhow::Ok(())
+    })();
+    if let Err(why) = written {
+        drop(out);
+        let _ = remove_file(&path);
+        return Err(why.context(format!("failed to write {path}")));
+    }
+    if options.preserve_permissions {
+        copy_permissions(&first.path, &path)?;
+    }
+    Ok(Subtitle {
+        path,
+        lang: first.lang,
+        series_info: first.series_info,
+        part: None,
+        edition: first.edition.clone(),
+        source: first.source,
+        label: first.label.clone(),
+        flags: first.flags,
+        title: first.title.clone(),
+        shared: first.shared,
+    })
+}
+
+// Sits next to the first part, e.g. Subs/3_English.cd1.srt becomes
+// Subs/3_English.merged.srt
+fn merged_path(first: &Subtitle) -> Utf8PathBuf {
+    let stem = first
+        .path
+        .file_stem()
+        .expect("subtitle should have file name");
+    let stem = PART_SUFFIX_REGEX.replace(stem, "");
+    first.path.with_file_name(format!("{stem}.merged.srt"))
+}
@@ -0,0 +1,245 @@
+use std::{
+    borrow::Cow,
+    fs::File,
+    io::{self, BufRead, BufReader, Read, Take, Write},
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Context};
+use camino::Utf8Path;
+
+use crate::{
+    normalize::{strip_bom, LineEnding},
+    stats,
+};
+
+/// Anything bigger than this isn't a real subtitle file
+pub const MAX_SUBTITLE_SIZE: u64 = 64 * 1024 * 1024;
+/// Stops a malformed file with no blank lines becoming one enormous cue
+const MAX_CUE_TEXT: usize = 64 * 1024;
+
+/// A single SubRip cue; the index isn't kept as it's regenerated on write
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Cue {
+    pub start: Duration,
+    pub end: Duration,
+    pub text: String,
+}
+
+/// Reads cues one at a time, so files are never fully loaded into memory
+#[derive(Debug)]
+pub struct Cues<R> {
+    reader: R,
+    buf: Vec<u8>,
+    first_line: bool,
+    strict: bool,
+}
+
+pub fn open(path: &Utf8Path) -> anyhow::Result<Cues<BufReader<Take<File>>>> {
+    let file =
+        File::open(path).with_context(|| format!("failed to open {path}"))?;
+    let size = file.metadata().map(|meta| meta.len()).unwrap_or_default();
+    if size > MAX_SUBTITLE_SIZE {
+        bail!(
+            "{path} is {size} bytes, which is too big to be a subtitle (the \
+             limit is {MAX_SUBTITLE_SIZE})"
+        );
+    }
+    // In case the file grows while it's being read
+    Ok(Cues::new(BufReader::new(file.take(MAX_SUBTITLE_SIZE))))
+}
+
+impl<R: BufRead> Cues<R> {
+    pub fn new(reader: R) -> Self {
+        Cues {
+            reader,
+            buf: Vec::new(),
+            first_line: true,
+            strict: false,
+        }
+    }
+
+    /// Fails on text that isn't UTF-8 rather than replacing it, for cues
+    /// that are written out again, which would otherwise have `�` in their
+    /// text where the original had something else
+    pub fn strict(mut self) -> Self {
+        self.strict = true;
+        self
+    }
+
+    // Lossy as plenty of subtitles aren't UTF-8
+    fn next_line(&mut self) -> io::Result<Option<String>> {
+        self.buf.clear();
+        match self.reader.read_until(b'\n', &mut self.buf)? {
+            0 => return Ok(None),
+            read => stats::add_bytes_read(read),
+        }
+        let line = match self.strict {
+            true => {
+                Cow::Borrowed(std::str::from_utf8(&self.buf).map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "it isn't UTF-8, so rewriting it would garble its text",
+                    )
+                })?)
+            },
+            false => String::from_utf8_lossy(&self.buf),
+        };
+        let line = line.trim_end_matches(['\n', '\r']);
+        let line = match self.first_line {
+            true => strip_bom(line),
+            false => line,
+        };
+        self.first_line = false;
+        Ok(Some(line.to_owned()))
+    }
+
+    fn next_cue(&mut self) -> anyhow::Result<Option<Cue>> {
+        let line = loop {
+            match self.next_line()? {
+                Some(line) if line.trim().is_empty() => continue,
+                Some(line) => break line,
+                None => return Ok(None),
+            }
+        };
+        // Most files have an index line before the timings, tolerate ones
+        // that don't
+        let timings = match line.contains("-->") {
+            true => line,
+            false => self
+                .next_line()?
+                .ok_or_else(|| anyhow!("cue {line:?} has no timings"))?,
+        };
+        let (start, end) = timings
+            .split_once("-->")
+            .ok_or_else(|| anyhow!("invalid cue timings {timings:?}"))?;
+        let start = parse_timestamp(start.trim())?;
+        // Some files have positioning information after the end time
+        let end = end.split_whitespace().next().unwrap_or_default();
+        let end = parse_timestamp(end)?;
+        let mut text = String::new();
+        while let Some(line) = self.next_line()? {
+            if line.trim().is_empty() {
+                break;
+            }
+            if text.len() + line.len() > MAX_CUE_TEXT {
+                bail!(
+                    "cue at {} is unreasonably long",
+                    format_timestamp(start)
+                );
+            }
+            if !text.is_empty() {
+                text.push('\n');
+            }
+            text.push_str(&line);
+        }
+        Ok(Some(Cue { start, end, text }))
+    }
+}
+
+impl<R: BufRead> Iterator for Cues<R> {
+    type Item = anyhow::Result<Cue>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_cue().transpose()
+    }
+}
+
+/// Writes a cue with the given (1-based) index
+pub fn write_cue(
+    out: &mut impl Write,
+    index: usize,
+    cue: &Cue,
+    line_ending: LineEnding,
+) -> io::Result<()> {
+    let newline = line_ending.as_str();
+    write!(
+        out,
+        "{index}{newline}{} --> {}{newline}",
+        format_timestamp(cue.start),
+        format_timestamp(cue.end),
+    )?;
+    for line in cue.text.lines() {
+        write!(out, "{line}{newline}")?;
+    }
+    out.write_all(newline.as_bytes())
+}
+
+/// Parses `HH:MM:SS,mmm` (a `.` is also accepted before the milliseconds)
+pub fn parse_timestamp(s: &str) -> anyhow::Result<Duration> {
+    let (hms, millis) = s
+        .split_once([',', '.'])
+        .ok_or_else(|| anyhow!("timestamp {s:?} has no milliseconds"))?;
+    let mut parts = hms.split(':');
+    let (Some(hours), Some(minutes), Some(seconds), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        bail!("timestamp {s:?} isn't in the form HH:MM:SS,mmm");
+    };
+    let number = |part: &str| {
+        part.trim()
+            .parse::<u64>()
+            .with_context(|| format!("invalid timestamp {s:?}"))
+    };
+    let (hours, minutes, seconds, millis) = (
+        number(hours)?,
+        number(minutes)?,
+        number(seconds)?,
+        number(millis)?,
+    );
+    // Checked, as a subtitle file can say anything
+    hours
+        .checked_mul(3600)
+        .and_then(|total| total.checked_add(minutes.checked_mul(60)?))
+        .and_then(|total| total.checked_add(seconds))
+        .and_then(|total| {
+            Duration::from_secs(total)
+                .checked_add(Duration::from_millis(millis))
+        })
+        .ok_or_else(|| anyhow!("timestamp {s:?} is out of range"))
+}
+
+pub fn format_timestamp(timestamp: Duration) -> String {
+    let millis = timestamp.as_millis();
+    format!(
+        "{:02}:{:02}:{:02},{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(
+            parse_timestamp("01:02:03,456").unwrap(),
+            Duration::from_millis(3_723_456)
+        );
+        for bad in [
+            "00:00:01",
+            "00:01,000",
+            "aa:00:01,000",
+            "18446744073709551615:00:00,000",
+            "00:18446744073709551615:00,000",
+            "00:00:18446744073709551615,18446744073709551615",
+        ] {
+            assert!(parse_timestamp(bad).is_err(), "{bad:?} was read");
+        }
+    }
+
+    #[test]
+    fn strict_reading() {
+        let latin1 = b"1\n00:00:01,000 --> 00:00:02,000\nCaf\xe9\n";
+        let lossy = Cues::new(&latin1[..]).next().unwrap().unwrap();
+        assert_eq!(lossy.text, "Caf\u{fffd}");
+        assert!(Cues::new(&latin1[..]).strict().next().unwrap().is_err());
+        let utf8 = "1\n00:00:01,000 --> 00:00:02,000\nCafé\n";
+        let strict = Cues::new(utf8.as_bytes()).strict().next().unwrap();
+        assert_eq!(strict.unwrap().text, "Café");
+    }
+}
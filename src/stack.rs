@@ -0,0 +1,273 @@
+//! Movies stacked across several files, like `Movie-part1.mkv` &
+//! `Movie-part2.mkv`, which Jellyfin shows as one. Subtitles for a part are
+//! only linked to that part, and ones for the whole movie would be out of
+//! sync on every part but the first, so they're split into parts or left for
+//! someone to look at
+
+use camino::Utf8PathBuf;
+use log::{info, warn};
+// Without the probe feature there's no knowing where each part starts, so
+// subtitles for the whole movie can't be split
+#[cfg(feature = "probe")]
+use {
+    crate::{
+        fsops::{check_space, copy_permissions, create_file, remove_file},
+        probe,
+        srt::{self, Cue},
+    },
+    anyhow::{bail, Context},
+    camino::Utf8Path,
+    std::{
+        fs,
+        io::{BufWriter, Write},
+        time::{Duration, Instant},
+    },
+};
+
+use crate::{
+    cli::Options,
+    report::{Report, Skip, SkipReason},
+    subtitle::{Subtitle, PART_SUFFIX_REGEX},
+    video::Video,
+};
+
+pub fn is_stacked(videos: &[Video]) -> bool {
+    videos.iter().filter(|video| video.part.is_some()).count() > 1
+}
+
+/// Which part of a stacked movie a subtitle is for: its own `.cd2` style
+/// suffix, or the part it's named after (`Movie-part2.en.srt`) or in the
+/// folder of (`Movie-part2/English.srt`). `None` means the whole movie
+fn part_of(subtitle: &Subtitle, videos: &[Video]) -> Option<u8> {
+    if subtitle.part.is_some() {
+        return subtitle.part;
+    }
+    let stem = subtitle.path.file_stem()?;
+    videos.iter().find_map(|video| {
+        let part = video.part?;
+        let video_stem = video.path.file_stem()?;
+        let named_after = stem
+            .strip_prefix(video_stem)
+            .map(|tags| tags.is_empty() || tags.starts_with('.'))
+            .unwrap_or_default();
+        let in_folder = video
+            .path
+            .parent()
+            .and_then(|dir| subtitle.path.strip_prefix(dir).ok())
+            .and_then(|relative| relative.parent())
+            .and_then(|folder| folder.components().next())
+            .map(|folder| folder.as_str() == video_stem)
+            .unwrap_or_default();
+        (named_after || in_folder).then_some(part)
+    })
+}
+
+/// Whether a subtitle can be linked to a video as far as stacking goes, once
+/// [`sort_into_parts`] has been through them
+pub fn same_part(video: &Video, subtitle: &Subtitle) -> bool {
+    match video.part {
+        Some(part) => subtitle.shared || subtitle.part == Some(part),
+        None => true,
+    }
+}
+
+/// Works out which part each subtitle is for, and splits the ones for the
+/// whole movie into one per part, with `--split-stacked`. Ones that can't be
+/// (or without it) are skipped and flagged for review
+pub fn sort_into_parts(
+    videos: &[Video],
+    subs: &mut Vec<Subtitle>,
+    options: &Options,
+    report: &mut Report,
+) {
+    for sub in subs.iter_mut() {
+        sub.part = part_of(sub, videos);
+    }
+    let whole = subs
+        .iter()
+        .enumerate()
+        .filter(|(_, sub)| !sub.shared && sub.part.is_none())
+        .map(|(index, _)| index)
+        .collect::<Vec<_>>();
+    // Backwards, so removing one doesn't move the others
+    for index in whole.into_iter().rev() {
+        let sub = subs.remove(index);
+        // Split by an earlier run
+        let already =
+            videos.iter().filter_map(|video| video.part).all(|part| {
+                let path = part_path(&sub, part);
+                subs.iter().any(|sub| sub.path == path)
+            });
+        if already {
+            info!("{} has already been split into parts", sub.path);
+            report.skip(Skip::new(
+                sub.path,
+                SkipReason::SplitIntoParts,
+                "already split into a subtitle for each part",
+            ));
+            continue;
+        }
+        let split: Result<Vec<Subtitle>, String> = match options.split_stacked {
+            #[cfg(feature = "probe")]
+            true => {
+                let start = Instant::now();
+                let split = split(&sub, videos, options);
+                report.stats.execute += start.elapsed();
+                split.map_err(|why| format!("it couldn't be split: {why:#}"))
+            },
+            _ => Err("see --split-stacked".to_owned()),
+        };
+        match split {
+            Ok(parts) => {
+                info!(
+                    "split {} into {}",
+                    sub.path,
+                    parts
+                        .iter()
+                        .map(|part| part.path.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+                subs.extend(parts);
+                report.skip(Skip::new(
+                    sub.path,
+                    SkipReason::SplitIntoParts,
+                    "split into a subtitle for each part",
+                ));
+            },
+            Err(why) => {
+                let flag = format!(
+                    "{} is for the whole of a movie stacked across {} files, \
+                     so it would be out of sync on all but the first, and \
+                     isn't linked ({why})",
+                    sub.path,
+                    videos.len(),
+                );
+                warn!("{flag}");
+                report.review.push(flag);
+                report.skip(Skip::new(
+                    sub.path,
+                    SkipReason::StackedMovie,
+                    "for the whole of a stacked movie",
+                ));
+            },
+        }
+    }
+}
+
+/// Cuts an SRT subtitle where each part starts, going by how long the parts
+/// before it run, so each part's cues start from zero
+#[cfg(feature = "probe")]
+fn split(
+    subtitle: &Subtitle,
+    videos: &[Video],
+    options: &Options,
+) -> anyhow::Result<Vec<Subtitle>> {
+    if subtitle.path.extension() != Some("srt") {
+        bail!("only SRT subtitles can be split");
+    }
+    let mut parts = videos
+        .iter()
+        .filter_map(|video| Some((video.part?, video)))
+        .collect::<Vec<_>>();
+    parts.sort_by_key(|&(part, _)| part);
+    parts.dedup_by_key(|&mut (part, _)| part);
+    if parts.len() != videos.len() {
+        bail!(
+            "not every video is a part of the same stack (other versions are \
+             mixed in)"
+        );
+    }
+    // Where each part starts in the whole, and where the next does
+    let mut offset = Duration::ZERO;
+    let mut bounds = Vec::with_capacity(parts.len());
+    for (index, &(part, video)) in parts.iter().enumerate() {
+        match index + 1 == parts.len() {
+            true => bounds.push((part, offset, Duration::MAX)),
+            false => {
+                let end = offset
+                    .checked_add(probe::duration(&video.path)?)
+                    .context("the parts add up to too long to be a movie")?;
+                bounds.push((part, offset, end));
+                offset = end;
+            },
+        }
+    }
+    if let Some(dir) = subtitle.path.parent() {
+        let size = fs::metadata(&subtitle.path)
+            .map(|metadata| metadata.len())
+            .unwrap_or_default();
+        check_space(dir, size, bounds.len() as u64)?;
+    }
+    let cues = srt::open(&subtitle.path)?
+        .collect::<anyhow::Result<Vec<Cue>>>()
+        .with_context(|| format!("in {}", subtitle.path))?;
+    let mut written: Vec<Subtitle> = Vec::new();
+    for (part, start, end) in bounds {
+        let path = part_path(subtitle, part);
+        let result = write_part(&path, &cues, start, end, options);
+        if let Err(why) = result {
+            let _ = remove_file(&path);
+            written.iter().for_each(|sub| {
+                let _ = remove_file(&sub.path);
+            });
+            return Err(why.context(format!("failed to write {path}")));
+        }
+        if options.preserve_permissions {
+            copy_permissions(&subtitle.path, &path)?;
+        }
+        written.push(Subtitle {
+            path,
+            lang: subtitle.lang,
+            series_info: subtitle.series_info,
+            part: Some(part),
+            edition: subtitle.edition.clone(),
+            source: subtitle.source,
+            label: subtitle.label.clone(),
+            flags: subtitle.flags,
+            title: subtitle.title.clone(),
+            shared: false,
+        });
+    }
+    Ok(written)
+}
+
+/// Writes the cues starting in `start..end`, moved back by `start`
+#[cfg(feature = "probe")]
+fn write_part(
+    path: &Utf8Path,
+    cues: &[Cue],
+    start: Duration,
+    end: Duration,
+    options: &Options,
+) -> anyhow::Result<()> {
+    let mut out = BufWriter::new(create_file(path)?);
+    let mut index = 0;
+    for cue in cues
+        .iter()
+        .filter(|cue| cue.start >= start && cue.start < end)
+    {
+        let cue = Cue {
+            start: cue.start - start,
+            end: cue.end.saturating_sub(start),
+            text: cue.text.clone(),
+        };
+        index += 1;
+        srt::write_cue(&mut out, index, &cue, options.line_ending)?;
+    }
+    if index == 0 {
+        warn!("{path} has no cues, the part it's for may have no dialogue");
+    }
+    out.flush()?;
+    Ok(())
+}
+
+// Sits next to the whole, e.g. Subs/English.srt gives Subs/English.part2.srt
+fn part_path(whole: &Subtitle, part: u8) -> Utf8PathBuf {
+    let stem = whole
+        .path
+        .file_stem()
+        .expect("subtitle should have file name");
+    let stem = PART_SUFFIX_REGEX.replace(stem, "");
+    whole.path.with_file_name(format!("{stem}.part{part}.srt"))
+}
@@ -0,0 +1,59 @@
+//! Where the time goes, for tuning runs over slow (e.g. network) storage
+
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use crate::json::Value;
+
+static BYTES_READ: AtomicU64 = AtomicU64::new(0);
+
+/// Counts bytes read from subtitle, `.nfo`, and other files' contents
+pub fn add_bytes_read(bytes: usize) {
+    BYTES_READ.fetch_add(bytes as u64, Ordering::Relaxed);
+}
+
+pub fn bytes_read() -> u64 {
+    BYTES_READ.load(Ordering::Relaxed)
+}
+
+/// Resource usage while processing one directory
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Stats {
+    /// Listing directories
+    pub walk: Duration,
+    /// Working out subtitles' languages & episodes
+    pub parse: Duration,
+    /// Pairing subtitles with videos
+    pub matching: Duration,
+    /// Making links and writing files
+    pub execute: Duration,
+    /// Directory entries looked at
+    pub files: usize,
+    pub bytes_read: u64,
+}
+
+impl Stats {
+    pub fn add(&mut self, other: &Stats) {
+        self.walk += other.walk;
+        self.parse += other.parse;
+        self.matching += other.matching;
+        self.execute += other.execute;
+        self.files += other.files;
+        self.bytes_read += other.bytes_read;
+    }
+
+    pub fn to_json(self) -> Value {
+        let ms =
+            |duration: Duration| Value::Number(duration.as_secs_f64() * 1000.0);
+        Value::object([
+            ("walk_ms", ms(self.walk)),
+            ("parse_ms", ms(self.parse)),
+            ("match_ms", ms(self.matching)),
+            ("execute_ms", ms(self.execute)),
+            ("files", self.files.into()),
+            ("bytes_read", Value::Number(self.bytes_read as f64)),
+        ])
+    }
+}
@@ -0,0 +1,513 @@
+use std::{fs, str::FromStr};
+
+use anyhow::bail;
+use camino::{Utf8Component, Utf8Path, Utf8PathBuf};
+use isolang::Language;
+use log::{info, trace};
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex, RegexBuilder};
+
+use crate::{
+    edition,
+    naming::BIBLIOGRAPHIC,
+    predicates,
+    report::{Report, Skip, SkipReason},
+    series::{self, SeriesInfo},
+};
+
+#[derive(Debug)]
+pub struct Subtitle {
+    pub path: Utf8PathBuf,
+    pub lang: Language,
+    pub series_info: Option<SeriesInfo>,
+    /// Which part this is of a subtitle split across multiple files
+    pub part: Option<u8>,
+    pub edition: Option<String>,
+    pub source: Source,
+    /// Used in the link name instead of the language code, e.g. `zh-en` for
+    /// a subtitle with both Chinese & English lines
+    pub label: Option<String>,
+    pub flags: Flags,
+    /// Shown by Jellyfin to tell tracks of the same language apart
+    pub title: Option<String>,
+    /// Linked to every episode, like the karaoke for an opening song
+    pub shared: bool,
+}
+
+/// What sets a track apart from others of the same language
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct Flags {
+    /// Only covers foreign dialogue or on-screen text, so shouldn't be picked
+    /// as the main track
+    pub forced: bool,
+    /// Also describes sounds, for the deaf & hard of hearing (SDH or CC)
+    pub hearing_impaired: bool,
+    /// The text of an audio description, what's happening on screen for the
+    /// blind & partially sighted (AD)
+    pub audio_description: bool,
+}
+
+/// Where a subtitle was found, used to pick between subtitles of the same
+/// language
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Source {
+    /// Right next to the videos
+    Adjacent,
+    /// In a folder named after one of the videos
+    VideoFolder,
+    /// In a generic subtitle folder, like `Subs`
+    SubsFolder,
+    /// Anywhere else, like download folders searched with `--all-folders`
+    Other,
+}
+
+impl Source {
+    pub const DEFAULT_ORDER: [Source; 4] = [
+        Source::Adjacent,
+        Source::VideoFolder,
+        Source::SubsFolder,
+        Source::Other,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Source::Adjacent => "adjacent",
+            Source::VideoFolder => "video-folder",
+            Source::SubsFolder => "subs-folder",
+            Source::Other => "other",
+        }
+    }
+}
+
+impl FromStr for Source {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match Source::DEFAULT_ORDER
+            .into_iter()
+            .find(|source| source.as_str() == s)
+        {
+            Some(source) => Ok(source),
+            None => bail!(
+                "unknown subtitle source {s:?}, expected adjacent, \
+                 video-folder, subs-folder, or other"
+            ),
+        }
+    }
+}
+
+static NUMBER_PREFIX_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^\d+_").unwrap());
+
+// Anime releases ship on-screen text and song lyrics separately from the
+// full dialogue, as "Signs & Songs" or just "Signs"
+static SIGNS_AND_SONGS_REGEX: Lazy<Regex> = Lazy::new(|| {
+    RegexBuilder::new(
+        r"^(english[ ._-]*)?(?P<title>signs([ ._-]*(&|and)[ ._-]*songs)?|s[ ._-]*&[ ._-]*s)$",
+    )
+    .case_insensitive(true)
+    .build()
+    .unwrap()
+});
+
+// The suffixes Jellyfin stacks videos by, which subtitles split the same way
+// use too
+pub static PART_SUFFIX_REGEX: Lazy<Regex> = Lazy::new(|| {
+    RegexBuilder::new(
+        r"[ ._-]*(cd|dvd|part|pt|disc|disk)[ ._-]*(?P<part>\d{1,2})$",
+    )
+    .case_insensitive(true)
+    .build()
+    .unwrap()
+});
+
+// Markers like `English (SDH)`, `English.forced`, `English [CC]` or
+// `English - Audio Description`
+static FLAG_REGEX: Lazy<Regex> = Lazy::new(|| {
+    RegexBuilder::new(
+        r"[ ._-]*[(\[]?\b(?P<flag>forced|foreign|sdh|cc|hi|hearing[ ._-]*impaired|ad|audio[ ._-]*description|described)\b[)\]]?",
+    )
+    .case_insensitive(true)
+    .build()
+    .unwrap()
+});
+
+/// The title given to audio description tracks
+pub const AUDIO_DESCRIPTION: &str = "Audio Description";
+
+// A forced track this much smaller than another of the same language is
+// taken to be one, even if it isn't named as one
+const FORCED_SIZE_RATIO: u64 = 5;
+
+impl Subtitle {
+    /// Only the part of `path` below `root` (the folder of the videos) is
+    /// looked at, as folders above it are about the library, not the
+    /// subtitle
+    pub fn new(
+        path: Utf8PathBuf,
+        root: &Utf8Path,
+        source: Source,
+        strict: bool,
+    ) -> Result<Self, Skip> {
+        Self::parse(path, root, None, source, strict)
+    }
+
+    /// Like [`Subtitle::new`], but with the language already known rather
+    /// than guessed from the file name
+    pub fn with_language(
+        path: Utf8PathBuf,
+        root: &Utf8Path,
+        lang: Language,
+        source: Source,
+        strict: bool,
+    ) -> Result<Self, Skip> {
+        Self::parse(path, root, Some(lang), source, strict)
+    }
+
+    fn parse(
+        path: Utf8PathBuf,
+        root: &Utf8Path,
+        lang: Option<Language>,
+        source: Source,
+        strict: bool,
+    ) -> Result<Self, Skip> {
+        let relative = path.strip_prefix(root).unwrap_or(&path);
+        let file_name =
+            path.file_stem().expect("subtitle should have file name");
+        trace!("regexing {file_name:?}");
+        let (file_name, part) = split_part_suffix(file_name);
+        if let Some(part) = part {
+            info!("{path} looks like part {part} of a split subtitle");
+        }
+        let (language, flags) = split_flags(language_hint(file_name));
+        let language = language.as_str();
+        let signs = signs_and_songs(language);
+        if let Some(title) = signs {
+            info!("{path} looks like {title}, so it's a forced English track");
+        }
+        if flags.audio_description {
+            info!("{path} looks like the text of an audio description");
+        }
+        let lang = match lang {
+            Some(lang) => lang,
+            None => {
+                info!("guessing language is {language:?}");
+                let Some((lang, found)) =
+                    find_language(relative, file_name, language)
+                else {
+                    let detail = format!("couldn't find language {language:?}");
+                    return Err(Skip::new(
+                        path,
+                        SkipReason::UnknownLanguage,
+                        detail,
+                    ));
+                };
+                match found {
+                    LanguageSource::SignsAndSongs | LanguageSource::Name => {},
+                    LanguageSource::ReleaseTag => {
+                        info!("{path} is tagged as {}", lang.to_name())
+                    },
+                    LanguageSource::Folder(folder) => info!(
+                        "{path} is in {folder:?}, so it's {}",
+                        lang.to_name()
+                    ),
+                    LanguageSource::Parts => {
+                        info!("{path} has {} in its name", lang.to_name())
+                    },
+                }
+                lang
+            },
+        };
+
+        let series_info = match series::find_in_path(relative, strict) {
+            Ok(series_info) => {
+                if let Some(series_info) = series_info {
+                    info!("found series info {series_info} in {path}");
+                }
+                series_info
+            },
+            Err(why) => {
+                return Err(Skip::new(
+                    path,
+                    SkipReason::BadSeriesInfo,
+                    format!("{why:#}"),
+                ))
+            },
+        };
+
+        let edition = edition::detect(relative.as_str());
+
+        Ok(Self {
+            path,
+            lang,
+            series_info,
+            part,
+            edition,
+            source,
+            label: None,
+            flags: Flags {
+                forced: flags.forced || signs.is_some(),
+                ..flags
+            },
+            // Jellyfin has no flag for it, so it's told apart by its title
+            title: signs
+                .or(flags.audio_description.then_some(AUDIO_DESCRIPTION))
+                .map(str::to_owned),
+            shared: false,
+        })
+    }
+}
+
+/// Where a subtitle's language was read from, in the order they're tried
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum LanguageSource<'a> {
+    /// A signs & songs track, which is English
+    SignsAndSongs,
+    /// The name of a language, as in `English`
+    Name,
+    /// A scene tag like `VOSTFR`
+    ReleaseTag,
+    /// The nearest folder named after a language, as in `Subs/eng/2.srt`
+    Folder(&'a str),
+    /// A code tagged on the end, as in `Movie.2019.eng`
+    Parts,
+}
+
+/// The language of a subtitle at `relative` (below the videos' folder),
+/// going by `file_name`, its stem without a part suffix, and `language`, the
+/// part of that expected to name the language without any flags
+pub fn find_language<'a>(
+    relative: &'a Utf8Path,
+    file_name: &str,
+    language: &str,
+) -> Option<(Language, LanguageSource<'a>)> {
+    if signs_and_songs(language).is_some() {
+        return Some((Language::Eng, LanguageSource::SignsAndSongs));
+    }
+    let found = |source| move |lang| (lang, source);
+    Language::from_name(language)
+        .map(found(LanguageSource::Name))
+        .or_else(|| {
+            predicates::language_of_release(file_name)
+                .map(found(LanguageSource::ReleaseTag))
+        })
+        .or_else(|| {
+            let (folder, lang) = language_from_folders(relative)?;
+            Some((lang, LanguageSource::Folder(folder)))
+        })
+        .or_else(|| {
+            language_from_parts(language).map(found(LanguageSource::Parts))
+        })
+}
+
+/// The title of a signs & songs track, if `language` names one
+pub fn signs_and_songs(language: &str) -> Option<&'static str> {
+    SIGNS_AND_SONGS_REGEX.captures(language).map(|captures| {
+        match captures["title"].eq_ignore_ascii_case("signs") {
+            true => "Signs",
+            false => "Signs & Songs",
+        }
+    })
+}
+
+/// Separates markers like `forced` or `SDH` from the name of a language, as
+/// in `English (Forced)`. `hi` only counts as hearing impaired after
+/// something else, as on its own it's Hindi, and `ad` likewise, as the whole
+/// name it's more likely to be something else
+pub fn split_flags(hint: &str) -> (String, Flags) {
+    let mut flags = Flags::default();
+    let language = FLAG_REGEX.replace_all(hint, |captures: &Captures| {
+        let flag = captures["flag"].to_lowercase();
+        match flag.as_str() {
+            "hi" | "ad" if captures.get(0).unwrap().start() == 0 => {
+                return captures[0].to_owned();
+            },
+            "forced" | "foreign" => flags.forced = true,
+            "ad" | "described" => flags.audio_description = true,
+            flag if flag.starts_with("audio") => flags.audio_description = true,
+            _ => flags.hearing_impaired = true,
+        }
+        String::new()
+    });
+    (language.into_owned(), flags)
+}
+
+/// Separates a `.cd1` style suffix from a file stem
+pub fn split_part_suffix(file_stem: &str) -> (&str, Option<u8>) {
+    match PART_SUFFIX_REGEX.captures(file_stem) {
+        Some(captures) => (
+            &file_stem[..captures.get(0).unwrap().start()],
+            captures["part"].parse().ok(),
+        ),
+        None => (file_stem, None),
+    }
+}
+
+/// The part of a file stem expected to name the language, i.e. `French` from
+/// `3_French`
+pub fn language_hint(file_stem: &str) -> &str {
+    NUMBER_PREFIX_REGEX.splitn(file_stem, 2).last().unwrap()
+}
+
+// Folders further from the file than this are likely to be about the library
+// rather than the subtitle
+const LANGUAGE_FOLDER_DEPTH: usize = 3;
+
+/// The nearest folder above a subtitle that's named after a language, as in
+/// `Subs/eng/2.srt` or `Subs/By Language/French/sub.srt`
+fn language_from_folders(path: &Utf8Path) -> Option<(&str, Language)> {
+    path.parent()?
+        .components()
+        .rev()
+        .take_while(|component| matches!(component, Utf8Component::Normal(_)))
+        .take(LANGUAGE_FOLDER_DEPTH)
+        .find_map(|folder| {
+            let folder = folder.as_str();
+            Some((folder, language_from_tag(folder)?))
+        })
+}
+
+/// The language tagged on the end of a name made of several parts, as in
+/// `Movie.2019.eng` or `subs_pt-BR`, trying the last part first. Only the
+/// languages with a two letter code count, as the rest of the three letter
+/// codes are too easily words (`the`, `web`), and a region (`BR`) gives way
+/// to the language it's part of
+fn language_from_parts(name: &str) -> Option<Language> {
+    name.rsplit(['.', '_'])
+        .flat_map(|part| {
+            // The whole of `pt-BR`, then `pt`
+            std::iter::once(part).chain(part.split('-'))
+        })
+        .filter(|part| !part.is_empty() && part.len() < name.len())
+        .filter_map(language_from_tag)
+        .find(|lang| lang.to_639_1().is_some())
+}
+
+/// Reads a language from an ISO 639 code or English name, as found between
+/// the dots of existing Jellyfin-style subtitle names
+pub fn language_from_tag(tag: &str) -> Option<Language> {
+    let lowercase = tag.to_ascii_lowercase();
+    match lowercase.len() {
+        2 => Language::from_639_1(&lowercase),
+        3 => Language::from_639_3(&lowercase).or_else(|| {
+            let (terminological, _) = BIBLIOGRAPHIC
+                .iter()
+                .find(|(_, bibliographic)| *bibliographic == lowercase)?;
+            Language::from_639_3(terminological)
+        }),
+        _ => Language::from_name(tag),
+    }
+}
+
+/// Marks subtitles as forced when they're far smaller than another of the
+/// same language for the same video, as forced tracks only have the odd line
+pub fn flag_small_as_forced(subs: &mut [Subtitle]) {
+    let sizes = subs
+        .iter()
+        .map(|sub| fs::metadata(&sub.path).map(|meta| meta.len()).ok())
+        .collect::<Vec<_>>();
+    for i in 0..subs.len() {
+        let (sub, Some(size)) = (&subs[i], sizes[i]) else {
+            continue;
+        };
+        if sub.flags.forced || sub.shared {
+            continue;
+        }
+        let dwarfed = subs.iter().zip(&sizes).any(|(other, other_size)| {
+            other.lang == sub.lang
+                && other.series_info == sub.series_info
+                && !other.flags.forced
+                && other_size
+                    .map(|other_size| size * FORCED_SIZE_RATIO < other_size)
+                    .unwrap_or_default()
+        });
+        if dwarfed {
+            info!(
+                "{} is much smaller than another {} subtitle, so it's taken \
+                 to be forced",
+                sub.path,
+                sub.lang.to_name()
+            );
+            subs[i].flags.forced = true;
+        }
+    }
+}
+
+pub fn remove_duplicate_languages(
+    subs: &mut Vec<Subtitle>,
+    source_order: &[Source],
+    report: &mut Report,
+) {
+    // Stable, so walk order still breaks ties within a source
+    subs.sort_by_key(|sub| {
+        source_order
+            .iter()
+            .position(|&source| source == sub.source)
+            .unwrap_or(source_order.len())
+    });
+    let mut seen = Vec::new();
+    subs.retain(|sub| {
+        // Each one was asked for
+        if sub.shared {
+            return true;
+        }
+        // A forced or SDH track doesn't stand in for the full one, nor one
+        // part of a stacked movie for another
+        if seen.contains(&(sub.lang, sub.flags, sub.series_info, sub.part)) {
+            report.warn_skip(
+                format!(
+                    "skipping duplicate {} subtitle {}",
+                    sub.lang.to_name(),
+                    &sub.path
+                ),
+                Skip::new(
+                    &sub.path,
+                    SkipReason::DuplicateLanguage,
+                    format!("already have a {} subtitle", sub.lang.to_name()),
+                ),
+            );
+            false
+        } else {
+            seen.push((sub.lang, sub.flags, sub.series_info, sub.part));
+            true
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn language(name: &str) -> Option<Language> {
+        let root = Utf8Path::new("/library/Movie (2019)");
+        Subtitle::new(root.join(name), root, Source::Adjacent, false)
+            .ok()
+            .map(|subtitle| subtitle.lang)
+    }
+
+    #[test]
+    fn language_tagged_on_the_end() {
+        assert_eq!(language("Movie.2019.eng.srt"), Some(Language::Eng));
+        assert_eq!(language("subs.de.srt"), Some(Language::Deu));
+        assert_eq!(language("x.fre.srt"), Some(Language::Fra));
+        assert_eq!(language("x.pt-BR.srt"), Some(Language::Por));
+        assert_eq!(
+            language("Movie (2019).en.default.srt"),
+            Some(Language::Eng)
+        );
+        assert_eq!(language("some_subs_es.srt"), Some(Language::Spa));
+    }
+
+    #[test]
+    fn language_names() {
+        assert_eq!(language("English.srt"), Some(Language::Eng));
+        assert_eq!(language("3_French.srt"), Some(Language::Fra));
+        assert_eq!(language("German (Forced).srt"), Some(Language::Deu));
+    }
+
+    #[test]
+    fn parts_that_only_look_like_codes() {
+        assert_eq!(language("Movie.2019.1080p.WEB.srt"), None);
+        assert_eq!(language("The.srt"), None);
+    }
+}
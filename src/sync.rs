@@ -0,0 +1,193 @@
+//! Lining subtitles up with their video using another program, like alass
+//! or ffsubsync, given as a command line to fill in. What it writes is a
+//! hidden copy next to the subtitle, which is linked instead, the same way
+//! as fixed timings
+
+use std::{fmt, str::FromStr};
+
+use anyhow::bail;
+use camino::{Utf8Path, Utf8PathBuf};
+// Without the sync feature, only enough is built to recognise the synced
+// copies made before
+#[cfg(feature = "sync")]
+use {
+    crate::{
+        cli::Options,
+        fsops::{is_dry_run, run_creating},
+        matcher::Chain,
+        report::{dry_run_prefix, Report},
+        stack::same_part,
+        subtitle::Subtitle,
+        video::Video,
+    },
+    anyhow::Context,
+    log::{debug, info, warn},
+    std::{fs, process::Command, time::Instant},
+};
+
+/// Where the provider's read from when `--sync-provider` isn't given
+pub const PROVIDER_VAR: &str = "SUBFIX_SYNC_PROVIDER";
+
+const SYNCED_SUFFIX: &str = ".synced";
+
+#[cfg(feature = "sync")]
+const VIDEO: &str = "{video}";
+const SUBTITLE: &str = "{subtitle}";
+const OUTPUT: &str = "{output}";
+
+/// A program's command line, split on whitespace, with `{video}`,
+/// `{subtitle}` and `{output}` standing in for the paths of each pair
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SyncProvider {
+    args: Vec<String>,
+}
+
+impl FromStr for SyncProvider {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let args = s.split_whitespace().map(str::to_owned).collect::<Vec<_>>();
+        if args.is_empty() {
+            bail!("the sync provider's command line is empty");
+        }
+        for placeholder in [SUBTITLE, OUTPUT] {
+            if !args.iter().any(|arg| arg.contains(placeholder)) {
+                bail!(
+                    "the sync provider's command line {s:?} needs \
+                     {placeholder} in it"
+                );
+            }
+        }
+        Ok(SyncProvider { args })
+    }
+}
+
+impl fmt::Display for SyncProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.args.join(" "))
+    }
+}
+
+#[cfg(feature = "sync")]
+impl SyncProvider {
+    fn command(
+        &self,
+        video: &Utf8Path,
+        subtitle: &Utf8Path,
+        output: &Utf8Path,
+    ) -> Command {
+        // Filled in after splitting, so paths with spaces stay whole
+        let mut args = self.args.iter().map(|arg| {
+            arg.replace(VIDEO, video.as_str())
+                .replace(SUBTITLE, subtitle.as_str())
+                .replace(OUTPUT, output.as_str())
+        });
+        let mut command = Command::new(args.next().unwrap_or_default());
+        command.args(args);
+        command
+    }
+}
+
+/// Swaps each subtitle for a copy synced to the video it's linked to. Ones
+/// for every video, or no video, or that the provider fails on, are linked
+/// as they are
+#[cfg(feature = "sync")]
+pub fn sync_subtitles(
+    videos: &[Video],
+    subs: &mut [Subtitle],
+    provider: &SyncProvider,
+    options: &Options,
+    report: &mut Report,
+) {
+    let chain = Chain::new(&options.matchers, videos).needing_evidence(
+        !options.content_type.one_per_folder() && videos.len() > 1,
+    );
+    for sub in subs.iter_mut().filter(|sub| !sub.shared) {
+        // Different versions of the same thing are close enough that the
+        // first will do for the others
+        let Some(video) = videos
+            .iter()
+            .find(|video| chain.matches(video, sub) && same_part(video, sub))
+        else {
+            debug!("not syncing {}, no video matches it", sub.path);
+            continue;
+        };
+        let start = Instant::now();
+        let synced = sync(&video.path, &sub.path, provider);
+        report.stats.execute += start.elapsed();
+        match synced {
+            Ok(path) => sub.path = path,
+            Err(why) => warn!("couldn't sync {}: {why:#}", sub.path),
+        }
+    }
+}
+
+/// Whether a subtitle is the synced copy of another, which is made again
+/// from the original rather than looked at itself
+pub fn is_synced_copy(path: &Utf8Path) -> bool {
+    originals_of(path)
+        .map(|originals| originals.iter().any(|original| original.is_file()))
+        .unwrap_or_default()
+}
+
+#[cfg(feature = "sync")]
+fn sync(
+    video: &Utf8Path,
+    subtitle: &Utf8Path,
+    provider: &SyncProvider,
+) -> anyhow::Result<Utf8PathBuf> {
+    let synced = synced_path(subtitle);
+    // Made by an earlier run, from the subtitle & video as they are now
+    if is_up_to_date(&synced, &[subtitle, video]) {
+        debug!("{synced} has already been synced");
+        return Ok(synced);
+    }
+    info!(
+        "{}syncing {subtitle} to {video} with {provider}, writing {synced}",
+        dry_run_prefix(),
+    );
+    let mut command = provider.command(video, subtitle, &synced);
+    run_creating(&mut command, &synced)
+        .with_context(|| format!("failed to run {provider}"))?;
+    if !is_dry_run() && !synced.is_file() {
+        bail!("{provider} didn't write {synced}");
+    }
+    Ok(synced)
+}
+
+#[cfg(feature = "sync")]
+fn is_up_to_date(synced: &Utf8Path, sources: &[&Utf8Path]) -> bool {
+    let modified =
+        |path: &Utf8Path| fs::metadata(path).and_then(|m| m.modified());
+    let Ok(synced) = modified(synced) else {
+        return false;
+    };
+    sources.iter().all(|source| {
+        modified(source)
+            .map(|source| synced >= source)
+            .unwrap_or_default()
+    })
+}
+
+// Next to the original, e.g. Subs/English.srt gives Subs/.English.synced.srt
+// (and a hidden fixed copy keeps just the one `.`)
+#[cfg(feature = "sync")]
+fn synced_path(original: &Utf8Path) -> Utf8PathBuf {
+    let stem = original.file_stem().unwrap_or_default();
+    let stem = stem.strip_prefix('.').unwrap_or(stem);
+    let extension = original.extension().unwrap_or_default();
+    original.with_file_name(format!(".{stem}{SYNCED_SUFFIX}.{extension}"))
+}
+
+// Either of these, as the original could have been hidden itself
+fn originals_of(synced: &Utf8Path) -> Option<[Utf8PathBuf; 2]> {
+    let extension = synced.extension()?;
+    let stem = synced
+        .file_stem()?
+        .strip_prefix('.')?
+        .strip_suffix(SYNCED_SUFFIX)?;
+    Some([
+        synced.with_file_name(format!("{stem}.{extension}")),
+        synced.with_file_name(format!(".{stem}.{extension}")),
+    ])
+}
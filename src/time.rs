@@ -0,0 +1,164 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{bail, Context};
+
+use crate::json::Value;
+
+/// Formats a time as RFC 3339 in UTC, to the millisecond, e.g.
+/// `2024-05-01T12:34:56.789Z`
+pub fn rfc3339(time: SystemTime) -> String {
+    let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since.as_secs();
+    let (year, month, day) = civil_from_days((seconds / 86_400) as i64);
+    let seconds_of_day = seconds % 86_400;
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        seconds_of_day / 3600,
+        seconds_of_day / 60 % 60,
+        seconds_of_day % 60,
+        since.subsec_millis(),
+    )
+}
+
+/// When something was done, for putting records in order. The time is UTC,
+/// so it means the same on every machine & whatever the time of year, and
+/// never goes backwards within a run even if the clock does. The sequence
+/// number orders things done within the same millisecond
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Stamp {
+    pub at: String,
+    pub seq: u64,
+}
+
+// The last stamp's time (in milliseconds since the epoch) & sequence number
+static LAST_STAMP: Mutex<(u128, u64)> = Mutex::new((0, 0));
+
+impl Stamp {
+    pub fn now() -> Self {
+        let mut last = LAST_STAMP
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis()
+            .max(last.0);
+        *last = (now, last.1 + 1);
+        let time = UNIX_EPOCH + Duration::from_millis(now as u64);
+        Stamp {
+            at: rfc3339(time),
+            seq: last.1,
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        Value::object([
+            ("at", self.at.as_str().into()),
+            ("seq", self.seq.into()),
+        ])
+    }
+
+    /// Reads what [`Stamp::to_json`] wrote
+    pub fn from_json(value: &Value) -> Option<Self> {
+        let at = value.get("at")?.as_str()?.to_owned();
+        let seq = match value.get("seq")? {
+            Value::Number(seq) if *seq >= 0.0 => *seq as u64,
+            _ => return None,
+        };
+        Some(Stamp { at, seq })
+    }
+}
+
+// Howard Hinnant's algorithm for turning days since the Unix epoch into a
+// Gregorian date
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524
+        - day_of_era / 146_096)
+        / 365;
+    let day_of_year =
+        day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Parses a `YYYY-MM-DD` date as midnight UTC
+pub fn parse_date(s: &str) -> anyhow::Result<SystemTime> {
+    let parts = s.splitn(3, '-').collect::<Vec<_>>();
+    let [year, month, day] = parts[..] else {
+        bail!("date {s:?} isn't in the form YYYY-MM-DD");
+    };
+    // Four digits, which keeps the calendar maths well away from overflowing
+    let year = year
+        .parse::<i64>()
+        .ok()
+        .filter(|year| (0..=9999).contains(year))
+        .with_context(|| format!("invalid year in {s:?}"))?;
+    let month = month
+        .parse::<u32>()
+        .ok()
+        .filter(|month| (1..=12).contains(month))
+        .with_context(|| format!("invalid month in {s:?}"))?;
+    let day = day
+        .parse::<u32>()
+        .ok()
+        .filter(|day| (1..=31).contains(day))
+        .with_context(|| format!("invalid day in {s:?}"))?;
+    let days = days_from_civil(year, month, day);
+    if civil_from_days(days) != (year, month, day) {
+        bail!("{s:?} isn't a real date");
+    }
+    let seconds = days
+        .checked_mul(86_400)
+        .with_context(|| format!("{s:?} is out of range"))?;
+    let seconds = u64::try_from(seconds)
+        .with_context(|| format!("{s:?} is before 1970"))?;
+    UNIX_EPOCH
+        .checked_add(Duration::from_secs(seconds))
+        .with_context(|| format!("{s:?} is out of range"))
+}
+
+// The inverse of `civil_from_days`
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = year - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month_index = i64::from(if month > 2 { month - 3 } else { month + 9 });
+    let day_of_year = (153 * month_index + 2) / 5 + i64::from(day) - 1;
+    let day_of_era =
+        year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dates() {
+        let date = parse_date("2024-02-29").unwrap();
+        assert_eq!(rfc3339(date), "2024-02-29T00:00:00.000Z");
+        for bad in [
+            "2023-02-29",
+            "1969-12-31",
+            "2024-13-01",
+            "10000-01-01",
+            "9223372036854775807-01-01",
+            "-9223372036854775808-01-01",
+        ] {
+            assert!(parse_date(bad).is_err(), "{bad:?} was read");
+        }
+    }
+}
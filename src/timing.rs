@@ -0,0 +1,245 @@
+//! Fixing SRT subtitles whose timings Jellyfin's renderer trips over: cues
+//! out of order, ending before they start, or running into the next one.
+//! The fix is written to a hidden copy next to the original, which is
+//! linked instead, so the original's left as it was and Jellyfin doesn't
+//! find the copy as a subtitle of its own
+
+use std::{
+    fs,
+    io::{BufWriter, Write},
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use camino::{Utf8Path, Utf8PathBuf};
+use log::{debug, info, warn};
+
+use crate::{
+    cli::Options,
+    fsops::{check_space, copy_permissions, create_file, remove_file},
+    report::{dry_run_prefix, Report},
+    srt::{self, Cue},
+    subtitle::Subtitle,
+};
+
+/// How long a cue that ended before it started is shown for instead, unless
+/// the next one starts sooner
+const FALLBACK_DURATION: Duration = Duration::from_secs(2);
+
+const FIXED_SUFFIX: &str = ".fixed";
+
+/// What was wrong with a subtitle's timings
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+struct Defects {
+    out_of_order: usize,
+    negative: usize,
+    overlapping: usize,
+}
+
+impl Defects {
+    fn any(self) -> bool {
+        self != Defects::default()
+    }
+}
+
+/// Swaps each SRT subtitle with bad timings for a fixed copy. Ones that
+/// can't be read or fixed are linked as they are
+pub fn fix_timings(
+    subs: &mut [Subtitle],
+    options: &Options,
+    report: &mut Report,
+) {
+    for sub in subs.iter_mut() {
+        if sub.path.extension() != Some("srt") {
+            continue;
+        }
+        let start = Instant::now();
+        let fixed = fix(&sub.path, options);
+        report.stats.execute += start.elapsed();
+        match fixed {
+            Ok(Some(path)) => sub.path = path,
+            Ok(None) => {},
+            Err(why) => {
+                warn!("couldn't fix the timings of {}: {why:#}", sub.path)
+            },
+        }
+    }
+}
+
+/// Whether a subtitle is the fixed copy of another, which is made again
+/// from the original rather than looked at itself
+pub fn is_fixed_copy(path: &Utf8Path) -> bool {
+    original_of(path)
+        .map(|original| original.is_file())
+        .unwrap_or_default()
+}
+
+// The path of the fixed copy, if one was needed
+fn fix(
+    path: &Utf8Path,
+    options: &Options,
+) -> anyhow::Result<Option<Utf8PathBuf>> {
+    let fixed_path = fixed_path(path);
+    // Made by an earlier run, from the original as it is now
+    if is_up_to_date(&fixed_path, path) {
+        debug!("{fixed_path} has already been fixed");
+        return Ok(Some(fixed_path));
+    }
+    let mut cues = srt::open(path)?
+        .strict()
+        .collect::<anyhow::Result<Vec<Cue>>>()
+        .with_context(|| format!("in {path}"))?;
+    let defects = repair(&mut cues)?;
+    if !defects.any() {
+        return Ok(None);
+    }
+    info!(
+        "{}fixing {path}: {} cues out of order, {} ending as or before they \
+         start, {} overlapping the next, writing {fixed_path}",
+        dry_run_prefix(),
+        defects.out_of_order,
+        defects.negative,
+        defects.overlapping,
+    );
+    if let Some(dir) = fixed_path.parent() {
+        let size = fs::metadata(path)
+            .map(|metadata| metadata.len())
+            .unwrap_or_default();
+        check_space(dir, size, 1)?;
+    }
+    let written = (|| {
+        let mut out = BufWriter::new(create_file(&fixed_path)?);
+        for (index, cue) in cues.iter().enumerate() {
+            srt::write_cue(&mut out, index + 1, cue, options.line_ending)?;
+        }
+        out.flush()?;
+        anyhow::Ok(())
+    })();
+    if let Err(why) = written {
+        let _ = remove_file(&fixed_path);
+        return Err(why.context(format!("failed to write {fixed_path}")));
+    }
+    if options.preserve_permissions {
+        copy_permissions(path, &fixed_path)?;
+    }
+    Ok(Some(fixed_path))
+}
+
+// Cues are put in order, then each is made to end after it starts & before
+// the next one does. Ones starting together are shown as one
+fn repair(cues: &mut Vec<Cue>) -> anyhow::Result<Defects> {
+    let mut defects = Defects {
+        out_of_order: cues
+            .windows(2)
+            .filter(|pair| pair[1].start < pair[0].start)
+            .count(),
+        ..Defects::default()
+    };
+    // Stable, so cues starting together keep their order
+    cues.sort_by_key(|cue| cue.start);
+    let mut merged: Vec<Cue> = Vec::with_capacity(cues.len());
+    for cue in cues.drain(..) {
+        match merged.last_mut() {
+            Some(last) if last.start == cue.start => {
+                defects.overlapping += 1;
+                last.end = last.end.max(cue.end);
+                last.text.push('\n');
+                last.text.push_str(&cue.text);
+            },
+            _ => merged.push(cue),
+        }
+    }
+    let starts = merged
+        .iter()
+        .skip(1)
+        .map(|cue| Some(cue.start))
+        .chain([None])
+        .collect::<Vec<_>>();
+    for (cue, next_start) in merged.iter_mut().zip(starts) {
+        if cue.end <= cue.start {
+            defects.negative += 1;
+            cue.end = cue.start.checked_add(FALLBACK_DURATION).with_context(
+                || {
+                    format!(
+                        "the cue at {} starts too late to be given an end",
+                        srt::format_timestamp(cue.start)
+                    )
+                },
+            )?;
+        }
+        if let Some(next_start) = next_start {
+            if cue.end > next_start {
+                defects.overlapping += 1;
+                cue.end = next_start;
+            }
+        }
+    }
+    *cues = merged;
+    Ok(defects)
+}
+
+fn is_up_to_date(fixed: &Utf8Path, original: &Utf8Path) -> bool {
+    let modified =
+        |path: &Utf8Path| fs::metadata(path).and_then(|m| m.modified());
+    match (modified(fixed), modified(original)) {
+        (Ok(fixed), Ok(original)) => fixed >= original,
+        _ => false,
+    }
+}
+
+// Next to the original, e.g. Subs/English.srt gives Subs/.English.fixed.srt
+fn fixed_path(original: &Utf8Path) -> Utf8PathBuf {
+    let stem = original.file_stem().unwrap_or_default();
+    original.with_file_name(format!(".{stem}{FIXED_SUFFIX}.srt"))
+}
+
+fn original_of(fixed: &Utf8Path) -> Option<Utf8PathBuf> {
+    if fixed.extension() != Some("srt") {
+        return None;
+    }
+    let stem = fixed
+        .file_stem()?
+        .strip_prefix('.')?
+        .strip_suffix(FIXED_SUFFIX)?;
+    Some(fixed.with_file_name(format!("{stem}.srt")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cue(start: Duration, end: Duration) -> Cue {
+        Cue {
+            start,
+            end,
+            text: "Hello".to_owned(),
+        }
+    }
+
+    #[test]
+    fn repairs() {
+        let second = Duration::from_secs(1);
+        let mut cues = vec![
+            cue(5 * second, 4 * second),
+            cue(second, 3 * second),
+            cue(2 * second, 8 * second),
+        ];
+        let defects = repair(&mut cues).unwrap();
+        assert_eq!(defects, Defects {
+            out_of_order: 1,
+            negative: 1,
+            overlapping: 2,
+        });
+        assert_eq!(cues, [
+            cue(second, 2 * second),
+            cue(2 * second, 5 * second),
+            cue(5 * second, 7 * second),
+        ]);
+    }
+
+    #[test]
+    fn too_late_to_repair() {
+        let mut cues = vec![cue(Duration::MAX, Duration::ZERO)];
+        assert!(repair(&mut cues).is_err());
+    }
+}
@@ -0,0 +1,502 @@
+//! Planning from a `.torrent` file's list of files, before anything's been
+//! downloaded, to see whether a release comes with subtitles worth having.
+//! Nothing's read but the torrent, so what can only be told from the
+//! subtitles' contents (or the videos') is left out
+
+use std::{collections::BTreeMap, fs};
+
+use anyhow::{bail, Context};
+use camino::{Utf8Path, Utf8PathBuf};
+use isolang::Language;
+use log::{info, warn};
+
+use crate::{
+    cli::Options,
+    discovery::source_of,
+    gzip,
+    json::Value,
+    link::{link_name, safe_title, same_edition},
+    matcher::Chain,
+    predicates, remove_duplicates_per_video,
+    report::{Report, Skip, SkipReason},
+    stack::{self, same_part},
+    subtitle::{remove_duplicate_languages, Subtitle},
+    video::Video,
+};
+
+/// What a torrent would give
+#[derive(Debug)]
+pub struct Plan {
+    /// The torrent's own name, which is the folder it downloads to if it
+    /// has more than one file
+    pub name: String,
+    pub videos: Vec<VideoPlan>,
+    /// Subtitles that wouldn't be linked to anything
+    pub skipped: Vec<Skip>,
+}
+
+#[derive(Debug)]
+pub struct VideoPlan {
+    /// Within the torrent
+    pub video: Utf8PathBuf,
+    /// The links that would be made, and the subtitles they'd be for
+    pub links: Vec<(Utf8PathBuf, Utf8PathBuf, Language)>,
+}
+
+impl Plan {
+    pub fn to_json(&self) -> Value {
+        Value::object([
+            ("torrent", self.name.as_str().into()),
+            (
+                "videos",
+                Value::Array(
+                    self.videos
+                        .iter()
+                        .map(|video| {
+                            Value::object([
+                                ("path", video.video.as_str().into()),
+                                (
+                                    "links",
+                                    Value::Array(
+                                        video
+                                            .links
+                                            .iter()
+                                            .map(link_json)
+                                            .collect(),
+                                    ),
+                                ),
+                            ])
+                        })
+                        .collect(),
+                ),
+            ),
+            (
+                "skipped",
+                Value::Array(self.skipped.iter().map(Skip::to_json).collect()),
+            ),
+        ])
+    }
+
+    /// Like the coverage table: how many of the videos would have a
+    /// subtitle in `lang`
+    pub fn videos_with(&self, lang: Language) -> usize {
+        self.videos
+            .iter()
+            .filter(|video| video.links.iter().any(|&(_, _, l)| l == lang))
+            .count()
+    }
+}
+
+fn link_json(
+    (link, subtitle, lang): &(Utf8PathBuf, Utf8PathBuf, Language),
+) -> Value {
+    Value::object([
+        ("link", link.as_str().into()),
+        ("subtitle", subtitle.as_str().into()),
+        (
+            "language",
+            lang.to_639_1().unwrap_or(lang.to_639_3()).into(),
+        ),
+    ])
+}
+
+/// Works out the links `torrent` would get once it's downloaded, going by
+/// the names of its files. Magnet links don't list the files, so they can't
+/// be planned from
+pub fn plan(torrent: &Utf8Path, options: &Options) -> anyhow::Result<Plan> {
+    if torrent.as_str().starts_with("magnet:") {
+        bail!(
+            "magnet links don't list the files in them, give the .torrent \
+             instead (most clients can save one once they've fetched the \
+             metadata)"
+        );
+    }
+    let bytes = fs::read(torrent)
+        .with_context(|| format!("failed to read {torrent}"))?;
+    let (name, files) =
+        file_list(&bytes).with_context(|| format!("in {torrent}"))?;
+    info!("{torrent} has {} files", files.len());
+    // Each folder of videos is planned like a folder being processed
+    let mut folders = BTreeMap::<Utf8PathBuf, Vec<Video>>::new();
+    for path in files
+        .iter()
+        .filter(|path| predicates::has_video_extension(path))
+    {
+        match Video::from_path(path.clone(), options.strict) {
+            Ok(mut video) => {
+                if !options.content_type.has_episodes() {
+                    video.series_info = None;
+                }
+                folders
+                    .entry(
+                        path.parent().unwrap_or(Utf8Path::new("")).to_owned(),
+                    )
+                    .or_default()
+                    .push(video)
+            },
+            Err(skip) => warn!("skipped path {}: {}", skip.path, skip.detail),
+        }
+    }
+    let mut report = Report::default();
+    let mut planned = Vec::new();
+    for (dir, mut videos) in folders {
+        let mut subs = subtitles(&dir, &files, &videos, options, &mut report);
+        if !options.content_type.has_episodes() {
+            subs.iter_mut().for_each(|sub| sub.series_info = None);
+        }
+        match options.content_type.stacks() && stack::is_stacked(&videos) {
+            true => {
+                stack::sort_into_parts(&videos, &mut subs, options, &mut report)
+            },
+            false => videos.iter_mut().for_each(|video| video.part = None),
+        }
+        match options.content_type.one_per_folder() {
+            true => remove_duplicate_languages(
+                &mut subs,
+                &options.source_order,
+                &mut report,
+            ),
+            false => remove_duplicates_per_video(
+                &videos,
+                &mut subs,
+                options,
+                &mut report,
+            ),
+        }
+        let chain = Chain::new(&options.matchers, &videos).needing_evidence(
+            !options.content_type.one_per_folder() && videos.len() > 1,
+        );
+        let same_edition = same_edition(&videos, options);
+        let is_for = |video: &Video, subtitle: &Subtitle| {
+            (subtitle.shared || chain.matches(video, subtitle))
+                && same_part(video, subtitle)
+                && same_edition(video, subtitle)
+        };
+        for subtitle in &subs {
+            if !videos.iter().any(|video| is_for(video, subtitle)) {
+                report.skip(Skip::new(
+                    &subtitle.path,
+                    SkipReason::NoMatchingVideo,
+                    "no video matches",
+                ));
+            }
+        }
+        for video in &videos {
+            let links = subs
+                .iter()
+                .filter(|subtitle| is_for(video, subtitle))
+                .filter_map(|subtitle| {
+                    let title = safe_title(subtitle, options, &mut report);
+                    let link = link_name(
+                        &dir,
+                        video,
+                        subtitle,
+                        title.as_deref(),
+                        true,
+                        options,
+                    )?;
+                    Some((link, subtitle.path.clone(), subtitle.lang))
+                })
+                .collect();
+            planned.push(VideoPlan {
+                video: video.path.clone(),
+                links,
+            });
+        }
+    }
+    Ok(Plan {
+        name,
+        videos: planned,
+        skipped: report.skipped,
+    })
+}
+
+/// Prints the plan for a torrent, as JSON with `--json`
+pub fn show(torrent: &Utf8Path, options: &Options) -> anyhow::Result<()> {
+    let plan = plan(torrent, options)?;
+    if options.json {
+        println!("{}", plan.to_json());
+        return Ok(());
+    }
+    println!("{}", plan.name);
+    for video in &plan.videos {
+        println!("\n{}", video.video);
+        if video.links.is_empty() {
+            println!("   no subtitles");
+        }
+        for (link, subtitle, _) in &video.links {
+            println!(
+                "   {} <- {subtitle}",
+                link.file_name().unwrap_or_default()
+            );
+        }
+    }
+    if !plan.skipped.is_empty() {
+        println!("\nnot linked:");
+        for skip in &plan.skipped {
+            println!("   {} ({})", skip.path, skip.detail);
+        }
+    }
+    if !options.coverage.is_empty() {
+        println!();
+        for &lang in &options.coverage {
+            println!(
+                "{}: {} of {} videos",
+                lang.to_name(),
+                plan.videos_with(lang),
+                plan.videos.len()
+            );
+        }
+    }
+    Ok(())
+}
+
+// The subtitles in the torrent under `dir` that would be found when
+// processing it, which is everything in its subtitle folders & the folders
+// named after its videos, or everything at all with `--all-folders`
+fn subtitles(
+    dir: &Utf8Path,
+    files: &[Utf8PathBuf],
+    videos: &[Video],
+    options: &Options,
+    report: &mut Report,
+) -> Vec<Subtitle> {
+    files
+        .iter()
+        .filter_map(|path| {
+            let relative = path.strip_prefix(dir).ok()?;
+            let mut folders = relative.parent()?.components();
+            let searched = match folders.next() {
+                None => true,
+                Some(folder) => {
+                    options.all_folders
+                        || predicates::is_subtitle_folder(
+                            folder.as_str(),
+                            videos,
+                            &options.subtitle_folders,
+                        )
+                },
+            };
+            // Compressed ones are decompressed before they're looked at
+            let path =
+                gzip::inner_subtitle(path).unwrap_or_else(|| path.clone());
+            (searched && predicates::has_subtitle_extension(&path))
+                .then_some(path)
+        })
+        .filter_map(|path| {
+            let source = source_of(dir, &path, videos, options);
+            let subtitle = match options.assume_lang {
+                Some(lang) => Subtitle::with_language(
+                    path,
+                    dir,
+                    lang,
+                    source,
+                    options.strict,
+                ),
+                None => Subtitle::new(path, dir, source, options.strict),
+            };
+            match subtitle {
+                Ok(subtitle) => Some(subtitle),
+                Err(skip) => {
+                    report.warn_skip(
+                        format!("skipping {}: {}", skip.path, skip.detail),
+                        skip,
+                    );
+                    None
+                },
+            }
+        })
+        .collect()
+}
+
+/// The torrent's name & the paths of its files, under the name if there's
+/// more than one. Both v1 (`files`) and v2 (`file tree`) torrents are read
+fn file_list(bytes: &[u8]) -> anyhow::Result<(String, Vec<Utf8PathBuf>)> {
+    let mut at = 0;
+    let torrent = Bencode::parse(bytes, &mut at, 0)?;
+    let info = torrent.get(b"info").context("no info dictionary")?;
+    let name = info
+        .get(b"name.utf-8")
+        .or_else(|| info.get(b"name"))
+        .and_then(Bencode::as_str)
+        .context("no name")?
+        .to_owned();
+    let root = Utf8PathBuf::from(safe_component(&name).context("bad name")?);
+    let mut files = Vec::new();
+    if let Some(Bencode::List(entries)) = info.get(b"files") {
+        for entry in entries {
+            // Padding, to line the next file up with a piece
+            let attributes = entry.get(b"attr").and_then(Bencode::as_str);
+            if attributes
+                .map(|attr| attr.contains('p'))
+                .unwrap_or_default()
+            {
+                continue;
+            }
+            let Some(Bencode::List(components)) =
+                entry.get(b"path.utf-8").or_else(|| entry.get(b"path"))
+            else {
+                bail!("a file has no path");
+            };
+            let mut path = root.clone();
+            for component in components {
+                match component.as_str().and_then(safe_component) {
+                    Some(component) => path.push(component),
+                    None => bail!("a file has a bad path"),
+                }
+            }
+            files.push(path);
+        }
+    } else if let Some(tree) = info.get(b"file tree") {
+        walk_tree(tree, root, &mut files)?;
+    } else {
+        // A single file, named after the torrent
+        files.push(root);
+    }
+    files.retain(|path| {
+        !path
+            .file_name()
+            .map(|name| name.starts_with(".pad"))
+            .unwrap_or_default()
+    });
+    Ok((name, files))
+}
+
+// Each folder is a dictionary of its contents, and each file a dictionary
+// with an empty key
+fn walk_tree(
+    tree: &Bencode,
+    path: Utf8PathBuf,
+    files: &mut Vec<Utf8PathBuf>,
+) -> anyhow::Result<()> {
+    let Bencode::Dict(entries) = tree else {
+        bail!("bad file tree");
+    };
+    for (key, value) in entries {
+        if key.is_empty() {
+            files.push(path.clone());
+            continue;
+        }
+        let component = std::str::from_utf8(key)
+            .ok()
+            .and_then(safe_component)
+            .context("a file has a bad path")?;
+        walk_tree(value, path.join(component), files)?;
+    }
+    Ok(())
+}
+
+// Anything that could step out of the torrent's folder is refused, though
+// no files are made from these
+fn safe_component(component: &str) -> Option<&str> {
+    (!component.is_empty()
+        && component != "."
+        && component != ".."
+        && !component.contains(['/', '\\']))
+    .then_some(component)
+}
+
+/// Just enough bencode to read a torrent's file list
+#[derive(Debug)]
+enum Bencode {
+    /// Only checked, as nothing needed is a number
+    Integer,
+    Bytes(Vec<u8>),
+    List(Vec<Bencode>),
+    /// Sorted by key in a well-formed torrent, but not relied on
+    Dict(Vec<(Vec<u8>, Bencode)>),
+}
+
+// Deeper than any real torrent, but stops a crafted one overflowing the stack
+const MAX_DEPTH: usize = 64;
+
+impl Bencode {
+    fn parse(
+        bytes: &[u8],
+        at: &mut usize,
+        depth: usize,
+    ) -> anyhow::Result<Self> {
+        if depth > MAX_DEPTH {
+            bail!("nested too deeply");
+        }
+        match bytes.get(*at) {
+            Some(b'i') => {
+                *at += 1;
+                Self::until(bytes, at, b'e')?
+                    .parse::<i64>()
+                    .context("invalid integer")?;
+                Ok(Bencode::Integer)
+            },
+            Some(b'l') => {
+                *at += 1;
+                let mut list = Vec::new();
+                while bytes.get(*at) != Some(&b'e') {
+                    list.push(Self::parse(bytes, at, depth + 1)?);
+                }
+                *at += 1;
+                Ok(Bencode::List(list))
+            },
+            Some(b'd') => {
+                *at += 1;
+                let mut dict = Vec::new();
+                while bytes.get(*at) != Some(&b'e') {
+                    let Bencode::Bytes(key) =
+                        Self::parse(bytes, at, depth + 1)?
+                    else {
+                        bail!("dictionary key isn't a string");
+                    };
+                    dict.push((key, Self::parse(bytes, at, depth + 1)?));
+                }
+                *at += 1;
+                Ok(Bencode::Dict(dict))
+            },
+            Some(b'0'..=b'9') => {
+                let length: usize = Self::until(bytes, at, b':')?
+                    .parse()
+                    .context("invalid string length")?;
+                let end = at
+                    .checked_add(length)
+                    .filter(|&end| end <= bytes.len())
+                    .context("string runs past the end")?;
+                let string = bytes[*at..end].to_vec();
+                *at = end;
+                Ok(Bencode::Bytes(string))
+            },
+            Some(byte) => bail!("unexpected {:?} at byte {at}", *byte as char),
+            None => bail!("ends early"),
+        }
+    }
+
+    // What's up to `end`, which is skipped over
+    fn until<'a>(
+        bytes: &'a [u8],
+        at: &mut usize,
+        end: u8,
+    ) -> anyhow::Result<&'a str> {
+        let length = bytes[*at..]
+            .iter()
+            .position(|&byte| byte == end)
+            .context("ends early")?;
+        let text = std::str::from_utf8(&bytes[*at..*at + length])
+            .context("invalid number")?;
+        *at += length + 1;
+        Ok(text)
+    }
+
+    fn get(&self, key: &[u8]) -> Option<&Bencode> {
+        match self {
+            Bencode::Dict(entries) => entries
+                .iter()
+                .find(|(k, _)| k.as_slice() == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Bencode::Bytes(bytes) => std::str::from_utf8(bytes).ok(),
+            _ => None,
+        }
+    }
+}
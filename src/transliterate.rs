@@ -0,0 +1,130 @@
+//! Writing Cyrillic & Greek titles in Latin letters, for shares that mangle
+//! other scripts in file names. There's no table for scripts like Chinese or
+//! Arabic, which would need a dictionary rather than one letter at a time
+
+const CYRILLIC: &[(char, &str)] = &[
+    ('а', "a"),
+    ('б', "b"),
+    ('в', "v"),
+    ('г', "g"),
+    ('д', "d"),
+    ('е', "e"),
+    ('ё', "yo"),
+    ('ж', "zh"),
+    ('з', "z"),
+    ('и', "i"),
+    ('й', "y"),
+    ('к', "k"),
+    ('л', "l"),
+    ('м', "m"),
+    ('н', "n"),
+    ('о', "o"),
+    ('п', "p"),
+    ('р', "r"),
+    ('с', "s"),
+    ('т', "t"),
+    ('у', "u"),
+    ('ф', "f"),
+    ('х', "kh"),
+    ('ц', "ts"),
+    ('ч', "ch"),
+    ('ш', "sh"),
+    ('щ', "shch"),
+    ('ъ', ""),
+    ('ы', "y"),
+    ('ь', ""),
+    ('э', "e"),
+    ('ю', "yu"),
+    ('я', "ya"),
+    // Ukrainian & Belarusian
+    ('є', "ye"),
+    ('і', "i"),
+    ('ї', "yi"),
+    ('ґ', "g"),
+    ('ў', "u"),
+    // Serbian & Macedonian
+    ('ђ', "dj"),
+    ('ј', "j"),
+    ('љ', "lj"),
+    ('њ', "nj"),
+    ('ћ', "c"),
+    ('џ', "dz"),
+    ('ѓ', "gj"),
+    ('ќ', "kj"),
+    ('ѕ', "dz"),
+];
+
+const GREEK: &[(char, &str)] = &[
+    ('α', "a"),
+    ('β', "v"),
+    ('γ', "g"),
+    ('δ', "d"),
+    ('ε', "e"),
+    ('ζ', "z"),
+    ('η', "i"),
+    ('θ', "th"),
+    ('ι', "i"),
+    ('κ', "k"),
+    ('λ', "l"),
+    ('μ', "m"),
+    ('ν', "n"),
+    ('ξ', "x"),
+    ('ο', "o"),
+    ('π', "p"),
+    ('ρ', "r"),
+    ('σ', "s"),
+    ('ς', "s"),
+    ('τ', "t"),
+    ('υ', "y"),
+    ('φ', "f"),
+    ('χ', "ch"),
+    ('ψ', "ps"),
+    ('ω', "o"),
+    ('ά', "a"),
+    ('έ', "e"),
+    ('ή', "i"),
+    ('ί', "i"),
+    ('ό', "o"),
+    ('ύ', "y"),
+    ('ώ', "o"),
+    ('ϊ', "i"),
+    ('ϋ', "y"),
+    ('ΐ', "i"),
+    ('ΰ', "y"),
+];
+
+/// `s` in Latin letters, or `None` if it has letters of a script there's no
+/// table for, so it's best left as it is. Capitals stay capitals, so
+/// `Режиссёрская версия` is `Rezhissyorskaya versiya`
+pub fn to_latin(s: &str) -> Option<String> {
+    let mut latin = String::with_capacity(s.len());
+    for c in s.chars() {
+        if c.is_ascii() || !c.is_alphabetic() {
+            latin.push(c);
+            continue;
+        }
+        let lower = c.to_lowercase().next().unwrap_or(c);
+        let letters = CYRILLIC
+            .iter()
+            .chain(GREEK)
+            .find(|(letter, _)| *letter == lower)
+            .map(|(_, letters)| *letters);
+        match letters {
+            Some(letters) if c.is_uppercase() => {
+                let mut letters = letters.chars();
+                latin.extend(letters.next().map(|c| c.to_ascii_uppercase()));
+                latin.extend(letters);
+            },
+            Some(letters) => latin.push_str(letters),
+            // Latin letters with accents are fine as they are
+            None if is_latin(c) => latin.push(c),
+            None => return None,
+        }
+    }
+    Some(latin)
+}
+
+// Latin-1 Supplement through Latin Extended-B
+fn is_latin(c: char) -> bool {
+    ('\u{c0}'..='\u{24f}').contains(&c)
+}
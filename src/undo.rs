@@ -0,0 +1,212 @@
+//! Taking away the links earlier runs made, going by the manifests they left:
+//! all of them with `--undo`, or before each run, the ones that are no use
+//! any more
+
+use std::{fs, io};
+
+use anyhow::{bail, Context};
+use camino::Utf8Path;
+use log::{debug, error, info, warn};
+
+use crate::{
+    cli::Options,
+    discovery::folders,
+    fsops::{create_dir_all, move_file, remove_file},
+    jellyfin,
+    lock::{Lock, DIRECTORY_LOCK},
+    manifest::{Entry, Manifest, Origin, Written, MANIFEST},
+    report::{dry_run_prefix, Report},
+};
+
+/// Removes every link subfix made anywhere under `root`, and moves the
+/// subtitles it moved back, returning how many went. Adopted links were the
+/// user's before they were subfix's, so they're left alone, as is anything
+/// changed since subfix made it
+pub fn undo_links(root: &Utf8Path, options: &Options) -> anyhow::Result<usize> {
+    if !options.force {
+        if let Some(why) = jellyfin::server_data_reason(root) {
+            bail!(
+                "not undoing links in {root}, it looks like Jellyfin's own \
+                 data: {why} (see --force)"
+            );
+        }
+    }
+    let mut removed = 0;
+    for dir in folders(root, options) {
+        if !dir.join(MANIFEST).exists() {
+            continue;
+        }
+        let _lock = Lock::acquire(dir.join(DIRECTORY_LOCK))?;
+        let mut manifest = Manifest::load(&dir)?;
+        // Put back first, as the copies made of them can only be told to be
+        // copies once the original's there again
+        let moved = manifest
+            .entries()
+            .iter()
+            .filter(|entry| entry.origin == Origin::Moved)
+            .cloned()
+            .collect::<Vec<_>>();
+        for entry in moved {
+            let subtitle = dir.join(&entry.name);
+            info!(
+                "{}moving {subtitle} back to {}",
+                dry_run_prefix(),
+                entry.target
+            );
+            match put_back(&subtitle, &entry) {
+                Ok(true) => removed += 1,
+                Ok(false) => {},
+                Err(why) => {
+                    error!("{why:#}");
+                    continue;
+                },
+            }
+            manifest.forget(&entry.name);
+        }
+        let created = manifest
+            .entries()
+            .iter()
+            .filter(|entry| entry.origin == Origin::Created)
+            .cloned()
+            .collect::<Vec<_>>();
+        for entry in created {
+            let link = dir.join(&entry.name);
+            info!("{}removing {link}", dry_run_prefix(),);
+            match remove_managed(&link, &entry) {
+                Ok(true) => removed += 1,
+                Ok(false) => {},
+                Err(why) => {
+                    error!("{why:#}");
+                    continue;
+                },
+            }
+            manifest.forget(&entry.name);
+        }
+        manifest.save()?;
+    }
+    Ok(removed)
+}
+
+/// Moves a subtitle `--mode rename` moved back to where it was, returning
+/// whether it was there to move. Anything that's taken its place since is
+/// left be, along with the subtitle
+fn put_back(subtitle: &Utf8Path, entry: &Entry) -> anyhow::Result<bool> {
+    if fs::symlink_metadata(subtitle).is_err() {
+        return Ok(false);
+    }
+    if fs::symlink_metadata(&entry.target).is_ok() {
+        warn!(
+            "not moving {subtitle} back, there's something at {} already",
+            entry.target
+        );
+        return Ok(false);
+    }
+    if let Some(dir) = entry.target.parent() {
+        create_dir_all(dir)
+            .with_context(|| format!("failed to create {dir}"))?;
+    }
+    move_file(subtitle, &entry.target).with_context(|| {
+        format!("failed to move {subtitle} back to {}", entry.target)
+    })?;
+    Ok(true)
+}
+
+/// Removes the links in `dir`'s manifest that are broken, or are for a video
+/// that's gone (renamed, or replaced by a new release), so they aren't left
+/// behind by the ones made for what's there now. Links that are already gone
+/// are forgotten
+pub(crate) fn remove_stale(
+    dir: &Utf8Path,
+    manifest: &mut Manifest,
+    report: &mut Report,
+) {
+    for entry in manifest.entries().to_vec() {
+        let link = dir.join(&entry.name);
+        // There's no link, only the video it's in, and a new one's a new set
+        // of tracks
+        if entry.origin == Origin::Embedded {
+            if let Some(video) = entry
+                .video
+                .as_ref()
+                .filter(|video| !dir.join(video).exists())
+            {
+                debug!("{video} isn't there any more, forgetting {link}");
+                manifest.forget(&entry.name);
+            }
+            continue;
+        }
+        if fs::symlink_metadata(&link).is_err() {
+            debug!("{link} is gone already, forgetting it");
+            manifest.forget(&entry.name);
+            continue;
+        }
+        // The user's, wherever it is, and only put back by `--undo`
+        if entry.origin == Origin::Moved {
+            continue;
+        }
+        let why = match &entry.video {
+            Some(video) if !dir.join(video).exists() => {
+                format!("{video} isn't there any more")
+            },
+            _ if !link.exists() => {
+                format!("it's broken ({} isn't there)", entry.target)
+            },
+            _ => continue,
+        };
+        info!("{}removing {link}, {why}", dry_run_prefix(),);
+        match remove_managed(&link, &entry) {
+            Ok(removed) => {
+                if removed {
+                    report.removed.push(link);
+                }
+                manifest.forget(&entry.name);
+            },
+            Err(why) => error!("{why:#}"),
+        }
+    }
+}
+
+/// Removes a link in a manifest, returning whether it was there to remove.
+/// A symlink that's been pointed somewhere else since is someone else's now
+pub(crate) fn remove_managed(
+    link: &Utf8Path,
+    entry: &Entry,
+) -> anyhow::Result<bool> {
+    let metadata = match fs::symlink_metadata(link) {
+        Ok(metadata) => metadata,
+        Err(why) if why.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(why) => {
+            return Err(why).with_context(|| format!("failed to read {link}"))
+        },
+    };
+    if metadata.file_type().is_symlink() {
+        let target = fs::read_link(link)
+            .with_context(|| format!("failed to read {link}"))?;
+        if target != entry.target {
+            warn!(
+                "not removing {link}, it's been changed to point at {} since \
+                 it was made",
+                target.display()
+            );
+            return Ok(false);
+        }
+    } else if metadata.is_dir() {
+        warn!("not removing {link}, it's a folder now");
+        return Ok(false);
+    } else if entry.written.is_some() && Written::of(&metadata) != entry.written
+    {
+        warn!("not removing {link}, it's been changed since it was copied");
+        return Ok(false);
+    } else if fs::symlink_metadata(&entry.target).is_err() {
+        // A copy (or hard link) of a subtitle that's gone is all that's left
+        // of it
+        warn!(
+            "not removing {link}, {} isn't there any more so it's the only \
+             copy",
+            entry.target
+        );
+        return Ok(false);
+    }
+    remove_file(link).with_context(|| format!("failed to remove {link}"))?;
+    Ok(true)
+}
@@ -0,0 +1,139 @@
+use std::{fmt, str::FromStr, time::Duration};
+
+use anyhow::bail;
+use camino::{Utf8Path, Utf8PathBuf};
+use log::info;
+
+use crate::{
+    edition,
+    report::{Skip, SkipReason},
+    series::{self, SeriesInfo},
+    subtitle::split_part_suffix,
+};
+
+/// The kind of Jellyfin library being processed, which decides what's read
+/// into the names of videos
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum ContentType {
+    /// Movies & shows, told apart by the names
+    #[default]
+    Mixed,
+    Movies,
+    Shows,
+    /// A folder of an artist's videos, all different
+    MusicVideos,
+    /// A folder of clips & photos, named anyhow
+    HomeVideos,
+}
+
+impl ContentType {
+    /// Whether a video can be an episode, if it's named like one
+    pub fn has_episodes(self) -> bool {
+        matches!(self, ContentType::Mixed | ContentType::Shows)
+    }
+
+    /// Whether `-part1` & `-part2` are stacked into one video
+    pub fn stacks(self) -> bool {
+        matches!(self, ContentType::Mixed | ContentType::Movies)
+    }
+
+    /// Whether the videos in a folder are versions of one thing, rather
+    /// than each its own, only matched with subtitles named for them
+    pub fn one_per_folder(self) -> bool {
+        !matches!(self, ContentType::MusicVideos | ContentType::HomeVideos)
+    }
+}
+
+impl FromStr for ContentType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "mixed" => Ok(ContentType::Mixed),
+            "movies" => Ok(ContentType::Movies),
+            "shows" | "tvshows" => Ok(ContentType::Shows),
+            "music-videos" | "musicvideos" => Ok(ContentType::MusicVideos),
+            "home-videos" | "homevideos" => Ok(ContentType::HomeVideos),
+            _ => bail!(
+                "unknown content type {s:?}, expected movies, shows, \
+                 music-videos, home-videos or mixed"
+            ),
+        }
+    }
+}
+
+impl fmt::Display for ContentType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ContentType::Mixed => "mixed",
+            ContentType::Movies => "movies",
+            ContentType::Shows => "shows",
+            ContentType::MusicVideos => "music-videos",
+            ContentType::HomeVideos => "home-videos",
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Video {
+    pub path: Utf8PathBuf,
+    pub series_info: Option<SeriesInfo>,
+    pub edition: Option<String>,
+    /// Which part this is of a movie stacked across several files, like
+    /// `Movie-part2.mkv`
+    pub part: Option<u8>,
+    /// How long it runs for, if it's been probed
+    pub runtime: Option<Duration>,
+}
+
+impl Video {
+    pub fn from_path(path: Utf8PathBuf, strict: bool) -> Result<Self, Skip> {
+        // Videos are at the top of the folder being processed, so the
+        // folders above are about the library rather than the video
+        let name = Utf8Path::new(path.file_name().unwrap_or_default());
+        let series_info = match series::find_in_path(name, strict) {
+            Ok(series_info) => {
+                if let Some(series_info) = series_info {
+                    info!("found series info {series_info} in {path}");
+                }
+                series_info
+            },
+            Err(why) => {
+                return Err(Skip::new(
+                    path,
+                    SkipReason::BadSeriesInfo,
+                    format!("{why:#}"),
+                ))
+            },
+        };
+        let edition = edition::detect(path.file_stem().unwrap_or_default());
+        if let Some(edition) = &edition {
+            info!("{path} is the {edition} edition");
+        }
+        // Episodes named `Part 2` are episodes, not parts of one
+        let part = match series_info {
+            Some(_) => None,
+            None => split_part_suffix(path.file_stem().unwrap_or_default()).1,
+        };
+        if let Some(part) = part {
+            info!("{path} is part {part} of a stacked movie");
+        }
+        Ok(Video {
+            path,
+            series_info,
+            edition,
+            part,
+            runtime: None,
+        })
+    }
+
+    pub fn part_of_series(&self) -> bool {
+        self.series_info.is_some()
+    }
+}
+
+impl AsRef<Utf8Path> for Video {
+    fn as_ref(&self) -> &Utf8Path {
+        self.path.as_ref()
+    }
+}
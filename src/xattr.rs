@@ -0,0 +1,150 @@
+//! Marking the files subfix makes with an extended attribute, so they can be
+//! told apart from the user's own files even without the manifest (if it's
+//! deleted, or the files are moved somewhere else)
+
+use std::{
+    io,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use camino::Utf8Path;
+use log::{debug, warn};
+
+use crate::fsops::{check_writable, is_dry_run};
+
+pub const ORIGIN: &str = "user.subfix.origin";
+/// What [`ORIGIN`] is set to
+const CREATED: &str = "created";
+
+static WARNED: AtomicBool = AtomicBool::new(false);
+
+/// Marks a file subfix made. Filesystems (or platforms) without extended
+/// attributes, and Linux's refusal to put them on symlinks, only get a
+/// warning, the first time, as the manifest still says what's subfix's
+pub fn mark_created(path: &Utf8Path) {
+    if is_dry_run() {
+        return;
+    }
+    let marked = check_writable(path).and_then(|()| set(path, ORIGIN, CREATED));
+    match marked {
+        Ok(()) => debug!("marked {path} with {ORIGIN}"),
+        Err(why) if !WARNED.swap(true, Ordering::Relaxed) => warn!(
+            "couldn't mark {path} with {ORIGIN} (others won't be mentioned): \
+             {why}"
+        ),
+        Err(why) => debug!("couldn't mark {path} with {ORIGIN}: {why}"),
+    }
+}
+
+/// Whether a file (or the link itself, for a symlink) is marked as made by
+/// subfix
+pub fn is_marked_created(path: &Utf8Path) -> bool {
+    get(path, ORIGIN).ok().flatten().as_deref() == Some(CREATED.as_bytes())
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn c_string(s: &str) -> io::Result<std::ffi::CString> {
+    std::ffi::CString::new(s)
+        .map_err(|why| io::Error::new(io::ErrorKind::InvalidInput, why))
+}
+
+// The `l` versions, so a symlink is marked rather than what it points at
+#[cfg(target_os = "linux")]
+fn set(path: &Utf8Path, name: &str, value: &str) -> io::Result<()> {
+    let (path, name) = (c_string(path.as_str())?, c_string(name)?);
+    // SAFETY: both strings are NUL-terminated and outlive the call, and the
+    // value's length is passed along with it
+    let result = unsafe {
+        libc::lsetxattr(
+            path.as_ptr(),
+            name.as_ptr(),
+            value.as_ptr().cast(),
+            value.len(),
+            0,
+        )
+    };
+    match result {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn get(path: &Utf8Path, name: &str) -> io::Result<Option<Vec<u8>>> {
+    let (path, name) = (c_string(path.as_str())?, c_string(name)?);
+    let mut value = [0u8; 64];
+    // SAFETY: as above, with the buffer's real length
+    let read = unsafe {
+        libc::lgetxattr(
+            path.as_ptr(),
+            name.as_ptr(),
+            value.as_mut_ptr().cast(),
+            value.len(),
+        )
+    };
+    match read {
+        -1 => match io::Error::last_os_error() {
+            why if why.raw_os_error() == Some(libc::ENODATA) => Ok(None),
+            why => Err(why),
+        },
+        read => Ok(Some(value[..read as usize].to_vec())),
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn set(path: &Utf8Path, name: &str, value: &str) -> io::Result<()> {
+    let (path, name) = (c_string(path.as_str())?, c_string(name)?);
+    // SAFETY: both strings are NUL-terminated and outlive the call, and the
+    // value's length is passed along with it
+    let result = unsafe {
+        libc::setxattr(
+            path.as_ptr(),
+            name.as_ptr(),
+            value.as_ptr().cast(),
+            value.len(),
+            0,
+            libc::XATTR_NOFOLLOW,
+        )
+    };
+    match result {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn get(path: &Utf8Path, name: &str) -> io::Result<Option<Vec<u8>>> {
+    let (path, name) = (c_string(path.as_str())?, c_string(name)?);
+    let mut value = [0u8; 64];
+    // SAFETY: as above, with the buffer's real length
+    let read = unsafe {
+        libc::getxattr(
+            path.as_ptr(),
+            name.as_ptr(),
+            value.as_mut_ptr().cast(),
+            value.len(),
+            0,
+            libc::XATTR_NOFOLLOW,
+        )
+    };
+    match read {
+        -1 => match io::Error::last_os_error() {
+            why if why.raw_os_error() == Some(libc::ENOATTR) => Ok(None),
+            why => Err(why),
+        },
+        read => Ok(Some(value[..read as usize].to_vec())),
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn set(_: &Utf8Path, _: &str, _: &str) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "extended attributes aren't supported on this platform",
+    ))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn get(_: &Utf8Path, _: &str) -> io::Result<Option<Vec<u8>>> {
+    Ok(None)
+}
@@ -0,0 +1,42 @@
+mod common;
+
+use common::{subfix, Library, SRT};
+
+#[test]
+fn dry_run_leaves_no_checkpoint() {
+    let library = Library::new("checkpoint");
+    library.file("Movie (2020)/Movie (2020).mkv", "");
+    library.file("Movie (2020)/English.srt", SRT);
+    let checkpoint = library.path("checkpoint.txt");
+    subfix(&library.root, &[
+        "--dry-run",
+        "--checkpoint",
+        checkpoint.to_str().unwrap(),
+        "Movie (2020)",
+    ]);
+    assert!(!checkpoint.exists());
+    assert!(!library.path("checkpoint.txt.lock").exists());
+    assert_eq!(library.listing(), [
+        "Movie (2020)/",
+        "Movie (2020)/English.srt",
+        "Movie (2020)/Movie (2020).mkv",
+    ]);
+}
+
+#[test]
+fn dry_run_keeps_checkpoint_of_real_run() {
+    let library = Library::new("checkpoint");
+    library.file("Done (2019)/Done (2019).mkv", "");
+    library.file("Movie (2020)/Movie (2020).mkv", "");
+    library.file("Movie (2020)/English.srt", SRT);
+    let done = format!("{}\n", library.path("Done (2019)").display());
+    let checkpoint = library.file("checkpoint.txt", &done);
+    subfix(&library.root, &[
+        "--dry-run",
+        "--checkpoint",
+        checkpoint.to_str().unwrap(),
+        "Done (2019)",
+        "Movie (2020)",
+    ]);
+    assert_eq!(std::fs::read_to_string(&checkpoint).unwrap(), done);
+}
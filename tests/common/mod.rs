@@ -0,0 +1,116 @@
+//! Libraries made up for a test, and running subfix on them. subfix is run as
+//! a program rather than called into, as dry runs and the like are settings
+//! for the whole process
+
+#![allow(dead_code)]
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// A folder in the temporary directory, removed when dropped
+pub struct Library {
+    pub root: PathBuf,
+}
+
+impl Library {
+    pub fn new(name: &str) -> Self {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let root = env::temp_dir().join(format!(
+            "subfix-test-{}-{name}-{}",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        // So symlinks resolve to what subfix will see
+        let root = root.canonicalize().unwrap();
+        Library { root }
+    }
+
+    pub fn path(&self, relative: &str) -> PathBuf {
+        self.root.join(relative)
+    }
+
+    /// Creates the file, and any folders it's in
+    pub fn file(&self, relative: &str, contents: impl AsRef<[u8]>) -> PathBuf {
+        let path = self.path(relative);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    /// Every file, link & folder in the library, relative to it and sorted
+    pub fn listing(&self) -> Vec<String> {
+        let mut listing = Vec::new();
+        list(&self.root, &self.root, &mut listing);
+        listing.sort();
+        listing
+    }
+}
+
+fn list(root: &Path, dir: &Path, listing: &mut Vec<String>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        let relative = path.strip_prefix(root).unwrap().display().to_string();
+        let meta = path.symlink_metadata().unwrap();
+        if meta.is_dir() {
+            listing.push(format!("{relative}/"));
+            list(root, &path, listing);
+        } else {
+            listing.push(relative);
+        }
+    }
+}
+
+impl Drop for Library {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
+
+/// Some subtitles, long enough not to be skipped as a stub
+pub const SRT: &str = "1\n00:00:01,000 --> 00:00:04,000\nHello \
+                       there.\n\n2\n00:00:05,000 --> 00:00:08,000\nGeneral \
+                       Kenobi.\n\n3\n00:00:09,000 --> 00:00:12,000\nYou are a \
+                       bold one.\n";
+
+/// What a dry run exits with when a real run would have changed things
+pub const DRY_RUN_CHANGES: i32 = 2;
+
+/// Runs subfix with `args` in `dir`, panicking with its output if it fails
+pub fn subfix(dir: &Path, args: &[&str]) -> Output {
+    let output = subfix_unchecked(dir, args);
+    assert!(
+        matches!(output.status.code(), Some(0 | DRY_RUN_CHANGES)),
+        "subfix {args:?} failed ({}):\n{}",
+        output.status,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output
+}
+
+pub fn subfix_unchecked(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_subfix"))
+        .args(args)
+        .current_dir(dir)
+        .env("SUBFIX_LOG", "debug")
+        .env_remove("SUBFIX_TOKEN")
+        .output()
+        .unwrap()
+}
+
+/// The links in the `--json` output of a run, made or planned
+pub fn links(output: &Output) -> Vec<String> {
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let link = regex::Regex::new(r#""link":"([^"]*)""#).unwrap();
+    let mut links = link
+        .captures_iter(&stdout)
+        .map(|captures| captures[1].to_owned())
+        .collect::<Vec<_>>();
+    links.sort();
+    links
+}
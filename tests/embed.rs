@@ -0,0 +1,58 @@
+#![cfg(all(unix, feature = "embed"))]
+
+mod common;
+
+use std::{fs, os::unix::fs::PermissionsExt, path::Path, process::Command};
+
+use common::{subfix, Library, SRT};
+
+// Runs `embed` with an mkvmerge that notes what it's asked to do, and copies
+// the video as it is
+fn embed(library: &Library, log: &Path) {
+    let bin = library.path("bin");
+    let mkvmerge = bin.join("mkvmerge");
+    if !mkvmerge.exists() {
+        fs::create_dir_all(&bin).unwrap();
+        let script = format!(
+            "#!/bin/sh\necho \"$@\" >> '{}'\ncp \"$4\" \"$3\"\n",
+            log.display()
+        );
+        fs::write(&mkvmerge, script).unwrap();
+        fs::set_permissions(&mkvmerge, fs::Permissions::from_mode(0o755))
+            .unwrap();
+    }
+    let path = format!("{}:{}", bin.display(), std::env::var("PATH").unwrap());
+    let output = Command::new(env!("CARGO_BIN_EXE_subfix"))
+        .args(["embed", "Movie (2020)"])
+        .current_dir(&library.root)
+        .env("PATH", path)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn embedding_twice_adds_the_tracks_once() {
+    let library = Library::new("embed");
+    library.file("Movie (2020)/Movie (2020).mkv", "not really a video");
+    library.file("Movie (2020)/Subs/English.srt", SRT);
+    let log = library.path("mkvmerge.log");
+    let link = library.path("Movie (2020)/Movie (2020).en.default.srt");
+    subfix(&library.root, &["Movie (2020)"]);
+    assert!(link.exists());
+    embed(&library, &log);
+    assert!(!link.exists());
+    // Neither linked again, nor embedded again
+    subfix(&library.root, &["Movie (2020)"]);
+    assert!(!link.exists());
+    embed(&library, &log);
+    let runs = fs::read_to_string(&log).unwrap();
+    assert_eq!(runs.lines().count(), 1, "{runs}");
+    let manifest =
+        fs::read_to_string(library.path("Movie (2020)/.subfix.json")).unwrap();
+    assert!(manifest.contains(r#""origin":"embedded""#), "{manifest}");
+}
@@ -0,0 +1,36 @@
+mod common;
+
+use std::{fs::File, io::Write, process::Command};
+
+use common::{links, subfix, Library, SRT};
+
+#[test]
+fn dry_run_plans_gzipped_subtitles() {
+    let library = Library::new("gzip");
+    library.file("Movie (2020)/Movie (2020).mkv", "");
+    let srt = library.file("Movie (2020)/English.srt", SRT);
+    let gzipped = Command::new("gzip").arg(&srt).status().unwrap();
+    assert!(gzipped.success());
+    let before = library.listing();
+    let dry = subfix(&library.root, &["--dry-run", "--json", "Movie (2020)"]);
+    assert_eq!(library.listing(), before);
+    let real = subfix(&library.root, &["--json", "Movie (2020)"]);
+    assert_eq!(links(&dry), links(&real));
+    assert_eq!(links(&real), [library
+        .path("Movie (2020)/Movie (2020).en.default.srt")
+        .display()
+        .to_string()]);
+    assert!(library.path("Movie (2020)/English.srt").is_file());
+}
+
+#[test]
+fn corrupt_gzip_is_skipped() {
+    let library = Library::new("gzip");
+    library.file("Movie (2020)/Movie (2020).mkv", "");
+    let mut gz =
+        File::create(library.path("Movie (2020)/English.srt.gz")).unwrap();
+    gz.write_all(b"not gzip at all").unwrap();
+    let real = subfix(&library.root, &["--json", "Movie (2020)"]);
+    assert!(links(&real).is_empty());
+    assert!(String::from_utf8_lossy(&real.stdout).contains("decompress"));
+}
@@ -0,0 +1,70 @@
+mod common;
+
+use common::{subfix, Library, SRT};
+
+/// Videos, a subtitle for each, and the link it should get, named with
+/// what breaks quoting, globbing & regexes
+const CASES: &[(&str, &str, &str)] = &[
+    (
+        "Amélie (2001) [+Extras] 🎬.mkv",
+        "Subs/English.srt",
+        "Amélie (2001) [+Extras] 🎬.en.default.srt",
+    ),
+    (
+        "ترجمة (2020).mkv",
+        "Subs/ترجمة \u{202e}txt/2_Arabic.srt",
+        "ترجمة (2020).ar.srt",
+    ),
+    (
+        "Line\nBreak (2020).mkv",
+        "Subs/English.srt",
+        "Line\nBreak (2020).en.default.srt",
+    ),
+    (
+        "Up (2009).mkv",
+        "Subs/(1)_English.srt",
+        "Up (2009).en.default.srt",
+    ),
+    (
+        "C++ (2020).mkv",
+        "C++ (2020).English.srt",
+        "C++ (2020).en.default.srt",
+    ),
+    (
+        "Heat [1995].mkv",
+        "Heat [1995].eng.forced.srt",
+        "Heat [1995].en.forced.srt",
+    ),
+];
+
+#[test]
+fn hostile_names_are_matched() {
+    for &(video, subtitle, link) in CASES {
+        let library = Library::new("hostile");
+        library.file(&format!("Movie/{video}"), "");
+        library.file(&format!("Movie/{subtitle}"), SRT);
+        subfix(&library.root, &["Movie"]);
+        let link = library.path(&format!("Movie/{link}"));
+        assert!(
+            link.symlink_metadata().is_ok(),
+            "{video:?} & {subtitle:?} didn't give {link:?}: {:?}",
+            library.listing()
+        );
+    }
+}
+
+#[test]
+fn hostile_names_are_ignored() {
+    for &(video, subtitle, _) in CASES {
+        let library = Library::new("hostile");
+        library.file(&format!("Movie/{video}"), "");
+        library.file(&format!("Movie/{subtitle}"), SRT);
+        // The subtitle's own name, with the glob characters in it escaped
+        let name = subtitle.rsplit('/').next().unwrap();
+        let pattern = name.replace('[', "\\[").replace(']', "\\]");
+        library.file("Movie/.stignore", pattern.replace('\n', "?"));
+        let before = library.listing();
+        subfix(&library.root, &["--respect-ignore-files", "Movie"]);
+        assert_eq!(library.listing(), before, "{pattern:?} wasn't ignored");
+    }
+}
@@ -0,0 +1,138 @@
+mod common;
+
+use common::{links, subfix, Library, SRT};
+
+#[test]
+fn empty_subtitle_is_skipped() {
+    let library = Library::new("link");
+    library.file("Movie (2020)/Movie (2020).mkv", "");
+    library.file("Movie (2020)/English.srt", "");
+    library.file("Movie (2020)/French.srt", SRT);
+    let output = subfix(&library.root, &["--json", "Movie (2020)"]);
+    assert_eq!(links(&output), [library
+        .path("Movie (2020)/Movie (2020).fr.srt")
+        .display()
+        .to_string()]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains(r#""reason":"incomplete","detail":"it's empty""#),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn rename_records_where_the_subtitle_was() {
+    let library = Library::new("link");
+    library.file("Movie (2020)/Movie (2020) - 1080p.mkv", "");
+    library.file("Movie (2020)/Movie (2020) - 720p.mkv", "");
+    let english = library.file("Movie (2020)/Subs/English.srt", SRT);
+    subfix(&library.root, &["--mode", "rename", "Movie (2020)"]);
+    assert!(!english.exists());
+    let manifest =
+        std::fs::read_to_string(library.path("Movie (2020)/.subfix.json"))
+            .unwrap();
+    let target = format!(r#""target":"{}""#, english.display());
+    for (name, origin) in [
+        ("Movie (2020) - 1080p.en.default.srt", "moved"),
+        ("Movie (2020) - 720p.en.default.srt", "created"),
+    ] {
+        assert!(library.path(&format!("Movie (2020)/{name}")).is_file());
+        let entry = format!(r#"{{"name":"{name}",{target},"#);
+        let entry = &manifest[manifest.find(&entry).unwrap()..];
+        let entry = &entry[..entry.find('}').unwrap()];
+        assert!(
+            entry.contains(&format!(r#""origin":"{origin}""#)),
+            "{entry}"
+        );
+    }
+    // And nothing happens to them the next time round
+    let before = library.listing();
+    subfix(&library.root, &["--mode", "rename", "Movie (2020)"]);
+    assert_eq!(library.listing(), before);
+}
+
+#[test]
+fn copies_are_left_be_next_time() {
+    let library = Library::new("link");
+    library.file("Movie (2020)/Movie (2020).mkv", "");
+    library.file("Movie (2020)/Subs/English.srt", SRT);
+    subfix(&library.root, &["--mode", "copy", "Movie (2020)"]);
+    let before = library.listing();
+    let again = subfix(&library.root, &["--mode", "copy", "Movie (2020)"]);
+    assert_eq!(library.listing(), before);
+    let stderr = String::from_utf8_lossy(&again.stderr);
+    assert!(
+        !stderr.contains("WARN") && !stderr.contains("ERROR"),
+        "{stderr}"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn copies_follow_the_umask() {
+    use std::{fs, os::unix::fs::PermissionsExt, process::Command};
+
+    let library = Library::new("link");
+    library.file("Movie (2020)/Movie (2020).mkv", "");
+    let english = library.file("Movie (2020)/Subs/English.srt", SRT);
+    fs::set_permissions(&english, fs::Permissions::from_mode(0o644)).unwrap();
+    let status = Command::new("sh")
+        .args(["-c", r#"umask 077 && exec "$0" "$@""#])
+        .arg(env!("CARGO_BIN_EXE_subfix"))
+        .args(["--mode", "copy", "Movie (2020)"])
+        .current_dir(&library.root)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let copy = library.path("Movie (2020)/Movie (2020).en.default.srt");
+    let mode = fs::metadata(copy).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+}
+
+#[cfg(unix)]
+#[test]
+fn hard_links_across_filesystems_fall_back() {
+    use std::{fs, os::unix::fs::MetadataExt};
+
+    // Somewhere on another filesystem to the temporary directory, if there is
+    let elsewhere = Library {
+        root: std::path::PathBuf::from(format!(
+            "/dev/shm/subfix-test-{}",
+            std::process::id()
+        )),
+    };
+    let dev = |path: &std::path::Path| fs::metadata(path).map(|m| m.dev());
+    if fs::create_dir_all(&elsewhere.root).is_err()
+        || dev(&elsewhere.root).ok() == dev(&std::env::temp_dir()).ok()
+    {
+        eprintln!("no second filesystem to test with");
+        return;
+    }
+    elsewhere.file("Subs/English.srt", SRT);
+    for (fallback, made) in [
+        ("copy", Some(false)),
+        ("symlink", Some(true)),
+        ("skip", None),
+    ] {
+        let library = Library::new("link");
+        library.file("Movie (2020)/Movie (2020).mkv", "");
+        std::os::unix::fs::symlink(
+            elsewhere.path("Subs"),
+            library.path("Movie (2020)/Subs"),
+        )
+        .unwrap();
+        let args = ["--mode", "hardlink", "--fallback-mode", fallback];
+        let output = subfix(
+            &library.root,
+            &[&args[..], &["-n", "Movie (2020)"]].concat(),
+        );
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("on another filesystem"), "{stderr}");
+        subfix(&library.root, &[&args[..], &["Movie (2020)"]].concat());
+        let link = library.path("Movie (2020)/Movie (2020).en.default.srt");
+        let is_symlink = fs::symlink_metadata(&link)
+            .ok()
+            .map(|metadata| metadata.file_type().is_symlink());
+        assert_eq!(is_symlink, made, "--fallback-mode {fallback}");
+    }
+}
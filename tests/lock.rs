@@ -0,0 +1,33 @@
+mod common;
+
+use common::{subfix, Library, SRT};
+
+#[test]
+fn dry_runs_take_no_locks() {
+    let library = Library::new("lock");
+    library.file("Movie (2020)/Movie (2020).mkv", "");
+    library.file("Movie (2020)/English.srt", SRT);
+    // Held by a run that's still going, which a dry run doesn't wait for
+    library.file("Movie (2020)/.subfix.lock", "1\n");
+    let before = library.listing();
+    subfix(&library.root, &["--dry-run", "Movie (2020)"]);
+    assert_eq!(library.listing(), before);
+}
+
+#[test]
+fn locks_are_audited() {
+    let library = Library::new("lock");
+    library.file("Movie (2020)/Movie (2020).mkv", "");
+    library.file("Movie (2020)/English.srt", SRT);
+    let log = library.path("audit.log");
+    subfix(&library.root, &[
+        "--audit-log",
+        log.to_str().unwrap(),
+        "Movie (2020)",
+    ]);
+    let log = std::fs::read_to_string(log).unwrap();
+    let lock = library.path("Movie (2020)/.subfix.lock");
+    let lock = lock.display();
+    assert!(log.contains(&format!("\tlock\t{lock}\n")), "{log}");
+    assert!(log.contains(&format!("\tdelete\t{lock}\n")), "{log}");
+}
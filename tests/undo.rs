@@ -0,0 +1,93 @@
+mod common;
+
+use common::{subfix, Library, SRT};
+
+fn undoes(mode: &str) {
+    let library = Library::new(&format!("undo-{mode}"));
+    library.file("Movie (2020)/Movie (2020) - 1080p.mkv", "");
+    library.file("Movie (2020)/Movie (2020) - 720p.mkv", "");
+    library.file("Movie (2020)/Subs/English.srt", SRT);
+    library.file(
+        "Movie (2020)/Subs/French.srt",
+        SRT.replace("Hello", "Salut"),
+    );
+    let before = library.listing();
+    subfix(&library.root, &["--mode", mode, "Movie (2020)"]);
+    assert!(library.path("Movie (2020)/.subfix.json").exists());
+    assert_ne!(library.listing(), before);
+    subfix(&library.root, &["--undo", "Movie (2020)"]);
+    assert_eq!(library.listing(), before);
+    let french = library.path("Movie (2020)/Subs/French.srt");
+    assert!(std::fs::read_to_string(french).unwrap().contains("Salut"));
+}
+
+#[test]
+fn undoes_symlinks() {
+    undoes("symlink");
+}
+
+#[test]
+fn undoes_hard_links() {
+    undoes("hardlink");
+}
+
+#[test]
+fn undoes_copies() {
+    undoes("copy");
+}
+
+#[test]
+fn undoes_renames() {
+    undoes("rename");
+}
+
+#[test]
+fn keeps_copies_of_subtitles_that_are_gone() {
+    let library = Library::new("undo-gone");
+    library.file("Movie (2020)/Movie (2020).mkv", "");
+    let english = library.file("Movie (2020)/Subs/English.srt", SRT);
+    subfix(&library.root, &["--mode", "copy", "Movie (2020)"]);
+    std::fs::remove_file(english).unwrap();
+    subfix(&library.root, &["--undo", "Movie (2020)"]);
+    assert!(library
+        .path("Movie (2020)/Movie (2020).en.default.srt")
+        .is_file());
+}
+
+#[cfg(unix)]
+#[test]
+fn leaves_symlinked_folders_alone() {
+    let library = Library::new("undo-symlinked");
+    library.file("Elsewhere/Movie (2020)/Movie (2020).mkv", "");
+    library.file("Elsewhere/Movie (2020)/Subs/English.srt", SRT);
+    subfix(&library.root, &["Elsewhere/Movie (2020)"]);
+    std::fs::create_dir(library.path("Library")).unwrap();
+    std::os::unix::fs::symlink(
+        library.path("Elsewhere/Movie (2020)"),
+        library.path("Library/Movie (2020)"),
+    )
+    .unwrap();
+    // And one back up the tree, which mustn't go round forever
+    std::os::unix::fs::symlink(
+        library.path("Library"),
+        library.path("Library/Loop"),
+    )
+    .unwrap();
+    subfix(&library.root, &["--undo", "Library"]);
+    assert!(library
+        .path("Elsewhere/Movie (2020)/Movie (2020).en.default.srt")
+        .symlink_metadata()
+        .is_ok());
+}
+
+#[test]
+fn keeps_copies_that_were_edited() {
+    let library = Library::new("undo-edited");
+    library.file("Movie (2020)/Movie (2020).mkv", "");
+    library.file("Movie (2020)/Subs/English.srt", SRT);
+    subfix(&library.root, &["--mode", "copy", "Movie (2020)"]);
+    let copy = library.path("Movie (2020)/Movie (2020).en.default.srt");
+    std::fs::write(&copy, SRT.replace("Hello", "Hi")).unwrap();
+    subfix(&library.root, &["--undo", "Movie (2020)"]);
+    assert!(std::fs::read_to_string(copy).unwrap().contains("Hi there"));
+}